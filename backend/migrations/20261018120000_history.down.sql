@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS history.idx_stop_time_observation_source_route_arrival;
+
+DROP TABLE IF EXISTS history.stop_time_observation;
+
+DROP SCHEMA IF EXISTS history CASCADE;
@@ -0,0 +1,23 @@
+CREATE SCHEMA IF NOT EXISTS history;
+
+-- Observed arrivals and departures, frozen once a stop has been passed.
+-- There are no foreign keys to realtime or static tables so observations outlive the trips and stops they came from.
+CREATE TABLE IF NOT EXISTS history.stop_time_observation (
+    trip_id UUID NOT NULL,
+    stop_id VARCHAR NOT NULL,
+    source source_enum NOT NULL,
+    original_trip_id VARCHAR NOT NULL,
+    route_id VARCHAR NOT NULL,
+    direction SMALLINT NOT NULL,
+    arrival TIMESTAMP WITH TIME ZONE NOT NULL,
+    departure TIMESTAMP WITH TIME ZONE NOT NULL,
+    -- NULL when the source has no static schedule for the trip
+    scheduled_arrival TIMESTAMP WITH TIME ZONE,
+    scheduled_departure TIMESTAMP WITH TIME ZONE,
+    recorded_at TIMESTAMP WITH TIME ZONE NOT NULL,
+
+    PRIMARY KEY (trip_id, stop_id, source)
+);
+
+CREATE INDEX idx_stop_time_observation_source_route_arrival
+    ON history.stop_time_observation (source, route_id, arrival);
@@ -0,0 +1,5 @@
+ALTER TABLE realtime.stop_time DROP COLUMN IF EXISTS status;
+ALTER TABLE realtime.trip DROP COLUMN IF EXISTS status;
+
+DROP TYPE IF EXISTS stop_time_status;
+DROP TYPE IF EXISTS trip_status;
@@ -0,0 +1,6 @@
+-- Whether trips and stops run as scheduled, from GTFS-RT schedule_relationship
+CREATE TYPE trip_status AS ENUM ('scheduled', 'added', 'canceled', 'duplicated');
+CREATE TYPE stop_time_status AS ENUM ('scheduled', 'skipped', 'no_data');
+
+ALTER TABLE realtime.trip ADD COLUMN status trip_status NOT NULL DEFAULT 'scheduled';
+ALTER TABLE realtime.stop_time ADD COLUMN status stop_time_status NOT NULL DEFAULT 'scheduled';
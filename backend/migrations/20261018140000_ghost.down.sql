@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS history.ghost_sample;
+
+ALTER TABLE realtime.trip DROP COLUMN IF EXISTS ghost_confidence;
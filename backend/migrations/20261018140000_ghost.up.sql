@@ -0,0 +1,14 @@
+-- How likely it is that a trip's predictions aren't backed by a vehicle actually running it, from 0 to 1.
+-- NULL until the ghost detection engine has checked the trip.
+ALTER TABLE realtime.trip ADD COLUMN ghost_confidence REAL;
+
+-- Number of running and suspected ghost trips per route, sampled every time ghost detection runs
+CREATE TABLE IF NOT EXISTS history.ghost_sample (
+    source source_enum NOT NULL,
+    route_id VARCHAR NOT NULL,
+    recorded_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    trips INTEGER NOT NULL,
+    ghosts INTEGER NOT NULL,
+
+    PRIMARY KEY (source, route_id, recorded_at)
+);
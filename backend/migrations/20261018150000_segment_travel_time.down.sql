@@ -0,0 +1 @@
+DROP TABLE IF EXISTS history.segment_travel_time;
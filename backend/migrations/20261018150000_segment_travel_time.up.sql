@@ -0,0 +1,15 @@
+-- Typical time to travel between consecutive stops of a route, measured from trip history points.
+-- Used to predict arrivals when a realtime feed's own predictions are missing or stale.
+CREATE TABLE IF NOT EXISTS history.segment_travel_time (
+    source source_enum NOT NULL,
+    route_id VARCHAR NOT NULL,
+    direction SMALLINT NOT NULL,
+    from_stop_id VARCHAR NOT NULL,
+    to_stop_id VARCHAR NOT NULL,
+    -- Median seconds from passing from_stop_id to passing to_stop_id, including dwell time at to_stop_id
+    travel_time REAL NOT NULL,
+    samples INTEGER NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL,
+
+    PRIMARY KEY (source, route_id, direction, from_stop_id, to_stop_id)
+);
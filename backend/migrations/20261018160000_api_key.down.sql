@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS auth.api_key_usage;
+DROP TABLE IF EXISTS auth.api_key;
+DROP SCHEMA IF EXISTS auth;
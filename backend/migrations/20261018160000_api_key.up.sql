@@ -0,0 +1,27 @@
+CREATE SCHEMA IF NOT EXISTS auth;
+
+-- Keys issued to API clients. Only a blake3 hash of each key is stored, since the key itself is shown once when it's
+-- created.
+CREATE TABLE IF NOT EXISTS auth.api_key (
+    id UUID PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    key_hash VARCHAR NOT NULL UNIQUE,
+    -- First characters of the key, to tell keys apart without storing them
+    prefix VARCHAR NOT NULL,
+    -- Requests per second the key's token bucket refills at, and the most tokens it holds
+    rate_limit INTEGER NOT NULL,
+    burst INTEGER NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    revoked_at TIMESTAMP WITH TIME ZONE
+);
+
+-- Requests served per key, endpoint and day. Counted in Redis and added here every minute.
+-- Anonymous requests are counted under the nil UUID.
+CREATE TABLE IF NOT EXISTS auth.api_key_usage (
+    key_id UUID NOT NULL,
+    endpoint VARCHAR NOT NULL,
+    day DATE NOT NULL,
+    requests BIGINT NOT NULL,
+
+    PRIMARY KEY (key_id, endpoint, day)
+);
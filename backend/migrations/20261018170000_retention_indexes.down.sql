@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS history.idx_ghost_sample_source_recorded_at;
+DROP INDEX IF EXISTS realtime.idx_alert_source_updated_at;
+DROP INDEX IF EXISTS realtime.idx_trip_source_updated_at;
+DROP INDEX IF EXISTS realtime.idx_trip_history_point_recorded_at;
+DROP INDEX IF EXISTS realtime.idx_stop_time_source_departure;
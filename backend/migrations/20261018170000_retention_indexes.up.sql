@@ -0,0 +1,10 @@
+-- Retention prunes each table by source and age, which would otherwise scan the whole table
+CREATE INDEX IF NOT EXISTS idx_stop_time_source_departure ON realtime.stop_time (source, departure);
+
+CREATE INDEX IF NOT EXISTS idx_trip_history_point_recorded_at ON realtime.trip_history_point (recorded_at);
+
+CREATE INDEX IF NOT EXISTS idx_trip_source_updated_at ON realtime.trip (source, updated_at);
+
+CREATE INDEX IF NOT EXISTS idx_alert_source_updated_at ON realtime.alert (source, updated_at);
+
+CREATE INDEX IF NOT EXISTS idx_ghost_sample_source_recorded_at ON history.ghost_sample (source, recorded_at);
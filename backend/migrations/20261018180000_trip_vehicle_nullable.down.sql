@@ -0,0 +1,7 @@
+ALTER TABLE realtime.trip DROP CONSTRAINT trip_original_id_vehicle_id_created_at_direction_key;
+
+UPDATE realtime.trip SET vehicle_id = original_id WHERE vehicle_id IS NULL;
+ALTER TABLE realtime.trip ALTER COLUMN vehicle_id SET NOT NULL;
+
+ALTER TABLE realtime.trip ADD CONSTRAINT trip_original_id_vehicle_id_created_at_direction_key
+    UNIQUE (original_id, vehicle_id, created_at, direction);
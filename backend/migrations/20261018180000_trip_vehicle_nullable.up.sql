@@ -0,0 +1,6 @@
+-- Canceled trips don't always have a vehicle assigned
+ALTER TABLE realtime.trip ALTER COLUMN vehicle_id DROP NOT NULL;
+
+ALTER TABLE realtime.trip DROP CONSTRAINT trip_original_id_vehicle_id_created_at_direction_key;
+ALTER TABLE realtime.trip ADD CONSTRAINT trip_original_id_vehicle_id_created_at_direction_key
+    UNIQUE NULLS NOT DISTINCT (original_id, vehicle_id, created_at, direction);
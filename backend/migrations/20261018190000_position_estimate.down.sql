@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS static.idx_stop_geom_6538;
+DROP TABLE IF EXISTS realtime.position_estimate;
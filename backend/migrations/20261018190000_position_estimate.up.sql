@@ -0,0 +1,15 @@
+-- Where each vehicle was estimated to be at the last realtime update. Kept out of vehicle_position so updating
+-- estimates doesn't fire the trip history trigger.
+CREATE TABLE IF NOT EXISTS realtime.position_estimate (
+    vehicle_id VARCHAR NOT NULL,
+    source source_enum NOT NULL,
+    geom geometry(POINT, 4326) NOT NULL,
+    bearing REAL,
+    PRIMARY KEY (vehicle_id, source),
+    FOREIGN KEY (vehicle_id, source) REFERENCES realtime.vehicle_position(vehicle_id, source) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_position_estimate_geom ON realtime.position_estimate USING GIST (geom);
+
+-- Nearby stops are measured in EPSG:6538 (meters), so the radius can use an index without a bounding box in degrees
+CREATE INDEX IF NOT EXISTS idx_stop_geom_6538 ON static.stop USING GIST (ST_Transform(geom, 6538));
@@ -0,0 +1,178 @@
+use super::{AppError, AppState, CurrentTime, TimeParams};
+use crate::feed::FeedMessage;
+use crate::integrations::gtfs_rt_export::{
+    ServiceDates, alerts_feed, trip_updates_feed, vehicle_positions_feed,
+};
+use crate::models::{source::Source, trip::Trip};
+use crate::sources::source_timezone;
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::response::{IntoResponse, Response};
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use http::header;
+use prost::Message;
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Deserialize, ToSchema, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedFormat {
+    #[default]
+    Protobuf,
+    /// The same feed as JSON, for debugging
+    Json,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct FeedParameters {
+    /// Response format. Defaults to protobuf.
+    #[serde(default)]
+    format: FeedFormat,
+}
+
+impl FeedFormat {
+    fn respond(self, feed: FeedMessage) -> Response {
+        match self {
+            FeedFormat::Protobuf => (
+                [(header::CONTENT_TYPE, "application/x-protobuf")],
+                feed.encode_to_vec(),
+            )
+                .into_response(),
+            FeedFormat::Json => Json(feed).into_response(),
+        }
+    }
+}
+
+/// Service dates of the trips from the static schedule, since trips after midnight can belong to the previous day
+async fn service_dates(
+    state: &AppState,
+    source: Source,
+    trips: &[Trip],
+    tz: Tz,
+) -> anyhow::Result<ServiceDates> {
+    let lookups: Vec<_> = trips
+        .iter()
+        .map(|t| (t.original_id.as_str(), t.created_at))
+        .collect();
+    let scheduled = state
+        .static_cache_store
+        .find_trips_running_at(source, &lookups, tz)
+        .await?;
+
+    Ok(trips
+        .iter()
+        .zip(scheduled)
+        .filter_map(|(trip, cached)| {
+            let date = NaiveDate::parse_from_str(&cached?.start_date, "%Y%m%d").ok()?;
+            Some((trip.id, date))
+        })
+        .collect())
+}
+
+#[utoipa::path(
+    get,
+    path = "/gtfs-rt/{source}/trip_updates.pb",
+    tag = "REALTIME",
+    description = "GTFS-Realtime trip updates feed built from the normalized trips and stop times. IDs match the ones used by the other endpoints.",
+    params(
+        ("source" = Source, Path, description = "Data source"),
+        FeedParameters,
+        TimeParams
+    ),
+    responses(
+        (status = 200, description = "GTFS-RT FeedMessage", content_type = "application/x-protobuf")
+    )
+)]
+pub async fn trip_updates_handler(
+    State(state): State<AppState>,
+    Path(source): Path<Source>,
+    params: Query<FeedParameters>,
+    current_time: CurrentTime,
+) -> Result<Response, AppError> {
+    let at = current_time.at();
+    let (trips, stop_times) = tokio::try_join!(
+        state.trip_store.get_all(source, at),
+        state.stop_time_store.get_all(source, at, None)
+    )?;
+
+    let tz = source_timezone(source);
+    let service_dates = service_dates(&state, source, &trips, tz).await?;
+
+    let feed = trip_updates_feed(
+        source,
+        tz,
+        &trips,
+        &service_dates,
+        &stop_times,
+        current_time.time,
+    );
+    Ok(params.format.respond(feed))
+}
+
+#[utoipa::path(
+    get,
+    path = "/gtfs-rt/{source}/vehicle_positions.pb",
+    tag = "REALTIME",
+    description = "GTFS-Realtime vehicle positions feed built from the normalized vehicle positions and their trips.",
+    params(
+        ("source" = Source, Path, description = "Data source"),
+        FeedParameters,
+        TimeParams
+    ),
+    responses(
+        (status = 200, description = "GTFS-RT FeedMessage", content_type = "application/x-protobuf")
+    )
+)]
+pub async fn vehicle_positions_handler(
+    State(state): State<AppState>,
+    Path(source): Path<Source>,
+    params: Query<FeedParameters>,
+    current_time: CurrentTime,
+) -> Result<Response, AppError> {
+    let at = current_time.at();
+    let (positions, trips) = tokio::try_join!(
+        state.position_store.get_all(source, at),
+        state.trip_store.get_all(source, at)
+    )?;
+
+    let tz = source_timezone(source);
+    let service_dates = service_dates(&state, source, &trips, tz).await?;
+
+    let feed = vehicle_positions_feed(
+        source,
+        tz,
+        &positions,
+        &trips,
+        &service_dates,
+        current_time.time,
+    );
+    Ok(params.format.respond(feed))
+}
+
+#[utoipa::path(
+    get,
+    path = "/gtfs-rt/{source}/alerts.pb",
+    tag = "REALTIME",
+    description = "GTFS-Realtime service alerts feed built from the normalized alerts.",
+    params(
+        ("source" = Source, Path, description = "Data source"),
+        FeedParameters,
+        TimeParams
+    ),
+    responses(
+        (status = 200, description = "GTFS-RT FeedMessage", content_type = "application/x-protobuf")
+    )
+)]
+pub async fn alerts_handler(
+    State(state): State<AppState>,
+    Path(source): Path<Source>,
+    params: Query<FeedParameters>,
+    current_time: CurrentTime,
+) -> Result<Response, AppError> {
+    let at = current_time.at();
+    let alerts = state.alert_store.get_all(source, at).await?;
+
+    let feed = alerts_feed(&alerts, current_time.time);
+    Ok(params.format.respond(feed))
+}
@@ -0,0 +1,131 @@
+use super::{AppError, AppState};
+use crate::models::ghost::GhostRate;
+use crate::models::history::RoutePerformance;
+use crate::models::source::Source;
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Duration, Utc};
+use http::StatusCode;
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+/// Default reporting period when `from` isn't specified
+const DEFAULT_PERIOD: Duration = Duration::days(7);
+/// Default length in minutes of each ghost rate period
+const DEFAULT_GHOST_INTERVAL: i64 = 60;
+
+#[derive(Deserialize, IntoParams)]
+pub struct PerformanceParameters {
+    /// Only return this route
+    #[param(example = "1")]
+    route_id: Option<String>,
+    /// Unix timestamp of the start of the period. Defaults to 7 days before `to`.
+    from: Option<i64>,
+    /// Unix timestamp of the end of the period. Defaults to the current time.
+    to: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/performance/{source}",
+    tag = "HISTORY",
+    description = "On-time performance, average delay and headway adherence per route, from stop times archived after trips passed them. Arrivals are on time from 1 minute early to 5 minutes late. Delays are only available for sources with a static schedule for their realtime trips.",
+    params(
+        ("source" = Source, Path, description = "Data source"),
+        PerformanceParameters
+    ),
+    responses(
+        (status = 200, description = "Performance of each route with observations in the period", body = [RoutePerformance]),
+        (status = 400, description = "Invalid period")
+    )
+)]
+pub async fn performance_handler(
+    State(state): State<AppState>,
+    Path(source): Path<Source>,
+    params: Query<PerformanceParameters>,
+) -> Result<Response, AppError> {
+    let (from, to) = match period(params.from, params.to) {
+        Ok(period) => period,
+        Err(e) => return Ok((StatusCode::BAD_REQUEST, e).into_response()),
+    };
+
+    let performance = state
+        .history_store
+        .get_performance(source, params.route_id.as_deref(), from, to)
+        .await?;
+    Ok(Json(performance).into_response())
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct GhostRateParameters {
+    /// Only return this route
+    #[param(example = "B46")]
+    route_id: Option<String>,
+    /// Unix timestamp of the start of the period. Defaults to 7 days before `to`.
+    from: Option<i64>,
+    /// Unix timestamp of the end of the period. Defaults to the current time.
+    to: Option<i64>,
+    /// Length of each reported period in minutes. Defaults to 60.
+    interval: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/ghosts/{source}/rates",
+    tag = "HISTORY",
+    description = "Share of each route's running trips that were suspected ghosts, split into periods of `interval` minutes. Trips are sampled every time the realtime data is updated, and samples are kept for 30 days.",
+    params(
+        ("source" = Source, Path, description = "Data source"),
+        GhostRateParameters
+    ),
+    responses(
+        (status = 200, description = "Ghost rate of each route for every period with samples, ordered by route then time", body = [GhostRate]),
+        (status = 400, description = "Invalid period or interval")
+    )
+)]
+pub async fn ghost_rates_handler(
+    State(state): State<AppState>,
+    Path(source): Path<Source>,
+    params: Query<GhostRateParameters>,
+) -> Result<Response, AppError> {
+    let (from, to) = match period(params.from, params.to) {
+        Ok(period) => period,
+        Err(e) => return Ok((StatusCode::BAD_REQUEST, e).into_response()),
+    };
+    let interval = params.interval.unwrap_or(DEFAULT_GHOST_INTERVAL);
+    if interval <= 0 {
+        return Ok((StatusCode::BAD_REQUEST, "interval must be positive").into_response());
+    }
+
+    let rates = state
+        .history_store
+        .get_ghost_rates(
+            source,
+            params.route_id.as_deref(),
+            from,
+            to,
+            Duration::minutes(interval),
+        )
+        .await?;
+    Ok(Json(rates).into_response())
+}
+
+/// Parse a reporting period from Unix timestamps, defaulting to the 7 days before now
+fn period(
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Result<(DateTime<Utc>, DateTime<Utc>), &'static str> {
+    let timestamp = |t: Option<i64>| match t {
+        Some(t) => DateTime::from_timestamp(t, 0)
+            .ok_or("Invalid timestamp")
+            .map(Some),
+        None => Ok(None),
+    };
+    let to = timestamp(to)?.unwrap_or_else(Utc::now);
+    let from = timestamp(from)?.unwrap_or(to - DEFAULT_PERIOD);
+    if from >= to {
+        return Err("from must be before to");
+    }
+    Ok((from, to))
+}
@@ -0,0 +1,349 @@
+//! API keys and per-client rate limits.
+//!
+//! Clients send their key in the `X-API-Key` header, or the `api_key` query parameter for clients like map libraries
+//! and browser websockets that can't set headers. Each key has its own token bucket in Redis, and requests without a
+//! key share a smaller limit per address unless anonymous access is disabled. Every request is also charged to its
+//! address before its key is looked up, so requests with made-up keys can't flood Postgres.
+//!
+//! The limiter fails open: if Redis doesn't answer quickly, requests are let through instead of the whole API going
+//! down with it. Looking up a key fails closed: if Postgres can't be reached, requests with a key that isn't cached
+//! get a 503, since the key can't be verified.
+
+use std::{net::SocketAddr, time::Duration};
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, MatchedPath, Path, Query, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use http::{HeaderMap, HeaderValue, StatusCode, header};
+use serde::Deserialize;
+use tracing::warn;
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+use super::{AppError, AppState};
+use crate::{
+    admin_token, anonymous_rate_limit,
+    models::api_key::{ApiKey, ApiKeyUsage, BucketStatus, CreatedApiKey, NewApiKey, RateLimit},
+    trusted_proxy_hops,
+};
+
+const API_KEY_HEADER: &str = "x-api-key";
+/// How long to wait for the rate limiter before letting a request through
+const LIMITER_TIMEOUT: Duration = Duration::from_millis(250);
+/// Limit for each address, with or without a key. It's higher than the default key limit so it doesn't get in the way
+/// of keys.
+const ADDRESS_LIMIT: RateLimit = RateLimit {
+    rate: 100,
+    burst: 200,
+};
+/// Default and max days of usage returned
+const DEFAULT_USAGE_DAYS: u32 = 30;
+const MAX_USAGE_DAYS: u32 = 366;
+
+#[derive(Deserialize)]
+struct KeyParams {
+    api_key: Option<String>,
+}
+
+/// The key from the header, or the query parameter if there's no header
+fn request_key(request: &Request) -> Option<String> {
+    if let Some(key) = request.headers().get(API_KEY_HEADER) {
+        return key.to_str().ok().map(|k| k.to_owned());
+    }
+    Query::<KeyParams>::try_from_uri(request.uri())
+        .ok()
+        .and_then(|params| params.0.api_key)
+}
+
+/// Address requests are limited by. Behind `proxy_hops` trusted reverse proxies, it's the `X-Forwarded-For` entry
+/// added by the outermost one, since entries before it are whatever the client sent. Otherwise the header is ignored
+/// and the connecting address is used.
+fn client_address(headers: &HeaderMap, peer: Option<SocketAddr>, proxy_hops: usize) -> String {
+    let forwarded = match proxy_hops {
+        0 => None,
+        hops => {
+            let entries: Vec<&str> = headers
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(','))
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .collect();
+            entries.len().checked_sub(hops).map(|i| entries[i])
+        }
+    };
+    match (forwarded, peer) {
+        (Some(address), _) => address.to_owned(),
+        (None, Some(peer)) => peer.ip().to_string(),
+        (None, None) => "unknown".to_owned(),
+    }
+}
+
+fn rate_limit_headers(headers: &mut HeaderMap, status: &BucketStatus) {
+    headers.insert("x-ratelimit-limit", HeaderValue::from(status.limit));
+    headers.insert("x-ratelimit-remaining", HeaderValue::from(status.remaining));
+    headers.insert("x-ratelimit-reset", HeaderValue::from(status.reset));
+}
+
+fn too_many_requests(status: &BucketStatus) -> Response {
+    let mut response = (StatusCode::TOO_MANY_REQUESTS, "Too many requests").into_response();
+    rate_limit_headers(response.headers_mut(), status);
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(status.retry_after));
+    response
+}
+
+/// Take a token from a bucket. Returns `None` if the limiter fails or doesn't answer in time, so the request is let
+/// through.
+async fn take_token(state: &AppState, bucket: &str, limit: RateLimit) -> Option<BucketStatus> {
+    match tokio::time::timeout(
+        LIMITER_TIMEOUT,
+        state.api_key_store.take_token(bucket, limit),
+    )
+    .await
+    {
+        Ok(Ok(status)) => Some(status),
+        Ok(Err(e)) => {
+            warn!("Rate limiter error: {:#}", e);
+            None
+        }
+        Err(_) => {
+            warn!("Rate limiter timed out");
+            None
+        }
+    }
+}
+
+/// Middleware that checks the request's API key, takes a token from its bucket, and counts the request
+pub async fn rate_limit(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let endpoint = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| request.uri().path().to_owned());
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0);
+    let address = client_address(request.headers(), peer, trusted_proxy_hops());
+
+    if let Some(status) = take_token(&state, &format!("address:{}", address), ADDRESS_LIMIT).await
+        && !status.allowed
+    {
+        return too_many_requests(&status);
+    }
+
+    let (key_id, bucket, limit) = match request_key(&request) {
+        Some(key) => match state.api_key_store.get_by_key(&key).await {
+            Ok(Some(api_key)) => (api_key.id, api_key.id.to_string(), api_key.limit()),
+            Ok(None) => return (StatusCode::UNAUTHORIZED, "Invalid API key").into_response(),
+            Err(e) => {
+                warn!("Failed to look up API key: {:#}", e);
+                return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "API keys can't be checked right now",
+                )
+                    .into_response();
+            }
+        },
+        None => match anonymous_rate_limit() {
+            Some(rate) => {
+                let limit = RateLimit {
+                    rate,
+                    burst: rate.saturating_mul(2),
+                };
+                (Uuid::nil(), format!("anonymous:{}", address), limit)
+            }
+            None => {
+                return (StatusCode::UNAUTHORIZED, "An API key is required").into_response();
+            }
+        },
+    };
+
+    let status = take_token(&state, &bucket, limit).await;
+    if let Some(status) = &status
+        && !status.allowed
+    {
+        return too_many_requests(status);
+    }
+
+    let api_key_store = state.api_key_store.clone();
+    tokio::spawn(async move {
+        let record = api_key_store.record_usage(key_id, &endpoint, Utc::now().date_naive());
+        match tokio::time::timeout(LIMITER_TIMEOUT, record).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("Failed to record usage of {}: {:#}", endpoint, e),
+            Err(_) => warn!("Recording usage of {} timed out", endpoint),
+        }
+    });
+
+    let mut response = next.run(request).await;
+    if let Some(status) = &status {
+        rate_limit_headers(response.headers_mut(), status);
+    }
+    response
+}
+
+/// Whether the request has the admin token as its bearer token. Hashes are compared since blake3 compares them in
+/// constant time.
+fn is_admin(headers: &HeaderMap) -> bool {
+    let Some(token) = admin_token() else {
+        return false;
+    };
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|given| blake3::hash(given.as_bytes()) == blake3::hash(token.as_bytes()))
+}
+
+fn unauthorized() -> Response {
+    (StatusCode::UNAUTHORIZED, "Admin token required").into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/keys",
+    tag = "ADMIN",
+    description = "Every issued key, including revoked ones. Requires the admin token as a bearer token.",
+    responses(
+        (status = 200, description = "API keys", body = [ApiKey]),
+        (status = 401, description = "Missing or wrong admin token")
+    )
+)]
+pub async fn keys_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    if !is_admin(&headers) {
+        return Ok(unauthorized());
+    }
+    Ok(Json(state.api_key_store.get_all().await?).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/keys",
+    tag = "ADMIN",
+    description = "Issue a key. The key is only returned in this response. Requires the admin token as a bearer token.",
+    request_body = NewApiKey,
+    responses(
+        (status = 201, description = "The new key", body = CreatedApiKey),
+        (status = 400, description = "Empty name or limits that aren't positive"),
+        (status = 401, description = "Missing or wrong admin token")
+    )
+)]
+pub async fn create_key_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(new_key): Json<NewApiKey>,
+) -> Result<Response, AppError> {
+    if !is_admin(&headers) {
+        return Ok(unauthorized());
+    }
+    if new_key.name.trim().is_empty() {
+        return Ok((StatusCode::BAD_REQUEST, "Name is required").into_response());
+    }
+    if new_key.rate_limit.is_some_and(|r| r <= 0) || new_key.burst.is_some_and(|b| b <= 0) {
+        return Ok((StatusCode::BAD_REQUEST, "Limits must be positive").into_response());
+    }
+
+    let (api_key, key) = state.api_key_store.create(new_key).await?;
+    Ok((StatusCode::CREATED, Json(CreatedApiKey { api_key, key })).into_response())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/keys/{id}",
+    tag = "ADMIN",
+    description = "Revoke a key. Requests with it are rejected right away. Requires the admin token as a bearer token.",
+    params(
+        ("id" = Uuid, Path, description = "Key id")
+    ),
+    responses(
+        (status = 204, description = "The key was revoked"),
+        (status = 401, description = "Missing or wrong admin token"),
+        (status = 404, description = "No active key with the id")
+    )
+)]
+pub async fn revoke_key_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    if !is_admin(&headers) {
+        return Ok(unauthorized());
+    }
+    if !state.api_key_store.revoke(id).await? {
+        return Ok((StatusCode::NOT_FOUND, "Key not found").into_response());
+    }
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UsageParameters {
+    /// Days of usage to return, including today. Defaults to 30, max 366.
+    days: Option<u32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/keys/{id}/usage",
+    tag = "ADMIN",
+    description = "Requests a key made per endpoint and day. Usage is updated every minute. Requests without a key are counted under the nil UUID. Requires the admin token as a bearer token.",
+    params(
+        ("id" = Uuid, Path, description = "Key id"),
+        UsageParameters
+    ),
+    responses(
+        (status = 200, description = "Requests per endpoint and day", body = [ApiKeyUsage]),
+        (status = 401, description = "Missing or wrong admin token")
+    )
+)]
+pub async fn usage_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<UsageParameters>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    if !is_admin(&headers) {
+        return Ok(unauthorized());
+    }
+    let days = params
+        .days
+        .unwrap_or(DEFAULT_USAGE_DAYS)
+        .clamp(1, MAX_USAGE_DAYS);
+    let since = Utc::now().date_naive() - chrono::Duration::days(days as i64 - 1);
+    Ok(Json(state.api_key_store.get_usage(id, since).await?).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_address() {
+        let peer = Some("10.0.0.5:51234".parse().unwrap());
+        let mut headers = HeaderMap::new();
+        assert_eq!(client_address(&headers, peer, 0), "10.0.0.5");
+        assert_eq!(client_address(&headers, None, 0), "unknown");
+
+        // Without trusted proxies the header is ignored
+        headers.insert("x-forwarded-for", "1.2.3.4, 203.0.113.7".parse().unwrap());
+        assert_eq!(client_address(&headers, peer, 0), "10.0.0.5");
+
+        // A spoofed first entry is ignored in favor of the one the proxy added
+        assert_eq!(client_address(&headers, peer, 1), "203.0.113.7");
+        assert_eq!(client_address(&headers, peer, 2), "1.2.3.4");
+
+        // The request didn't come through every proxy
+        assert_eq!(client_address(&headers, peer, 3), "10.0.0.5");
+    }
+}
@@ -0,0 +1,48 @@
+use std::time::Instant;
+
+use axum::{
+    Router,
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use http::header;
+
+use crate::metrics;
+
+/// Router for the internal metrics listener
+pub fn router() -> Router {
+    Router::new().route("/metrics", get(metrics_handler))
+}
+
+/// Every metric in the Prometheus text format. Feeds that stopped updating can be found with
+/// `time() - feed_last_success_timestamp_seconds`.
+pub async fn metrics_handler() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render(),
+    )
+}
+
+/// Middleware that records request latency by route. Requests that don't match a route are grouped together so
+/// random paths don't each get their own series.
+pub async fn track(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| "unmatched".to_owned());
+    let method = request.method().clone();
+    let start = Instant::now();
+
+    let response = next.run(request).await;
+    metrics::histogram!(
+        metrics::HTTP_REQUEST_SECONDS,
+        "method" => method.as_str().to_owned(),
+        "route" => route,
+        "status" => response.status().as_str().to_owned()
+    )
+    .record(start.elapsed().as_secs_f64());
+    response
+}
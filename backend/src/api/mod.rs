@@ -1,4 +1,4 @@
-use crate::AppState;
+use crate::{AppState, models::source::Source};
 use axum::{
     extract::{FromRequestParts, Query},
     response::{IntoResponse, Response},
@@ -8,13 +8,19 @@ use http::{HeaderMap, StatusCode, request::Parts};
 use serde::{Deserialize, Deserializer};
 use std::sync::OnceLock;
 use tracing::error;
-use utoipa::IntoParams;
+use utoipa::{IntoParams, ToSchema};
 use utoipa_axum::{router::OpenApiRouter, routes};
 
-// pub mod websocket;
+pub mod gtfs_rt;
+pub mod history;
+pub mod keys;
+pub mod metrics;
 pub mod realtime;
 pub mod static_data;
+pub mod status;
+pub mod tiles;
 pub mod util;
+pub mod websocket;
 
 pub struct AppError(anyhow::Error);
 
@@ -39,13 +45,44 @@ where
 
 pub fn router(state: AppState) -> OpenApiRouter {
     OpenApiRouter::new()
-        .route("/health", axum::routing::get(|| async { "OK" }))
         .routes(routes!(static_data::routes_handler))
         .routes(routes!(static_data::stops_handler))
+        .routes(routes!(static_data::merged_routes_handler))
+        .routes(routes!(static_data::merged_stops_handler))
         .routes(routes!(realtime::trips_handler))
+        .routes(routes!(realtime::trip_handler))
         .routes(routes!(realtime::stop_times_handler))
         .routes(routes!(realtime::positions_handler))
         .routes(routes!(realtime::alerts_handler))
+        .routes(routes!(realtime::merged_trips_handler))
+        .routes(routes!(realtime::merged_stop_times_handler))
+        .routes(routes!(realtime::merged_positions_handler))
+        .routes(routes!(realtime::merged_alerts_handler))
+        .routes(routes!(realtime::departures_handler))
+        .routes(routes!(realtime::nearby_stops_handler))
+        .routes(routes!(realtime::ghosts_handler))
+        .routes(routes!(realtime::headways_handler))
+        .routes(routes!(realtime::plan_handler))
+        .routes(routes!(gtfs_rt::trip_updates_handler))
+        .routes(routes!(gtfs_rt::vehicle_positions_handler))
+        .routes(routes!(gtfs_rt::alerts_handler))
+        .routes(routes!(history::performance_handler))
+        .routes(routes!(history::ghost_rates_handler))
+        .routes(routes!(tiles::tile_handler))
+        .routes(routes!(tiles::tilejson_handler))
+        .routes(routes!(websocket::updates_handler))
+        .route("/ws", axum::routing::get(websocket::websocket_handler))
+        // Only the routes above need a key. Health checks, status and admin endpoints aren't rate limited.
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            keys::rate_limit,
+        ))
+        .route("/health", axum::routing::get(status::health_handler))
+        .routes(routes!(status::status_handler))
+        .routes(routes!(keys::keys_handler, keys::create_key_handler))
+        .routes(routes!(keys::revoke_key_handler))
+        .routes(routes!(keys::usage_handler))
+        .layer(axum::middleware::from_fn(metrics::track))
         .with_state(state)
 }
 
@@ -72,6 +109,68 @@ where
         .collect())
 }
 
+/// Parses a comma-separated list of source ids, where `all` means every source. Duplicates are dropped.
+pub fn parse_sources<'de, D>(deserializer: D) -> Result<Vec<Source>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let ids = parse_list(deserializer)?;
+    if ids.iter().any(|id| id == "all") {
+        return Ok(Source::all());
+    }
+
+    let mut sources = Vec::new();
+    for id in ids {
+        let source = Source::from_id(&id)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown source {id}")))?;
+        if !sources.contains(&source) {
+            sources.push(source);
+        }
+    }
+    Ok(sources)
+}
+
+/// Content type of GeoJSON responses
+pub const GEOJSON_CONTENT_TYPE: &str = "application/geo+json";
+
+#[derive(Deserialize, ToSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Json,
+    /// A FeatureCollection with each item's `data` flattened into its properties
+    Geojson,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FormatParams {
+    /// Response format. Defaults to JSON, or GeoJSON if the `Accept` header is `application/geo+json`.
+    pub format: Option<ResponseFormat>,
+}
+
+impl FormatParams {
+    /// Whether the client asked for GeoJSON, with the `format` parameter or the `Accept` header
+    pub fn is_geojson(&self, headers: &HeaderMap) -> bool {
+        match self.format {
+            Some(format) => format == ResponseFormat::Geojson,
+            None => headers
+                .get(http::header::ACCEPT)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|accept| accept.contains(GEOJSON_CONTENT_TYPE)),
+        }
+    }
+}
+
+/// Sources to merge into one response, for clients that use several modes at once
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SourcesParams {
+    /// Comma-separated list of sources, or `all` for every source. Defaults to every source.
+    #[serde(deserialize_with = "parse_sources", default = "Source::all")]
+    #[param(value_type = String, example = "mta_subway,njt_bus")]
+    pub sources: Vec<Source>,
+}
+
 // impl AppState {
 //     // wrapper for redis get that handles when the cache is reset
 //     // only use for getting static data
@@ -123,9 +222,6 @@ where
 // pub struct Parameters {
 //     /// Data source
 //     source: Source,
-//     // /// Return in GeoJSON format instead of JSON
-//     // #[serde(default)]
-//     // geojson: bool,
 //     // /// Filter by route type. If none provided, all routes are returned.
 //     // #[serde(default)]
 //     // route_type: Option<route::RouteType>,
@@ -140,6 +236,13 @@ pub struct CurrentTime {
     // pub finished: bool,
 }
 
+impl CurrentTime {
+    /// The time the client asked for, or `None` for the current time so stores can use their caches
+    pub fn at(&self) -> Option<DateTime<Utc>> {
+        self.user_specified.then_some(self.time)
+    }
+}
+
 #[derive(Deserialize, IntoParams)]
 #[into_params(parameter_in = Query)]
 pub struct TimeParams {
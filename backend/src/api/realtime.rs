@@ -1,15 +1,41 @@
-use super::{AppError, AppState, CurrentTime, TimeParams, parse_list};
-use crate::models::position::VehiclePosition;
-use crate::models::source::Source;
+use super::{
+    AppError, AppState, CurrentTime, FormatParams, GEOJSON_CONTENT_TYPE, SourcesParams, TimeParams,
+    parse_list,
+};
+use crate::engines::ghost::GHOST_THRESHOLD;
+use crate::engines::headway;
+use crate::engines::position;
+use crate::integrations::geojson_export;
+use crate::models::departure::{DepartureBoard, NearbyStop, parent_station};
+use crate::models::headway::RouteHeadways;
+use crate::models::plan::Itinerary;
+use crate::models::position::ApiVehiclePosition;
+use crate::models::source::{Source, SourceId};
 use crate::models::trip::StopTime;
-use crate::models::trip::Trip;
+use crate::models::trip::{Trip, TripDetail};
 use crate::stores::alert::ApiAlert;
 use axum::Json;
 use axum::extract::{Path, Query, State};
+use axum::response::{IntoResponse, Response};
+use futures::future::try_join_all;
+use geo::{Rect, coord};
+use http::{HeaderMap, StatusCode, header};
 use serde::Deserialize;
+use std::collections::HashSet;
 use utoipa::IntoParams;
+use uuid::Uuid;
 
 const REQUIRE_ROUTE_FILTER_SOURCES: [Source; 2] = [Source::MtaBus, Source::NjtBus];
+const DEFAULT_DEPARTURES_LIMIT: i64 = 20;
+const MAX_DEPARTURES_LIMIT: i64 = 100;
+const DEFAULT_NEARBY_RADIUS: f64 = 500.0;
+const MAX_NEARBY_RADIUS: f64 = 2000.0;
+const DEFAULT_NEARBY_LIMIT: i64 = 20;
+const MAX_NEARBY_LIMIT: i64 = 50;
+/// Departures included for each nearby stop
+const NEARBY_DEPARTURES: i64 = 3;
+/// Next stops included for each vehicle in a bounding box
+const NEXT_DEPARTURES: i64 = 3;
 
 #[utoipa::path(
     get,
@@ -29,13 +55,135 @@ pub async fn trips_handler(
     Path(source): Path<Source>,
     current_time: CurrentTime,
 ) -> Result<Json<Vec<Trip>>, AppError> {
-    let at = if current_time.user_specified {
-        Some(current_time.time)
+    let at = current_time.at();
+    let trips = state.trip_store.get_all(source, at).await?;
+    Ok(Json(trips))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct TripParameters {
+    /// Comma-separated list of extra data to include. `shape` includes the route geometry between the trip's first and last stops.
+    #[serde(deserialize_with = "parse_list", default)]
+    #[param(example = "shape")]
+    include: Vec<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/trip/{id}",
+    tag = "REALTIME",
+    description = "A single trip with its stop times, the current position of its vehicle and the path the vehicle has taken so far. Trips are returned even after they finish, until they're pruned.",
+    params(
+        ("id" = Uuid, Path, description = "Trip ID"),
+        TripParameters
+    ),
+    responses(
+        (status = 200, description = "The trip", body = TripDetail),
+        (status = 404, description = "Trip not found")
+    )
+)]
+pub async fn trip_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    params: Query<TripParameters>,
+) -> Result<Response, AppError> {
+    let Some(trip) = state.trip_store.get(id).await? else {
+        return Ok((StatusCode::NOT_FOUND, "Trip not found").into_response());
+    };
+
+    let (stop_times, position, path) = tokio::try_join!(
+        state.trip_store.get_stop_times(id),
+        state.position_store.get_by_trip(id),
+        state.trip_store.get_path(id),
+    )?;
+    let shape = if params.include.iter().any(|i| i == "shape") {
+        state.trip_store.get_shape(id).await?
     } else {
         None
     };
-    let trips = state.trip_store.get_all(source, at).await?;
-    Ok(Json(trips))
+
+    Ok(Json(TripDetail {
+        trip,
+        stop_times,
+        position,
+        path,
+        shape,
+    })
+    .into_response())
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct GhostsParameters {
+    /// Minimum confidence from 0 to 1. Defaults to 0.5.
+    #[param(example = 0.8)]
+    min_confidence: Option<f32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/ghosts/{source}",
+    tag = "REALTIME",
+    description = "Running trips that are suspected ghosts: their predictions keep updating but their vehicle position is missing, frozen, far from the route or several stops away from where the trip is predicted to be. `ghost_confidence` is how likely it is that the trip isn't actually running.",
+    params(
+        ("source" = Source, Path, description = "Data source"),
+        GhostsParameters,
+        TimeParams
+    ),
+    responses(
+        (status = 200, description = "Suspected ghost trips, most likely first", body = [Trip]),
+        (status = 400, description = "Invalid confidence")
+    )
+)]
+pub async fn ghosts_handler(
+    State(state): State<AppState>,
+    Path(source): Path<Source>,
+    params: Query<GhostsParameters>,
+    current_time: CurrentTime,
+) -> Result<Response, AppError> {
+    let min_confidence = params.min_confidence.unwrap_or(GHOST_THRESHOLD);
+    if !(0.0..=1.0).contains(&min_confidence) {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            "min_confidence must be between 0 and 1",
+        )
+            .into_response());
+    }
+
+    let trips = state
+        .trip_store
+        .get_ghosts(source, min_confidence, current_time.time)
+        .await?;
+    Ok(Json(trips).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/routes/{source}/{route_id}/headways",
+    tag = "REALTIME",
+    description = "Predicted headways between the route's trips at each of its stops over the next 2 hours. Trips arriving within 2 minutes of each other are bunched, and headways at least twice the scheduled headway are gaps. Scheduled headways are only available for sources with a static schedule for their realtime trips. MTA bus schedules aren't loaded yet, so MTA bus routes have no scheduled headways: `schedule_unavailable_reason` is set and gaps are compared to the stop's average predicted headway instead.",
+    params(
+        ("source" = Source, Path, description = "Data source"),
+        ("route_id" = String, Path, description = "Route ID", example = "M15"),
+        TimeParams
+    ),
+    responses(
+        (status = 200, description = "Headways at each stop of the route", body = RouteHeadways)
+    )
+)]
+pub async fn headways_handler(
+    State(state): State<AppState>,
+    Path((source, route_id)): Path<(Source, String)>,
+    current_time: CurrentTime,
+) -> Result<Json<RouteHeadways>, AppError> {
+    let headways = headway::route_headways(
+        source,
+        &route_id,
+        current_time.time,
+        &state.stop_time_store,
+        &state.static_cache_store,
+    )
+    .await?;
+    Ok(Json(headways))
 }
 
 #[derive(Deserialize, IntoParams)]
@@ -70,11 +218,7 @@ pub async fn stop_times_handler(
         return Ok(Json(vec![]));
     }
 
-    let at = if current_time.user_specified {
-        Some(current_time.time)
-    } else {
-        None
-    };
+    let at = current_time.at();
     let route_ids = if params.route_ids.is_empty() {
         None
     } else {
@@ -92,22 +236,40 @@ pub async fn stop_times_handler(
         ("source" = Source, Path, description = "Data source"),
         TimeParams
     ),
+    description = "Vehicles on a trip are moved along their route's line: subway trains are placed between stops from their predicted arrivals, and vehicles with coordinates are snapped to the route when they're close to it. These positions have `estimated` set.",
     responses(
-        (status = 200, description = "Vehicle positions for the specified source", body = [VehiclePosition])
+        (status = 200, description = "Vehicle positions for the specified source", content(
+            ([ApiVehiclePosition] = "application/json"),
+            (Object = "application/geo+json")
+        ))
     )
 )]
 pub async fn positions_handler(
     State(state): State<AppState>,
     Path(source): Path<Source>,
+    Query(format): Query<FormatParams>,
+    headers: HeaderMap,
     current_time: CurrentTime,
-) -> Result<Json<Vec<VehiclePosition>>, AppError> {
-    let at = if current_time.user_specified {
-        Some(current_time.time)
-    } else {
-        None
-    };
-    let positions = state.position_store.get_all(source, at).await?;
-    Ok(Json(positions))
+) -> Result<Response, AppError> {
+    let at = current_time.at();
+    let positions = position::estimated_positions(source, at, &state.position_store).await?;
+    Ok(positions_response(positions, format.is_geojson(&headers))?)
+}
+
+/// Positions as JSON, or as a GeoJSON FeatureCollection of points
+fn positions_response(
+    positions: Vec<ApiVehiclePosition>,
+    geojson: bool,
+) -> anyhow::Result<Response> {
+    if geojson {
+        let collection = geojson_export::positions_collection(&positions)?;
+        return Ok((
+            [(header::CONTENT_TYPE, GEOJSON_CONTENT_TYPE)],
+            collection.to_string(),
+        )
+            .into_response());
+    }
+    Ok(Json(positions).into_response())
 }
 
 #[utoipa::path(
@@ -128,11 +290,389 @@ pub async fn alerts_handler(
     Path(source): Path<Source>,
     current_time: CurrentTime,
 ) -> Result<Json<Vec<ApiAlert>>, AppError> {
-    let at = if current_time.user_specified {
-        Some(current_time.time)
-    } else {
-        None
-    };
+    let at = current_time.at();
     let alerts = state.alert_store.get_all(source, at).await?;
     Ok(Json(alerts))
 }
+
+#[utoipa::path(
+    get,
+    path = "/trips",
+    tag = "REALTIME",
+    params(SourcesParams, TimeParams),
+    responses(
+        (status = 200, description = "Trips for every requested source", body = [Trip])
+    )
+)]
+pub async fn merged_trips_handler(
+    State(state): State<AppState>,
+    Query(params): Query<SourcesParams>,
+    current_time: CurrentTime,
+) -> Result<Json<Vec<Trip>>, AppError> {
+    let at = current_time.at();
+    let trips = try_join_all(
+        params
+            .sources
+            .iter()
+            .map(|&source| state.trip_store.get_all(source, at)),
+    )
+    .await?;
+    Ok(Json(trips.into_iter().flatten().collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/stop_times",
+    tag = "REALTIME",
+    params(SourcesParams, StopTimesParameters, TimeParams),
+    description = "Sources with too many stop times to return at once (MTA and NJT buses) are left out unless `route_ids` is set.",
+    responses(
+        (status = 200, description = "Stop times for every requested source", body = [StopTime])
+    )
+)]
+pub async fn merged_stop_times_handler(
+    State(state): State<AppState>,
+    Query(sources): Query<SourcesParams>,
+    params: Query<StopTimesParameters>,
+    current_time: CurrentTime,
+) -> Result<Json<Vec<StopTime>>, AppError> {
+    let at = current_time.at();
+    let route_ids = if params.route_ids.is_empty() {
+        None
+    } else {
+        Some(params.route_ids.as_slice())
+    };
+    let stop_times = try_join_all(
+        sources
+            .sources
+            .iter()
+            .filter(|&&source| {
+                route_ids.is_some() || !REQUIRE_ROUTE_FILTER_SOURCES.contains(&source)
+            })
+            .map(|&source| state.stop_time_store.get_all(source, at, route_ids)),
+    )
+    .await?;
+    Ok(Json(stop_times.into_iter().flatten().collect()))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct PositionsParameters {
+    /// Only return vehicles inside this bounding box, as `min_lon,min_lat,max_lon,max_lat`. They're ordered by
+    /// distance from its center and include their trip's next stops.
+    #[param(example = "-74.02,40.70,-73.93,40.80")]
+    bbox: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/positions",
+    tag = "REALTIME",
+    params(SourcesParams, PositionsParameters, FormatParams, TimeParams),
+    description = "Positions are estimated the same way as for a single source. The bounding box is applied to the estimated positions, so subway trains, which don't have coordinates in the feed, are included.",
+    responses(
+        (status = 200, description = "Vehicle positions for every requested source", content(
+            ([ApiVehiclePosition] = "application/json"),
+            (Object = "application/geo+json")
+        )),
+        (status = 400, description = "Invalid bounding box")
+    )
+)]
+pub async fn merged_positions_handler(
+    State(state): State<AppState>,
+    Query(sources): Query<SourcesParams>,
+    Query(params): Query<PositionsParameters>,
+    Query(format): Query<FormatParams>,
+    headers: HeaderMap,
+    current_time: CurrentTime,
+) -> Result<Response, AppError> {
+    let bbox = match params.bbox.as_deref() {
+        Some(bbox) => match parse_bbox(bbox) {
+            Some(bbox) => Some(bbox),
+            None => return Ok((StatusCode::BAD_REQUEST, "Invalid bounding box").into_response()),
+        },
+        None => None,
+    };
+
+    let at = current_time.at();
+    let Some(bbox) = bbox else {
+        let positions = try_join_all(
+            sources
+                .sources
+                .iter()
+                .map(|&source| position::estimated_positions(source, at, &state.position_store)),
+        )
+        .await?;
+        let positions = positions.into_iter().flatten().collect();
+        return Ok(positions_response(positions, format.is_geojson(&headers))?);
+    };
+
+    let mut positions =
+        position::in_bbox(&sources.sources, bbox, at, &state.position_store).await?;
+    let trip_ids: Vec<_> = positions
+        .iter()
+        .filter_map(|p| p.position.trip_id)
+        .collect();
+    let mut next_departures = state
+        .trip_store
+        .get_next_stop_times(&trip_ids, current_time.time, NEXT_DEPARTURES)
+        .await?;
+    for p in &mut positions {
+        p.next_departures = Some(
+            p.position
+                .trip_id
+                .and_then(|id| next_departures.remove(&id))
+                .unwrap_or_default(),
+        );
+    }
+    Ok(positions_response(positions, format.is_geojson(&headers))?)
+}
+
+/// Parses `min_lon,min_lat,max_lon,max_lat`
+fn parse_bbox(bbox: &str) -> Option<Rect> {
+    let coords: Vec<f64> = bbox
+        .split(',')
+        .map(|c| c.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    let [min_lon, min_lat, max_lon, max_lat] = coords[..] else {
+        return None;
+    };
+    if coords.iter().any(|c| !c.is_finite()) {
+        return None;
+    }
+    Some(Rect::new(
+        coord! { x: min_lon, y: min_lat },
+        coord! { x: max_lon, y: max_lat },
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/alerts",
+    tag = "REALTIME",
+    params(SourcesParams, TimeParams),
+    responses(
+        (status = 200, description = "Alerts for every requested source", body = [ApiAlert])
+    )
+)]
+pub async fn merged_alerts_handler(
+    State(state): State<AppState>,
+    Query(params): Query<SourcesParams>,
+    current_time: CurrentTime,
+) -> Result<Json<Vec<ApiAlert>>, AppError> {
+    let at = current_time.at();
+    let alerts = try_join_all(
+        params
+            .sources
+            .iter()
+            .map(|&source| state.alert_store.get_all(source, at)),
+    )
+    .await?;
+    Ok(Json(alerts.into_iter().flatten().collect()))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct DeparturesParameters {
+    /// Maximum number of departures to return. Defaults to 20, up to 100.
+    limit: Option<i64>,
+    /// Only return trips in this direction. For the MTA subway, 1 is northbound and 3 is southbound.
+    direction: Option<i16>,
+    /// Comma-separated list of route IDs to filter by. Be sure to URL encode this.
+    #[serde(deserialize_with = "parse_list", default)]
+    route_ids: Vec<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/stops/{source}/{stop_id}/departures",
+    tag = "REALTIME",
+    description = "Upcoming departures from a stop and every stop it has an in-station transfer to, so a station complex like Times Sq-42 St returns departures from all of its platforms. MTA subway platform IDs (e.g. `127N`) are resolved to their station and direction.",
+    params(
+        ("source" = Source, Path, description = "Data source"),
+        ("stop_id" = String, Path, description = "Stop ID", example = "127"),
+        DeparturesParameters,
+        TimeParams
+    ),
+    responses(
+        (status = 200, description = "Departure board for the stop", body = DepartureBoard),
+        (status = 404, description = "Stop not found")
+    )
+)]
+pub async fn departures_handler(
+    State(state): State<AppState>,
+    Path((source, stop_id)): Path<(Source, String)>,
+    params: Query<DeparturesParameters>,
+    current_time: CurrentTime,
+) -> Result<Response, AppError> {
+    let (stop_id, platform_direction) = parent_station(source, &stop_id);
+    let stops = state.stop_store.get_board_stops(source, &stop_id).await?;
+    if !stops
+        .first()
+        .is_some_and(|s| s.source == source && s.id == stop_id)
+    {
+        return Ok((StatusCode::NOT_FOUND, "Stop not found").into_response());
+    }
+
+    let route_ids: Vec<String> = params
+        .route_ids
+        .iter()
+        .filter(|r| !r.is_empty())
+        .map(|r| r.to_uppercase())
+        .collect();
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_DEPARTURES_LIMIT)
+        .clamp(1, MAX_DEPARTURES_LIMIT);
+    // A platform's direction only applies to the subway, not to the other sources' stops on the board
+    let (direction, direction_source) = match (params.direction, platform_direction) {
+        (Some(direction), _) => (Some(direction), None),
+        (None, Some(direction)) => (Some(direction), Some(Source::MtaSubway)),
+        (None, None) => (None, None),
+    };
+    let departures = state
+        .stop_time_store
+        .get_departures(
+            &stops,
+            current_time.time,
+            direction,
+            direction_source,
+            &route_ids,
+            limit,
+        )
+        .await?;
+
+    let at = current_time.at();
+    let sources: HashSet<Source> = stops.iter().map(|s| s.source).collect();
+    let mut alerts = Vec::new();
+    for alert_source in sources {
+        let source_stops: Vec<_> = stops.iter().filter(|s| s.source == alert_source).collect();
+        alerts.extend(
+            state
+                .alert_store
+                .get_all(alert_source, at)
+                .await?
+                .into_iter()
+                .filter(|a| {
+                    a.entities
+                        .iter()
+                        .any(|e| source_stops.iter().any(|s| s.is_affected(e)))
+                }),
+        );
+    }
+
+    Ok(Json(DepartureBoard {
+        stops,
+        departures,
+        alerts,
+    })
+    .into_response())
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct NearbyParameters {
+    #[param(example = 40.7557)]
+    lat: f64,
+    #[param(example = -73.9870)]
+    lon: f64,
+    /// Search radius in meters. Defaults to 500, up to 2000.
+    radius: Option<f64>,
+    /// Maximum number of stops to return. Defaults to 20, up to 50.
+    limit: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/stops/nearby",
+    tag = "REALTIME",
+    description = "Stops near a point, closest first, each with its next few departures. Useful for finding stops near a rider without downloading every stop.",
+    params(
+        NearbyParameters,
+        SourcesParams,
+        TimeParams
+    ),
+    responses(
+        (status = 200, description = "Stops within the radius", body = [NearbyStop]),
+        (status = 400, description = "Invalid coordinates or radius")
+    )
+)]
+pub async fn nearby_stops_handler(
+    State(state): State<AppState>,
+    Query(params): Query<NearbyParameters>,
+    Query(sources): Query<SourcesParams>,
+    current_time: CurrentTime,
+) -> Result<Response, AppError> {
+    if !(-90.0..=90.0).contains(&params.lat) || !(-180.0..=180.0).contains(&params.lon) {
+        return Ok((StatusCode::BAD_REQUEST, "Invalid coordinates").into_response());
+    }
+    let radius = params.radius.unwrap_or(DEFAULT_NEARBY_RADIUS);
+    if !radius.is_finite() || radius <= 0.0 {
+        return Ok((StatusCode::BAD_REQUEST, "Radius must be positive").into_response());
+    }
+    let radius = radius.min(MAX_NEARBY_RADIUS);
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_NEARBY_LIMIT)
+        .clamp(1, MAX_NEARBY_LIMIT);
+
+    let mut stops = state
+        .stop_store
+        .get_nearby(&sources.sources, params.lat, params.lon, radius, limit)
+        .await?;
+    let board_stops: Vec<_> = stops.iter().map(|s| &s.stop).collect();
+    let departures = state
+        .stop_time_store
+        .get_departures_by_stop(&board_stops, current_time.time, NEARBY_DEPARTURES)
+        .await?;
+    for departure in departures {
+        if let Some(stop) = stops
+            .iter_mut()
+            .find(|s| s.stop.source == departure.source && s.stop.id == departure.stop_id)
+        {
+            stop.departures.push(departure);
+        }
+    }
+
+    Ok(Json(stops).into_response())
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct PlanParameters {
+    /// Origin stop as `{source}:{stop_id}`
+    #[param(value_type = String, example = "mta_subway:127")]
+    from: SourceId,
+    /// Destination stop as `{source}:{stop_id}`
+    #[param(value_type = String, example = "njt_bus:10001")]
+    to: SourceId,
+}
+
+#[utoipa::path(
+    get,
+    path = "/plan",
+    tag = "REALTIME",
+    description = "Plans trips leaving now between two stops using realtime stop times from every source. Transfers between sources use the stop transfers, including ones calculated from proximity. Returns the fastest itinerary for each number of transfers, so later itineraries arrive earlier but change vehicles more often. Only the latest stop times are kept for planning, so `at` isn't supported.",
+    params(PlanParameters),
+    responses(
+        (status = 200, description = "Itineraries between the stops, empty if there are none", body = [Itinerary]),
+        (status = 400, description = "`at` was given")
+    )
+)]
+pub async fn plan_handler(
+    State(state): State<AppState>,
+    params: Query<PlanParameters>,
+    current_time: CurrentTime,
+) -> Result<Response, AppError> {
+    if current_time.user_specified {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            "Trips can only be planned from the current time",
+        )
+            .into_response());
+    }
+
+    // Stop IDs are saved uppercase
+    let itineraries = state.planner.router().plan(
+        (params.from.source, &params.from.id.to_uppercase()),
+        (params.to.source, &params.to.id.to_uppercase()),
+        current_time.time,
+    );
+    Ok(Json(itineraries).into_response())
+}
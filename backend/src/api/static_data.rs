@@ -1,17 +1,20 @@
 use crate::AppState;
-use crate::api::AppError;
+use crate::api::{AppError, FormatParams, GEOJSON_CONTENT_TYPE, SourcesParams};
+use crate::integrations::geojson_export;
 use crate::models::route::Route;
 use crate::models::source::Source;
 use crate::models::stop::Stop;
-use axum::extract::{Path, State};
+use crate::stores::merge_etags;
+use axum::extract::{Path, Query, State};
 use axum::response::{IntoResponse, Response};
+use futures::future::try_join_all;
 use http::{HeaderMap, StatusCode};
 
 // TODO: refactor etag logic so if there is no etag, it doesnt just return a blank string as the etag
-fn cache_headers(etag_hash: &str) -> HeaderMap {
+fn cache_headers(etag_hash: &str, content_type: &str) -> HeaderMap {
     use http::header;
     let mut headers = HeaderMap::new();
-    headers.insert("content-type", "application/json".parse().unwrap());
+    headers.insert("content-type", content_type.parse().unwrap());
     // ETag must be a quoted string per RFC 7232
     headers.insert(header::ETAG, format!("\"{}\"", etag_hash).parse().unwrap());
     headers.insert(
@@ -20,11 +23,13 @@ fn cache_headers(etag_hash: &str) -> HeaderMap {
             .parse()
             .unwrap(),
     );
+    // The same URL returns GeoJSON when it's requested with the Accept header
+    headers.insert(header::VARY, "accept".parse().unwrap());
     headers
 }
 
 /// Returns `true` if the client's `If-None-Match` header matches the stored etag.
-fn etag_matches(request_headers: &HeaderMap, etag_hash: &str) -> bool {
+pub(super) fn etag_matches(request_headers: &HeaderMap, etag_hash: &str) -> bool {
     if let Some(inm) = request_headers.get(http::header::IF_NONE_MATCH)
         && let Ok(inm_str) = inm.to_str() {
             let quoted = format!("\"{}\"", etag_hash);
@@ -33,39 +38,120 @@ fn etag_matches(request_headers: &HeaderMap, etag_hash: &str) -> bool {
     false
 }
 
+async fn route_etag(state: &AppState, source: Source) -> anyhow::Result<String> {
+    // If Redis was flushed, lazily repopulate cache so we can always return an ETag.
+    // TODO: figure out why this sometimes happens
+    match state.route_store.get_etag(source).await? {
+        Some(etag) => Ok(etag),
+        None => state.route_store.populate_cache(source).await,
+    }
+}
+
+async fn stop_etag(state: &AppState, source: Source) -> anyhow::Result<String> {
+    // If Redis was flushed, lazily repopulate cache so we can always return an ETag.
+    match state.stop_store.get_etag(source).await? {
+        Some(etag) => Ok(etag),
+        None => state.stop_store.populate_cache(source).await,
+    }
+}
+
+/// GeoJSON responses need their own ETag since they're a different representation of the same data
+fn format_etag(etag: String, geojson: bool) -> String {
+    if geojson {
+        format!("{}-geojson", etag)
+    } else {
+        etag
+    }
+}
+
+/// Routes of every source, as JSON or as GeoJSON with their geometry
+async fn routes_response(
+    state: &AppState,
+    sources: &[Source],
+    etag: &str,
+    geojson: bool,
+) -> anyhow::Result<Response> {
+    let routes = try_join_all(sources.iter().map(|&source| async move {
+        // Cached routes don't have their geometry
+        if geojson {
+            state.route_store.query_all(source).await
+        } else {
+            state.route_store.get_all(source).await
+        }
+    }))
+    .await?;
+    let routes: Vec<Route> = routes.into_iter().flatten().collect();
+
+    if geojson {
+        let collection = geojson_export::routes_collection(&routes)?;
+        return Ok((
+            cache_headers(etag, GEOJSON_CONTENT_TYPE),
+            collection.to_string(),
+        )
+            .into_response());
+    }
+    let json = serde_json::to_string(&routes)?;
+    Ok((cache_headers(etag, "application/json"), json).into_response())
+}
+
+/// Stops of every source, as JSON or GeoJSON
+async fn stops_response(
+    state: &AppState,
+    sources: &[Source],
+    etag: &str,
+    geojson: bool,
+) -> anyhow::Result<Response> {
+    let stops = try_join_all(
+        sources
+            .iter()
+            .map(|&source| state.stop_store.get_all(source)),
+    )
+    .await?;
+    let stops: Vec<Stop> = stops.into_iter().flatten().collect();
+
+    if geojson {
+        let collection = geojson_export::stops_collection(&stops)?;
+        return Ok((
+            cache_headers(etag, GEOJSON_CONTENT_TYPE),
+            collection.to_string(),
+        )
+            .into_response());
+    }
+    let json = serde_json::to_string(&stops)?;
+    Ok((cache_headers(etag, "application/json"), json).into_response())
+}
+
 #[utoipa::path(
     get,
     path = "/routes/{source}",
     tag = "STATIC",
     params(
-        ("source" = Source, Path, description = "Data source")
+        ("source" = Source, Path, description = "Data source"),
+        FormatParams
     ),
     responses(
-        (status = 200, description = "Subway and bus routes. WARNING: W train geometry is missing.", body = [Route]),
+        (status = 200, description = "Subway and bus routes. WARNING: W train geometry is missing. As GeoJSON, routes include their geometry as MultiLineStrings.", content(
+            ([Route] = "application/json"),
+            (Object = "application/geo+json")
+        )),
         (status = 304, description = "If no parameters are provided and the etag matches the request")
     )
 )]
 pub async fn routes_handler(
     State(state): State<AppState>,
     Path(source): Path<Source>,
+    Query(format): Query<FormatParams>,
     headers: HeaderMap,
 ) -> Result<Response, AppError> {
-    // If Redis was flushed, lazily repopulate cache so we can always return an ETag.
-    // TODO: figure out why this sometimes happens
-    let etag = match state.route_store.get_etag(source).await? {
-        Some(etag) => etag,
-        None => state.route_store.populate_cache(source).await?,
-    };
+    let geojson = format.is_geojson(&headers);
+    let etag = format_etag(route_etag(&state, source).await?, geojson);
 
     // Check ETag before fetching full data
     if etag_matches(&headers, &etag) {
         return Ok(StatusCode::NOT_MODIFIED.into_response());
     }
 
-    let routes = state.route_store.get_all(source).await?;
-
-    let json = serde_json::to_string(&routes).map_err(anyhow::Error::from)?;
-    Ok((cache_headers(&etag), json).into_response())
+    Ok(routes_response(&state, &[source], &etag, geojson).await?)
 }
 
 #[utoipa::path(
@@ -73,31 +159,100 @@ pub async fn routes_handler(
     path = "/stops/{source}",
     tag = "STATIC",
     params(
-        ("source" = Source, Path, description = "Data source")
+        ("source" = Source, Path, description = "Data source"),
+        FormatParams
     ),
     responses(
-        (status = 200, description = "Source stops", body = [Stop]),
+        (status = 200, description = "Source stops", content(
+            ([Stop] = "application/json"),
+            (Object = "application/geo+json")
+        )),
         (status = 304, description = "If no parameters are provided and the etag matches the request")
     )
 )]
 pub async fn stops_handler(
     State(state): State<AppState>,
     Path(source): Path<Source>,
+    Query(format): Query<FormatParams>,
     headers: HeaderMap,
 ) -> Result<Response, AppError> {
-    // If Redis was flushed, lazily repopulate cache so we can always return an ETag.
-    let etag = match state.stop_store.get_etag(source).await? {
-        Some(etag) => etag,
-        None => state.stop_store.populate_cache(source).await?,
-    };
+    let geojson = format.is_geojson(&headers);
+    let etag = format_etag(stop_etag(&state, source).await?, geojson);
 
     // Check ETag before fetching full data
     if etag_matches(&headers, &etag) {
         return Ok(StatusCode::NOT_MODIFIED.into_response());
     }
 
-    let stops = state.stop_store.get_all(source).await?;
+    Ok(stops_response(&state, &[source], &etag, geojson).await?)
+}
+
+#[utoipa::path(
+    get,
+    path = "/routes",
+    tag = "STATIC",
+    params(SourcesParams, FormatParams),
+    description = "Routes for several sources at once. The ETag is computed from each source's ETag, so it changes whenever any of the sources' routes do.",
+    responses(
+        (status = 200, description = "Routes for every requested source", content(
+            ([Route] = "application/json"),
+            (Object = "application/geo+json")
+        )),
+        (status = 304, description = "If the etag matches the request")
+    )
+)]
+pub async fn merged_routes_handler(
+    State(state): State<AppState>,
+    Query(params): Query<SourcesParams>,
+    Query(format): Query<FormatParams>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let etags = try_join_all(params.sources.iter().map(|&source| {
+        let state = &state;
+        async move { anyhow::Ok((source, route_etag(state, source).await?)) }
+    }))
+    .await?;
+    let geojson = format.is_geojson(&headers);
+    let etag = format_etag(merge_etags(&etags), geojson);
+
+    if etag_matches(&headers, &etag) {
+        return Ok(StatusCode::NOT_MODIFIED.into_response());
+    }
+
+    Ok(routes_response(&state, &params.sources, &etag, geojson).await?)
+}
+
+#[utoipa::path(
+    get,
+    path = "/stops",
+    tag = "STATIC",
+    params(SourcesParams, FormatParams),
+    description = "Stops for several sources at once. The ETag is computed from each source's ETag, so it changes whenever any of the sources' stops do.",
+    responses(
+        (status = 200, description = "Stops for every requested source", content(
+            ([Stop] = "application/json"),
+            (Object = "application/geo+json")
+        )),
+        (status = 304, description = "If the etag matches the request")
+    )
+)]
+pub async fn merged_stops_handler(
+    State(state): State<AppState>,
+    Query(params): Query<SourcesParams>,
+    Query(format): Query<FormatParams>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let etags = try_join_all(params.sources.iter().map(|&source| {
+        let state = &state;
+        async move { anyhow::Ok((source, stop_etag(state, source).await?)) }
+    }))
+    .await?;
+    let geojson = format.is_geojson(&headers);
+    let etag = format_etag(merge_etags(&etags), geojson);
+
+    if etag_matches(&headers, &etag) {
+        return Ok(StatusCode::NOT_MODIFIED.into_response());
+    }
 
-    let json = serde_json::to_string(&stops).map_err(anyhow::Error::from)?;
-    Ok((cache_headers(&etag), json).into_response())
+    Ok(stops_response(&state, &params.sources, &etag, geojson).await?)
 }
@@ -0,0 +1,38 @@
+use axum::{
+    Json,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use http::StatusCode;
+
+use crate::{engines::status, models::status::StatusReport};
+
+#[utoipa::path(
+    get,
+    path = "/status",
+    tag = "STATUS",
+    description = "How fresh each source's realtime feeds and static data are. A source is degraded when some of its data is stale or its last run failed, and unhealthy when none of its realtime feeds have fresh data or its pipeline is stuck.",
+    responses(
+        (status = 200, description = "Status of every source", body = StatusReport)
+    )
+)]
+pub async fn status_handler() -> Json<StatusReport> {
+    Json(status::report(Utc::now()))
+}
+
+/// Readiness check. Fails while one of this replica's pipelines is stuck or panicked, or a required source is
+/// unhealthy, so a replica that stopped updating is taken out of rotation. Stale feeds are left to `/v1/status`, since
+/// restarting or replacing the replica won't make the agency update them.
+pub async fn health_handler() -> Response {
+    let unready = status::unready(Utc::now());
+    if unready.is_empty() {
+        return "OK".into_response();
+    }
+
+    let unready: Vec<_> = unready.iter().map(|s| s.as_str()).collect();
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        format!("Unhealthy: {}", unready.join(", ")),
+    )
+        .into_response()
+}
@@ -0,0 +1,142 @@
+use super::static_data::etag_matches;
+use super::{AppError, AppState};
+use crate::models::tile::{MAX_ZOOM, TileCoord, TileJson, TileLayer, VectorLayer};
+use crate::{api_prefix, prefixed_path, trusted_proxy_hops};
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::response::{IntoResponse, Response};
+use http::{HeaderMap, StatusCode, header};
+
+const TILE_CONTENT_TYPE: &str = "application/vnd.mapbox-vector-tile";
+
+#[utoipa::path(
+    get,
+    path = "/tiles/{layer}/{z}/{x}/{y}",
+    tag = "TILES",
+    description = "Mapbox Vector Tile for a layer, with features from every source. Each feature has the properties listed in the layer's TileJSON, plus the fields of its source's `data`. Tiles below the layer's minimum zoom are empty.",
+    params(
+        ("layer" = TileLayer, Path, description = "Tile layer"),
+        ("z" = u32, Path, description = "Zoom level"),
+        ("x" = u32, Path, description = "Tile column"),
+        ("y" = String, Path, description = "Tile row followed by `.pbf`", example = "12345.pbf")
+    ),
+    responses(
+        (status = 200, description = "Vector tile", content_type = "application/vnd.mapbox-vector-tile"),
+        (status = 204, description = "The layer has no features at this zoom"),
+        (status = 304, description = "If the etag matches the request"),
+        (status = 404, description = "Tile not found")
+    )
+)]
+pub async fn tile_handler(
+    State(state): State<AppState>,
+    Path((layer, z, x, y)): Path<(TileLayer, u32, u32, String)>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let Some(coord) = y
+        .strip_suffix(".pbf")
+        .and_then(|y| y.parse().ok())
+        .and_then(|y| TileCoord::new(z, x, y))
+    else {
+        return Ok((StatusCode::NOT_FOUND, "Tile not found").into_response());
+    };
+    if coord.z < layer.min_zoom() {
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    }
+
+    let tile = match state.tile_store.get_cached(layer, coord).await {
+        Some(tile) => tile,
+        None => match layer {
+            TileLayer::Routes => state.tile_store.render_routes(coord).await?,
+            TileLayer::Stops => state.tile_store.render_stops(coord).await?,
+            TileLayer::Positions => state.tile_store.render_positions(coord).await?,
+        },
+    };
+
+    if etag_matches(&headers, &tile.etag) {
+        return Ok(StatusCode::NOT_MODIFIED.into_response());
+    }
+
+    let cache_control = match layer {
+        TileLayer::Positions => "public, max-age=5",
+        TileLayer::Routes | TileLayer::Stops => "public, max-age=3600",
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, TILE_CONTENT_TYPE.to_owned()),
+            (header::ETAG, format!("\"{}\"", tile.etag)),
+            (header::CACHE_CONTROL, cache_control.to_owned()),
+        ],
+        tile.data,
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/tiles/{layer}",
+    tag = "TILES",
+    description = "TileJSON descriptor for a layer, which can be used as a vector source in MapLibre. Tile URLs are built from the request's `Host` header. `X-Forwarded-Proto` is only used behind trusted proxies (`TRUSTED_PROXY_HOPS`).",
+    params(
+        ("layer" = TileLayer, Path, description = "Tile layer")
+    ),
+    responses(
+        (status = 200, description = "TileJSON 3.0.0 descriptor", body = TileJson)
+    )
+)]
+pub async fn tilejson_handler(Path(layer): Path<TileLayer>, headers: HeaderMap) -> Json<TileJson> {
+    let header_value = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let proto = forwarded_proto(&headers, trusted_proxy_hops()).unwrap_or("http");
+    let host = header_value(header::HOST.as_str()).unwrap_or("localhost");
+    let path = prefixed_path(
+        &prefixed_path(api_prefix(), "v1"),
+        &format!("tiles/{}/{{z}}/{{x}}/{{y}}.pbf", layer.as_str()),
+    );
+
+    Json(TileJson {
+        tilejson: "3.0.0",
+        name: layer.as_str(),
+        tiles: vec![format!("{proto}://{host}{path}")],
+        minzoom: layer.min_zoom(),
+        maxzoom: MAX_ZOOM,
+        vector_layers: vec![VectorLayer {
+            id: layer.as_str(),
+            minzoom: layer.min_zoom(),
+            maxzoom: MAX_ZOOM,
+            fields: layer.fields(),
+        }],
+    })
+}
+
+/// The scheme the client connected to the first proxy with. Clients can set `X-Forwarded-Proto` themselves, so like
+/// `X-Forwarded-For` it's ignored unless there are trusted proxies to set it.
+fn forwarded_proto(headers: &HeaderMap, proxy_hops: usize) -> Option<&str> {
+    if proxy_hops == 0 {
+        return None;
+    }
+    headers
+        .get("x-forwarded-proto")?
+        .to_str()
+        .ok()?
+        .split(',')
+        .map(str::trim)
+        .find(|proto| matches!(*proto, "http" | "https"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forwarded_proto() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(forwarded_proto(&headers, 1), None);
+
+        headers.insert("x-forwarded-proto", "https".parse().unwrap());
+        // Without trusted proxies the header is ignored
+        assert_eq!(forwarded_proto(&headers, 0), None);
+        assert_eq!(forwarded_proto(&headers, 1), Some("https"));
+
+        headers.insert("x-forwarded-proto", "javascript".parse().unwrap());
+        assert_eq!(forwarded_proto(&headers, 1), None);
+    }
+}
@@ -1,117 +1,64 @@
-use crate::AppState;
-use axum::extract::{
-    ws::{Message, WebSocket, WebSocketUpgrade},
-    Extension, State,
-};
-use futures::{sink::SinkExt, stream::StreamExt};
-use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
-use std::sync::Arc;
-use tokio::sync::{Mutex, RwLock};
-
-pub type Clients = Arc<Mutex<HashMap<String, HashSet<String>>>>;
-
-pub async fn realtime_handler(
-    ws: WebSocketUpgrade,
-    // Extension(clients): Extension<Clients>,
-    // Extension(tx): Extension<crossbeam::channel::Sender<serde_json::Value>>,
-    State(state): State<AppState>,
-) -> impl axum::response::IntoResponse {
-    ws.on_upgrade(move |socket| handle_socket(socket, state.clients, state.rx, state.initial_data))
+use std::convert::Infallible;
+use std::time::Duration;
+
+use super::parse_list;
+use crate::engines::push::{self, Subscription, Update};
+use crate::models::source::Source;
+use axum::extract::Query;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::response::IntoResponse;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::{SinkExt, Stream, StreamExt, stream};
+use http::StatusCode;
+use serde::Deserialize;
+use tokio::sync::{broadcast, watch};
+use utoipa::IntoParams;
+
+/// Upgrades to a websocket that pushes realtime changes.
+/// Clients send a JSON subscription like `{"sources": ["mta_subway"], "routes": ["mta_bus:M15"], "stops": ["njt_bus:10001"], "trips": []}`
+/// and can send a new one at any time to replace it. Each message from the server is a JSON [`Update`].
+pub async fn websocket_handler(ws: WebSocketUpgrade) -> impl IntoResponse {
+    ws.on_upgrade(handle_socket)
 }
 
-#[derive(Deserialize)]
-pub struct FollowingData {
-    route_ids: Vec<String>,
-}
-
-#[derive(Clone)]
-pub struct Update {
-    pub route_id: String,
-    pub data_type: String,
-    pub data: serde_json::Value,
-}
-
-async fn handle_socket(
-    socket: WebSocket,
-    clients: Clients,
-    // tx: crossbeam::channel::Sender<serde_json::Value>,
-    rx: crossbeam::channel::Receiver<Vec<Update>>,
-    initial_data: Arc<RwLock<serde_json::Value>>,
-) {
-    // TODO: maybe switch to message::binary
+async fn handle_socket(socket: WebSocket) {
     let (mut sender, mut receiver) = socket.split();
+    let (subscription_tx, subscription_rx) = watch::channel(Subscription::default());
 
-    sender
-        .send(Message::Text(initial_data.read().await.to_string()))
-        .await
-        .unwrap();
-
-    let client_id = uuid::Uuid::now_v7().to_string();
-    clients
-        .lock()
-        .await
-        .insert(client_id.clone(), HashSet::new());
-
-    let recv_clients = clients.clone();
-    let recv_client_id = client_id.clone();
     let mut recv_task = tokio::spawn(async move {
         while let Some(Ok(msg)) = receiver.next().await {
             match msg {
-                Message::Text(text) => {
-                    if let Ok(follow_routes) = serde_json::from_str::<FollowingData>(&text) {
-                        // dbg!(&follow_routes);
-
-                        let mut clients = recv_clients.lock().await;
-
-                        clients.insert(
-                            recv_client_id.clone(),
-                            follow_routes.route_ids.into_iter().collect(),
-                        );
+                Message::Text(text) => match serde_json::from_str::<Subscription>(&text) {
+                    Ok(subscription) => {
+                        let _ = subscription_tx.send(subscription.normalized());
                     }
-                }
+                    Err(e) => tracing::debug!("Invalid websocket subscription: {}", e),
+                },
+                Message::Close(_) => break,
                 _ => {}
             }
         }
     });
 
-    // let mut rx = rx.clone();
-    let send_clients = clients.clone();
     let mut send_task = tokio::spawn(async move {
-        while let Ok(update) = rx.recv() {
-            let clients = send_clients.lock().await;
-
-            for (_client_id, followed_routes) in clients.iter() {
-                let updates = update
-                    .iter()
-                    .filter_map(|u| {
-                        // we only send specific stop_times for buses. For train we send all
-                        if u.data_type == "train" || followed_routes.contains(&u.route_id) {
-                            Some(&u.data)
-                        } else {
-                            None
-                        }
-                    })
-                    .collect::<Vec<_>>();
-
-                if updates.is_empty() {
+        let mut updates = push::subscribe();
+        loop {
+            let update = match updates.recv().await {
+                Ok(update) => update,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Websocket client lagged behind by {} updates", skipped);
                     continue;
                 }
-
-                let msg = serde_json::to_string(&updates).unwrap();
-                if sender.send(Message::Text(msg)).await.is_err() {
-                    break;
-                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            let Some(update) = update.filter(&subscription_rx.borrow()) else {
+                continue;
+            };
+
+            let msg = serde_json::to_string(&update).unwrap();
+            if sender.send(Message::Text(msg.into())).await.is_err() {
+                break;
             }
-
-            // for (client_id, followed_routes) in clients.iter() {
-            //     if followed_routes.contains(&update.route_id) {
-            //         let msg = serde_json::to_string(&update.data).unwrap();
-            //         if sender.send(Message::Text(msg)).await.is_err() {
-            //             break;
-            //         }
-            //     }
-            // }
         }
     });
 
@@ -119,98 +66,102 @@ async fn handle_socket(
         _ = &mut send_task => recv_task.abort(),
         _ = &mut recv_task => send_task.abort(),
     };
+}
 
-    // remove client from clients when recv_task is done
-    clients.lock().await.remove(&client_id);
-
-    // let mut followed_routes = HashSet::new();
-
-    // let mut recv_task = tokio::spawn(async move {
-    //     // let mut cnt = 0;
-    //     while let Some(Ok(msg)) = receiver.next().await {
-    //         dbg!(&msg);
-
-    //         match msg {
-    //             Message::Text(text) => {
-    //                 if let Ok(follow_routes) = serde_json::from_str::<FollowingData>(&text) {
-    //                     let mut clients = clients.lock().await;
-    //                     let client_id = uuid::Uuid::now_v7().to_string();
-    //                     clients.insert(client_id, follow_routes.route_ids.into_iter().collect());
-
-    //                     // let clients = clients.clone();
-
-    //                     // let tx = tx.clone();
-    //                     // tokio::spawn(async move {
-    //                     //     let mut rx = tx.subscribe();
-
-    //                     //     while let Ok(update) = rx.recv().await {
-    //                     //         if clients.get(&client_id).unwrap().contains(&update.route_id) {
-    //                     //             let msg = serde_json::to_string(&update.data).unwrap();
-    //                     //             if sender.send(Message::Text(msg)).await.is_err() {
-    //                     //                 break;
-    //                     //             }
-    //                     //         }
-    //                     //     }
-    //                     //     clients.remove(&client_id);
-    //                     // });
-    //                 }
-    //             }
-    //             // Message::Close(_) => break,
-    //             _ => {}
-    //         }
-
-    //         // cnt += 1;
-    //         // // print message and break if instructed to do so
-    //         // if process_message(msg, who).is_break() {
-    //         //     break;
-    //         // }
-    //     }
-    //     // cnt
-    // })
-    // .await;
-
-    // tokio::select! {
-    //     _ = recv_task => {
-    //         // println!("{} received {} messages", who, cnt);
-    //     }
-    //     _ = async {
-    //         let mut rx = tx.subscribe();
-    //         while let Ok(update) = rx.recv().await {
-    //             if sender.send(Message::Text(update.data.to_string())).await.is_err() {
-    //                 break;
-    //             }
-    //         }
-    //     } => {
-    //         // println!("{} sent {} messages", who, cnt);
-    //     }
-    // }
+#[derive(Deserialize, IntoParams)]
+pub struct UpdatesParameters {
+    /// Comma-separated list of sources to receive every update for
+    #[serde(deserialize_with = "parse_list", default)]
+    sources: Vec<String>,
+    /// Comma-separated list of routes as `{source}:{route_id}`
+    #[serde(deserialize_with = "parse_list", default)]
+    #[param(example = "mta_bus:M15")]
+    routes: Vec<String>,
+    /// Comma-separated list of stops as `{source}:{stop_id}`
+    #[serde(deserialize_with = "parse_list", default)]
+    #[param(example = "njt_bus:10001")]
+    stops: Vec<String>,
+    /// Comma-separated list of trip IDs
+    #[serde(deserialize_with = "parse_list", default)]
+    trips: Vec<String>,
+}
 
-    // while let Some(Ok(message)) = socket.next().await {
-    //     if let Message::Text(text) = message {
-    //         if let Ok(follow_routes) = serde_json::from_str::<FollowingData>(&text) {
-    //             followed_routes = follow_routes.route_ids.into_iter().collect();
-    //             let client_id = uuid::Uuid::now_v7().to_string();
-    //             clients
-    //                 .lock()
-    //                 .unwrap()
-    //                 .insert(client_id.clone(), followed_routes.clone());
+impl UpdatesParameters {
+    fn subscription(&self) -> Result<Subscription, String> {
+        // parse_list turns an empty string into a single empty item
+        let items = |list: &[String]| {
+            list.iter()
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        Ok(Subscription {
+            sources: items(&self.sources)
+                .iter()
+                .map(|s| Source::from_id(s).ok_or_else(|| format!("unknown source {s}")))
+                .collect::<Result<_, _>>()?,
+            routes: items(&self.routes)
+                .iter()
+                .map(|s| s.parse())
+                .collect::<Result<_, _>>()?,
+            stops: items(&self.stops)
+                .iter()
+                .map(|s| s.parse())
+                .collect::<Result<_, _>>()?,
+            trips: items(&self.trips)
+                .iter()
+                .map(|s| s.parse().map_err(|_| format!("invalid trip ID {s}")))
+                .collect::<Result<_, _>>()?,
+        }
+        .normalized())
+    }
+}
 
-    //             let clients = clients.clone();
-    //             let tx = tx.clone();
-    //             task::spawn(async move {
-    //                 let mut rx = tx.subscribe();
+#[utoipa::path(
+    get,
+    path = "/updates",
+    tag = "REALTIME",
+    description = "Server-sent events stream of realtime changes. Each event is named after the update type (`trips`, `positions` or `alerts`) and only contains what changed since the last realtime pipeline run that matches the subscription. Use `/ws` instead to change the subscription without reconnecting.",
+    params(UpdatesParameters),
+    responses(
+        (status = 200, description = "Stream of updates", body = Update, content_type = "text/event-stream"),
+        (status = 400, description = "Invalid subscription")
+    )
+)]
+pub async fn updates_handler(
+    params: Query<UpdatesParameters>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    let subscription = params
+        .subscription()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let events = stream::unfold(
+        (push::subscribe(), subscription),
+        |(mut updates, subscription)| async move {
+            loop {
+                match updates.recv().await {
+                    Ok(update) => {
+                        if let Some(update) = update.filter(&subscription) {
+                            let name = match update {
+                                Update::Trips { .. } => "trips",
+                                Update::Positions { .. } => "positions",
+                                Update::Alerts { .. } => "alerts",
+                            };
+                            let event = Event::default()
+                                .event(name)
+                                .data(serde_json::to_string(&update).unwrap());
+                            return Some((Ok(event), (updates, subscription)));
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!("SSE client lagged behind by {} updates", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        },
+    );
 
-    //                 while let Ok(update) = rx.recv().await {
-    //                     if followed_routes.contains(&update.route_id) {
-    //                         let msg = serde_json::to_string(&update.data).unwrap();
-    //                         if socket.send(Message::Text(msg)).await.is_err() {
-    //                             break;
-    //                         }
-    //                     }
-    //                 }
-    //                 clients.lock().unwrap().remove(&client_id);
-    //             });
-    //         }
-    //     }
-    // }
+    Ok(Sse::new(events).keep_alive(KeepAlive::new().interval(Duration::from_secs(15))))
 }
@@ -0,0 +1,132 @@
+//! Archives realtime stop times once they are passed, so there's a record of when trips actually arrived.
+//!
+//! Realtime stop times are upserted in place, so the last prediction before a stop drops out of the feed is the
+//! closest thing to an observed arrival. Those are copied to `history.stop_time_observation` along with the static
+//! schedule from [`StaticCacheStore`], for sources that cache one.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use tracing::debug;
+
+use crate::{
+    models::{history::StopTimeObservation, source::Source, static_cache::CachedTrip},
+    sources::source_timezone,
+    stores::{history::HistoryStore, static_cache::StaticCacheStore},
+};
+
+/// Archive every passed stop time for a source. Called after each realtime update.
+pub async fn archive(
+    source: Source,
+    history_store: &HistoryStore,
+    static_cache_store: &StaticCacheStore,
+) -> anyhow::Result<()> {
+    let now = Utc::now();
+    let mut observations = history_store.get_passed(source, now).await?;
+    if observations.is_empty() {
+        return Ok(());
+    }
+
+    let tz = source_timezone(source);
+    let trips: HashSet<(String, DateTime<Utc>)> = observations
+        .iter()
+        .map(|o| (o.original_trip_id.clone(), o.trip_created_at))
+        .collect();
+    let mut schedules = HashMap::new();
+    for (trip_id, created_at) in trips {
+        if let Some(schedule) = static_cache_store
+            .scheduled_trip(source, &trip_id, created_at, tz)
+            .await?
+        {
+            schedules.insert((trip_id, created_at), schedule);
+        }
+    }
+
+    for observation in observations.iter_mut() {
+        let key = (
+            observation.original_trip_id.clone(),
+            observation.trip_created_at,
+        );
+        if let Some(schedule) = schedules.get(&key) {
+            apply_schedule(observation, schedule);
+        }
+    }
+
+    let saved = history_store
+        .save_observations(source, &observations, now)
+        .await?;
+    debug!("Archived {} stop times for {:?}", saved, source);
+
+    Ok(())
+}
+
+/// Copy the scheduled times for the observation's stop. Stop IDs are compared case-insensitively since realtime
+/// stop IDs are saved uppercase.
+fn apply_schedule(observation: &mut StopTimeObservation, schedule: &CachedTrip) {
+    let Some(stop_time) = schedule
+        .stop_times
+        .iter()
+        .find(|st| st.stop_id.eq_ignore_ascii_case(&observation.stop_id))
+    else {
+        return;
+    };
+    observation.scheduled_arrival = Some(stop_time.arrival);
+    observation.scheduled_departure = Some(stop_time.departure);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::static_cache::CachedStopTime;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_apply_schedule() {
+        let time = |h, m| Utc.with_ymd_and_hms(2025, 3, 1, h, m, 0).unwrap();
+        let schedule = CachedTrip {
+            trip_id: "GO103_25_2641".into(),
+            route_id: "1".into(),
+            headsign: "Babylon".into(),
+            direction_id: 0,
+            start_date: "20250301".into(),
+            start_time: time(12, 0),
+            stop_times: vec![
+                CachedStopTime {
+                    stop_id: "237".into(),
+                    arrival: time(12, 0),
+                    departure: time(12, 1),
+                    stop_sequence: 1,
+                },
+                CachedStopTime {
+                    stop_id: "wdd".into(),
+                    arrival: time(12, 20),
+                    departure: time(12, 21),
+                    stop_sequence: 2,
+                },
+            ],
+        };
+        let observation = |stop_id: &str| StopTimeObservation {
+            trip_id: Uuid::now_v7(),
+            stop_id: stop_id.into(),
+            original_trip_id: "GO103_25_2641".into(),
+            route_id: "1".into(),
+            direction: 0,
+            trip_created_at: time(12, 0),
+            arrival: time(12, 24),
+            departure: time(12, 25),
+            scheduled_arrival: None,
+            scheduled_departure: None,
+        };
+
+        let mut matched = observation("WDD");
+        apply_schedule(&mut matched, &schedule);
+        assert_eq!(matched.scheduled_arrival, Some(time(12, 20)));
+        assert_eq!(matched.scheduled_departure, Some(time(12, 21)));
+
+        // Stops that aren't in the schedule stay unscheduled
+        let mut unmatched = observation("102");
+        apply_schedule(&mut unmatched, &schedule);
+        assert_eq!(unmatched.scheduled_arrival, None);
+    }
+}
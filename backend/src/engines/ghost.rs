@@ -0,0 +1,169 @@
+//! Flags ghost trips: trips whose predictions keep updating without a vehicle actually running them.
+//!
+//! Running trips are cross-checked against their vehicle position. A position that's missing, frozen, far from the
+//! route or several stops away from where the trip is predicted to be each make it more likely that the trip is a
+//! ghost. The signals are combined into a confidence between 0 and 1 that's saved on the trip, and the number of
+//! suspected ghosts on each route is sampled to [`HistoryStore`] so ghost rates can be tracked over time. A sample
+//! is taken every update, so only the last [`SAMPLE_WINDOW`] of them are kept.
+
+use std::collections::BTreeMap;
+
+use chrono::{Duration, Utc};
+use tracing::debug;
+
+use crate::{
+    models::{
+        ghost::{GhostSample, GhostSignals},
+        source::Source,
+    },
+    stores::{history::HistoryStore, trip::TripStore},
+};
+
+/// Trips with at least this confidence are counted as ghosts
+pub const GHOST_THRESHOLD: f32 = 0.5;
+/// How long ghost samples are kept
+pub const SAMPLE_WINDOW: Duration = Duration::days(30);
+
+/// Confidence for a trip without a position, when the source has positions for other trips. It isn't certain since
+/// vehicles aren't always linked to their trip.
+const MISSING_POSITION: f32 = 0.6;
+/// How far a position can lag behind its trip's predictions before it counts as frozen, and when it's certainly frozen
+const STALE_POSITION: (Duration, Duration) = (Duration::minutes(3), Duration::minutes(10));
+/// Distance in meters from the route before a vehicle counts as off route, and when it's certainly off route
+const OFF_ROUTE: (f64, f64) = (200.0, 1000.0);
+/// Stops between the vehicle and the trip's next stop before it counts as out of place, and when it certainly is
+const STOP_GAP: (i32, i32) = (3, 8);
+
+/// Check every running trip for a source. Called after each realtime update.
+pub async fn detect(
+    source: Source,
+    trip_store: &TripStore,
+    history_store: &HistoryStore,
+) -> anyhow::Result<()> {
+    let now = Utc::now();
+    let signals = trip_store.get_ghost_signals(source, now).await?;
+    if signals.is_empty() {
+        return Ok(());
+    }
+
+    // Some sources don't have positions at all, so missing positions are only suspicious if others have them
+    let has_positions = signals.iter().any(|s| s.position_updated_at.is_some());
+    let confidences: Vec<(&GhostSignals, f32)> = signals
+        .iter()
+        .map(|s| (s, confidence(s, has_positions)))
+        .collect();
+
+    let mut routes: BTreeMap<&str, GhostSample> = BTreeMap::new();
+    for (trip, confidence) in &confidences {
+        let sample = routes
+            .entry(trip.route_id.as_str())
+            .or_insert_with(|| GhostSample {
+                route_id: trip.route_id.clone(),
+                trips: 0,
+                ghosts: 0,
+            });
+        sample.trips += 1;
+        if *confidence >= GHOST_THRESHOLD {
+            sample.ghosts += 1;
+        }
+    }
+    let samples: Vec<GhostSample> = routes.into_values().collect();
+
+    let confidences: Vec<_> = confidences
+        .into_iter()
+        .map(|(s, confidence)| (s.trip_id, confidence))
+        .collect();
+    trip_store.save_ghost_confidences(&confidences).await?;
+    history_store
+        .save_ghost_samples(source, &samples, now)
+        .await?;
+    history_store
+        .prune_ghost_samples(source, now - SAMPLE_WINDOW)
+        .await?;
+    debug!(
+        "Found {} suspected ghost trips out of {} for {:?}",
+        samples.iter().map(|s| s.ghosts).sum::<i32>(),
+        confidences.len(),
+        source
+    );
+
+    Ok(())
+}
+
+/// Combine each signal's probability that the trip is a ghost, treating them as independent.
+fn confidence(signals: &GhostSignals, source_has_positions: bool) -> f32 {
+    let missing = match signals.position_updated_at {
+        None if source_has_positions => MISSING_POSITION,
+        _ => 0.0,
+    };
+    let stale = signals.position_updated_at.map_or(0.0, |updated_at| {
+        let lag = signals.trip_updated_at - updated_at;
+        ramp(
+            lag.num_seconds() as f64,
+            STALE_POSITION.0.num_seconds() as f64,
+            STALE_POSITION.1.num_seconds() as f64,
+        )
+    });
+    let off_route = signals
+        .distance_from_route
+        .map_or(0.0, |d| ramp(d, OFF_ROUTE.0, OFF_ROUTE.1));
+    let out_of_place = signals.stop_gap.map_or(0.0, |gap| {
+        ramp(gap as f64, STOP_GAP.0 as f64, STOP_GAP.1 as f64)
+    });
+
+    1.0 - [missing, stale, off_route, out_of_place]
+        .iter()
+        .map(|p| 1.0 - p)
+        .product::<f32>()
+}
+
+/// 0 at or below `start`, 1 at or above `end` and linear in between
+fn ramp(value: f64, start: f64, end: f64) -> f32 {
+    ((value - start) / (end - start)).clamp(0.0, 1.0) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_confidence() {
+        let now = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap();
+        let healthy = GhostSignals {
+            trip_id: Uuid::now_v7(),
+            route_id: "B46".into(),
+            trip_updated_at: now,
+            position_updated_at: Some(now - Duration::seconds(30)),
+            distance_from_route: Some(15.0),
+            stop_gap: Some(1),
+        };
+        assert_eq!(confidence(&healthy, true), 0.0);
+
+        // Missing positions only count when the source has them
+        let missing = GhostSignals {
+            position_updated_at: None,
+            distance_from_route: None,
+            stop_gap: None,
+            ..healthy.clone()
+        };
+        assert!((confidence(&missing, true) - MISSING_POSITION).abs() < 1e-6);
+        assert_eq!(confidence(&missing, false), 0.0);
+
+        let frozen = GhostSignals {
+            position_updated_at: Some(now - Duration::minutes(15)),
+            ..healthy.clone()
+        };
+        assert_eq!(confidence(&frozen, true), 1.0);
+
+        // Signals that are only a little off add up
+        let drifting = GhostSignals {
+            distance_from_route: Some(600.0),
+            stop_gap: Some(4),
+            ..healthy
+        };
+        let c = confidence(&drifting, true);
+        assert!(c > 0.5 && c < 1.0, "{c}");
+    }
+}
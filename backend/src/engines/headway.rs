@@ -0,0 +1,210 @@
+//! Headways between a route's trips at each of its upcoming stops, from realtime predictions.
+//!
+//! Consecutive predicted arrivals at a stop in the same direction are compared to find bunched trips and big gaps.
+//! Scheduled headways come from the static schedule in [`StaticCacheStore`], for sources that cache one.
+//!
+//! MTA bus doesn't: its routes and stops are imported from OneBusAway, and its GTFS schedule isn't loaded, so bus
+//! routes like the M15 and B46 aren't compared to a schedule yet. Their gaps are judged against the average predicted
+//! headway at each stop, and `schedule_unavailable_reason` says so in every response.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    models::{
+        headway::{Headway, HeadwayArrival, HeadwayStatus, RouteHeadways, StopHeadways},
+        source::Source,
+        static_cache::CachedTrip,
+    },
+    sources::source_timezone,
+    stores::{static_cache::StaticCacheStore, stop_time::StopTimeStore},
+};
+
+/// Trips arriving within this many seconds of each other are bunched
+const BUNCHED_HEADWAY: i64 = 120;
+/// Headways at least this many times the expected headway are gaps
+const GAP_FACTOR: f64 = 2.0;
+
+/// Current headways at every upcoming stop of a route.
+pub async fn route_headways(
+    source: Source,
+    route_id: &str,
+    at: DateTime<Utc>,
+    stop_time_store: &StopTimeStore,
+    static_cache_store: &StaticCacheStore,
+) -> anyhow::Result<RouteHeadways> {
+    let mut arrivals = stop_time_store
+        .get_route_arrivals(source, route_id, at)
+        .await?;
+
+    let schedule_unavailable_reason = schedule_unavailable_reason(source);
+    if schedule_unavailable_reason.is_none() {
+        let trips: Vec<(&str, DateTime<Utc>)> = arrivals
+            .iter()
+            .map(|a| (a.original_trip_id.as_str(), a.trip_created_at))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let schedules: HashMap<(String, DateTime<Utc>), CachedTrip> = static_cache_store
+            .find_trips_running_at(source, &trips, source_timezone(source))
+            .await?
+            .into_iter()
+            .zip(&trips)
+            .filter_map(|(schedule, &(trip_id, created_at))| {
+                Some(((trip_id.to_owned(), created_at), schedule?))
+            })
+            .collect();
+
+        for arrival in arrivals.iter_mut() {
+            let key = (arrival.original_trip_id.clone(), arrival.trip_created_at);
+            arrival.scheduled_arrival = schedules.get(&key).and_then(|schedule| {
+                schedule
+                    .stop_times
+                    .iter()
+                    .find(|st| st.stop_id.eq_ignore_ascii_case(&arrival.stop_id))
+                    .map(|st| st.arrival)
+            });
+        }
+    }
+
+    Ok(RouteHeadways {
+        schedule_unavailable_reason: schedule_unavailable_reason.map(str::to_owned),
+        ..headways(route_id, &arrivals)
+    })
+}
+
+/// Why a source has no static schedule cached for its realtime trips, so there's no point looking one up
+fn schedule_unavailable_reason(source: Source) -> Option<&'static str> {
+    match source {
+        Source::MtaBus => Some(
+            "MTA bus schedules aren't loaded, since routes and stops are imported from OneBusAway. Gaps are compared to the average headway at each stop instead.",
+        ),
+        _ => None,
+    }
+}
+
+/// Group arrivals, which are ordered by direction, stop and arrival, into headways at each stop.
+fn headways(route_id: &str, arrivals: &[HeadwayArrival]) -> RouteHeadways {
+    let stops: Vec<StopHeadways> = arrivals
+        .chunk_by(|a, b| a.direction == b.direction && a.stop_id == b.stop_id)
+        .map(stop_headways)
+        .collect();
+
+    // The same two trips are usually bunched at several stops in a row, so they're only counted once
+    let pairs = |status| {
+        stops
+            .iter()
+            .flat_map(|s| &s.headways)
+            .filter(|h| h.status == status)
+            .map(|h| (h.leading_trip_id, h.trip_id))
+            .collect::<HashSet<_>>()
+            .len()
+    };
+
+    RouteHeadways {
+        route_id: route_id.to_uppercase(),
+        bunched_pairs: pairs(HeadwayStatus::Bunched),
+        gap_pairs: pairs(HeadwayStatus::Gap),
+        schedule_unavailable_reason: None,
+        stops,
+    }
+}
+
+fn stop_headways(arrivals: &[HeadwayArrival]) -> StopHeadways {
+    let mut headways: Vec<Headway> = arrivals
+        .windows(2)
+        .map(|pair| {
+            let (leading, following) = (&pair[0], &pair[1]);
+            Headway {
+                leading_trip_id: leading.trip_id,
+                trip_id: following.trip_id,
+                arrival: following.arrival,
+                headway: (following.arrival - leading.arrival).num_seconds(),
+                scheduled_headway: following
+                    .scheduled_arrival
+                    .zip(leading.scheduled_arrival)
+                    .map(|(f, l)| (f - l).num_seconds()),
+                status: HeadwayStatus::Normal,
+            }
+        })
+        .collect();
+
+    let average = |values: Vec<i64>| {
+        (!values.is_empty()).then(|| values.iter().sum::<i64>() as f64 / values.len() as f64)
+    };
+    let average_headway = average(headways.iter().map(|h| h.headway).collect());
+    let average_scheduled_headway = average(
+        headways
+            .iter()
+            .filter_map(|h| h.scheduled_headway)
+            .collect(),
+    );
+
+    for headway in headways.iter_mut() {
+        // Without a schedule, gaps are compared to the stop's average instead
+        let expected = headway
+            .scheduled_headway
+            .map(|h| h as f64)
+            .or(average_headway)
+            .filter(|h| *h > 0.0);
+        headway.status = if headway.headway <= BUNCHED_HEADWAY {
+            HeadwayStatus::Bunched
+        } else if expected.is_some_and(|e| headway.headway as f64 >= e * GAP_FACTOR) {
+            HeadwayStatus::Gap
+        } else {
+            HeadwayStatus::Normal
+        };
+    }
+
+    StopHeadways {
+        stop_id: arrivals[0].stop_id.clone(),
+        direction: arrivals[0].direction,
+        average_headway,
+        average_scheduled_headway,
+        headways,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_headways() {
+        let time = |m| Utc.with_ymd_and_hms(2025, 3, 1, 12, m, 0).unwrap();
+        let trips = [Uuid::now_v7(), Uuid::now_v7(), Uuid::now_v7()];
+        let arrival = |trip: usize, stop_id: &str, minute, scheduled| HeadwayArrival {
+            trip_id: trips[trip],
+            original_trip_id: format!("trip_{trip}"),
+            trip_created_at: time(0),
+            direction: 0,
+            stop_id: stop_id.into(),
+            arrival: time(minute),
+            scheduled_arrival: Some(time(scheduled)),
+        };
+        let arrivals = [
+            arrival(0, "401921", 10, 10),
+            arrival(1, "401921", 11, 15),
+            arrival(2, "401921", 25, 20),
+            arrival(0, "401922", 12, 12),
+            arrival(1, "401922", 13, 17),
+        ];
+
+        let route = headways("m15", &arrivals);
+        assert_eq!(route.route_id, "M15");
+        assert_eq!(route.stops.len(), 2);
+
+        let stop = &route.stops[0];
+        assert_eq!(stop.average_scheduled_headway, Some(300.0));
+        let statuses: Vec<_> = stop.headways.iter().map(|h| h.status).collect();
+        assert_eq!(statuses, [HeadwayStatus::Bunched, HeadwayStatus::Gap]);
+        assert_eq!(stop.headways[1].headway, 840);
+
+        // The first two trips are bunched at both stops but only count as one pair
+        assert_eq!(route.bunched_pairs, 1);
+        assert_eq!(route.gap_pairs, 1);
+    }
+}
@@ -1,5 +1,15 @@
 pub mod alerts;
+pub mod archive;
+pub mod ghost;
+pub mod headway;
+pub mod planner;
+pub mod position;
+pub mod prediction;
+pub mod push;
 pub mod realtime;
+pub mod retention;
 pub mod static_cache;
 pub mod static_data;
+pub mod status;
+pub mod usage;
 pub mod valhalla;
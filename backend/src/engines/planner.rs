@@ -0,0 +1,715 @@
+//! In-memory RAPTOR router over live stop times.
+//!
+//! Each realtime cycle replaces one source's timetable and rebuilds the router from every source, so itineraries
+//! can change vehicles across sources using the transfers in `static.stop_transfer`.
+//! See <https://www.microsoft.com/en-us/research/publication/round-based-public-transit-routing/> for the algorithm.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use chrono::{DateTime, Utc};
+use geo::{Distance, Haversine, Point};
+use uuid::Uuid;
+
+use crate::{
+    models::{
+        plan::{Itinerary, Leg, LegStop},
+        source::Source,
+        stop::Stop,
+        trip::{StopTime, StopTimeStatus, Trip},
+    },
+    stores::{stop::StopStore, stop_time::StopTimeStore, trip::TripStore},
+};
+
+/// Maximum number of vehicles in an itinerary
+pub const MAX_ROUNDS: usize = 5;
+/// Walking speed in meters per second used when a transfer doesn't have a `min_transfer_time`
+const WALKING_SPEED: f64 = 1.2;
+/// Minimum time for any walking transfer, to account for getting in and out of stations
+const MIN_WALK_TIME: i64 = 60;
+
+/// Everything the router needs from one source
+#[derive(Default)]
+pub struct SourceTimetable {
+    pub trips: Vec<Trip>,
+    pub stop_times: Vec<StopTime>,
+    pub stops: Vec<Stop>,
+}
+
+/// Holds the latest timetable for every source and the router built from them
+#[derive(Clone, Default)]
+pub struct Planner {
+    timetables: Arc<RwLock<HashMap<Source, Arc<SourceTimetable>>>>,
+    router: Arc<RwLock<Arc<Raptor>>>,
+    /// Held while the router is rebuilt, so a build from an older snapshot can't replace a newer router
+    rebuild: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Planner {
+    /// Reloads a source's timetable from the stores and rebuilds the router.
+    /// Called by the realtime engine after the stop times cache is populated.
+    pub async fn refresh(
+        &self,
+        source: Source,
+        trip_store: &TripStore,
+        stop_time_store: &StopTimeStore,
+        stop_store: &StopStore,
+    ) -> anyhow::Result<()> {
+        let timetable = SourceTimetable {
+            trips: trip_store.get_all(source, None).await?,
+            stop_times: stop_time_store.get_all(source, None, None).await?,
+            stops: stop_store.get_all(source).await?,
+        };
+
+        self.timetables
+            .write()
+            .unwrap()
+            .insert(source, Arc::new(timetable));
+
+        // Snapshot after taking the lock, so it includes every timetable saved before this rebuild started
+        let _rebuild = self.rebuild.lock().await;
+        let timetables: Vec<(Source, Arc<SourceTimetable>)> = self
+            .timetables
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(s, t)| (*s, t.clone()))
+            .collect();
+
+        let router = tokio::task::spawn_blocking(move || {
+            Raptor::build(timetables.iter().map(|(s, t)| (*s, t.as_ref())))
+        })
+        .await?;
+
+        tracing::debug!(
+            "Rebuilt planner after {} update: {} stops, {} patterns",
+            source.as_str(),
+            router.stops.len(),
+            router.patterns.len()
+        );
+        *self.router.write().unwrap() = Arc::new(router);
+
+        Ok(())
+    }
+
+    pub fn router(&self) -> Arc<Raptor> {
+        self.router.read().unwrap().clone()
+    }
+}
+
+struct StopInfo {
+    source: Source,
+    id: String,
+    name: String,
+}
+
+/// Trips on the same route that serve the exact same sequence of stops
+struct Pattern {
+    source: Source,
+    route_id: String,
+    stops: Vec<usize>,
+    trips: Vec<PatternTrip>,
+}
+
+struct PatternTrip {
+    id: Uuid,
+    /// (arrival, departure) unix timestamps for each stop in the pattern
+    times: Vec<(i64, i64)>,
+}
+
+struct Footpath {
+    to: usize,
+    duration: i64,
+    transfer_type: i16,
+}
+
+#[derive(Default)]
+pub struct Raptor {
+    stops: Vec<StopInfo>,
+    stop_index: HashMap<(Source, String), usize>,
+    patterns: Vec<Pattern>,
+    /// (pattern, position in pattern) for every pattern serving a stop
+    stop_patterns: Vec<Vec<(usize, usize)>>,
+    footpaths: Vec<Vec<Footpath>>,
+    /// Time needed to change vehicles without leaving the stop, from transfers to the same stop
+    min_change: Vec<i64>,
+}
+
+#[derive(Clone, Copy)]
+enum Label {
+    Origin,
+    Transit {
+        pattern: usize,
+        trip: usize,
+        board: usize,
+        alight: usize,
+    },
+    Walk {
+        from: usize,
+        footpath: usize,
+    },
+}
+
+/// Per-query state, indexed by round and then stop
+struct Search {
+    arrivals: Vec<Vec<i64>>,
+    labels: Vec<Vec<Option<Label>>>,
+    /// Earliest arrival at each stop over all rounds
+    best: Vec<i64>,
+    target: usize,
+}
+
+impl Search {
+    fn new(stops: usize, target: usize) -> Self {
+        Self {
+            arrivals: vec![vec![i64::MAX; stops]; MAX_ROUNDS + 1],
+            labels: vec![vec![None; stops]; MAX_ROUNDS + 1],
+            best: vec![i64::MAX; stops],
+            target,
+        }
+    }
+
+    /// Records an arrival if it's earlier than anything seen at the stop or the target
+    fn improve(&mut self, k: usize, stop: usize, arrival: i64, label: Label) -> bool {
+        if arrival >= self.best[stop].min(self.best[self.target]) {
+            return false;
+        }
+        self.arrivals[k][stop] = arrival;
+        self.labels[k][stop] = Some(label);
+        self.best[stop] = arrival;
+        true
+    }
+}
+
+impl Raptor {
+    pub fn build<'a>(timetables: impl IntoIterator<Item = (Source, &'a SourceTimetable)>) -> Self {
+        let mut raptor = Raptor::default();
+        let mut points: Vec<Option<Point>> = vec![];
+        // Transfers are resolved after every source's stops are indexed since they can point to other sources
+        let mut transfers = vec![];
+
+        for (source, timetable) in timetables {
+            // route_stop ordering breaks ties between stops with the same realtime arrival
+            let mut sequences: HashMap<(String, &str), i16> = HashMap::new();
+
+            for stop in &timetable.stops {
+                let idx = raptor.stop(source, &stop.id, &stop.name);
+                points.resize(raptor.stops.len(), None);
+                if let geo::Geometry::Point(point) = stop.geom.0 {
+                    points[idx] = Some(point);
+                }
+                for route in &stop.routes {
+                    // Trip route IDs are saved uppercase but route_stop IDs aren't always
+                    sequences.insert(
+                        (route.route_id.to_uppercase(), route.stop_id.as_str()),
+                        route.stop_sequence,
+                    );
+                }
+                transfers.extend(stop.transfers.iter().map(|t| (idx, t)));
+            }
+
+            let mut stop_times_by_trip: HashMap<Uuid, Vec<&StopTime>> = HashMap::new();
+            // Trips can't be boarded or left at skipped stops, which includes every stop of a canceled trip
+            for st in timetable
+                .stop_times
+                .iter()
+                .filter(|st| st.status != StopTimeStatus::Skipped)
+            {
+                stop_times_by_trip.entry(st.trip_id).or_default().push(st);
+            }
+
+            let mut pattern_index: HashMap<(String, Vec<usize>), usize> = HashMap::new();
+            for trip in &timetable.trips {
+                let Some(mut stop_times) = stop_times_by_trip.remove(&trip.id) else {
+                    continue;
+                };
+                stop_times.sort_by_cached_key(|st| {
+                    let sequence = sequences
+                        .get(&(trip.route_id.clone(), st.stop_id.as_str()))
+                        .copied()
+                        .unwrap_or_default();
+                    (st.arrival, st.departure, sequence)
+                });
+                if stop_times.len() < 2 {
+                    continue;
+                }
+
+                let stops: Vec<usize> = stop_times
+                    .iter()
+                    .map(|st| raptor.stop(source, &st.stop_id, &st.stop_id))
+                    .collect();
+                let times = stop_times
+                    .iter()
+                    .map(|st| (st.arrival.timestamp(), st.departure.timestamp()))
+                    .collect();
+
+                let pattern = *pattern_index
+                    .entry((trip.route_id.clone(), stops.clone()))
+                    .or_insert_with(|| {
+                        raptor.patterns.push(Pattern {
+                            source,
+                            route_id: trip.route_id.clone(),
+                            stops,
+                            trips: vec![],
+                        });
+                        raptor.patterns.len() - 1
+                    });
+                raptor.patterns[pattern]
+                    .trips
+                    .push(PatternTrip { id: trip.id, times });
+            }
+        }
+
+        let n = raptor.stops.len();
+        points.resize(n, None);
+        raptor.stop_patterns = vec![vec![]; n];
+        raptor.footpaths = (0..n).map(|_| vec![]).collect();
+        raptor.min_change = vec![0; n];
+
+        for (p, pattern) in raptor.patterns.iter_mut().enumerate() {
+            pattern.trips.sort_by_key(|t| t.times[0].1);
+            for (pos, &stop) in pattern.stops.iter().enumerate() {
+                raptor.stop_patterns[stop].push((p, pos));
+            }
+        }
+
+        for (from, transfer) in transfers {
+            // 3 means no transfer is possible, 4 and 5 are in-seat transfers
+            if matches!(transfer.transfer_type, 3..=5) {
+                continue;
+            }
+            let Some(&to) = raptor
+                .stop_index
+                .get(&(transfer.to_stop_source, transfer.to_stop_id.clone()))
+            else {
+                continue;
+            };
+
+            if from == to {
+                raptor.min_change[from] = transfer.min_transfer_time.unwrap_or_default() as i64;
+                continue;
+            }
+
+            let duration = match (transfer.min_transfer_time, points[from], points[to]) {
+                (Some(t), _, _) => t as i64,
+                (None, Some(a), Some(b)) => {
+                    ((Haversine.distance(a, b) / WALKING_SPEED).ceil() as i64).max(MIN_WALK_TIME)
+                }
+                _ => MIN_WALK_TIME,
+            };
+            raptor.footpaths[from].push(Footpath {
+                to,
+                duration,
+                transfer_type: transfer.transfer_type,
+            });
+        }
+
+        raptor
+    }
+
+    /// Returns the index of a stop, adding it if it hasn't been seen yet
+    fn stop(&mut self, source: Source, id: &str, name: &str) -> usize {
+        if let Some(&idx) = self.stop_index.get(&(source, id.to_owned())) {
+            return idx;
+        }
+        self.stops.push(StopInfo {
+            source,
+            id: id.to_owned(),
+            name: name.to_owned(),
+        });
+        self.stop_index
+            .insert((source, id.to_owned()), self.stops.len() - 1);
+        self.stops.len() - 1
+    }
+
+    /// Finds the fastest itinerary for each number of transfers, leaving `from` at or after `at`.
+    pub fn plan(
+        &self,
+        from: (Source, &str),
+        to: (Source, &str),
+        at: DateTime<Utc>,
+    ) -> Vec<Itinerary> {
+        let (Some(&origin), Some(&target)) = (
+            self.stop_index.get(&(from.0, from.1.to_owned())),
+            self.stop_index.get(&(to.0, to.1.to_owned())),
+        ) else {
+            return vec![];
+        };
+
+        let mut search = Search::new(self.stops.len(), target);
+        search.improve(0, origin, at.timestamp(), Label::Origin);
+        let mut marked = vec![origin];
+        self.relax_footpaths(&mut search, 0, &mut marked);
+
+        for k in 1..=MAX_ROUNDS {
+            // Earliest marked position of each pattern, so each pattern is only scanned once per round
+            let mut queue: HashMap<usize, usize> = HashMap::new();
+            for &stop in &marked {
+                for &(pattern, pos) in &self.stop_patterns[stop] {
+                    queue
+                        .entry(pattern)
+                        .and_modify(|p| *p = (*p).min(pos))
+                        .or_insert(pos);
+                }
+            }
+
+            let mut improved = vec![];
+            for (pattern_idx, start_pos) in queue {
+                let pattern = &self.patterns[pattern_idx];
+                // (trip, boarding position)
+                let mut current: Option<(usize, usize)> = None;
+
+                for pos in start_pos..pattern.stops.len() {
+                    let stop = pattern.stops[pos];
+
+                    if let Some((trip, board)) = current {
+                        let label = Label::Transit {
+                            pattern: pattern_idx,
+                            trip,
+                            board,
+                            alight: pos,
+                        };
+                        if search.improve(k, stop, pattern.trips[trip].times[pos].0, label) {
+                            improved.push(stop);
+                        }
+                    }
+
+                    let previous = search.arrivals[k - 1][stop];
+                    if previous == i64::MAX {
+                        continue;
+                    }
+                    // Staying on the platform after getting off still takes the stop's change time
+                    let ready = match search.labels[k - 1][stop] {
+                        Some(Label::Transit { .. }) => previous + self.min_change[stop],
+                        _ => previous,
+                    };
+                    let current_departure = current.map(|(t, _)| pattern.trips[t].times[pos].1);
+                    // Realtime trips can overtake each other, so look for the earliest departure rather than
+                    // the first trip in the pattern's order
+                    let earliest = pattern
+                        .trips
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, t)| t.times[pos].1 >= ready)
+                        .min_by_key(|(_, t)| t.times[pos].1);
+                    if let Some((trip, t)) = earliest
+                        && current_departure.is_none_or(|d| t.times[pos].1 < d)
+                    {
+                        current = Some((trip, pos));
+                    }
+                }
+            }
+
+            improved.sort_unstable();
+            improved.dedup();
+            marked = improved;
+            self.relax_footpaths(&mut search, k, &mut marked);
+            if marked.is_empty() {
+                break;
+            }
+        }
+
+        let mut itineraries: Vec<Itinerary> = vec![];
+        for k in 1..=MAX_ROUNDS {
+            let arrival = search.arrivals[k][target];
+            let improves = itineraries
+                .last()
+                .is_none_or(|i| arrival < i.arrival.timestamp());
+            if arrival == i64::MAX || !improves {
+                continue;
+            }
+            if let Some(itinerary) = self.itinerary(&search, k) {
+                itineraries.push(itinerary);
+            }
+        }
+        itineraries
+    }
+
+    /// Walks from every stop reached this round, adding the walked-to stops to `marked`
+    fn relax_footpaths(&self, search: &mut Search, k: usize, marked: &mut Vec<usize>) {
+        let reached: Vec<(usize, i64)> =
+            marked.iter().map(|&s| (s, search.arrivals[k][s])).collect();
+        for (from, arrival) in reached {
+            for (i, footpath) in self.footpaths[from].iter().enumerate() {
+                let label = Label::Walk { from, footpath: i };
+                if search.improve(k, footpath.to, arrival + footpath.duration, label) {
+                    marked.push(footpath.to);
+                }
+            }
+        }
+    }
+
+    fn itinerary(&self, search: &Search, rounds: usize) -> Option<Itinerary> {
+        let mut legs = vec![];
+        let mut k = rounds;
+        let mut stop = search.target;
+        // Guards against cycles of walking labels
+        let mut steps = 0;
+
+        loop {
+            steps += 1;
+            if steps > 4 * (MAX_ROUNDS + 1) {
+                return None;
+            }
+            match search.labels[k][stop]? {
+                Label::Origin => break,
+                Label::Walk { from, footpath } => {
+                    let footpath = &self.footpaths[from][footpath];
+                    let arrival = search.arrivals[k][stop];
+                    legs.push(Leg::Walk {
+                        from: self.leg_stop(from),
+                        to: self.leg_stop(stop),
+                        departure: timestamp(arrival - footpath.duration)?,
+                        arrival: timestamp(arrival)?,
+                        duration: footpath.duration,
+                        transfer_type: footpath.transfer_type,
+                    });
+                    stop = from;
+                }
+                Label::Transit {
+                    pattern,
+                    trip,
+                    board,
+                    alight,
+                } => {
+                    let pattern = &self.patterns[pattern];
+                    let trip = &pattern.trips[trip];
+                    let boarding_stop = pattern.stops[board];
+                    let departure = trip.times[board].1;
+                    legs.push(Leg::Transit {
+                        source: pattern.source,
+                        route_id: pattern.route_id.clone(),
+                        trip_id: trip.id,
+                        from: self.leg_stop(boarding_stop),
+                        to: self.leg_stop(stop),
+                        departure: timestamp(departure)?,
+                        arrival: timestamp(trip.times[alight].0)?,
+                        stops: alight - board,
+                        wait: departure - search.arrivals[k - 1][boarding_stop],
+                    });
+                    stop = boarding_stop;
+                    k -= 1;
+                }
+            }
+        }
+
+        legs.reverse();
+        let departure = match legs.first()? {
+            Leg::Transit { departure, .. } | Leg::Walk { departure, .. } => *departure,
+        };
+        let arrival = timestamp(search.arrivals[rounds][search.target])?;
+
+        Some(Itinerary {
+            departure,
+            arrival,
+            duration: (arrival - departure).num_seconds(),
+            transfers: rounds - 1,
+            legs,
+        })
+    }
+
+    fn leg_stop(&self, idx: usize) -> LegStop {
+        let stop = &self.stops[idx];
+        LegStop {
+            id: stop.id.clone(),
+            source: stop.source,
+            name: stop.name.clone(),
+        }
+    }
+}
+
+fn timestamp(secs: i64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(secs, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        stop::{NjtBusStopData, StopData, Transfer},
+        trip::{GtfsTripData, StopTimeData, TripData, TripStatus},
+    };
+    use chrono::TimeZone;
+
+    fn time(h: u32, m: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 6, 4, h, m, 0).unwrap()
+    }
+
+    fn stop(id: &str, lon: f64, lat: f64, transfers: Vec<Transfer>) -> Stop {
+        Stop {
+            id: id.into(),
+            name: format!("Stop {id}"),
+            geom: Point::new(lon, lat).into(),
+            transfers,
+            data: StopData::NjtBus(NjtBusStopData {
+                stop_code: id.into(),
+            }),
+            routes: vec![],
+        }
+    }
+
+    fn transfer(to: &str, source: Source, min_transfer_time: Option<i16>) -> Transfer {
+        Transfer {
+            to_stop_id: to.into(),
+            to_stop_source: source,
+            transfer_type: if min_transfer_time.is_some() { 2 } else { 6 },
+            min_transfer_time,
+        }
+    }
+
+    fn trip(route_id: &str, stops: &[(&str, DateTime<Utc>)]) -> (Trip, Vec<StopTime>) {
+        let trip = Trip {
+            id: Uuid::now_v7(),
+            original_id: route_id.into(),
+            vehicle_id: Some(route_id.into()),
+            route_id: route_id.into(),
+            direction: 0,
+            created_at: stops[0].1,
+            updated_at: stops[0].1,
+            status: TripStatus::Scheduled,
+            ghost_confidence: None,
+            data: TripData::Gtfs(GtfsTripData {
+                headsign: String::new(),
+                delay: None,
+            }),
+        };
+        let stop_times = stops
+            .iter()
+            .map(|(stop_id, t)| StopTime {
+                trip_id: trip.id,
+                stop_id: (*stop_id).into(),
+                arrival: *t,
+                departure: *t,
+                status: StopTimeStatus::Scheduled,
+                data: StopTimeData::Gtfs,
+            })
+            .collect();
+        (trip, stop_times)
+    }
+
+    fn timetable(stops: Vec<Stop>, trips: Vec<(Trip, Vec<StopTime>)>) -> SourceTimetable {
+        let (trips, stop_times): (Vec<_>, Vec<_>) = trips.into_iter().unzip();
+        SourceTimetable {
+            trips,
+            stop_times: stop_times.into_iter().flatten().collect(),
+            stops,
+        }
+    }
+
+    fn transit_routes(itinerary: &Itinerary) -> Vec<&str> {
+        itinerary
+            .legs
+            .iter()
+            .filter_map(|leg| match leg {
+                Leg::Transit { route_id, .. } => Some(route_id.as_str()),
+                Leg::Walk { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_plan_across_sources() {
+        // Subway A -> B, then a 3 minute walk to bus stop X, then bus X -> Y
+        let subway = timetable(
+            vec![
+                stop("A", -74.0, 40.70, vec![]),
+                stop(
+                    "B",
+                    -74.0,
+                    40.71,
+                    vec![transfer("X", Source::NjtBus, Some(180))],
+                ),
+            ],
+            vec![
+                trip("1", &[("A", time(12, 0)), ("B", time(12, 10))]),
+                trip("1", &[("A", time(12, 20)), ("B", time(12, 30))]),
+            ],
+        );
+        let bus = timetable(
+            vec![
+                stop("X", -74.0, 40.711, vec![]),
+                stop("Y", -74.0, 40.75, vec![]),
+            ],
+            vec![
+                // Leaves before the walk from B is finished
+                trip("158", &[("X", time(12, 12)), ("Y", time(12, 40))]),
+                trip("159", &[("X", time(12, 15)), ("Y", time(12, 45))]),
+            ],
+        );
+
+        let raptor = Raptor::build([(Source::MtaSubway, &subway), (Source::NjtBus, &bus)]);
+        let itineraries = raptor.plan(
+            (Source::MtaSubway, "A"),
+            (Source::NjtBus, "Y"),
+            time(11, 55),
+        );
+
+        assert_eq!(itineraries.len(), 1);
+        let itinerary = &itineraries[0];
+        assert_eq!(itinerary.transfers, 1);
+        assert_eq!(itinerary.departure, time(12, 0));
+        assert_eq!(itinerary.arrival, time(12, 45));
+        assert_eq!(transit_routes(itinerary), vec!["1", "159"]);
+
+        match &itinerary.legs[1] {
+            Leg::Walk {
+                from,
+                to,
+                duration,
+                arrival,
+                ..
+            } => {
+                assert_eq!((from.id.as_str(), to.id.as_str()), ("B", "X"));
+                assert_eq!(*duration, 180);
+                assert_eq!(*arrival, time(12, 13));
+            }
+            leg => panic!("expected a walk, got {leg:?}"),
+        }
+        match &itinerary.legs[2] {
+            Leg::Transit { wait, .. } => assert_eq!(*wait, 120),
+            leg => panic!("expected a transit leg, got {leg:?}"),
+        }
+    }
+
+    #[test]
+    fn test_plan_returns_pareto_itineraries() {
+        let source = Source::NjtBus;
+        let timetable = timetable(
+            vec![
+                stop("A", -74.0, 40.70, vec![]),
+                // Proximity transfer, so walking time comes from the ~110 m distance
+                stop("B", -74.0, 40.71, vec![transfer("C", source, None)]),
+                stop("C", -74.0, 40.711, vec![]),
+                stop("D", -74.0, 40.75, vec![]),
+            ],
+            vec![
+                // Slow one seat ride
+                trip(
+                    "1",
+                    &[("A", time(12, 0)), ("B", time(12, 10)), ("D", time(13, 0))],
+                ),
+                // Faster with a transfer
+                trip("2", &[("C", time(12, 15)), ("D", time(12, 30))]),
+            ],
+        );
+
+        let raptor = Raptor::build([(source, &timetable)]);
+        let itineraries = raptor.plan((source, "A"), (source, "D"), time(12, 0));
+
+        assert_eq!(itineraries.len(), 2);
+        assert_eq!(transit_routes(&itineraries[0]), vec!["1"]);
+        assert_eq!(itineraries[0].arrival, time(13, 0));
+        assert_eq!(transit_routes(&itineraries[1]), vec!["1", "2"]);
+        assert_eq!(itineraries[1].arrival, time(12, 30));
+        assert_eq!(itineraries[1].duration, 30 * 60);
+
+        // Nothing leaves A after the only trip
+        assert!(
+            raptor
+                .plan((source, "A"), (source, "D"), time(12, 1))
+                .is_empty()
+        );
+    }
+}
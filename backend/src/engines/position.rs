@@ -0,0 +1,295 @@
+//! Estimates where vehicles are between feed updates, along their route's line in `static.route`.
+//!
+//! Subway positions only say which stop a train is at or heading to, so trains are placed between the stop they
+//! last arrived at and the next one, based on how much of the time between the two stops has passed. Positions that
+//! do have coordinates are snapped to their route, which smooths out GPS noise. Estimates are made once per realtime
+//! update, cached next to the positions and saved to `realtime.position_estimate` for bounding box lookups. They're
+//! kept apart from `realtime.vehicle_position` so they don't end up in trip history.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use futures::future::try_join_all;
+use geo::{Distance, Geometry, Haversine, Intersects, Point, Rect};
+
+use crate::{
+    models::{
+        position::{ApiVehiclePosition, PositionData, PositionEstimate, VehiclePosition},
+        source::Source,
+    },
+    stores::position::PositionStore,
+};
+
+/// Estimate every current position of a source, then save and cache the estimates for [`estimated_positions`] and
+/// [`in_bbox`]. Called after each realtime update.
+pub async fn refresh(
+    source: Source,
+    position_store: &PositionStore,
+) -> anyhow::Result<Vec<ApiVehiclePosition>> {
+    let now = Utc::now();
+    let positions = position_store.get_all(source, None).await?;
+    let estimates = position_store.get_estimates(source, now).await?;
+    position_store.save_estimates(source, &estimates).await?;
+    let positions = apply_estimates(positions, estimates);
+    position_store.cache_estimated(source, &positions).await?;
+    Ok(positions)
+}
+
+/// A source's positions with their estimates. Current positions are read from the last realtime update's estimates,
+/// and positions at a past time are estimated for that time.
+pub async fn estimated_positions(
+    source: Source,
+    at: Option<DateTime<Utc>>,
+    position_store: &PositionStore,
+) -> anyhow::Result<Vec<ApiVehiclePosition>> {
+    match at {
+        Some(at) => {
+            let positions = position_store.get_all(source, Some(at)).await?;
+            estimate(source, positions, at, position_store).await
+        }
+        None => match position_store.get_estimated(source).await {
+            Some(positions) => Ok(positions),
+            // Until the first realtime update, or if the cache expired because updates stopped
+            None => refresh(source, position_store).await,
+        },
+    }
+}
+
+/// Move each position to where its vehicle is estimated to be at a time.
+async fn estimate(
+    source: Source,
+    positions: Vec<VehiclePosition>,
+    at: DateTime<Utc>,
+    position_store: &PositionStore,
+) -> anyhow::Result<Vec<ApiVehiclePosition>> {
+    let estimates = position_store.get_estimates(source, at).await?;
+    Ok(apply_estimates(positions, estimates))
+}
+
+/// Replace the coordinates of positions that have an estimate. The feed's bearing is used when the estimate doesn't
+/// have one.
+fn apply_estimates(
+    positions: Vec<VehiclePosition>,
+    estimates: Vec<PositionEstimate>,
+) -> Vec<ApiVehiclePosition> {
+    let mut estimates: HashMap<String, PositionEstimate> = estimates
+        .into_iter()
+        .map(|e| (e.vehicle_id.clone(), e))
+        .collect();
+
+    positions
+        .into_iter()
+        .map(|position| {
+            let estimate = estimates.remove(&position.vehicle_id);
+            with_estimate(position, estimate)
+        })
+        .collect()
+}
+
+fn with_estimate(
+    position: VehiclePosition,
+    estimate: Option<PositionEstimate>,
+) -> ApiVehiclePosition {
+    let feed_bearing = match &position.data {
+        PositionData::MtaBus(data) => Some(data.bearing),
+        PositionData::Gtfs(data) => data.bearing,
+        _ => None,
+    };
+    match estimate {
+        Some(estimate) => ApiVehiclePosition {
+            bearing: estimate.bearing.or(feed_bearing),
+            estimated: true,
+            position: VehiclePosition {
+                geom: Some(estimate.geom),
+                ..position
+            },
+            next_departures: None,
+        },
+        None => ApiVehiclePosition {
+            bearing: feed_bearing,
+            estimated: false,
+            position,
+            next_departures: None,
+        },
+    }
+}
+
+/// Positions of several sources inside a bounding box, closest to its center first. Current positions are filtered
+/// in the database by their saved estimates, so trains that only have coordinates once they're estimated are
+/// included. Positions at a past time are estimated for that time first.
+pub async fn in_bbox(
+    sources: &[Source],
+    bbox: Rect,
+    at: Option<DateTime<Utc>>,
+    position_store: &PositionStore,
+) -> anyhow::Result<Vec<ApiVehiclePosition>> {
+    let Some(at) = at else {
+        return Ok(position_store
+            .get_in_bbox(sources, bbox)
+            .await?
+            .into_iter()
+            .map(|(position, estimate)| with_estimate(position, estimate))
+            .collect());
+    };
+
+    let positions = try_join_all(
+        sources
+            .iter()
+            .map(|&source| estimated_positions(source, Some(at), position_store)),
+    )
+    .await?;
+    Ok(within_bbox(positions.into_iter().flatten().collect(), bbox))
+}
+
+/// Positions inside a bounding box, closest to its center first.
+pub fn within_bbox(positions: Vec<ApiVehiclePosition>, bbox: Rect) -> Vec<ApiVehiclePosition> {
+    let center = Point::from(bbox.center());
+    let mut positions: Vec<(f64, ApiVehiclePosition)> = positions
+        .into_iter()
+        .filter_map(|p| {
+            let point = match p.position.geom.as_ref().map(|g| &g.0) {
+                Some(Geometry::Point(point)) if bbox.intersects(point) => *point,
+                _ => return None,
+            };
+            Some((Haversine.distance(center, point), p))
+        })
+        .collect();
+    positions.sort_by(|a, b| a.0.total_cmp(&b.0));
+    positions.into_iter().map(|(_, p)| p).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::position::{MtaBusPositionData, MtaSubwayPositionData};
+    use geo::coord;
+
+    #[test]
+    fn test_apply_estimates() {
+        let now = Utc::now();
+        let position = |vehicle_id: &str, data, geom: Option<Point>| VehiclePosition {
+            vehicle_id: vehicle_id.into(),
+            trip_id: None,
+            stop_id: None,
+            updated_at: now,
+            data,
+            geom: geom.map(Into::into),
+        };
+        let train = position(
+            "1 1234+ 242/SFT",
+            PositionData::MtaSubway(MtaSubwayPositionData {
+                assigned: true,
+                status: Some("IN_TRANSIT_TO".into()),
+            }),
+            None,
+        );
+        let bus = position(
+            "MTA NYCT_7153",
+            PositionData::MtaBus(MtaBusPositionData {
+                bearing: 90.0,
+                passengers: None,
+                capacity: None,
+                status: None,
+                phase: None,
+            }),
+            Some(Point::new(-73.95, 40.65)),
+        );
+        let unmatched = position("MTA NYCT_8000", bus.data.clone(), None);
+        let estimates = vec![
+            PositionEstimate {
+                vehicle_id: train.vehicle_id.clone(),
+                geom: Point::new(-73.98, 40.75).into(),
+                bearing: Some(15.0),
+            },
+            PositionEstimate {
+                vehicle_id: bus.vehicle_id.clone(),
+                geom: Point::new(-73.9501, 40.65).into(),
+                bearing: None,
+            },
+        ];
+
+        let positions = apply_estimates(vec![train, bus, unmatched], estimates);
+        let point = |p: &ApiVehiclePosition| match p.position.geom.as_ref().map(|g| &g.0) {
+            Some(Geometry::Point(point)) => Some(point.x_y()),
+            _ => None,
+        };
+
+        assert!(positions[0].estimated);
+        assert_eq!(point(&positions[0]), Some((-73.98, 40.75)));
+        assert_eq!(positions[0].bearing, Some(15.0));
+
+        // Snapped positions keep the feed's bearing
+        assert!(positions[1].estimated);
+        assert_eq!(point(&positions[1]), Some((-73.9501, 40.65)));
+        assert_eq!(positions[1].bearing, Some(90.0));
+
+        assert!(!positions[2].estimated);
+        assert_eq!(point(&positions[2]), None);
+        assert_eq!(positions[2].bearing, Some(90.0));
+    }
+
+    #[test]
+    fn test_estimated_positions_cache_round_trip() {
+        let position = ApiVehiclePosition {
+            position: VehiclePosition {
+                vehicle_id: "MTA NYCT_7153".into(),
+                trip_id: None,
+                stop_id: Some("401921".into()),
+                updated_at: Utc::now(),
+                data: PositionData::MtaBus(MtaBusPositionData {
+                    bearing: 90.0,
+                    passengers: Some(12),
+                    capacity: None,
+                    status: None,
+                    phase: None,
+                }),
+                geom: Some(Point::new(-73.95, 40.65).into()),
+            },
+            estimated: true,
+            bearing: Some(45.0),
+            next_departures: None,
+        };
+
+        let json = serde_json::to_string(&[position]).unwrap();
+        let cached: Vec<ApiVehiclePosition> = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&cached).unwrap(), json);
+    }
+
+    #[test]
+    fn test_within_bbox() {
+        let position = |vehicle_id: &str, lon: f64, lat: f64| ApiVehiclePosition {
+            position: VehiclePosition {
+                vehicle_id: vehicle_id.into(),
+                trip_id: None,
+                stop_id: None,
+                updated_at: Utc::now(),
+                data: PositionData::MtaSubway(MtaSubwayPositionData {
+                    assigned: true,
+                    status: None,
+                }),
+                geom: Some(Point::new(lon, lat).into()),
+            },
+            estimated: true,
+            bearing: None,
+            next_departures: None,
+        };
+        let mut no_geom = position("no geom", 0.0, 0.0);
+        no_geom.position.geom = None;
+        let positions = vec![
+            position("edge", -73.99, 40.75),
+            position("outside", -73.90, 40.75),
+            position("center", -73.98, 40.75),
+            no_geom,
+        ];
+
+        let bbox = Rect::new(
+            coord! { x: -73.97, y: 40.74 },
+            coord! { x: -73.99, y: 40.76 },
+        );
+        let ids: Vec<_> = within_bbox(positions, bbox)
+            .into_iter()
+            .map(|p| p.position.vehicle_id)
+            .collect();
+        assert_eq!(ids, ["center", "edge"]);
+    }
+}
@@ -0,0 +1,221 @@
+//! Predicts bus arrivals when the realtime feed's own predictions are missing or stale.
+//!
+//! Vehicles are projected onto their route's line to find how far along they are between two stops. Arrivals at
+//! the stops ahead are then predicted from the median travel time between each pair of consecutive stops, which is
+//! measured from trip history points every few hours. Predicted stop times are flagged with
+//! [`PredictedBy::Backend`] so clients can tell them apart from the feed's.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Duration, Utc};
+use tracing::{debug, error, info};
+
+use crate::{
+    models::{
+        prediction::PredictionStop,
+        source::Source,
+        trip::{MtaBusStopTimeData, PredictedBy, StopTime, StopTimeData, StopTimeStatus},
+    },
+    stores::{history::HistoryStore, stop_time::StopTimeStore},
+};
+
+/// Sources that the backend predicts arrivals for
+pub const PREDICTED_SOURCES: [Source; 1] = [Source::MtaBus];
+
+/// How often segment travel times are recomputed
+const REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(6 * 60 * 60);
+/// How far back trip history points are used for segment travel times
+const HISTORY_PERIOD: Duration = Duration::days(7);
+/// Speed in meters per second for segments that don't have a travel time yet, which is about how fast NYC buses go
+const FALLBACK_SPEED: f64 = 4.0;
+
+/// Periodically recompute segment travel times for every predicted source.
+pub async fn run(history_store: &HistoryStore) {
+    let history_store = history_store.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            for source in PREDICTED_SOURCES {
+                let now = Utc::now();
+                match history_store
+                    .refresh_segment_travel_times(source, now - HISTORY_PERIOD, now)
+                    .await
+                {
+                    Ok(segments) => {
+                        info!("Updated {} segment travel times for {:?}", segments, source)
+                    }
+                    Err(e) => error!("Segment travel time error for {:?}: {:#}", source, e),
+                }
+            }
+        }
+    });
+}
+
+/// Predict arrivals for every trip of a source that needs them. Called after each realtime update.
+pub async fn predict(
+    source: Source,
+    stop_time_store: &StopTimeStore,
+    history_store: &HistoryStore,
+) -> anyhow::Result<()> {
+    // Sources need a way to flag predictions from the backend before they can be predicted
+    let data = match source {
+        Source::MtaBus => StopTimeData::MtaBus(MtaBusStopTimeData {
+            predicted_by: PredictedBy::Backend,
+        }),
+        _ => return Ok(()),
+    };
+
+    let now = Utc::now();
+    let stops = stop_time_store.get_prediction_stops(source, now).await?;
+
+    let route_ids: Vec<String> = stops
+        .iter()
+        .map(|s| s.route_id.clone())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let travel_times: HashMap<_, f64> = history_store
+        .get_segment_travel_times(source, &route_ids)
+        .await?
+        .into_iter()
+        .map(|s| {
+            let key = (s.route_id, s.direction, s.from_stop_id, s.to_stop_id);
+            (key, s.travel_time as f64)
+        })
+        .collect();
+
+    let mut stop_times = Vec::new();
+    for trip_stops in stops.chunk_by(|a, b| a.trip_id == b.trip_id) {
+        let travel_time = |from: &PredictionStop, to: &PredictionStop| {
+            let key = (
+                to.route_id.clone(),
+                to.direction,
+                from.stop_id.clone(),
+                to.stop_id.clone(),
+            );
+            travel_times.get(&key).copied()
+        };
+        stop_times.extend(predict_trip(trip_stops, travel_time).into_iter().map(
+            |(stop_id, arrival)| StopTime {
+                trip_id: trip_stops[0].trip_id,
+                stop_id,
+                arrival,
+                departure: arrival,
+                status: StopTimeStatus::Scheduled,
+                data: data.clone(),
+            },
+        ));
+    }
+
+    let saved = stop_time_store
+        .save_predictions(source, &stop_times, now)
+        .await?;
+    debug!("Predicted {} stop times for {:?}", saved, source);
+
+    Ok(())
+}
+
+/// Predict arrivals at the stops ahead of a trip's vehicle. Stops are in route order, but the route line can be
+/// drawn in either direction.
+fn predict_trip(
+    stops: &[PredictionStop],
+    travel_time: impl Fn(&PredictionStop, &PredictionStop) -> Option<f64>,
+) -> Vec<(String, DateTime<Utc>)> {
+    let (Some(first), Some(last)) = (stops.first(), stops.last()) else {
+        return vec![];
+    };
+    // Flip distances so they increase in the direction of travel
+    let sign = if last.stop_distance >= first.stop_distance {
+        1.0
+    } else {
+        -1.0
+    };
+    let vehicle = first.vehicle_distance * sign;
+    let Some(next) = stops.iter().position(|s| s.stop_distance * sign > vehicle) else {
+        return vec![];
+    };
+
+    let mut arrival = first.position_updated_at;
+    let mut predictions = Vec::new();
+    for (i, to) in stops.iter().enumerate().skip(next) {
+        let remaining = to.stop_distance * sign - vehicle;
+        let seconds = match i.checked_sub(1).map(|p| &stops[p]) {
+            Some(from) => {
+                let length = (to.stop_distance - from.stop_distance) * sign;
+                // Only part of the segment the vehicle is on is left
+                let share = if i == next {
+                    (remaining / length).clamp(0.0, 1.0)
+                } else {
+                    1.0
+                };
+                match travel_time(from, to) {
+                    Some(time) if length > 0.0 => time * share,
+                    _ => length.max(0.0) * share / FALLBACK_SPEED,
+                }
+            }
+            // The vehicle hasn't reached the first stop yet
+            None => remaining / FALLBACK_SPEED,
+        };
+        arrival += Duration::milliseconds((seconds * 1000.0) as i64);
+        predictions.push((to.stop_id.clone(), arrival));
+    }
+
+    predictions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_predict_trip() {
+        let now = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap();
+        let trip_id = Uuid::now_v7();
+        let stop = |stop_id: &str, stop_sequence, stop_distance, vehicle_distance| PredictionStop {
+            trip_id,
+            route_id: "B46".into(),
+            direction: 0,
+            position_updated_at: now,
+            vehicle_distance,
+            stop_id: stop_id.into(),
+            stop_sequence,
+            stop_distance,
+        };
+        let travel_time = |from: &PredictionStop, to: &PredictionStop| match (
+            from.stop_id.as_str(),
+            to.stop_id.as_str(),
+        ) {
+            ("A", "B") => Some(100.0),
+            ("B", "C") => Some(60.0),
+            _ => None,
+        };
+
+        // Halfway between A and B
+        let stops = [
+            stop("A", 1, 0.0, 250.0),
+            stop("B", 2, 500.0, 250.0),
+            stop("C", 3, 1000.0, 250.0),
+            stop("D", 4, 1400.0, 250.0),
+        ];
+        let expected = vec![
+            ("B".to_owned(), now + Duration::seconds(50)),
+            ("C".to_owned(), now + Duration::seconds(110)),
+            // No travel time, so 400 m at the fallback speed
+            ("D".to_owned(), now + Duration::seconds(210)),
+        ];
+        assert_eq!(predict_trip(&stops, travel_time), expected);
+
+        // Same trip on a line drawn in the opposite direction
+        let reversed = [
+            stop("A", 1, 1400.0, 1150.0),
+            stop("B", 2, 900.0, 1150.0),
+            stop("C", 3, 400.0, 1150.0),
+            stop("D", 4, 0.0, 1150.0),
+        ];
+        assert_eq!(predict_trip(&reversed, travel_time), expected);
+    }
+}
@@ -0,0 +1,368 @@
+//! Pushes realtime changes to websocket and SSE clients.
+//!
+//! The stores publish what each upsert actually changed, and every connection filters those updates with its own
+//! [`Subscription`] before sending them, so clients don't have to poll the realtime endpoints.
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, OnceLock},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    models::{
+        position::VehiclePosition,
+        source::{Source, SourceId},
+        trip::{StopTime, Trip},
+    },
+    stores::alert::ApiAlert,
+};
+
+/// Number of updates a slow client can fall behind before it starts missing them
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Clone, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Update {
+    /// Trips that are new, or whose data or stop times changed, with the stop times that changed
+    Trips {
+        source: Source,
+        trips: Vec<Trip>,
+        stop_times: Vec<StopTime>,
+    },
+    /// Vehicles that are new, or whose trip, stop, position or data changed
+    Positions {
+        source: Source,
+        positions: Vec<VehiclePosition>,
+    },
+    /// Alerts that are new or changed. Changed alerts that are no longer active are listed in `removed`.
+    Alerts {
+        source: Source,
+        alerts: Vec<ApiAlert>,
+        removed: Vec<Uuid>,
+    },
+}
+
+impl Update {
+    pub fn source(&self) -> Source {
+        match self {
+            Update::Trips { source, .. }
+            | Update::Positions { source, .. }
+            | Update::Alerts { source, .. } => *source,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Update::Trips {
+                trips, stop_times, ..
+            } => trips.is_empty() && stop_times.is_empty(),
+            Update::Positions { positions, .. } => positions.is_empty(),
+            Update::Alerts {
+                alerts, removed, ..
+            } => alerts.is_empty() && removed.is_empty(),
+        }
+    }
+
+    /// Returns only the parts of the update that the subscription covers, or `None` if there aren't any.
+    pub fn filter(&self, subscription: &Subscription) -> Option<Update> {
+        let source = self.source();
+        if subscription.sources.contains(&source) {
+            return Some(self.clone());
+        }
+
+        let filtered = match self {
+            Update::Trips {
+                trips, stop_times, ..
+            } => {
+                let mut trip_ids: HashSet<Uuid> = trips
+                    .iter()
+                    .filter(|t| {
+                        subscription.trips.contains(&t.id)
+                            || subscription.has_route(source, &t.route_id)
+                    })
+                    .map(|t| t.id)
+                    .collect();
+                let stop_times: Vec<StopTime> = stop_times
+                    .iter()
+                    .filter(|st| {
+                        trip_ids.contains(&st.trip_id) || subscription.has_stop(source, &st.stop_id)
+                    })
+                    .cloned()
+                    .collect();
+                // Stop subscribers also get the trips serving their stops
+                trip_ids.extend(stop_times.iter().map(|st| st.trip_id));
+
+                Update::Trips {
+                    source,
+                    trips: trips
+                        .iter()
+                        .filter(|t| trip_ids.contains(&t.id))
+                        .cloned()
+                        .collect(),
+                    stop_times,
+                }
+            }
+            Update::Positions { positions, .. } => Update::Positions {
+                source,
+                positions: positions
+                    .iter()
+                    .filter(|p| {
+                        p.trip_id.is_some_and(|id| subscription.trips.contains(&id))
+                            || p.stop_id
+                                .as_ref()
+                                .is_some_and(|id| subscription.has_stop(source, id))
+                    })
+                    .cloned()
+                    .collect(),
+            },
+            Update::Alerts {
+                alerts, removed, ..
+            } => {
+                let alerts: Vec<ApiAlert> = alerts
+                    .iter()
+                    .filter(|a| {
+                        a.entities.iter().any(|e| {
+                            subscription.has_route(source, &e.route_id)
+                                || e.stop_id
+                                    .as_ref()
+                                    .is_some_and(|id| subscription.has_stop(source, id))
+                        })
+                    })
+                    .cloned()
+                    .collect();
+                // Removed alerts don't have entities anymore, so send them to anyone following the source
+                let follows_source = subscription
+                    .routes
+                    .iter()
+                    .chain(&subscription.stops)
+                    .any(|r| r.source == source);
+                Update::Alerts {
+                    source,
+                    alerts,
+                    removed: if follows_source {
+                        removed.clone()
+                    } else {
+                        vec![]
+                    },
+                }
+            }
+        };
+
+        (!filtered.is_empty()).then_some(filtered)
+    }
+}
+
+/// What a client wants updates for. Anything matching at least one entry is sent.
+#[derive(Deserialize, Default, Debug, Clone)]
+pub struct Subscription {
+    /// Every update for these sources
+    #[serde(default)]
+    pub sources: HashSet<Source>,
+    /// Trips on these routes, and alerts affecting them
+    #[serde(default)]
+    pub routes: HashSet<SourceId>,
+    /// Stop times and vehicles at these stops, and alerts affecting them
+    #[serde(default)]
+    pub stops: HashSet<SourceId>,
+    /// These trips, their stop times and vehicles
+    #[serde(default)]
+    pub trips: HashSet<Uuid>,
+}
+
+impl Subscription {
+    /// Route and stop IDs are saved uppercase, so subscriptions are too
+    pub fn normalized(self) -> Self {
+        let uppercase = |ids: HashSet<SourceId>| {
+            ids.into_iter()
+                .map(|s| SourceId {
+                    source: s.source,
+                    id: s.id.to_uppercase(),
+                })
+                .collect()
+        };
+        Self {
+            routes: uppercase(self.routes),
+            stops: uppercase(self.stops),
+            ..self
+        }
+    }
+
+    fn has_route(&self, source: Source, route_id: &str) -> bool {
+        !self.routes.is_empty()
+            && self.routes.contains(&SourceId {
+                source,
+                id: route_id.to_owned(),
+            })
+    }
+
+    fn has_stop(&self, source: Source, stop_id: &str) -> bool {
+        !self.stops.is_empty()
+            && self.stops.contains(&SourceId {
+                source,
+                id: stop_id.to_owned(),
+            })
+    }
+}
+
+fn sender() -> &'static broadcast::Sender<Arc<Update>> {
+    static SENDER: OnceLock<broadcast::Sender<Arc<Update>>> = OnceLock::new();
+    SENDER.get_or_init(|| broadcast::channel(CHANNEL_CAPACITY).0)
+}
+
+/// Sends an update to every connected client. Empty updates are dropped.
+pub fn publish(update: Update) {
+    if update.is_empty() {
+        return;
+    }
+    // Sending only fails when nobody is connected
+    let _ = sender().send(Arc::new(update));
+}
+
+pub fn subscribe() -> broadcast::Receiver<Arc<Update>> {
+    sender().subscribe()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::trip::{GtfsTripData, StopTimeData, StopTimeStatus, TripData, TripStatus};
+    use crate::stores::alert::ApiAlertEntity;
+    use chrono::Utc;
+
+    fn trip(route_id: &str) -> Trip {
+        Trip {
+            id: Uuid::now_v7(),
+            original_id: route_id.into(),
+            vehicle_id: Some(route_id.into()),
+            route_id: route_id.into(),
+            direction: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            status: TripStatus::Scheduled,
+            ghost_confidence: None,
+            data: TripData::Gtfs(GtfsTripData {
+                headsign: String::new(),
+                delay: None,
+            }),
+        }
+    }
+
+    fn stop_time(trip: &Trip, stop_id: &str) -> StopTime {
+        StopTime {
+            trip_id: trip.id,
+            stop_id: stop_id.into(),
+            arrival: Utc::now(),
+            departure: Utc::now(),
+            status: StopTimeStatus::Scheduled,
+            data: StopTimeData::Gtfs,
+        }
+    }
+
+    fn subscription(json: serde_json::Value) -> Subscription {
+        serde_json::from_value::<Subscription>(json)
+            .unwrap()
+            .normalized()
+    }
+
+    #[test]
+    fn test_filter_trips() {
+        let m15 = trip("M15");
+        let b63 = trip("B63");
+        let update = Update::Trips {
+            source: Source::MtaBus,
+            trips: vec![m15.clone(), b63.clone()],
+            stop_times: vec![
+                stop_time(&m15, "400001"),
+                stop_time(&m15, "400002"),
+                stop_time(&b63, "300001"),
+            ],
+        };
+
+        let Some(Update::Trips {
+            trips, stop_times, ..
+        }) = update.filter(&subscription(
+            serde_json::json!({ "routes": ["mta_bus:m15"] }),
+        ))
+        else {
+            panic!("route subscription should match");
+        };
+        assert_eq!(trips, vec![m15.clone()]);
+        assert_eq!(stop_times.len(), 2);
+
+        // Stop subscribers get the stop time and the trip serving it
+        let Some(Update::Trips {
+            trips, stop_times, ..
+        }) = update.filter(&subscription(
+            serde_json::json!({ "stops": ["mta_bus:300001"] }),
+        ))
+        else {
+            panic!("stop subscription should match");
+        };
+        assert_eq!(trips, vec![b63]);
+        assert_eq!(stop_times.len(), 1);
+
+        // Same route ID in a different source
+        assert!(
+            update
+                .filter(&subscription(
+                    serde_json::json!({ "routes": ["njt_bus:M15"] })
+                ))
+                .is_none()
+        );
+        assert!(
+            update
+                .filter(&subscription(serde_json::json!({ "sources": ["mta_bus"] })))
+                .is_some()
+        );
+        assert!(update.filter(&Subscription::default()).is_none());
+    }
+
+    #[test]
+    fn test_filter_alerts() {
+        let alert = ApiAlert {
+            id: Uuid::now_v7(),
+            original_id: "lmm:alert:1".into(),
+            data: crate::models::alert::AlertData::Gtfs,
+            translations: vec![],
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            start_time: Utc::now(),
+            end_time: None,
+            entities: vec![ApiAlertEntity {
+                route_id: "A".into(),
+                sort_order: 1,
+                stop_id: Some("A02".into()),
+            }],
+        };
+        let removed = Uuid::now_v7();
+        let update = Update::Alerts {
+            source: Source::MtaSubway,
+            alerts: vec![alert],
+            removed: vec![removed],
+        };
+
+        let Some(Update::Alerts {
+            alerts, removed: r, ..
+        }) = update.filter(&subscription(
+            serde_json::json!({ "stops": ["mta_subway:A02"] }),
+        ))
+        else {
+            panic!("stop subscription should match");
+        };
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(r, vec![removed]);
+
+        // Only the removed alert is sent to subscribers of other routes in the source
+        let Some(Update::Alerts { alerts, .. }) = update.filter(&subscription(
+            serde_json::json!({ "routes": ["mta_subway:C"] }),
+        )) else {
+            panic!("removed alerts should be sent");
+        };
+        assert!(alerts.is_empty());
+    }
+}
@@ -1,19 +1,31 @@
+use crate::engines::archive;
+use crate::engines::ghost;
+use crate::engines::planner::Planner;
+use crate::engines::position;
+use crate::engines::prediction;
 use crate::engines::static_data::StaticController;
+use crate::engines::status;
 use crate::sources::RealtimeAdapter;
+use crate::stores::history::HistoryStore;
 use crate::stores::position::PositionStore;
 use crate::stores::static_cache::StaticCacheStore;
+use crate::stores::stop::StopStore;
 use crate::stores::stop_time::StopTimeStore;
 use crate::stores::trip::TripStore;
 use std::sync::Arc;
 use tokio::time::sleep;
 use tracing::error;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     // pool: &PgPool,
     trip_store: &TripStore,
     stop_time_store: &StopTimeStore,
     position_store: &PositionStore,
     static_cache_store: &StaticCacheStore,
+    stop_store: &StopStore,
+    history_store: &HistoryStore,
+    planner: &Planner,
     adapters: Vec<Arc<dyn RealtimeAdapter>>,
     static_controller: StaticController,
 ) {
@@ -23,25 +35,67 @@ pub async fn run(
         let stop_time_store = stop_time_store.clone();
         let position_store = position_store.clone();
         let static_cache_store = static_cache_store.clone();
+        let stop_store = stop_store.clone();
+        let history_store = history_store.clone();
+        let planner = planner.clone();
 
-        tokio::spawn(async move {
+        let source = adapter.source();
+        status::register(source);
+
+        let pipeline = tokio::spawn(async move {
             loop {
-                let source = adapter.source();
-                if let Err(e) = adapter
-                    .run(&controller, &static_cache_store, &trip_store, &position_store)
-                    .await
-                {
+                let result = adapter
+                    .run(
+                        &controller,
+                        &static_cache_store,
+                        &trip_store,
+                        &position_store,
+                    )
+                    .await;
+                if let Err(e) = &result {
                     error!("Realtime pipeline error for {:?}: {}", source, e);
                 }
+                status::record_run(source, &result);
+                if let Err(e) = position::refresh(source, &position_store).await {
+                    error!("Position estimate error for {:?}: {}", source, e);
+                }
+                if prediction::PREDICTED_SOURCES.contains(&source)
+                    && let Err(e) =
+                        prediction::predict(source, &stop_time_store, &history_store).await
+                {
+                    error!("Arrival prediction error for {:?}: {}", source, e);
+                }
                 // TODO: use stop time store to save stop times (instead of trip store)
                 // then we don't have to add this separate cache population step just for stop times
                 // Populate stop times cache after trips are committed
                 if let Err(e) = stop_time_store.populate_cache(source).await {
                     error!("Stop time cache populate error for {:?}: {}", source, e);
                 }
+                if let Err(e) = planner
+                    .refresh(source, &trip_store, &stop_time_store, &stop_store)
+                    .await
+                {
+                    error!("Planner refresh error for {:?}: {}", source, e);
+                }
+                if let Err(e) = archive::archive(source, &history_store, &static_cache_store).await
+                {
+                    error!("Stop time archive error for {:?}: {}", source, e);
+                }
+                if let Err(e) = ghost::detect(source, &trip_store, &history_store).await {
+                    error!("Ghost detection error for {:?}: {}", source, e);
+                }
 
                 sleep(adapter.refresh_interval()).await;
             }
         });
+        // The loop only ends by panicking. The panic itself is logged by the panic hook.
+        tokio::spawn(async move {
+            if let Err(e) = pipeline.await
+                && e.is_panic()
+            {
+                error!("Realtime pipeline for {:?} panicked and stopped", source);
+                status::record_panic(source);
+            }
+        });
     }
 }
@@ -0,0 +1,515 @@
+//! Prunes old realtime data so the database doesn't grow forever.
+//!
+//! Pruning is opt-in: it only runs when `RETENTION_CONFIG` is set, and only tables with a retention period in the
+//! config are pruned. Each table's period can be overridden per source. Rows are deleted in batches so
+//! the realtime pipelines aren't blocked for long, and each batch can be exported to a gzipped CSV file first.
+//! The delete and the export happen in the same transaction, so rows are only deleted once they've been written.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::Instant,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use flate2::{Compression, write::GzEncoder};
+use futures::TryStreamExt;
+use serde::Deserialize;
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::{metrics, models::source::Source};
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// How often to prune, in seconds
+    pub interval_secs: u64,
+    /// Maximum rows deleted by one statement
+    pub batch_size: u32,
+    /// Directory that pruned rows are exported to as gzipped CSV. Rows aren't exported if this isn't set.
+    pub export_dir: Option<PathBuf>,
+    pub trip_history_point: TableRetention,
+    pub stop_time: TableRetention,
+    pub trip: TableRetention,
+    pub alert: TableRetention,
+    pub stop_time_observation: TableRetention,
+    pub ghost_sample: TableRetention,
+    pub segment_travel_time: TableRetention,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 60 * 60,
+            batch_size: 10_000,
+            export_dir: None,
+            trip_history_point: TableRetention::default(),
+            stop_time: TableRetention::default(),
+            trip: TableRetention::default(),
+            alert: TableRetention::default(),
+            stop_time_observation: TableRetention::default(),
+            ghost_sample: TableRetention::default(),
+            segment_travel_time: TableRetention::default(),
+        }
+    }
+}
+
+/// How long a table's rows are kept. Rows are kept forever if there's no period or it is 0 days.
+#[derive(Debug, Default, Deserialize)]
+pub struct TableRetention {
+    /// Days to keep rows for
+    pub days: Option<u32>,
+    /// Days to keep rows for specific sources, by source id
+    #[serde(default)]
+    pub sources: HashMap<String, u32>,
+}
+
+impl TableRetention {
+    /// Days to keep the source's rows for, or `None` if they are kept forever
+    fn days_for(&self, source: Source) -> Option<u32> {
+        self.sources
+            .get(source.as_str())
+            .copied()
+            .or(self.days)
+            .filter(|&days| days > 0)
+    }
+}
+
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<RetentionConfig> {
+    let path = path.as_ref();
+    let raw = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read retention config {}", path.display()))?;
+    toml::from_str(&raw)
+        .with_context(|| format!("Failed to parse retention config {}", path.display()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Table {
+    TripHistoryPoint,
+    StopTime,
+    Trip,
+    Alert,
+    StopTimeObservation,
+    GhostSample,
+    SegmentTravelTime,
+}
+
+impl Table {
+    /// Children are pruned before their parents, so trips are only deleted after their stop times and points are
+    const ALL: [Table; 7] = [
+        Table::TripHistoryPoint,
+        Table::StopTime,
+        Table::Trip,
+        Table::Alert,
+        Table::StopTimeObservation,
+        Table::GhostSample,
+        Table::SegmentTravelTime,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Table::TripHistoryPoint => "trip_history_point",
+            Table::StopTime => "stop_time",
+            Table::Trip => "trip",
+            Table::Alert => "alert",
+            Table::StopTimeObservation => "stop_time_observation",
+            Table::GhostSample => "ghost_sample",
+            Table::SegmentTravelTime => "segment_travel_time",
+        }
+    }
+
+    fn retention<'a>(&self, config: &'a RetentionConfig) -> &'a TableRetention {
+        match self {
+            Table::TripHistoryPoint => &config.trip_history_point,
+            Table::StopTime => &config.stop_time,
+            Table::Trip => &config.trip,
+            Table::Alert => &config.alert,
+            Table::StopTimeObservation => &config.stop_time_observation,
+            Table::GhostSample => &config.ghost_sample,
+            Table::SegmentTravelTime => &config.segment_travel_time,
+        }
+    }
+
+    /// A `deleted` CTE that deletes up to `limit` rows of a source older than a cutoff. Select from it with
+    /// [`Table::select_deleted`].
+    ///
+    /// `source` and `cutoff` are SQL expressions: bind parameters like `$1`, or [`literal`]s for COPY, which doesn't
+    /// support bind parameters.
+    fn delete_query(&self, source: &str, cutoff: &str, limit: u32) -> String {
+        match self {
+            Table::TripHistoryPoint => format!(
+                r#"
+                WITH deleted AS (
+                    DELETE FROM realtime.trip_history_point
+                    WHERE id IN (
+                        SELECT p.id
+                        FROM realtime.trip_history_point p
+                        JOIN realtime.trip t ON t.id = p.trip_id
+                        WHERE t.source = {source} AND p.recorded_at < {cutoff}
+                        LIMIT {limit}
+                    )
+                    RETURNING id, trip_id, ST_AsText(geom) AS geom, recorded_at
+                )"#
+            ),
+            Table::StopTime => format!(
+                r#"
+                WITH deleted AS (
+                    DELETE FROM realtime.stop_time
+                    WHERE (trip_id, stop_id, source) IN (
+                        SELECT trip_id, stop_id, source
+                        FROM realtime.stop_time
+                        WHERE source = {source} AND departure < {cutoff}
+                        LIMIT {limit}
+                    )
+                    RETURNING *
+                )"#
+            ),
+            // Deleting a trip cascades to its stop times and history points, so only trips that don't have any
+            // left are deleted. Otherwise they would be deleted without being exported.
+            Table::Trip => format!(
+                r#"
+                WITH deleted AS (
+                    DELETE FROM realtime.trip
+                    WHERE id IN (
+                        SELECT t.id
+                        FROM realtime.trip t
+                        WHERE
+                            t.source = {source}
+                            AND t.updated_at < {cutoff}
+                            AND NOT EXISTS (SELECT 1 FROM realtime.stop_time st WHERE st.trip_id = t.id)
+                            AND NOT EXISTS (SELECT 1 FROM realtime.trip_history_point p WHERE p.trip_id = t.id)
+                        LIMIT {limit}
+                    )
+                    RETURNING *
+                )"#
+            ),
+            // Alerts are deleted once they haven't been in the feed and none of their active periods have run since
+            // the cutoff. Their translations, active periods and entities are deleted by cascade.
+            Table::Alert => format!(
+                r#"
+                WITH deleted AS (
+                    DELETE FROM realtime.alert
+                    WHERE id IN (
+                        SELECT a.id
+                        FROM realtime.alert a
+                        WHERE
+                            a.source = {source}
+                            AND a.updated_at < {cutoff}
+                            AND NOT EXISTS (
+                                SELECT 1
+                                FROM realtime.active_period ap
+                                WHERE ap.alert_id = a.id AND (ap.end_time IS NULL OR ap.end_time >= {cutoff})
+                            )
+                        LIMIT {limit}
+                    )
+                    RETURNING *
+                )"#
+            ),
+            Table::StopTimeObservation => format!(
+                r#"
+                WITH deleted AS (
+                    DELETE FROM history.stop_time_observation
+                    WHERE (trip_id, stop_id, source) IN (
+                        SELECT trip_id, stop_id, source
+                        FROM history.stop_time_observation
+                        WHERE source = {source} AND arrival < {cutoff}
+                        LIMIT {limit}
+                    )
+                    RETURNING *
+                )"#
+            ),
+            Table::GhostSample => format!(
+                r#"
+                WITH deleted AS (
+                    DELETE FROM history.ghost_sample
+                    WHERE (source, route_id, recorded_at) IN (
+                        SELECT source, route_id, recorded_at
+                        FROM history.ghost_sample
+                        WHERE source = {source} AND recorded_at < {cutoff}
+                        LIMIT {limit}
+                    )
+                    RETURNING *
+                )"#
+            ),
+            // Segments that haven't been measured since the cutoff, like ones on routes that changed
+            Table::SegmentTravelTime => format!(
+                r#"
+                WITH deleted AS (
+                    DELETE FROM history.segment_travel_time
+                    WHERE (source, route_id, direction, from_stop_id, to_stop_id) IN (
+                        SELECT source, route_id, direction, from_stop_id, to_stop_id
+                        FROM history.segment_travel_time
+                        WHERE source = {source} AND updated_at < {cutoff}
+                        LIMIT {limit}
+                    )
+                    RETURNING *
+                )"#
+            ),
+        }
+    }
+
+    /// Selects the rows deleted by [`Table::delete_query`] for exporting
+    fn select_deleted(&self) -> &'static str {
+        match self {
+            // Alerts are exported with the rows deleted by cascade. The select uses the snapshot from before the
+            // delete, so they are still visible.
+            Table::Alert => {
+                r#"
+                SELECT
+                    d.*,
+                    (SELECT jsonb_agg(to_jsonb(tr) - 'alert_id') FROM realtime.alert_translation tr WHERE tr.alert_id = d.id) AS translations,
+                    (SELECT jsonb_agg(to_jsonb(ap) - 'alert_id') FROM realtime.active_period ap WHERE ap.alert_id = d.id) AS active_periods,
+                    (SELECT jsonb_agg(to_jsonb(ae) - 'alert_id') FROM realtime.affected_entity ae WHERE ae.alert_id = d.id) AS entities
+                FROM deleted d"#
+            }
+            _ => "SELECT * FROM deleted",
+        }
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Totals for one table and source since startup
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PruneStats {
+    pub rows_deleted: u64,
+    pub bytes_exported: u64,
+    pub last_run: Option<DateTime<Utc>>,
+    /// Seconds the last run took
+    pub last_duration: f64,
+}
+
+fn stats_map() -> &'static Mutex<HashMap<(Table, Source), PruneStats>> {
+    static STATS: OnceLock<Mutex<HashMap<(Table, Source), PruneStats>>> = OnceLock::new();
+    STATS.get_or_init(Default::default)
+}
+
+/// Pruning totals for every table and source that has been pruned
+pub fn stats() -> HashMap<(Table, Source), PruneStats> {
+    stats_map().lock().unwrap().clone()
+}
+
+pub async fn run(pool: &PgPool, config: RetentionConfig) {
+    for id in Table::ALL
+        .iter()
+        .flat_map(|t| t.retention(&config).sources.keys())
+    {
+        if Source::from_id(id).is_none() {
+            tracing::warn!("Retention config has a period for unknown source {}", id);
+        }
+    }
+
+    let pool = pool.clone();
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(config.interval_secs));
+        loop {
+            interval.tick().await;
+
+            for table in Table::ALL {
+                for source in Source::all() {
+                    let Some(days) = table.retention(&config).days_for(source) else {
+                        continue;
+                    };
+                    let cutoff = Utc::now() - Duration::days(days.into());
+
+                    if let Err(e) = prune(&pool, &config, table, source, cutoff).await {
+                        error!("Retention error for {} {:?}: {:#}", table, source, e);
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Delete every row of a table older than `cutoff`, one batch at a time
+async fn prune(
+    pool: &PgPool,
+    config: &RetentionConfig,
+    table: Table,
+    source: Source,
+    cutoff: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let mut rows_deleted = 0;
+    let mut bytes_exported = 0;
+
+    for batch in 0.. {
+        let deleted = match &config.export_dir {
+            Some(dir) => {
+                let query = table.delete_query(
+                    &literal(source.as_str(), "source_enum"),
+                    &literal(
+                        &cutoff.to_rfc3339_opts(SecondsFormat::Secs, true),
+                        "timestamptz",
+                    ),
+                    config.batch_size,
+                );
+                let path = dir.join(table.as_str()).join(source.as_str()).join(format!(
+                    "{}-{batch}.csv.gz",
+                    cutoff.format("%Y%m%dT%H%M%SZ")
+                ));
+                let query = format!("{query} {}", table.select_deleted());
+                let (rows, bytes) = delete_and_export(pool, &query, path).await?;
+                bytes_exported += bytes;
+                rows
+            }
+            None => {
+                let query = table.delete_query("$1", "$2", config.batch_size);
+                sqlx::query_scalar::<_, i64>(&format!("{query} SELECT COUNT(*) FROM deleted"))
+                    .bind(source)
+                    .bind(cutoff)
+                    .fetch_one(pool)
+                    .await? as u64
+            }
+        };
+
+        rows_deleted += deleted;
+        if deleted < config.batch_size as u64 {
+            break;
+        }
+    }
+
+    let duration = start.elapsed().as_secs_f64();
+    if rows_deleted > 0 {
+        info!(
+            table = table.as_str(),
+            source = source.as_str(),
+            rows_deleted,
+            bytes_exported,
+            duration,
+            "Pruned rows older than {}",
+            cutoff
+        );
+    }
+
+    let labels = [("table", table.as_str()), ("source", source.as_str())];
+    metrics::counter!(metrics::RETENTION_ROWS_DELETED, &labels).increment(rows_deleted);
+    metrics::counter!(metrics::RETENTION_BYTES_EXPORTED, &labels).increment(bytes_exported);
+
+    let mut stats = stats_map().lock().unwrap();
+    let stats = stats.entry((table, source)).or_default();
+    stats.rows_deleted += rows_deleted;
+    stats.bytes_exported += bytes_exported;
+    stats.last_run = Some(Utc::now());
+    stats.last_duration = duration;
+
+    Ok(())
+}
+
+/// Run a delete query with COPY and write the deleted rows to `path`. The transaction is only committed after the
+/// file is written. Returns the number of rows and compressed bytes written.
+async fn delete_and_export(
+    pool: &PgPool,
+    query: &str,
+    path: PathBuf,
+) -> anyhow::Result<(u64, u64)> {
+    let mut tx = pool.begin().await?;
+    let csv: Vec<u8> = tx
+        .copy_out_raw(&format!(
+            "COPY ({query}) TO STDOUT WITH (FORMAT csv, HEADER)"
+        ))
+        .await?
+        .try_fold(Vec::new(), |mut csv, chunk| async move {
+            csv.extend_from_slice(&chunk);
+            Ok(csv)
+        })
+        .await?;
+
+    let rows = count_csv_rows(&csv).saturating_sub(1);
+    if rows == 0 {
+        return Ok((0, 0));
+    }
+
+    let bytes = tokio::task::spawn_blocking(move || -> anyhow::Result<u64> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let file = std::fs::File::create(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        encoder.write_all(&csv)?;
+        let file = encoder.finish()?;
+        file.sync_all()?;
+        Ok(file.metadata()?.len())
+    })
+    .await??;
+
+    tx.commit().await?;
+    Ok((rows, bytes))
+}
+
+/// A quoted SQL literal cast to `ty`, for queries that can't use bind parameters
+fn literal(value: &str, ty: &str) -> String {
+    format!("'{}'::{ty}", value.replace('\'', "''"))
+}
+
+/// Count CSV records. Newlines inside quoted values, like alert text, don't end a record.
+fn count_csv_rows(csv: &[u8]) -> u64 {
+    let mut quoted = false;
+    let mut rows = 0;
+    for &b in csv {
+        match b {
+            b'"' => quoted = !quoted,
+            b'\n' if !quoted => rows += 1,
+            _ => {}
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_overrides() {
+        let config: RetentionConfig = toml::from_str(
+            r#"
+            batch_size = 500
+
+            [trip_history_point]
+            days = 3
+
+            [stop_time.sources]
+            mta_bus = 7
+            lirr = 0
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.batch_size, 500);
+        assert_eq!(config.interval_secs, 3600);
+        let days = |table: Table, source| table.retention(&config).days_for(source);
+        assert_eq!(days(Table::TripHistoryPoint, Source::Lirr), Some(3));
+        assert_eq!(days(Table::StopTime, Source::MtaBus), Some(7));
+        // 0 days keeps rows forever
+        assert_eq!(days(Table::StopTime, Source::Lirr), None);
+        // Tables without a period aren't pruned
+        assert_eq!(days(Table::StopTime, Source::Mnr), None);
+        assert_eq!(days(Table::Alert, Source::Lirr), None);
+        assert_eq!(days(Table::GhostSample, Source::Lirr), None);
+    }
+
+    #[test]
+    fn test_literal() {
+        assert_eq!(literal("lirr", "source_enum"), "'lirr'::source_enum");
+        assert_eq!(literal("it's", "text"), "'it''s'::text");
+    }
+
+    #[test]
+    fn test_count_csv_rows() {
+        let csv = b"id,text\n1,\"Trains are\nrunning with delays\"\n2,\"\"\"quoted\"\"\"\n";
+        assert_eq!(count_csv_rows(csv), 3);
+    }
+}
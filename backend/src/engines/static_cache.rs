@@ -1,21 +1,42 @@
-use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, TimeZone, Utc};
-use chrono_tz::America::New_York;
+use chrono::{Datelike, NaiveDate, Utc};
+use chrono_tz::Tz;
 use gtfs_structures::Exception;
 
 use crate::models::source::Source;
-use crate::models::static_cache::{CachedStopTime, CachedTrip};
+use crate::models::static_cache::{CachedStopTime, CachedTrip, calculate_datetime};
+use crate::sources::set_source_timezone;
+
+/// Sets the source's timezone to the one from `agency.txt`, or `fallback` if the feed doesn't have a valid one,
+/// and returns it. GTFS requires every agency in a feed to share a timezone, so the first one is used.
+pub fn update_timezone(source: Source, gtfs: &gtfs_structures::Gtfs, fallback: Tz) -> Tz {
+    let tz = gtfs
+        .agencies
+        .first()
+        .and_then(|agency| agency.timezone.parse().ok())
+        .unwrap_or(fallback);
+    set_source_timezone(source, tz);
+    tz
+}
 
-pub fn expand_gtfs(_source: Source, gtfs: &gtfs_structures::Gtfs) -> Vec<CachedTrip> {
-    // TODO: don't hardcode timezone
-    let now = Utc::now().with_timezone(&New_York);
-    let today = now.date_naive();
+/// Expand trips running yesterday, today and tomorrow (in `tz`) into concrete stop times.
+/// Yesterday is included since its service can run past midnight.
+pub fn expand_gtfs(source: Source, gtfs: &gtfs_structures::Gtfs, tz: Tz) -> Vec<CachedTrip> {
+    let today = Utc::now().with_timezone(&tz).date_naive();
+    let yesterday = today.pred_opt().unwrap();
     let tomorrow = today.succ_opt().unwrap();
-    let dates = vec![today, tomorrow];
+    expand_gtfs_for_dates(source, gtfs, tz, &[yesterday, today, tomorrow])
+}
 
+pub fn expand_gtfs_for_dates(
+    _source: Source,
+    gtfs: &gtfs_structures::Gtfs,
+    tz: Tz,
+    dates: &[NaiveDate],
+) -> Vec<CachedTrip> {
     let mut cached_trips = Vec::new();
 
     for trip in gtfs.trips.values() {
-        for &date in &dates {
+        for &date in dates {
             if runs_on_date(&trip.service_id, date, gtfs) {
                 let Some(first_stop_time) = trip.stop_times.first() else {
                     continue;
@@ -25,7 +46,7 @@ pub fn expand_gtfs(_source: Source, gtfs: &gtfs_structures::Gtfs) -> Vec<CachedT
                 let Some(start_time_seconds) = first_stop_time.arrival_time else {
                     continue;
                 };
-                let Some(start_time) = calculate_datetime(date, start_time_seconds) else {
+                let Some(start_time) = calculate_datetime(date, start_time_seconds, tz) else {
                     continue;
                 };
 
@@ -33,8 +54,8 @@ pub fn expand_gtfs(_source: Source, gtfs: &gtfs_structures::Gtfs) -> Vec<CachedT
                     .stop_times
                     .iter()
                     .filter_map(|st| {
-                        let arrival = calculate_datetime(date, st.arrival_time?)?;
-                        let departure = calculate_datetime(date, st.departure_time?)?;
+                        let arrival = calculate_datetime(date, st.arrival_time?, tz)?;
+                        let departure = calculate_datetime(date, st.departure_time?, tz)?;
 
                         Some(CachedStopTime {
                             stop_id: st.stop.id.clone(),
@@ -95,24 +116,3 @@ fn runs_on_date(service_id: &str, date: NaiveDate, gtfs: &gtfs_structures::Gtfs)
 
     runs
 }
-
-fn calculate_datetime(date: NaiveDate, seconds_since_midnight: u32) -> Option<DateTime<Utc>> {
-    let hours = seconds_since_midnight / 3600;
-    let minutes = (seconds_since_midnight % 3600) / 60;
-    let seconds = seconds_since_midnight % 60;
-
-    // Handle times > 24:00:00 (GTFS allows this)
-    let (extra_days, hours) = (hours / 24, hours % 24);
-    let actual_date = date.checked_add_signed(chrono::Duration::days(extra_days as i64))?;
-
-    let naive_dt = NaiveDateTime::new(
-        actual_date,
-        chrono::NaiveTime::from_hms_opt(hours, minutes, seconds)?,
-    );
-
-    match New_York.from_local_datetime(&naive_dt) {
-        chrono::LocalResult::Single(dt) => Some(dt.with_timezone(&Utc)),
-        chrono::LocalResult::Ambiguous(dt1, _) => Some(dt1.with_timezone(&Utc)),
-        chrono::LocalResult::None => None,
-    }
-}
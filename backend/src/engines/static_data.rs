@@ -2,11 +2,14 @@ use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::{mpsc, oneshot};
 use tracing::{error, info, instrument};
 
+use crate::engines::status;
+use crate::metrics;
 use crate::models::source::Source;
-use crate::sources::StaticAdapter;
+use crate::sources::{self, StaticAdapter};
 use crate::stores::route::RouteStore;
 use crate::stores::static_cache::StaticCacheStore;
 use crate::stores::stop::StopStore;
@@ -77,6 +80,8 @@ pub async fn run(
     let mut tasks = Vec::new();
 
     for adapter in adapters {
+        sources::set_source_timezone(adapter.source(), adapter.timezone());
+
         let (tx, rx) = mpsc::channel::<UpdateRequest>(100);
         senders.insert(adapter.source(), tx);
 
@@ -210,6 +215,7 @@ fn spawn_import(
     import_in_progress: &mut bool,
 ) {
     *import_in_progress = true;
+    status::record_importing(adapter.source(), true);
 
     let pool_clone = pool.clone();
     let route_store_clone = route_store.clone();
@@ -219,6 +225,7 @@ fn spawn_import(
     let import_tx_clone = import_tx.clone();
 
     tokio::spawn(async move {
+        let start = Instant::now();
         let result = adapter_clone
             .import(
                 &route_store_clone,
@@ -227,7 +234,21 @@ fn spawn_import(
             )
             .await;
 
+        let source = adapter_clone.source().as_str();
+        let outcome = if result.is_ok() { "success" } else { "failure" };
+        metrics::histogram!(metrics::STATIC_IMPORT_SECONDS, "source" => source, "outcome" => outcome)
+            .record(start.elapsed().as_secs_f64());
+
+        status::record_importing(adapter_clone.source(), false);
+
         if result.is_ok() {
+            metrics::gauge!(metrics::STATIC_IMPORT_LAST_SUCCESS, "source" => source)
+                .set(Utc::now().timestamp() as f64);
+            status::record_static(
+                adapter_clone.source(),
+                Utc::now(),
+                adapter_clone.refresh_interval(),
+            );
             info!("Import successful");
             let _ = sqlx::query!(
                 "UPDATE source SET updated_at = NOW() WHERE id = $1",
@@ -282,6 +303,11 @@ async fn check_needs_update(pool: &PgPool, adapter: &dyn StaticAdapter) -> anyho
     )
     .fetch_one(pool)
     .await?;
+    status::record_static(
+        adapter.source(),
+        config.updated_at,
+        adapter.refresh_interval(),
+    );
 
     // Check if data is stale
     let elapsed = Utc::now()
@@ -0,0 +1,350 @@
+//! Tracks how fresh each source's data is, for `/v1/status` and the `/health` readiness check.
+//!
+//! Realtime pipelines report every run, feeds report every fetch, and the static engine reports `source.updated_at`
+//! whenever it reads or updates it. Everything is kept in memory so health checks never wait on the database.
+//!
+//! A source is unhealthy when none of its realtime feeds have had fresh data for [`UNHEALTHY_AFTER`], or when its pipeline
+//! hasn't finished a run in that long, which means it's stuck. It's degraded when only some feeds are stale, the last
+//! run failed, or its static data is overdue for an import. Sources are never unhealthy while a static import is
+//! running, since their pipelines wait for it.
+//!
+//! Stale feeds usually mean the agency stopped updating them, which every replica sees alike, so they don't affect
+//! readiness. A replica is only [`unready`] when one of its own pipelines is stuck or panicked, or when a source in
+//! `REQUIRED_SOURCES` is unhealthy.
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    models::{
+        source::Source,
+        status::{FeedKind, FeedStatus, Health, SourceStatus, StatusReport, Thresholds},
+    },
+    required_sources,
+};
+
+/// Realtime data older than this is degraded
+const DEGRADED_AFTER: Duration = Duration::from_secs(3 * 60);
+/// Realtime data older than this is unhealthy
+const UNHEALTHY_AFTER: Duration = Duration::from_secs(10 * 60);
+/// Static data is degraded once it's this many refresh intervals old, since an import should have replaced it by then
+const STATIC_DEGRADED_INTERVALS: u32 = 2;
+
+#[derive(Default)]
+struct FeedState {
+    kind: FeedKind,
+    last_success: Option<DateTime<Utc>>,
+    header_timestamp: Option<DateTime<Utc>>,
+    entities: usize,
+    last_error: Option<String>,
+    last_error_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Default)]
+struct SourceState {
+    /// When the realtime pipeline started, which is how long a source without any runs has been waiting for one
+    registered_at: Option<DateTime<Utc>>,
+    last_run: Option<DateTime<Utc>>,
+    last_run_error: Option<String>,
+    /// The realtime pipeline panicked, so it won't run again
+    panicked: bool,
+    static_updated_at: Option<DateTime<Utc>>,
+    static_refresh_interval: Option<Duration>,
+    importing: bool,
+    feeds: HashMap<String, FeedState>,
+}
+
+fn tracker() -> &'static Mutex<HashMap<Source, SourceState>> {
+    static TRACKER: OnceLock<Mutex<HashMap<Source, SourceState>>> = OnceLock::new();
+    TRACKER.get_or_init(Default::default)
+}
+
+fn update(source: Source, f: impl FnOnce(&mut SourceState)) {
+    f(tracker().lock().unwrap().entry(source).or_default());
+}
+
+/// Start tracking a source's realtime pipeline
+pub fn register(source: Source) {
+    update(source, |state| state.registered_at = Some(Utc::now()));
+}
+
+/// Record a finished realtime pipeline run
+pub fn record_run(source: Source, result: &anyhow::Result<()>) {
+    update(source, |state| {
+        state.last_run = Some(Utc::now());
+        state.last_run_error = result.as_ref().err().map(|e| format!("{:#}", e));
+    });
+}
+
+/// Record a realtime pipeline that panicked
+pub fn record_panic(source: Source) {
+    update(source, |state| state.panicked = true);
+}
+
+/// Record a fetched and decoded feed. `header_timestamp` is the feed header's POSIX time, if it has one.
+pub fn record_feed(
+    source: Source,
+    kind: FeedKind,
+    label: &str,
+    header_timestamp: Option<u64>,
+    entities: usize,
+) {
+    update(source, |state| {
+        let feed = state.feeds.entry(label.to_owned()).or_default();
+        feed.kind = kind;
+        feed.last_success = Some(Utc::now());
+        feed.header_timestamp = header_timestamp
+            .filter(|&t| t > 0)
+            .and_then(|t| DateTime::from_timestamp(t as i64, 0));
+        feed.entities = entities;
+    });
+}
+
+/// Record a feed that couldn't be fetched or decoded
+pub fn record_feed_error(source: Source, kind: FeedKind, label: &str, error: String) {
+    update(source, |state| {
+        let feed = state.feeds.entry(label.to_owned()).or_default();
+        feed.kind = kind;
+        feed.last_error = Some(error);
+        feed.last_error_at = Some(Utc::now());
+    });
+}
+
+/// Record when a source's static data was last imported, and how often it's supposed to be
+pub fn record_static(source: Source, updated_at: DateTime<Utc>, refresh_interval: Duration) {
+    update(source, |state| {
+        state.static_updated_at = Some(updated_at);
+        state.static_refresh_interval = Some(refresh_interval);
+    });
+}
+
+/// Record that a static import started or finished
+pub fn record_importing(source: Source, importing: bool) {
+    update(source, |state| state.importing = importing);
+}
+
+/// Health of data last updated at a time
+fn freshness(at: DateTime<Utc>, now: DateTime<Utc>) -> Health {
+    let age = (now - at).to_std().unwrap_or_default();
+    if age > UNHEALTHY_AFTER {
+        Health::Unhealthy
+    } else if age > DEGRADED_AFTER {
+        Health::Degraded
+    } else {
+        Health::Healthy
+    }
+}
+
+fn feed_status(label: &str, feed: &FeedState, now: DateTime<Utc>) -> FeedStatus {
+    // A feed can be fetched fine while the agency keeps serving the same old data, so its header time counts too
+    let data_at = match (feed.last_success, feed.header_timestamp) {
+        (Some(success), Some(header)) => Some(success.min(header)),
+        (success, _) => success,
+    };
+    // Feeds are only tracked once they've been fetched, so one without a success has only failed
+    let status = data_at.map_or(Health::Unhealthy, |at| freshness(at, now));
+
+    FeedStatus {
+        label: label.to_owned(),
+        kind: feed.kind,
+        status,
+        last_success: feed.last_success,
+        header_timestamp: feed.header_timestamp,
+        entities: feed.entities,
+        last_error: feed.last_error.clone(),
+        last_error_at: feed.last_error_at,
+    }
+}
+
+/// Whether a source's pipeline stopped running: it panicked, or hasn't finished a run in [`UNHEALTHY_AFTER`] without a
+/// static import to wait on
+fn stuck(state: &SourceState, now: DateTime<Utc>) -> bool {
+    state.panicked
+        || (!state.importing
+            && state
+                .last_run
+                .is_some_and(|last_run| freshness(last_run, now) == Health::Unhealthy))
+}
+
+fn source_status(source: Source, state: &SourceState, now: DateTime<Utc>) -> SourceStatus {
+    let mut feeds: Vec<_> = state
+        .feeds
+        .iter()
+        .map(|(label, feed)| feed_status(label, feed, now))
+        .collect();
+    feeds.sort_by(|a, b| a.label.cmp(&b.label));
+
+    // A pipeline that hasn't finished a run in a while is stuck. Before the first run, the pipeline is usually waiting
+    // on the first static import, so it's only degraded.
+    let mut status = match (state.last_run, state.registered_at) {
+        (Some(last_run), _) => freshness(last_run, now),
+        (None, Some(registered_at)) => freshness(registered_at, now).min(Health::Degraded),
+        (None, None) => Health::Healthy,
+    };
+    if state.last_run_error.is_some() {
+        status = status.max(Health::Degraded);
+    }
+
+    // The source only has no fresh data when every realtime feed is unhealthy. Anything else stale is degraded.
+    let freshest_realtime = feeds
+        .iter()
+        .filter(|f| f.kind == FeedKind::Realtime)
+        .map(|f| f.status)
+        .min();
+    if freshest_realtime == Some(Health::Unhealthy) {
+        status = Health::Unhealthy;
+    } else if feeds.iter().any(|f| f.status != Health::Healthy) {
+        status = status.max(Health::Degraded);
+    }
+
+    if let (Some(updated_at), Some(interval)) =
+        (state.static_updated_at, state.static_refresh_interval)
+        && (now - updated_at).to_std().unwrap_or_default() > interval * STATIC_DEGRADED_INTERVALS
+    {
+        status = status.max(Health::Degraded);
+    }
+
+    // Realtime pipelines wait for static imports, which can take a while, so they aren't stuck
+    if state.importing {
+        status = status.min(Health::Degraded);
+    }
+    if state.panicked {
+        status = Health::Unhealthy;
+    }
+
+    SourceStatus {
+        source,
+        status,
+        last_run: state.last_run,
+        last_run_error: state.last_run_error.clone(),
+        panicked: state.panicked,
+        static_updated_at: state.static_updated_at,
+        importing: state.importing,
+        feeds,
+    }
+}
+
+/// Status of every tracked source at a time
+pub fn report(now: DateTime<Utc>) -> StatusReport {
+    let tracker = tracker().lock().unwrap();
+    let sources: Vec<_> = Source::all()
+        .into_iter()
+        .filter_map(|source| Some(source_status(source, tracker.get(&source)?, now)))
+        .collect();
+
+    StatusReport {
+        status: sources
+            .iter()
+            .map(|s| s.status)
+            .max()
+            .unwrap_or(Health::Healthy),
+        checked_at: now,
+        thresholds: Thresholds {
+            degraded_after_secs: DEGRADED_AFTER.as_secs(),
+            unhealthy_after_secs: UNHEALTHY_AFTER.as_secs(),
+            static_degraded_intervals: STATIC_DEGRADED_INTERVALS,
+        },
+        sources,
+    }
+}
+
+/// Sources that make this process unready at a time: their pipeline is stuck, or they're required and unhealthy
+pub fn unready(now: DateTime<Utc>) -> Vec<Source> {
+    let tracker = tracker().lock().unwrap();
+    Source::all()
+        .into_iter()
+        .filter(|source| {
+            tracker.get(source).is_some_and(|state| {
+                stuck(state, now)
+                    || (required_sources().iter().any(|s| s == source.as_str())
+                        && source_status(*source, state, now).status == Health::Unhealthy)
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(kind: FeedKind, at: DateTime<Utc>) -> FeedState {
+        FeedState {
+            kind,
+            last_success: Some(at),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_source_status() {
+        let now = Utc::now();
+        let fresh = now - chrono::Duration::seconds(30);
+        let stale = now - chrono::Duration::minutes(15);
+
+        let mut state = SourceState {
+            last_run: Some(fresh),
+            feeds: HashMap::from([
+                ("ace".into(), feed(FeedKind::Realtime, fresh)),
+                ("g".into(), feed(FeedKind::Realtime, stale)),
+                ("alerts".into(), feed(FeedKind::Alerts, fresh)),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            source_status(Source::MtaSubway, &state, now).status,
+            Health::Degraded
+        );
+
+        // Fresh alerts don't make up for every realtime feed being stale
+        let ace = feed(FeedKind::Realtime, stale);
+        state.feeds.insert("ace".into(), ace);
+        assert_eq!(
+            source_status(Source::MtaSubway, &state, now).status,
+            Health::Unhealthy
+        );
+
+        // Pipelines wait on static imports, so they aren't stuck during one
+        state.importing = true;
+        assert_eq!(
+            source_status(Source::MtaSubway, &state, now).status,
+            Health::Degraded
+        );
+
+        // A feed that keeps serving the same data is stale even though it's fetched fine
+        let mut old_header = feed(FeedKind::Realtime, fresh);
+        old_header.header_timestamp = Some(stale);
+        let mut state = SourceState {
+            last_run: Some(fresh),
+            feeds: HashMap::from([("lirr-gtfs".into(), old_header)]),
+            ..Default::default()
+        };
+        assert_eq!(
+            source_status(Source::Lirr, &state, now).status,
+            Health::Unhealthy
+        );
+
+        assert!(!stuck(&state, now));
+
+        // A stuck pipeline is unhealthy even if its last feeds were fresh
+        state.feeds.clear();
+        state.last_run = Some(stale);
+        assert_eq!(
+            source_status(Source::Lirr, &state, now).status,
+            Health::Unhealthy
+        );
+        assert!(stuck(&state, now));
+
+        // A panicked pipeline never runs again
+        let state = SourceState {
+            last_run: Some(fresh),
+            panicked: true,
+            ..Default::default()
+        };
+        assert!(stuck(&state, now));
+    }
+}
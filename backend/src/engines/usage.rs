@@ -0,0 +1,30 @@
+//! Moves API usage counts from Redis to Postgres.
+//!
+//! Requests are counted in Redis so counting doesn't add a database write to every request. The counts are added to
+//! `auth.api_key_usage` every minute. Yesterday's counts are flushed too, for requests made just before midnight.
+
+use chrono::{Duration, Utc};
+use tracing::{debug, error};
+
+use crate::stores::api_key::ApiKeyStore;
+
+const FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+pub async fn run(api_key_store: &ApiKeyStore) {
+    let api_key_store = api_key_store.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let today = Utc::now().date_naive();
+            for day in [today - Duration::days(1), today] {
+                match api_key_store.flush_usage(day).await {
+                    Ok(0) => {}
+                    Ok(counts) => debug!("Flushed {} usage counts for {}", counts, day),
+                    Err(e) => error!("Usage flush error for {}: {:#}", day, e),
+                }
+            }
+        }
+    });
+}
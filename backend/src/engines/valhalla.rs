@@ -10,6 +10,8 @@ use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
 use tokio::task::JoinHandle;
 use tokio::time::sleep;
 
+use crate::metrics;
+
 // TODO: remove retry logic or make it based on http status code (e.g. dont retry on 400)
 const DEFAULT_VALHALLA_BASE_URL: &str = "http://127.0.0.1:8002";
 const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 60;
@@ -203,6 +205,7 @@ impl ValhallaManager {
             "Starting valhalla_service on-demand"
         );
 
+        let started = Instant::now();
         let child = Command::new(&self.config.executable)
             .arg(&self.config.config_path)
             .arg(self.config.threads.to_string())
@@ -214,15 +217,23 @@ impl ValhallaManager {
                     "Failed to start {} with config {}",
                     self.config.executable, self.config.config_path
                 )
+            })
+            .inspect_err(|_| {
+                metrics::counter!(metrics::VALHALLA_STARTS, "outcome" => "failure").increment(1)
             })?;
 
         state.process = Some(child);
 
         if let Err(err) = self.wait_until_ready().await {
+            metrics::counter!(metrics::VALHALLA_STARTS, "outcome" => "failure").increment(1);
             self.stop_locked(state, "readiness failure").await;
             return Err(err);
         }
 
+        metrics::counter!(metrics::VALHALLA_STARTS, "outcome" => "success").increment(1);
+        metrics::histogram!(metrics::VALHALLA_START_SECONDS)
+            .record(started.elapsed().as_secs_f64());
+        metrics::gauge!(metrics::VALHALLA_RUNNING).set(1.0);
         state.lifecycle = Lifecycle::Running;
         tracing::info!("valhalla_service is ready");
         Ok(())
@@ -295,6 +306,8 @@ impl ValhallaManager {
             if let Err(err) = process.wait().await {
                 tracing::warn!(error = %err, "Failed to wait on valhalla_service process");
             }
+            metrics::counter!(metrics::VALHALLA_STOPS, "reason" => reason.to_owned()).increment(1);
+            metrics::gauge!(metrics::VALHALLA_RUNNING).set(0.0);
         }
 
         state.lifecycle = Lifecycle::Stopped;
@@ -0,0 +1,107 @@
+//! Converts routes, stops and vehicle positions into GeoJSON FeatureCollections, for GIS tools like QGIS.
+//!
+//! Each feature's properties are the same fields as the JSON response, except that the source-specific `data` is
+//! flattened into them (its `source` tag included), since nested objects are awkward to work with in attribute
+//! tables.
+
+use geojson::{Feature, FeatureCollection, JsonObject};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::models::{geom::Geom, position::ApiVehiclePosition, route::Route, stop::Stop};
+
+/// Routes as MultiLineStrings. Routes without geometry have a null geometry.
+pub fn routes_collection(routes: &[Route]) -> anyhow::Result<FeatureCollection> {
+    collection(routes.iter().map(|r| (r, r.geom.as_ref())))
+}
+
+pub fn stops_collection(stops: &[Stop]) -> anyhow::Result<FeatureCollection> {
+    collection(stops.iter().map(|s| (s, Some(&s.geom))))
+}
+
+/// Positions as points. Positions without coordinates, like unestimated subway trains, have a null geometry.
+pub fn positions_collection(positions: &[ApiVehiclePosition]) -> anyhow::Result<FeatureCollection> {
+    collection(positions.iter().map(|p| (p, p.position.geom.as_ref())))
+}
+
+fn collection<'a, T: Serialize + 'a>(
+    items: impl Iterator<Item = (&'a T, Option<&'a Geom>)>,
+) -> anyhow::Result<FeatureCollection> {
+    let features = items
+        .map(|(item, geom)| {
+            Ok(Feature {
+                geometry: geom.map(|g| geojson::Geometry::from(&g.0)),
+                properties: Some(properties(item)?),
+                ..Default::default()
+            })
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    Ok(FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    })
+}
+
+/// The item's fields without its geometry, with the fields of `data` moved up a level. Fields of the item itself take
+/// precedence over `data` fields with the same name.
+fn properties<T: Serialize>(item: &T) -> anyhow::Result<JsonObject> {
+    let Value::Object(mut properties) = serde_json::to_value(item)? else {
+        anyhow::bail!("GeoJSON properties must be an object");
+    };
+    properties.remove("geom");
+    if let Some(Value::Object(data)) = properties.remove("data") {
+        for (key, value) in data {
+            properties.entry(key).or_insert(value);
+        }
+    }
+    Ok(properties)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::position::{MtaBusPositionData, PositionData, VehiclePosition};
+    use chrono::Utc;
+    use geo::Point;
+
+    #[test]
+    fn test_positions_collection() {
+        let position = ApiVehiclePosition {
+            position: VehiclePosition {
+                vehicle_id: "MTA NYCT_7153".into(),
+                trip_id: None,
+                stop_id: Some("401921".into()),
+                updated_at: Utc::now(),
+                data: PositionData::MtaBus(MtaBusPositionData {
+                    bearing: 90.0,
+                    passengers: Some(12),
+                    capacity: None,
+                    status: None,
+                    phase: None,
+                }),
+                geom: Some(Point::new(-73.95, 40.65).into()),
+            },
+            estimated: true,
+            bearing: Some(88.0),
+            next_departures: None,
+        };
+
+        let collection = positions_collection(&[position]).unwrap();
+        let feature = &collection.features[0];
+        assert!(matches!(
+            feature.geometry.as_ref().map(|g| &g.value),
+            Some(geojson::GeometryValue::Point { .. })
+        ));
+
+        let properties = feature.properties.as_ref().unwrap();
+        assert_eq!(properties["vehicle_id"], "MTA NYCT_7153");
+        assert_eq!(properties["source"], "mta_bus");
+        assert_eq!(properties["passengers"], 12);
+        assert_eq!(properties["estimated"], true);
+        // The estimated bearing wins over the feed's
+        assert_eq!(properties["bearing"], 88.0);
+        assert!(!properties.contains_key("data") && !properties.contains_key("geom"));
+    }
+}
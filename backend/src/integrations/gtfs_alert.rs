@@ -90,7 +90,9 @@ pub async fn run_pipeline<T: GtfsAlertSource>(
             // Track clone IDs for removing old versions
             let clone_id = match &parsed_alert.data {
                 crate::models::alert::AlertData::MtaSubway(data)
-                | crate::models::alert::AlertData::MtaBus(data) => data.clone_id.clone(),
+                | crate::models::alert::AlertData::MtaBus(data)
+                | crate::models::alert::AlertData::Lirr(data)
+                | crate::models::alert::AlertData::Mnr(data) => data.clone_id.clone(),
                 _ => None,
             };
             if let Some(clone_id) = clone_id {
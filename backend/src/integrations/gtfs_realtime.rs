@@ -1,19 +1,30 @@
 use crate::feed::{FeedMessage, TripUpdate, VehiclePosition};
 use crate::models::{
     position::VehiclePosition as VehiclePositionModel,
-    trip::{StopTime, Trip},
+    static_cache::CachedTrip,
+    status::FeedKind,
+    trip::{StopTime, StopTimeData, StopTimeStatus, Trip, TripStatus},
 };
+use crate::sources::source_timezone;
 use crate::stores::position::PositionStore;
 use crate::stores::static_cache::StaticCacheStore;
 use crate::stores::trip::TripStore;
-use crate::{debug_rt_data, engines::static_data::StaticController, models::source::Source};
+use crate::{
+    debug_rt_data,
+    engines::{static_data::StaticController, status},
+    metrics,
+    models::source::Source,
+};
 use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
 use futures::future::BoxFuture;
 use prost::Message;
 use prost::bytes;
 use std::collections::HashMap;
+use std::time::Instant;
 use tokio::fs::{create_dir_all, write};
-use tracing::{error, info, instrument};
+use tracing::{debug, error, info, instrument};
 use uuid::Uuid;
 /// A future that fetches a GTFS-RT feed and returns the raw protobuf bytes.
 pub type FeedFuture = BoxFuture<'static, anyhow::Result<bytes::Bytes>>;
@@ -52,16 +63,31 @@ pub trait GtfsSource: Send + Sync {
         vehicle: VehiclePosition,
         static_cache_store: &StaticCacheStore,
     ) -> Option<VehiclePositionModel>;
+
+    /// Copies vehicle-level details (e.g. consist length) onto the trip the vehicle is serving.
+    /// Called before trips are saved, for every trip with a position sharing its vehicle_id.
+    fn merge_vehicle(&self, _trip: &mut Trip, _position: &VehiclePositionModel) {}
 }
 
 /// Fetches and decodes GTFS-RT feeds from the provided labeled futures.
 /// Each entry is a `(label, future)` pair where the future returns raw protobuf bytes.
 /// If DEBUG_RT_DATA env var is set, saves raw protobuf and decoded data to ./gtfs/ for debugging.
-pub async fn fetch_feeds(labeled_futures: Vec<(String, FeedFuture)>) -> Vec<FeedMessage> {
+/// Fetch time, failures and decoded entities are recorded in metrics under each feed's label, and each feed's
+/// freshness is recorded for `/v1/status` under the source.
+pub async fn fetch_feeds(
+    source: Source,
+    kind: FeedKind,
+    labeled_futures: Vec<(String, FeedFuture)>,
+) -> Vec<FeedMessage> {
     let futures: Vec<_> = labeled_futures
         .into_iter()
         .map(|(name, fut)| async move {
-            match fut.await {
+            let start = Instant::now();
+            let result = fut.await;
+            metrics::histogram!(metrics::FEED_FETCH_SECONDS, "feed" => name.clone())
+                .record(start.elapsed().as_secs_f64());
+
+            match result {
                 Ok(bytes) => {
                     let bytes_for_debug = if *debug_rt_data() {
                         Some(bytes.clone())
@@ -85,16 +111,41 @@ pub async fn fetch_feeds(labeled_futures: Vec<(String, FeedFuture)>) -> Vec<Feed
                                     error!(txt_path, %e, "Failed to write debug output");
                                 }
                             }
+                            metrics::counter!(metrics::FEED_ENTITIES, "feed" => name.clone())
+                                .increment(msg.entity.len() as u64);
+                            metrics::gauge!(metrics::FEED_LAST_SUCCESS, "feed" => name.clone())
+                                .set(Utc::now().timestamp() as f64);
+                            status::record_feed(
+                                source,
+                                kind,
+                                &name,
+                                msg.header.timestamp,
+                                msg.entity.len(),
+                            );
                             Some(msg)
                         }
                         Err(e) => {
                             error!(name, %e, "Failed to decode protobuf");
+                            metrics::counter!(
+                                metrics::FEED_FETCH_FAILURES,
+                                "feed" => name.clone(),
+                                "stage" => "decode"
+                            )
+                            .increment(1);
+                            status::record_feed_error(source, kind, &name, e.to_string());
                             None
                         }
                     }
                 }
                 Err(e) => {
                     error!(name, %e, "Failed to fetch feed");
+                    metrics::counter!(
+                        metrics::FEED_FETCH_FAILURES,
+                        "feed" => name.clone(),
+                        "stage" => "fetch"
+                    )
+                    .increment(1);
+                    status::record_feed_error(source, kind, &name, format!("{:#}", e));
                     None
                 }
             }
@@ -134,11 +185,22 @@ pub async fn run_pipeline<T: GtfsSource>(
     for feed in feeds {
         for entity in feed.entity {
             if let Some(update) = entity.trip_update {
-                let (trip_opt, new_stop_times) =
+                let (trip_opt, mut new_stop_times) =
                     adapter.process_trip(update, static_cache_store).await;
                 if let Some(trip) = trip_opt {
+                    if trip.status == TripStatus::Canceled {
+                        new_stop_times = skip_canceled_stops(
+                            adapter.source(),
+                            &trip,
+                            new_stop_times,
+                            static_cache_store,
+                        )
+                        .await;
+                    }
                     // Map vehicle_id to trip_id for position linking
-                    vehicle_to_trip.insert(trip.vehicle_id.clone(), trip.id);
+                    if let Some(vehicle_id) = &trip.vehicle_id {
+                        vehicle_to_trip.insert(vehicle_id.clone(), trip.id);
+                    }
                     data.push((trip, new_stop_times));
                 }
             }
@@ -150,6 +212,20 @@ pub async fn run_pipeline<T: GtfsSource>(
         }
     }
 
+    let positions_by_vehicle: HashMap<&str, &VehiclePositionModel> = positions
+        .iter()
+        .map(|p| (p.vehicle_id.as_str(), p))
+        .collect();
+    for (trip, _) in &mut data {
+        if let Some(position) = trip
+            .vehicle_id
+            .as_deref()
+            .and_then(|vehicle_id| positions_by_vehicle.get(vehicle_id))
+        {
+            adapter.merge_vehicle(trip, position);
+        }
+    }
+
     info!(
         "Fetched {} trips, {} positions for {:?}",
         data.len(),
@@ -176,3 +252,105 @@ pub async fn run_pipeline<T: GtfsSource>(
 
     Ok(())
 }
+
+/// Every stop of a canceled trip is skipped. Feeds often leave the stop times out entirely, which would hide the trip
+/// from everything that looks for upcoming stop times, so they're filled in from the static schedule if there is one.
+async fn skip_canceled_stops(
+    source: Source,
+    trip: &Trip,
+    stop_times: Vec<StopTime>,
+    static_cache_store: &StaticCacheStore,
+) -> Vec<StopTime> {
+    if !stop_times.is_empty() {
+        return stop_times
+            .into_iter()
+            .map(|st| StopTime {
+                status: StopTimeStatus::Skipped,
+                ..st
+            })
+            .collect();
+    }
+
+    let schedule = match static_cache_store
+        .scheduled_trip(
+            source,
+            &trip.original_id,
+            trip.created_at,
+            source_timezone(source),
+        )
+        .await
+    {
+        Ok(Some(schedule)) => schedule,
+        Ok(None) => return vec![],
+        Err(e) => {
+            error!(
+                "Failed to get schedule for canceled trip {}: {:#}",
+                trip.original_id, e
+            );
+            return vec![];
+        }
+    };
+
+    schedule
+        .stop_times
+        .into_iter()
+        .map(|st| StopTime {
+            trip_id: trip.id,
+            stop_id: st.stop_id,
+            arrival: st.arrival,
+            departure: st.departure,
+            status: StopTimeStatus::Skipped,
+            data: StopTimeData::empty(source),
+        })
+        .collect()
+}
+
+/// The service date of a trip update, along with the trip's static schedule if there is one.
+/// The feed's `start_date` is used when it has one. Otherwise the service date comes from the schedule, by finding
+/// the service day that runs the trip at its first predicted stop. Returns `None` if neither is available.
+pub async fn service_date(
+    source: Source,
+    tz: Tz,
+    update: &TripUpdate,
+    static_cache_store: &StaticCacheStore,
+) -> Option<(NaiveDate, Option<CachedTrip>)> {
+    let trip_id = update.trip.trip_id.as_deref()?;
+
+    if let Some(start_date) = update.trip.start_date.as_deref() {
+        let date = NaiveDate::parse_from_str(start_date, "%Y%m%d").ok()?;
+        let cached_trip = static_cache_store
+            .get_trip(source, trip_id, start_date)
+            .await
+            .unwrap_or(None);
+        return Some((date, cached_trip));
+    }
+
+    let first_event = update.stop_time_update.iter().find_map(|st| {
+        st.arrival
+            .as_ref()
+            .and_then(|a| a.time)
+            .or_else(|| st.departure.as_ref()?.time)
+    })?;
+    let at = DateTime::from_timestamp(first_event, 0)?;
+
+    match static_cache_store
+        .find_trip_running_at(source, trip_id, at, tz)
+        .await
+    {
+        Ok(Some(cached_trip)) => {
+            let date = NaiveDate::parse_from_str(&cached_trip.start_date, "%Y%m%d").ok()?;
+            Some((date, Some(cached_trip)))
+        }
+        Ok(None) => {
+            debug!(
+                trip_id,
+                "Trip has no start_date and isn't in the static schedule"
+            );
+            None
+        }
+        Err(e) => {
+            error!(trip_id, "Failed to find service date: {:#}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,493 @@
+//! Re-encodes the normalized realtime data back into GTFS-RT feeds, so consumers that only understand GTFS-RT
+//! (trip planners, other apps) can use every source through one format.
+//!
+//! IDs are the normalized ones used by the rest of the API, so they match the stops and routes endpoints rather
+//! than the agencies' original static feeds.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use geo::Geometry;
+use uuid::Uuid;
+
+use crate::{
+    feed::{
+        Alert, EntitySelector, FeedEntity, FeedHeader, FeedMessage, NyctTripDescriptor, Position,
+        TimeRange, TranslatedString, TripDescriptor, TripUpdate, VehicleDescriptor,
+        VehiclePosition as FeedVehiclePosition,
+        feed_header::Incrementality,
+        nyct_trip_descriptor::Direction,
+        translated_string::Translation,
+        trip_descriptor::ScheduleRelationship as TripScheduleRelationship,
+        trip_update::{
+            MtaRailroadStopTimeUpdate, NyctStopTimeUpdate, StopTimeEvent, StopTimeUpdate,
+            stop_time_update::ScheduleRelationship as StopScheduleRelationship,
+        },
+        vehicle_position::VehicleStopStatus,
+    },
+    models::{
+        alert::{AlertFormat, AlertSection},
+        position::{PositionData, VehiclePosition},
+        source::Source,
+        static_cache::service_day_start,
+        trip::{StopTime, StopTimeData, StopTimeStatus, Trip, TripData, TripStatus},
+    },
+    stores::alert::ApiAlert,
+};
+
+const GTFS_REALTIME_VERSION: &str = "2.0";
+
+fn feed_message(entity: Vec<FeedEntity>, now: DateTime<Utc>) -> FeedMessage {
+    FeedMessage {
+        header: FeedHeader {
+            gtfs_realtime_version: GTFS_REALTIME_VERSION.into(),
+            incrementality: Some(Incrementality::FullDataset as i32),
+            timestamp: Some(now.timestamp() as u64),
+            ..Default::default()
+        },
+        entity,
+    }
+}
+
+/// Service dates of trips from the static schedule, keyed by trip ID. Trips that aren't in it are assumed to be
+/// on the service day of their local start date.
+pub type ServiceDates = HashMap<Uuid, NaiveDate>;
+
+/// Formats a time as GTFS `HH:MM:SS` since the start of the service day, which is past `24:00:00` for trips that
+/// start after midnight on the previous day's service.
+fn gtfs_time(start: DateTime<Utc>, service_date: NaiveDate, tz: Tz) -> Option<String> {
+    let seconds = (start - service_day_start(service_date, tz)?).num_seconds();
+    (seconds >= 0).then(|| {
+        format!(
+            "{:02}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    })
+}
+
+/// Builds a trip descriptor from a trip. `tz` is the source's timezone, which start dates and times are in.
+fn trip_descriptor(
+    source: Source,
+    trip: &Trip,
+    tz: Tz,
+    service_dates: &ServiceDates,
+) -> TripDescriptor {
+    let service_date = service_dates
+        .get(&trip.id)
+        .copied()
+        .unwrap_or_else(|| trip.created_at.with_timezone(&tz).date_naive());
+
+    let (direction_id, nyct_trip_descriptor) = match source {
+        // NYCT uses 1 for northbound and 3 for southbound, which are direction_id 0 and 1 in the static feed
+        Source::MtaSubway => (
+            Some(if trip.direction == 3 { 1 } else { 0 }),
+            Some(NyctTripDescriptor {
+                train_id: trip.vehicle_id.clone(),
+                direction: Some(if trip.direction == 3 {
+                    Direction::South as i32
+                } else {
+                    Direction::North as i32
+                }),
+                ..Default::default()
+            }),
+        ),
+        _ => (u32::try_from(trip.direction).ok(), None),
+    };
+
+    TripDescriptor {
+        trip_id: Some(trip.original_id.clone()),
+        route_id: Some(trip.route_id.clone()),
+        direction_id,
+        start_date: Some(service_date.format("%Y%m%d").to_string()),
+        // MTA bus trips are created when they are first seen, so created_at isn't their start time
+        start_time: if source == Source::MtaBus {
+            None
+        } else {
+            gtfs_time(trip.created_at, service_date, tz)
+        },
+        nyct_trip_descriptor,
+        schedule_relationship: Some(match trip.status {
+            TripStatus::Scheduled => TripScheduleRelationship::Scheduled,
+            TripStatus::Added => TripScheduleRelationship::Added,
+            TripStatus::Canceled => TripScheduleRelationship::Canceled,
+            TripStatus::Duplicated => TripScheduleRelationship::Duplicated,
+        } as i32),
+        ..Default::default()
+    }
+}
+
+fn vehicle_descriptor(trip: &Trip) -> VehicleDescriptor {
+    let label = match &trip.data {
+        TripData::Lirr(data) => Some(data.train_number.clone()),
+        TripData::Mnr(data) => Some(data.train_number.clone()),
+        TripData::NjtRail(data) => Some(data.train_number.clone()),
+        _ => None,
+    };
+
+    VehicleDescriptor {
+        id: trip.vehicle_id.clone(),
+        label,
+        ..Default::default()
+    }
+}
+
+fn stop_time_update(stop_time: &StopTime) -> StopTimeUpdate {
+    let event = |time: DateTime<Utc>| StopTimeEvent {
+        time: Some(time.timestamp()),
+        ..Default::default()
+    };
+
+    let mut update = StopTimeUpdate {
+        stop_id: Some(stop_time.stop_id.clone()),
+        arrival: Some(event(stop_time.arrival)),
+        departure: Some(event(stop_time.departure)),
+        schedule_relationship: Some(match stop_time.status {
+            StopTimeStatus::Scheduled => StopScheduleRelationship::Scheduled,
+            StopTimeStatus::Skipped => StopScheduleRelationship::Skipped,
+            StopTimeStatus::NoData => StopScheduleRelationship::NoData,
+        } as i32),
+        ..Default::default()
+    };
+    match &stop_time.data {
+        StopTimeData::MtaSubway(data) => {
+            if let Some(arrival) = update.arrival.as_mut() {
+                arrival.delay = data.delay;
+            }
+            update.nyct_stop_time_update = Some(NyctStopTimeUpdate {
+                scheduled_track: data.scheduled_track.clone(),
+                actual_track: data.actual_track.clone(),
+            });
+        }
+        StopTimeData::Lirr(data) => {
+            update.mta_railroad_stop_time_update = Some(MtaRailroadStopTimeUpdate {
+                track: data.track.clone(),
+                train_status: data.status.clone(),
+            });
+        }
+        StopTimeData::Mnr(data) => {
+            update.mta_railroad_stop_time_update = Some(MtaRailroadStopTimeUpdate {
+                track: data.track.clone(),
+                train_status: data.status.clone(),
+            });
+        }
+        _ => {}
+    }
+    update
+}
+
+/// One `TripUpdate` entity per trip, with its stop times in order of arrival
+pub fn trip_updates_feed(
+    source: Source,
+    tz: Tz,
+    trips: &[Trip],
+    service_dates: &ServiceDates,
+    stop_times: &[StopTime],
+    now: DateTime<Utc>,
+) -> FeedMessage {
+    let mut trip_stop_times: HashMap<_, Vec<&StopTime>> = HashMap::new();
+    for stop_time in stop_times {
+        trip_stop_times
+            .entry(stop_time.trip_id)
+            .or_default()
+            .push(stop_time);
+    }
+
+    let entity = trips
+        .iter()
+        .map(|trip| {
+            let mut stop_times = trip_stop_times.remove(&trip.id).unwrap_or_default();
+            stop_times.sort_by_key(|st| (st.arrival, st.departure));
+
+            let delay = match &trip.data {
+                TripData::MtaSubway(data) => data.delay,
+                TripData::MtaBus(data) => data.deviation,
+                TripData::NjtBus(data) => data.deviation,
+                TripData::Gtfs(data) => data.delay,
+                _ => None,
+            };
+
+            FeedEntity {
+                id: trip.id.to_string(),
+                trip_update: Some(TripUpdate {
+                    trip: trip_descriptor(source, trip, tz, service_dates),
+                    vehicle: Some(vehicle_descriptor(trip)),
+                    stop_time_update: stop_times.into_iter().map(stop_time_update).collect(),
+                    timestamp: Some(trip.updated_at.timestamp() as u64),
+                    delay,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }
+        })
+        .collect();
+
+    feed_message(entity, now)
+}
+
+fn vehicle_stop_status(status: Option<&str>) -> Option<i32> {
+    let status = match status? {
+        "incoming" => VehicleStopStatus::IncomingAt,
+        "at_stop" => VehicleStopStatus::StoppedAt,
+        "in_transit_to" => VehicleStopStatus::InTransitTo,
+        _ => return None,
+    };
+    Some(status as i32)
+}
+
+/// One `VehiclePosition` entity per vehicle. Positions without a linked trip are matched to trips by vehicle ID,
+/// which is how subway positions are related to their trips.
+pub fn vehicle_positions_feed(
+    source: Source,
+    tz: Tz,
+    positions: &[VehiclePosition],
+    trips: &[Trip],
+    service_dates: &ServiceDates,
+    now: DateTime<Utc>,
+) -> FeedMessage {
+    let trips_by_id: HashMap<_, _> = trips.iter().map(|t| (t.id, t)).collect();
+    let trips_by_vehicle: HashMap<_, _> = trips
+        .iter()
+        .filter_map(|t| Some((t.vehicle_id.as_deref()?, t)))
+        .collect();
+
+    let entity = positions
+        .iter()
+        .map(|position| {
+            let trip = match position.trip_id {
+                Some(id) => trips_by_id.get(&id).copied(),
+                None => trips_by_vehicle.get(position.vehicle_id.as_str()).copied(),
+            };
+
+            let (bearing, occupancy_status, current_status) = match &position.data {
+                PositionData::MtaSubway(data) => (None, None, data.status.as_deref()),
+                PositionData::MtaBus(data) => (Some(data.bearing), None, None),
+                PositionData::NjtBus(data) => (None, Some(data.occupancy_status), None),
+                PositionData::Lirr(data) => (None, None, data.status.as_deref()),
+                PositionData::Mnr(data) => (None, None, data.status.as_deref()),
+                PositionData::NjtRail(data) => (None, None, data.status.as_deref()),
+                PositionData::Gtfs(data) => (data.bearing, data.occupancy_status, None),
+            };
+
+            let position_point = match position.geom.as_ref().map(|g| &g.0) {
+                Some(Geometry::Point(point)) => Some(Position {
+                    latitude: point.y() as f32,
+                    longitude: point.x() as f32,
+                    bearing,
+                    ..Default::default()
+                }),
+                _ => None,
+            };
+
+            FeedEntity {
+                id: position.vehicle_id.clone(),
+                vehicle: Some(FeedVehiclePosition {
+                    trip: trip.map(|t| trip_descriptor(source, t, tz, service_dates)),
+                    vehicle: Some(match trip {
+                        Some(trip) => vehicle_descriptor(trip),
+                        None => VehicleDescriptor {
+                            id: Some(position.vehicle_id.clone()),
+                            ..Default::default()
+                        },
+                    }),
+                    position: position_point,
+                    stop_id: position.stop_id.clone(),
+                    current_status: vehicle_stop_status(current_status),
+                    timestamp: Some(position.updated_at.timestamp() as u64),
+                    occupancy_status: occupancy_status.map(|o| o as i32),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }
+        })
+        .collect();
+
+    feed_message(entity, now)
+}
+
+/// One `Alert` entity per alert. HTML translations use MTA's `{language}-html` convention.
+pub fn alerts_feed(alerts: &[ApiAlert], now: DateTime<Utc>) -> FeedMessage {
+    let entity = alerts
+        .iter()
+        .map(|alert| {
+            let text = |section: AlertSection| {
+                let translation: Vec<Translation> = alert
+                    .translations
+                    .iter()
+                    .filter(|t| t.section == section)
+                    .map(|t| Translation {
+                        text: t.text.clone(),
+                        language: Some(match t.format {
+                            AlertFormat::Html => format!("{}-html", t.language),
+                            AlertFormat::Plain => t.language.clone(),
+                        }),
+                    })
+                    .collect();
+                (!translation.is_empty()).then_some(TranslatedString { translation })
+            };
+
+            FeedEntity {
+                id: alert.original_id.clone(),
+                alert: Some(Alert {
+                    active_period: vec![TimeRange {
+                        start: Some(alert.start_time.timestamp() as u64),
+                        end: alert.end_time.map(|t| t.timestamp() as u64),
+                    }],
+                    informed_entity: alert
+                        .entities
+                        .iter()
+                        .map(|e| EntitySelector {
+                            route_id: Some(e.route_id.clone()),
+                            stop_id: e.stop_id.clone(),
+                            ..Default::default()
+                        })
+                        .collect(),
+                    header_text: text(AlertSection::Header),
+                    description_text: text(AlertSection::Description),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }
+        })
+        .collect();
+
+    feed_message(entity, now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::trip::{LirrData, LirrStopTimeData};
+    use chrono::TimeZone;
+    use chrono_tz::America::New_York;
+    use prost::Message;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_trip_updates_round_trip() {
+        let created_at = New_York
+            .with_ymd_and_hms(2025, 3, 1, 23, 45, 0)
+            .unwrap()
+            .with_timezone(&Utc);
+        let trip = Trip {
+            id: Uuid::now_v7(),
+            original_id: "GO103_25_2641".into(),
+            vehicle_id: Some("2641".into()),
+            route_id: "1".into(),
+            direction: 1,
+            created_at,
+            updated_at: created_at,
+            status: TripStatus::Scheduled,
+            ghost_confidence: None,
+            data: TripData::Lirr(LirrData {
+                train_number: "2641".into(),
+                headsign: "Babylon".into(),
+                car_count: None,
+                peak: None,
+            }),
+        };
+        let stop_time = |stop_id: &str, minutes: i64, track: Option<&str>| StopTime {
+            trip_id: trip.id,
+            stop_id: stop_id.into(),
+            arrival: created_at + chrono::Duration::minutes(minutes),
+            departure: created_at + chrono::Duration::minutes(minutes),
+            status: StopTimeStatus::Scheduled,
+            data: StopTimeData::Lirr(LirrStopTimeData {
+                track: track.map(Into::into),
+                status: None,
+            }),
+        };
+        // Out of order on purpose
+        let stop_times = vec![stop_time("102", 20, None), stop_time("237", 0, Some("19"))];
+
+        let feed = trip_updates_feed(
+            Source::Lirr,
+            New_York,
+            &[trip],
+            &ServiceDates::new(),
+            &stop_times,
+            Utc::now(),
+        );
+        let feed = FeedMessage::decode(feed.encode_to_vec().as_slice()).unwrap();
+
+        let update = feed.entity[0].trip_update.as_ref().unwrap();
+        assert_eq!(update.trip.trip_id.as_deref(), Some("GO103_25_2641"));
+        // Start date and time are local
+        assert_eq!(update.trip.start_date.as_deref(), Some("20250301"));
+        assert_eq!(update.trip.start_time.as_deref(), Some("23:45:00"));
+        assert_eq!(
+            update.vehicle.as_ref().unwrap().label.as_deref(),
+            Some("2641")
+        );
+
+        let stops: Vec<_> = update
+            .stop_time_update
+            .iter()
+            .map(|st| st.stop_id.as_deref().unwrap())
+            .collect();
+        assert_eq!(stops, vec!["237", "102"]);
+        assert_eq!(
+            update.stop_time_update[0]
+                .mta_railroad_stop_time_update
+                .as_ref()
+                .unwrap()
+                .track
+                .as_deref(),
+            Some("19")
+        );
+    }
+
+    #[test]
+    fn test_trip_after_midnight_uses_service_date() {
+        let created_at = New_York
+            .with_ymd_and_hms(2025, 3, 2, 1, 10, 0)
+            .unwrap()
+            .with_timezone(&Utc);
+        let trip = Trip {
+            id: Uuid::now_v7(),
+            original_id: "GO103_25_2711".into(),
+            vehicle_id: Some("2711".into()),
+            route_id: "1".into(),
+            direction: 0,
+            created_at,
+            updated_at: created_at,
+            status: TripStatus::Scheduled,
+            ghost_confidence: None,
+            data: TripData::Lirr(LirrData {
+                train_number: "2711".into(),
+                headsign: "Ronkonkoma".into(),
+                car_count: None,
+                peak: None,
+            }),
+        };
+
+        // The schedule puts the trip on the previous day's service, so its start time is past 24:00:00
+        let service_dates =
+            ServiceDates::from([(trip.id, NaiveDate::from_ymd_opt(2025, 3, 1).unwrap())]);
+        let descriptor = trip_descriptor(Source::Lirr, &trip, New_York, &service_dates);
+        assert_eq!(descriptor.start_date.as_deref(), Some("20250301"));
+        assert_eq!(descriptor.start_time.as_deref(), Some("25:10:00"));
+
+        // Without a schedule, the local date is used
+        let descriptor = trip_descriptor(Source::Lirr, &trip, New_York, &ServiceDates::new());
+        assert_eq!(descriptor.start_date.as_deref(), Some("20250302"));
+        assert_eq!(descriptor.start_time.as_deref(), Some("01:10:00"));
+    }
+
+    #[test]
+    fn test_gtfs_time_on_dst_day() {
+        // The service day starts at 11 PM EST the night before, so 3:30 AM EDT is 03:30:00 even though only 2.5
+        // hours have passed since midnight
+        let start = New_York
+            .with_ymd_and_hms(2025, 3, 9, 3, 30, 0)
+            .unwrap()
+            .with_timezone(&Utc);
+        let date = NaiveDate::from_ymd_opt(2025, 3, 9).unwrap();
+        assert_eq!(
+            gtfs_time(start, date, New_York).as_deref(),
+            Some("03:30:00")
+        );
+    }
+}
@@ -1,5 +1,5 @@
-// my attempt at centralizing the static data parsing into a generalized GTFS parser
-// but ended up being useless since each source needs a lot of custom mapping logic. For example, MTA bus GTFS doesn't include the shuttles, NJT bus shapes are terrible, etc.
+// Generalized GTFS static parser. The built-in sources need a lot of custom mapping logic (MTA bus GTFS doesn't include the shuttles, NJT bus shapes are terrible, etc.)
+// so they have their own importers, but config-driven GTFS sources use this with `DefaultMapper`.
 use std::{
     collections::{BTreeMap, HashMap, HashSet},
     io::Cursor,
@@ -15,7 +15,7 @@ use crate::{
     models::{
         route::{Route, RouteData},
         source::Source,
-        stop::{RouteStop, RouteStopData, Stop as StopModel, StopData},
+        stop::{GtfsStopData, RouteStop, RouteStopData, Stop as StopModel, StopData},
     },
     sources::{normalize_title, normalize_whitespace},
 };
@@ -67,6 +67,29 @@ pub trait StaticParserMapper {
     fn map_route_stop_data(&self, ctx: &RouteStopContext<'_>) -> anyhow::Result<RouteStopData>;
 }
 
+/// Maps every GTFS entity to the `Gtfs` variant of the source-specific data enums.
+pub struct DefaultMapper;
+
+impl StaticParserMapper for DefaultMapper {
+    fn map_route_data(&self, _route: &gtfs_structures::Route) -> anyhow::Result<RouteData> {
+        Ok(RouteData::Gtfs)
+    }
+
+    fn map_stop_data(&self, stop: &gtfs_structures::Stop) -> anyhow::Result<StopData> {
+        Ok(StopData::Gtfs(GtfsStopData {
+            code: stop.code.clone(),
+        }))
+    }
+
+    fn map_route_stop_data(&self, ctx: &RouteStopContext<'_>) -> anyhow::Result<RouteStopData> {
+        Ok(RouteStopData::Gtfs {
+            headsign: ctx.headsign.to_owned(),
+            direction: ctx.direction,
+            opposite_stop_id: ctx.opposite_stop_id.map(str::to_owned),
+        })
+    }
+}
+
 #[derive(Clone)]
 struct MergedTrip {
     trip: Trip,
@@ -98,19 +121,33 @@ struct DirectionalRouteStop {
 }
 
 pub async fn parse_archive<M: StaticParserMapper>(
-    _source: Source,
+    source: Source,
     archive: GtfsArchive,
     mapper: &M,
     options: &ParserOptions,
 ) -> anyhow::Result<ParsedStaticData> {
-    let feed_name = archive.name.clone();
+    let gtfs = read_archive(archive).await?;
+    parse_gtfs(source, &gtfs, mapper, options)
+}
+
+/// Parses a GTFS zip on a blocking thread. Useful when the caller also needs the raw feed,
+/// e.g. to expand trips for the static cache before calling [`parse_gtfs`].
+pub async fn read_archive(archive: GtfsArchive) -> anyhow::Result<Gtfs> {
+    let feed_name = archive.name;
     let bytes = archive.bytes;
-    let gtfs = tokio::task::spawn_blocking(move || Gtfs::from_reader(Cursor::new(bytes)))
+    tokio::task::spawn_blocking(move || Gtfs::from_reader(Cursor::new(bytes)))
         .await
         .context("GTFS parse task panicked")?
-        .with_context(|| format!("Failed to parse GTFS archive {feed_name}"))?;
+        .with_context(|| format!("Failed to parse GTFS archive {feed_name}"))
+}
 
-    let merged = merge_feed(&gtfs);
+pub fn parse_gtfs<M: StaticParserMapper>(
+    _source: Source,
+    gtfs: &Gtfs,
+    mapper: &M,
+    options: &ParserOptions,
+) -> anyhow::Result<ParsedStaticData> {
+    let merged = merge_feed(gtfs);
 
     let routes = build_routes(&merged, mapper, options)?;
     let stops = build_stops(&merged, mapper)?;
@@ -230,8 +267,8 @@ fn build_route_stops<M: StaticParserMapper>(
         let trip_headsign = merged_trip.trip.trip_headsign.as_deref().unwrap_or("");
 
         for stop_time in &merged_trip.trip.stop_times {
-            let route_id = merged_trip.trip.route_id.to_uppercase();
-            let stop_id = stop_time.stop.id.to_uppercase();
+            let route_id = merged_trip.trip.route_id.clone();
+            let stop_id = stop_time.stop.id.clone();
             let sequence = stop_time.stop_sequence as i16;
 
             let raw_headsign = stop_time
@@ -439,7 +476,7 @@ fn projected_stop_points(stops: &HashMap<String, Arc<Stop>>) -> HashMap<String,
             let lon = stop.longitude?;
             let mut point = Point::new(lon.to_radians(), lat.to_radians());
             transform(&proj_wgs84, &proj_ny, &mut point).ok()?;
-            Some((stop.id.to_uppercase(), point))
+            Some((stop.id.clone(), point))
         })
         .collect()
 }
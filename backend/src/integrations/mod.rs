@@ -1,4 +1,6 @@
+pub mod geojson_export;
 pub mod gtfs_alert;
 pub mod gtfs_realtime;
-// pub mod gtfs_static;
+pub mod gtfs_rt_export;
+pub mod gtfs_static;
 pub mod oba;
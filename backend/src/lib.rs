@@ -2,6 +2,7 @@ pub mod api;
 pub mod engines;
 pub mod integrations;
 pub mod macros;
+pub mod metrics;
 pub mod models;
 pub mod sources;
 pub mod stores;
@@ -24,9 +25,15 @@ pub struct AppState {
     pub stop_time_store: crate::stores::stop_time::StopTimeStore,
     pub position_store: crate::stores::position::PositionStore,
     pub alert_store: crate::stores::alert::AlertStore,
+    pub history_store: crate::stores::history::HistoryStore,
+    pub static_cache_store: crate::stores::static_cache::StaticCacheStore,
+    pub tile_store: crate::stores::tile::TileStore,
+    pub api_key_store: crate::stores::api_key::ApiKeyStore,
+    pub planner: crate::engines::planner::Planner,
 }
 
 impl AppState {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         route_store: crate::stores::route::RouteStore,
         stop_store: crate::stores::stop::StopStore,
@@ -34,6 +41,11 @@ impl AppState {
         stop_time_store: crate::stores::stop_time::StopTimeStore,
         position_store: crate::stores::position::PositionStore,
         alert_store: crate::stores::alert::AlertStore,
+        history_store: crate::stores::history::HistoryStore,
+        static_cache_store: crate::stores::static_cache::StaticCacheStore,
+        tile_store: crate::stores::tile::TileStore,
+        api_key_store: crate::stores::api_key::ApiKeyStore,
+        planner: crate::engines::planner::Planner,
     ) -> Self {
         Self {
             route_store,
@@ -42,6 +54,11 @@ impl AppState {
             stop_time_store,
             position_store,
             alert_store,
+            history_store,
+            static_cache_store,
+            tile_store,
+            api_key_store,
+            planner,
         }
     }
 }
@@ -57,6 +74,76 @@ pub fn valhalla_config() -> &'static str {
         .get_or_init(|| var("VALHALLA_CONFIG").unwrap_or_else(|_| "/data/valhalla.json".into()))
 }
 
+pub fn gtfs_sources_config() -> Option<&'static str> {
+    static GTFS_SOURCES_CONFIG: OnceLock<Option<String>> = OnceLock::new();
+    GTFS_SOURCES_CONFIG
+        .get_or_init(|| var("GTFS_SOURCES_CONFIG").ok())
+        .as_deref()
+}
+
+pub fn retention_config() -> Option<&'static str> {
+    static RETENTION_CONFIG: OnceLock<Option<String>> = OnceLock::new();
+    RETENTION_CONFIG
+        .get_or_init(|| var("RETENTION_CONFIG").ok())
+        .as_deref()
+}
+
+/// Token required by the admin endpoints. They're disabled when it isn't set.
+pub fn admin_token() -> Option<&'static str> {
+    static ADMIN_TOKEN: OnceLock<Option<String>> = OnceLock::new();
+    ADMIN_TOKEN
+        .get_or_init(|| var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty()))
+        .as_deref()
+}
+
+/// Requests per second allowed from each address without an API key. Defaults to 10; 0 requires a key.
+pub fn anonymous_rate_limit() -> Option<u32> {
+    static ANONYMOUS_RATE_LIMIT: OnceLock<Option<u32>> = OnceLock::new();
+    *ANONYMOUS_RATE_LIMIT.get_or_init(|| {
+        let limit = match var("ANONYMOUS_RATE_LIMIT") {
+            Ok(limit) => limit
+                .parse()
+                .expect("ANONYMOUS_RATE_LIMIT must be a number"),
+            Err(_) => 10,
+        };
+        (limit > 0).then_some(limit)
+    })
+}
+
+/// Reverse proxies in front of the API that append the address they're connected to to `X-Forwarded-For`. Defaults
+/// to 0, which ignores the header so clients can't pick the address they're limited by.
+pub fn trusted_proxy_hops() -> usize {
+    static TRUSTED_PROXY_HOPS: OnceLock<usize> = OnceLock::new();
+    *TRUSTED_PROXY_HOPS.get_or_init(|| match var("TRUSTED_PROXY_HOPS") {
+        Ok(hops) => hops.parse().expect("TRUSTED_PROXY_HOPS must be a number"),
+        Err(_) => 0,
+    })
+}
+
+/// Address of the listener `/metrics` is served on, which is separate from the API so it isn't public
+pub fn metrics_address() -> &'static str {
+    static METRICS_ADDRESS: OnceLock<String> = OnceLock::new();
+    METRICS_ADDRESS
+        .get_or_init(|| var("METRICS_ADDRESS").unwrap_or_else(|_| "127.0.0.1:9464".into()))
+}
+
+/// Sources that fail the `/health` readiness check when they're unhealthy, as comma-separated ids. Other sources only
+/// fail it when their pipeline is stuck.
+pub fn required_sources() -> &'static [String] {
+    static REQUIRED_SOURCES: OnceLock<Vec<String>> = OnceLock::new();
+    REQUIRED_SOURCES.get_or_init(|| {
+        var("REQUIRED_SOURCES")
+            .map(|sources| {
+                sources
+                    .split(',')
+                    .map(|s| s.trim().to_owned())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    })
+}
+
 pub fn debug_rt_data() -> &'static bool {
     static DEBUG_RT_DATA: OnceLock<bool> = OnceLock::new();
     DEBUG_RT_DATA.get_or_init(|| var("DEBUG_RT_DATA").is_ok())
@@ -68,6 +68,58 @@ macro_rules! impl_discriminated_data {
         );
     };
 
+    // Case 1b: Variant(..) => Type for tuple source variants (e.g. dynamic sources), followed by comma
+    (
+        @parse_arms
+        ($enum_name:ident)
+        ($source_type:ty)
+        ($json:ident)
+        ($var:ident (..) => $data:ty, $($rest:tt)*)
+        ($($arms:tt)*)
+    ) => {
+        impl_discriminated_data!(
+            @parse_arms
+            ($enum_name)
+            ($source_type)
+            ($json)
+            ($($rest)*)
+            (
+                $($arms)*
+                // Qualified paths aren't allowed in tuple struct patterns, so this goes through the alias
+                DiscriminatedSource::$var(..) => {
+                    let payload: $data = serde_json::from_str($json.0.get())
+                        .map_err(|e| sqlx::Error::ColumnDecode {
+                            index: "data".to_string(),
+                            source: Box::new(e),
+                        })?;
+                    Ok($enum_name::$var(payload))
+                },
+            )
+        );
+    };
+
+    // Case 2b: Variant(..) for tuple source variants without data, followed by comma
+    (
+        @parse_arms
+        ($enum_name:ident)
+        ($source_type:ty)
+        ($json:ident)
+        ($var:ident (..), $($rest:tt)*)
+        ($($arms:tt)*)
+    ) => {
+        impl_discriminated_data!(
+            @parse_arms
+            ($enum_name)
+            ($source_type)
+            ($json)
+            ($($rest)*)
+            (
+                $($arms)*
+                DiscriminatedSource::$var(..) => Ok($enum_name::$var),
+            )
+        );
+    };
+
     // Case 3: Handle (Variant => Type) at the very end (no trailing comma)
     (
         @parse_arms
@@ -105,6 +157,9 @@ macro_rules! impl_discriminated_data {
             fn from_row(row: &'r sqlx::postgres::PgRow) -> Result<Self, sqlx::Error> {
                 use sqlx::Row;
 
+                #[allow(dead_code)]
+                type DiscriminatedSource = $source_type;
+
                 let source: $source_type = row.try_get("source")?;
 
                 // We define the variable using the passed identifier $json
@@ -1,20 +1,19 @@
 use axum::{
     Json, ServiceExt,
     body::Body,
-    error_handling::HandleErrorLayer,
     extract::Request,
     response::{IntoResponse, Response},
     routing::get,
 };
 use bb8_redis::RedisConnectionManager;
-use http::{HeaderValue, Method, StatusCode, request::Parts};
+use http::StatusCode;
 use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
-use std::{convert::Infallible, env::var, sync::Arc, time::Duration};
+use std::{convert::Infallible, env::var, net::SocketAddr, sync::Arc, time::Duration};
 use tokio::{
     signal,
     sync::broadcast::{self, Sender},
 };
-use tower::{BoxError, Layer, ServiceBuilder, buffer::BufferLayer, limit::RateLimitLayer};
+use tower::{Layer, ServiceBuilder};
 use tower_http::{
     compression::CompressionLayer, normalize_path::NormalizePathLayer, trace::TraceLayer,
 };
@@ -24,10 +23,12 @@ use utoipa_axum::router::OpenApiRouter;
 use utoipa_scalar::{Scalar, Servable as ScalarServable};
 
 use backend::{
-    AppState, VERSION, api, api_prefix, engines, models, prefixed_path, sources,
+    AppState, VERSION, api, api_prefix, engines, gtfs_sources_config, metrics, metrics_address,
+    models, prefixed_path, retention_config, sources,
     sources::{
-        StaticAdapter, mta_bus::realtime::MtaBusRealtime, mta_subway::realtime::MtaSubwayRealtime,
-        njt_bus::realtime::NjtBusRealtime,
+        StaticAdapter, lirr, mnr, mta_bus::realtime::MtaBusRealtime,
+        mta_subway::realtime::MtaSubwayRealtime, njt_bus::realtime::NjtBusRealtime,
+        njt_rail::realtime::NjtRailRealtime,
     },
     stores, valhalla_config,
 };
@@ -42,6 +43,8 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
     tracing::info!("Starting Train Status API v{}", VERSION);
+    metrics::install();
+    tokio::spawn(serve_metrics());
 
     let pg_connect_option: PgConnectOptions = var("DATABASE_URL").unwrap().parse().unwrap();
     let pg_pool = PgPoolOptions::new()
@@ -80,18 +83,35 @@ async fn main() {
     let position_store = stores::position::PositionStore::new(pg_pool.clone(), redis_pool.clone());
     let alert_store = stores::alert::AlertStore::new(pg_pool.clone(), redis_pool.clone());
     let static_cache_store = stores::static_cache::StaticCacheStore::new(redis_pool.clone());
+    let tile_store = stores::tile::TileStore::new(pg_pool.clone(), redis_pool.clone());
+    let history_store = stores::history::HistoryStore::new(pg_pool.clone());
+    let api_key_store = stores::api_key::ApiKeyStore::new(pg_pool.clone(), redis_pool.clone());
 
     let valhalla_manager = engines::valhalla::ValhallaManager::new(
         engines::valhalla::ValhallaConfig::from_config_path(valhalla_config().to_owned()),
     );
 
-    let static_adapters: Vec<Arc<dyn StaticAdapter>> = vec![
+    let gtfs_adapters = match gtfs_sources_config() {
+        Some(path) => {
+            let config = sources::gtfs::config::load(path).expect("Failed to load GTFS sources");
+            sources::gtfs::register(&pg_pool, config)
+                .await
+                .expect("Failed to register GTFS sources")
+        }
+        None => sources::gtfs::GtfsAdapters::default(),
+    };
+
+    let mut static_adapters: Vec<Arc<dyn StaticAdapter>> = vec![
         Arc::new(sources::mta_subway::static_data::MtaSubwayStatic),
         Arc::new(sources::mta_bus::static_data::MtaBusStatic::new(
             valhalla_manager.clone(),
         )),
         Arc::new(sources::njt_bus::static_data::NjtBusStatic),
+        Arc::new(lirr::static_data::adapter()),
+        Arc::new(mnr::static_data::adapter()),
+        Arc::new(sources::njt_rail::static_data::NjtRailStatic),
     ];
+    static_adapters.extend(gtfs_adapters.static_adapters);
 
     let static_controller = engines::static_data::run(
         &pg_pool,
@@ -102,37 +122,62 @@ async fn main() {
     )
     .await;
 
-    let realtime_adapters: Vec<Arc<dyn sources::RealtimeAdapter>> = vec![
+    let mut realtime_adapters: Vec<Arc<dyn sources::RealtimeAdapter>> = vec![
         Arc::new(MtaSubwayRealtime),
         Arc::new(MtaBusRealtime),
         Arc::new(NjtBusRealtime),
+        Arc::new(lirr::realtime::adapter()),
+        Arc::new(mnr::realtime::adapter()),
+        Arc::new(NjtRailRealtime::new()),
     ];
+    realtime_adapters.extend(gtfs_adapters.realtime_adapters);
+
+    let planner = engines::planner::Planner::default();
 
     engines::realtime::run(
         &trip_store,
         &stop_time_store,
         &position_store,
         &static_cache_store,
+        &stop_store,
+        &history_store,
+        &planner,
         realtime_adapters,
         static_controller.clone(),
     )
     .await;
 
-    let alert_adapters: Vec<Arc<dyn sources::AlertsAdapter>> = vec![
+    let mut alert_adapters: Vec<Arc<dyn sources::AlertsAdapter>> = vec![
         Arc::new(sources::mta_bus::alerts::MtaBusAlerts),
         Arc::new(sources::mta_subway::alerts::MtaSubwayAlerts),
         Arc::new(sources::njt_bus::alerts::NjtBusAlerts),
+        Arc::new(lirr::alerts::adapter()),
+        Arc::new(mnr::alerts::adapter()),
+        Arc::new(sources::njt_rail::alerts::NjtRailAlerts),
     ];
+    alert_adapters.extend(gtfs_adapters.alert_adapters);
 
     engines::alerts::run(&alert_store, alert_adapters).await;
 
+    // Pruning deletes data, so it only runs when it has been configured
+    if let Some(path) = retention_config() {
+        let retention = engines::retention::load(path).expect("Failed to load retention config");
+        engines::retention::run(&pg_pool, retention).await;
+    }
+    engines::prediction::run(&history_store).await;
+    engines::usage::run(&api_key_store).await;
+
     let (shutdown_tx, _rx) = broadcast::channel::<()>(1);
 
     #[derive(OpenApi)]
-    #[openapi(info(title = "Train Status API", description = "The Train Status API is the simplest way to get MTA subway and bus data. Realtime data comes from the MTA's GTFS and SIRI feeds.", contact(email = "jonah@trainstat.us")),
+    #[openapi(info(title = "Train Status API", description = "The Train Status API is the simplest way to get MTA subway and bus data. Realtime data comes from the MTA's GTFS and SIRI feeds. Requests are rate limited per client; send an API key in the `X-API-Key` header (or the `api_key` parameter) for a higher limit. The `X-RateLimit-*` headers show how many requests are left.", contact(email = "jonah@trainstat.us")),
     servers((url = "/api")),
     tags(
         (name = "STATIC", description = "Data that doesn't change often (stops, routes, and shapes)"),
+        (name = "HISTORY", description = "Archived observations of realtime data, like on-time performance"),
+        (name = "ADMIN", description = "Issuing and revoking API keys, and their usage. These endpoints require the `ADMIN_TOKEN` as a bearer token."),
+        (name = "STATUS", description = "How fresh each source's data is"),
+        (name = "TILES", description = "Mapbox Vector Tiles of routes, stops and vehicle positions for every source"),
         (name = "REALTIME", description = "Data that changes around every 30 seconds (trips, stop times, and alerts). This will return data between current time and 4 hours + current time. By default, the current time is the time of the request, but you can specify the `at` parameter to get historical data.")
     ),
     components(schemas(models::source::Source))
@@ -146,6 +191,11 @@ async fn main() {
         stop_time_store,
         position_store,
         alert_store,
+        history_store,
+        static_cache_store,
+        tile_store,
+        api_key_store,
+        planner,
     };
 
     let api_prefix = api_prefix().to_owned();
@@ -173,15 +223,7 @@ async fn main() {
         .layer(
             ServiceBuilder::new()
                 .layer(TraceLayer::new_for_http())
-                .layer(CompressionLayer::new())
-                .layer(HandleErrorLayer::new(|err: BoxError| async move {
-                    (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("Unhandled error: {}", err),
-                    )
-                }))
-                .layer(BufferLayer::new(1024))
-                .layer(RateLimitLayer::new(750, Duration::from_secs(1))),
+                .layer(CompressionLayer::new()),
         )
         .fallback(handler_404);
 
@@ -193,10 +235,31 @@ async fn main() {
             .unwrap();
     tracing::info!("listening on {}", listener.local_addr().unwrap());
 
-    axum::serve(listener, ServiceExt::<Request>::into_make_service(app))
-        .with_graceful_shutdown(shutdown_signal(shutdown_tx))
+    // The peer address is used to rate limit requests without an API key
+    axum::serve(
+        listener,
+        ServiceExt::<Request>::into_make_service_with_connect_info::<SocketAddr>(app),
+    )
+    .with_graceful_shutdown(shutdown_signal(shutdown_tx))
+    .await
+    .unwrap();
+}
+
+/// Serve `/metrics` on its own listener, which is kept off the public API
+async fn serve_metrics() {
+    let listener = tokio::net::TcpListener::bind(metrics_address())
         .await
-        .unwrap();
+        .expect("Failed to bind metrics listener");
+    tracing::info!("serving metrics on {}", listener.local_addr().unwrap());
+
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(Duration::from_secs(5));
+        loop {
+            interval.tick().await;
+            metrics::run_upkeep();
+        }
+    });
+    axum::serve(listener, api::metrics::router()).await.unwrap();
 }
 
 async fn handler_404() -> impl IntoResponse {
@@ -0,0 +1,135 @@
+//! Prometheus metrics, served at `/metrics` on the internal metrics listener.
+//!
+//! Metrics are recorded with the `metrics` crate's macros from wherever the work happens, and rendered by
+//! `metrics-exporter-prometheus`. Every metric name is declared and described here so the full list is in one place.
+
+use std::sync::OnceLock;
+
+use ::metrics::{Unit, describe_counter, describe_gauge, describe_histogram};
+pub use ::metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+/// Buckets in seconds for requests and queries
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+/// Buckets in seconds for static imports, which can take several minutes
+const IMPORT_BUCKETS: &[f64] = &[
+    1.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1200.0, 1800.0,
+];
+
+pub const FEED_FETCH_SECONDS: &str = "feed_fetch_duration_seconds";
+pub const FEED_FETCH_FAILURES: &str = "feed_fetch_failures_total";
+pub const FEED_ENTITIES: &str = "feed_entities_decoded_total";
+pub const FEED_LAST_SUCCESS: &str = "feed_last_success_timestamp_seconds";
+pub const ROWS_UPSERTED: &str = "realtime_rows_upserted_total";
+pub const REALTIME_SAVE_SECONDS: &str = "realtime_save_duration_seconds";
+pub const STATIC_IMPORT_SECONDS: &str = "static_import_duration_seconds";
+pub const STATIC_IMPORT_LAST_SUCCESS: &str = "static_import_last_success_timestamp_seconds";
+pub const CACHE_LOOKUPS: &str = "cache_lookups_total";
+pub const VALHALLA_STARTS: &str = "valhalla_starts_total";
+pub const VALHALLA_START_SECONDS: &str = "valhalla_start_duration_seconds";
+pub const VALHALLA_STOPS: &str = "valhalla_stops_total";
+pub const VALHALLA_RUNNING: &str = "valhalla_running";
+pub const RETENTION_ROWS_DELETED: &str = "retention_rows_deleted_total";
+pub const RETENTION_BYTES_EXPORTED: &str = "retention_bytes_exported_total";
+pub const HTTP_REQUEST_SECONDS: &str = "http_request_duration_seconds";
+
+fn describe() {
+    describe_histogram!(
+        FEED_FETCH_SECONDS,
+        Unit::Seconds,
+        "Time to fetch and decode a realtime feed"
+    );
+    describe_counter!(
+        FEED_FETCH_FAILURES,
+        "Realtime feeds that couldn't be fetched or decoded, by stage"
+    );
+    describe_counter!(FEED_ENTITIES, "Entities decoded from realtime feeds");
+    describe_gauge!(
+        FEED_LAST_SUCCESS,
+        "Unix time a realtime feed was last fetched and decoded"
+    );
+    describe_counter!(
+        ROWS_UPSERTED,
+        "Trips, stop times and positions upserted from realtime feeds"
+    );
+    describe_histogram!(
+        REALTIME_SAVE_SECONDS,
+        Unit::Seconds,
+        "Time to upsert a source's trips and stop times"
+    );
+    describe_histogram!(
+        STATIC_IMPORT_SECONDS,
+        Unit::Seconds,
+        "Time a static data import took, by outcome"
+    );
+    describe_gauge!(
+        STATIC_IMPORT_LAST_SUCCESS,
+        "Unix time a source's static data was last imported"
+    );
+    describe_counter!(CACHE_LOOKUPS, "Redis cache lookups by cache and result");
+    describe_counter!(
+        VALHALLA_STARTS,
+        "valhalla_service process starts, by outcome"
+    );
+    describe_histogram!(
+        VALHALLA_START_SECONDS,
+        Unit::Seconds,
+        "Time for valhalla_service to become ready"
+    );
+    describe_counter!(VALHALLA_STOPS, "valhalla_service process stops, by reason");
+    describe_gauge!(
+        VALHALLA_RUNNING,
+        "Whether the valhalla_service process is running"
+    );
+    describe_counter!(
+        RETENTION_ROWS_DELETED,
+        "Rows pruned by the retention engine"
+    );
+    describe_counter!(
+        RETENTION_BYTES_EXPORTED,
+        "Compressed bytes of pruned rows exported to CSV"
+    );
+    describe_histogram!(
+        HTTP_REQUEST_SECONDS,
+        Unit::Seconds,
+        "HTTP request latency by method, route and status"
+    );
+}
+
+fn handle() -> &'static OnceLock<PrometheusHandle> {
+    static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+    &HANDLE
+}
+
+/// Install the Prometheus recorder as the global recorder. Metrics recorded before this are dropped, so it's called
+/// first thing at startup. Calling it again returns the installed recorder's handle.
+pub fn install() -> &'static PrometheusHandle {
+    handle().get_or_init(|| {
+        let handle = PrometheusBuilder::new()
+            .set_buckets(LATENCY_BUCKETS)
+            .and_then(|b| {
+                b.set_buckets_for_metric(
+                    Matcher::Full(STATIC_IMPORT_SECONDS.to_owned()),
+                    IMPORT_BUCKETS,
+                )
+            })
+            .and_then(|b| b.install_recorder())
+            .expect("failed to install the Prometheus recorder");
+        describe();
+        handle
+    })
+}
+
+/// Every recorded metric in the Prometheus text format, or nothing if the recorder isn't installed
+pub fn render() -> String {
+    handle().get().map(|h| h.render()).unwrap_or_default()
+}
+
+/// Drain histogram samples that are waiting to be rendered, so they don't pile up between scrapes
+pub fn run_upkeep() {
+    if let Some(handle) = handle().get() {
+        handle.run_upkeep();
+    }
+}
@@ -52,7 +52,7 @@ pub struct ApiAlertTranslation {
     pub text: String,
 }
 
-// used for mta subway and bus
+// used for mta subway, bus, and railroads
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
 pub struct MtaAlertData {
     pub display_before_active: i32,
@@ -67,6 +67,10 @@ pub enum AlertData {
     MtaSubway(MtaAlertData),
     MtaBus(MtaAlertData),
     NjtBus,
+    Lirr(MtaAlertData),
+    Mnr(MtaAlertData),
+    NjtRail,
+    Gtfs,
 }
 
 impl_discriminated_data!(
@@ -76,6 +80,10 @@ impl_discriminated_data!(
         MtaBus => MtaAlertData,
         MtaSubway => MtaAlertData,
         NjtBus,
+        Lirr => MtaAlertData,
+        Mnr => MtaAlertData,
+        NjtRail,
+        Gtfs(..),
     }
 );
 
@@ -0,0 +1,124 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Prefix of every key, so they're easy to recognize in code and logs
+const KEY_PREFIX: &str = "ts_";
+/// Characters of a key stored in the clear to tell keys apart
+const DISPLAY_PREFIX_LEN: usize = 10;
+
+/// Requests per second for keys that aren't given their own limit
+pub const DEFAULT_RATE_LIMIT: i32 = 50;
+
+/// A key issued to an API client
+#[derive(Clone, Debug, FromRow, Serialize, Deserialize, ToSchema)]
+pub struct ApiKey {
+    pub id: Uuid,
+    /// Who the key was issued to
+    #[schema(example = "Partner app")]
+    pub name: String,
+    /// First characters of the key
+    #[schema(example = "ts_3f9a1c2")]
+    pub prefix: String,
+    /// Requests per second the key's token bucket refills at
+    #[schema(example = 50)]
+    pub rate_limit: i32,
+    /// Most requests the key can make at once
+    #[schema(example = 100)]
+    pub burst: i32,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    pub fn limit(&self) -> RateLimit {
+        RateLimit {
+            rate: self.rate_limit.max(1) as u32,
+            burst: self.burst.max(1) as u32,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct NewApiKey {
+    #[schema(example = "Partner app")]
+    pub name: String,
+    /// Requests per second. Defaults to 50.
+    pub rate_limit: Option<i32>,
+    /// Most requests at once. Defaults to twice the rate limit.
+    pub burst: Option<i32>,
+}
+
+/// A newly issued key. The key itself is only returned here, so it has to be saved by whoever issued it.
+#[derive(Serialize, ToSchema)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    pub key: String,
+}
+
+/// Requests a key made to an endpoint on a day
+#[derive(Clone, Debug, FromRow, Serialize, Deserialize, ToSchema)]
+pub struct ApiKeyUsage {
+    #[schema(example = "/api/v1/stops/{source}")]
+    pub endpoint: String,
+    pub day: NaiveDate,
+    #[schema(example = 1520)]
+    pub requests: i64,
+}
+
+/// Token bucket settings. The bucket holds up to `burst` tokens and refills at `rate` tokens per second.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    pub rate: u32,
+    pub burst: u32,
+}
+
+/// Result of taking a token from a bucket
+#[derive(Clone, Copy, Debug)]
+pub struct BucketStatus {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until a request would be allowed again
+    pub retry_after: u64,
+    /// Seconds until the bucket is full
+    pub reset: u64,
+}
+
+/// A new random key, with 244 bits of randomness
+pub fn generate_key() -> String {
+    format!(
+        "{}{}{}",
+        KEY_PREFIX,
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    )
+}
+
+/// Hash a key is stored and looked up by
+pub fn hash_key(key: &str) -> String {
+    blake3::hash(key.as_bytes()).to_hex().to_string()
+}
+
+pub fn display_prefix(key: &str) -> String {
+    key.chars().take(DISPLAY_PREFIX_LEN).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_key() {
+        let key = generate_key();
+        assert!(key.starts_with(KEY_PREFIX));
+        assert_eq!(key.len(), KEY_PREFIX.len() + 64);
+        assert_ne!(key, generate_key());
+
+        assert_eq!(hash_key(&key), hash_key(&key));
+        assert_eq!(display_prefix(&key), key[..DISPLAY_PREFIX_LEN]);
+    }
+}
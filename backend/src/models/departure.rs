@@ -0,0 +1,263 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    api::util::point_schema,
+    models::{
+        geom::Geom,
+        source::Source,
+        stop::{RouteStop, RouteStopData, StopData},
+        trip::{StopTimeData, StopTimeStatus, TripData, TripStatus},
+    },
+    stores::alert::{ApiAlert, ApiAlertEntity},
+};
+
+/// Upcoming departures from a stop and every stop it has an in-station transfer to
+#[derive(Serialize, ToSchema)]
+pub struct DepartureBoard {
+    /// Stops included in the board. The requested stop is first.
+    pub stops: Vec<BoardStop>,
+    /// Departures from any of the stops, ordered by departure time
+    pub departures: Vec<Departure>,
+    /// Active alerts for the stops or for routes that serve them
+    pub alerts: Vec<ApiAlert>,
+}
+
+#[derive(Serialize, ToSchema, FromRow, Debug)]
+pub struct BoardStop {
+    #[schema(example = "127")]
+    pub id: String,
+    pub source: Source,
+    #[schema(example = "Times Sq-42 St")]
+    pub name: String,
+    #[serde(skip)]
+    #[sqlx(json)]
+    pub data: StopData,
+    #[serde(skip)]
+    #[sqlx(json)]
+    pub routes: Vec<RouteStop>,
+}
+
+/// A stop near a point, with its next departures
+#[derive(Serialize, ToSchema, FromRow, Debug)]
+pub struct NearbyStop {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub stop: BoardStop,
+    #[schema(schema_with = point_schema)]
+    pub geom: Geom,
+    /// Distance from the point in meters
+    #[schema(example = 212.5)]
+    pub distance: f64,
+    /// Next departures from the stop, ordered by departure time
+    #[sqlx(skip)]
+    pub departures: Vec<Departure>,
+}
+
+#[derive(Clone, Serialize, ToSchema, Debug)]
+pub struct Departure {
+    pub trip_id: Uuid,
+    pub source: Source,
+    /// Stop the trip departs from, which is one of the board's stops
+    #[schema(example = "725")]
+    pub stop_id: String,
+    #[schema(example = "7")]
+    pub route_id: String,
+    pub direction: i16,
+    #[schema(example = "Flushing-Main St")]
+    pub headsign: Option<String>,
+    pub arrival: DateTime<Utc>,
+    pub departure: DateTime<Utc>,
+    /// Canceled trips are included so riders can see why they're gone
+    pub trip_status: TripStatus,
+    /// Whether the trip stops here. Skipped stops are included for the same reason.
+    pub status: StopTimeStatus,
+    /// Track the trip departs from, if the source provides one
+    #[schema(example = "B2")]
+    pub track: Option<String>,
+}
+
+/// Row returned by the departures query, before headsigns and tracks are resolved
+#[derive(FromRow)]
+pub struct DepartureRow {
+    pub trip_id: Uuid,
+    pub source: Source,
+    pub stop_id: String,
+    pub route_id: String,
+    pub direction: i16,
+    pub arrival: DateTime<Utc>,
+    pub departure: DateTime<Utc>,
+    pub trip_status: TripStatus,
+    pub status: StopTimeStatus,
+    #[sqlx(json)]
+    pub trip_data: TripData,
+    #[sqlx(json)]
+    pub stop_time_data: StopTimeData,
+}
+
+impl Departure {
+    pub fn new(row: DepartureRow, stop: &BoardStop) -> Self {
+        let headsign = stop.headsign(&row.trip_data, &row.route_id, row.direction);
+        let track = match row.stop_time_data {
+            // Prefer the track the train is actually using when it's rerouted
+            StopTimeData::MtaSubway(data) => data.actual_track.or(data.scheduled_track),
+            StopTimeData::Lirr(data) => data.track,
+            StopTimeData::Mnr(data) => data.track,
+            StopTimeData::NjtRail(data) => data.track,
+            StopTimeData::MtaBus(_) | StopTimeData::NjtBus | StopTimeData::Gtfs => None,
+        };
+
+        Self {
+            trip_id: row.trip_id,
+            source: row.source,
+            stop_id: row.stop_id,
+            route_id: row.route_id,
+            direction: row.direction,
+            headsign,
+            arrival: row.arrival,
+            departure: row.departure,
+            trip_status: row.trip_status,
+            status: row.status,
+            track,
+        }
+    }
+}
+
+impl BoardStop {
+    /// Headsign of a trip at this stop. Trip headsigns are used when the source has them, then the headsign of the
+    /// route in the trip's direction, then the subway stop's headsign for the trip's direction.
+    pub fn headsign(&self, trip_data: &TripData, route_id: &str, direction: i16) -> Option<String> {
+        let trip_headsign = match trip_data {
+            TripData::NjtBus(data) => Some(&data.headsign),
+            TripData::Lirr(data) => Some(&data.headsign),
+            TripData::Mnr(data) => Some(&data.headsign),
+            TripData::NjtRail(data) => Some(&data.headsign),
+            TripData::Gtfs(data) => Some(&data.headsign),
+            TripData::MtaBus(_) | TripData::MtaSubway(_) => None,
+        };
+        if let Some(headsign) = trip_headsign.filter(|h| !h.is_empty()) {
+            return Some(headsign.clone());
+        }
+
+        let route_headsign = self.routes.iter().find_map(|r| match &r.data {
+            RouteStopData::MtaBus {
+                headsign,
+                direction: d,
+                ..
+            }
+            | RouteStopData::NjtBus {
+                headsign,
+                direction: d,
+                ..
+            }
+            | RouteStopData::Gtfs {
+                headsign,
+                direction: d,
+                ..
+            } if r.route_id == route_id && *d == direction => Some(headsign.clone()),
+            _ => None,
+        });
+        if route_headsign.is_some() {
+            return route_headsign;
+        }
+
+        match (&self.data, direction) {
+            (StopData::MtaSubway(data), 1) => Some(data.north_headsign.clone()),
+            (StopData::MtaSubway(data), 3) => Some(data.south_headsign.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether an alert entity is for this stop, or for a whole route that serves it
+    pub fn is_affected(&self, entity: &ApiAlertEntity) -> bool {
+        match &entity.stop_id {
+            Some(stop_id) => stop_id.eq_ignore_ascii_case(&self.id),
+            None => self.routes.iter().any(|r| r.route_id == entity.route_id),
+        }
+    }
+}
+
+/// Resolve a platform to its parent station. Subway platforms are the station ID followed by N or S, which also
+/// implies the direction (1 is northbound, 3 is southbound).
+pub fn parent_station(source: Source, stop_id: &str) -> (String, Option<i16>) {
+    let stop_id = stop_id.to_uppercase();
+    if source == Source::MtaSubway && stop_id.len() > 1 {
+        if let Some(station) = stop_id.strip_suffix('N') {
+            return (station.to_owned(), Some(1));
+        }
+        if let Some(station) = stop_id.strip_suffix('S') {
+            return (station.to_owned(), Some(3));
+        }
+    }
+    (stop_id, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::stop::{Borough, MtaSubwayStopData, StopType};
+    use crate::models::trip::{LirrData, MtaSubwayData};
+
+    fn times_sq() -> BoardStop {
+        BoardStop {
+            id: "725".into(),
+            source: Source::MtaSubway,
+            name: "Times Sq-42 St".into(),
+            data: StopData::MtaSubway(MtaSubwayStopData {
+                ada: true,
+                notes: None,
+                north_headsign: "Queens".into(),
+                south_headsign: "Manhattan".into(),
+                borough: Borough::Manhattan,
+            }),
+            routes: vec![RouteStop {
+                route_id: "7".into(),
+                stop_id: "725".into(),
+                stop_sequence: 22,
+                data: RouteStopData::MtaSubway {
+                    stop_type: StopType::FullTime,
+                },
+            }],
+        }
+    }
+
+    #[test]
+    fn test_headsign() {
+        let stop = times_sq();
+        let subway = TripData::MtaSubway(MtaSubwayData { delay: None });
+        assert_eq!(stop.headsign(&subway, "7", 1).as_deref(), Some("Queens"));
+        assert_eq!(stop.headsign(&subway, "7", 3).as_deref(), Some("Manhattan"));
+
+        let lirr = TripData::Lirr(LirrData {
+            train_number: "2711".into(),
+            headsign: "Port Washington".into(),
+            car_count: None,
+            peak: None,
+        });
+        assert_eq!(
+            stop.headsign(&lirr, "1", 0).as_deref(),
+            Some("Port Washington")
+        );
+    }
+
+    #[test]
+    fn test_parent_station() {
+        assert_eq!(
+            parent_station(Source::MtaSubway, "127n"),
+            ("127".into(), Some(1))
+        );
+        assert_eq!(
+            parent_station(Source::MtaSubway, "R16S"),
+            ("R16".into(), Some(3))
+        );
+        assert_eq!(
+            parent_station(Source::MtaSubway, "127"),
+            ("127".into(), None)
+        );
+        // Only subway platforms have direction suffixes
+        assert_eq!(parent_station(Source::Mnr, "1S"), ("1S".into(), None));
+    }
+}
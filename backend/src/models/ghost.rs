@@ -0,0 +1,46 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// What's known about the vehicle running a trip, used to decide whether the trip is a ghost
+#[derive(Clone, Debug, FromRow, PartialEq)]
+pub struct GhostSignals {
+    pub trip_id: Uuid,
+    pub route_id: String,
+    pub trip_updated_at: DateTime<Utc>,
+    /// When the trip's vehicle position was last updated. `None` if no position is linked to the trip.
+    pub position_updated_at: Option<DateTime<Utc>>,
+    /// Distance in meters between the vehicle and the route's shape. `None` if either has no geometry.
+    pub distance_from_route: Option<f64>,
+    /// Number of stops, in the trip's own stop order, between the stop the vehicle reports and the next stop the trip
+    /// is predicted at. `None` if the vehicle doesn't report a stop or it isn't one of the trip's stops.
+    pub stop_gap: Option<i32>,
+}
+
+/// Running and suspected ghost trips on a route at one point in time
+#[derive(Clone, Debug, PartialEq)]
+pub struct GhostSample {
+    pub route_id: String,
+    pub trips: i32,
+    pub ghosts: i32,
+}
+
+/// Share of a route's trips that were suspected ghosts over a period of time
+#[derive(Clone, Debug, FromRow, Serialize, Deserialize, ToSchema)]
+pub struct GhostRate {
+    #[schema(example = "B46")]
+    pub route_id: String,
+    /// Start of the period
+    pub time: DateTime<Utc>,
+    /// Average number of running trips
+    #[schema(example = 24.5)]
+    pub average_trips: f64,
+    /// Average number of suspected ghost trips
+    #[schema(example = 2.1)]
+    pub average_ghosts: f64,
+    /// Percentage of running trips that were suspected ghosts
+    #[schema(example = 8.6)]
+    pub ghost_percentage: Option<f64>,
+}
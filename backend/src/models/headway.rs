@@ -0,0 +1,72 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Predicted headways at every upcoming stop of a route
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug, PartialEq)]
+pub struct RouteHeadways {
+    #[schema(example = "M15")]
+    pub route_id: String,
+    /// Number of distinct pairs of trips that are bunched at any stop
+    pub bunched_pairs: usize,
+    /// Number of distinct pairs of trips with a big gap between them at any stop
+    pub gap_pairs: usize,
+    /// Why every scheduled headway is `null`, when the source has no static schedule for its realtime trips
+    pub schedule_unavailable_reason: Option<String>,
+    /// Stops in route order, for each direction
+    pub stops: Vec<StopHeadways>,
+}
+
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug, PartialEq)]
+pub struct StopHeadways {
+    #[schema(example = "401921")]
+    pub stop_id: String,
+    pub direction: i16,
+    /// Average predicted time between consecutive arrivals in seconds
+    pub average_headway: Option<f64>,
+    /// Average scheduled time between the same arrivals in seconds, for sources with a static schedule
+    pub average_scheduled_headway: Option<f64>,
+    /// Time between each predicted arrival and the one before it
+    pub headways: Vec<Headway>,
+}
+
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug, PartialEq)]
+pub struct Headway {
+    /// Trip that arrives first
+    pub leading_trip_id: Uuid,
+    /// Trip that arrives after the leading trip
+    pub trip_id: Uuid,
+    pub arrival: DateTime<Utc>,
+    /// Seconds between the leading trip's arrival and this trip's arrival
+    #[schema(example = 420)]
+    pub headway: i64,
+    /// Seconds between the two trips' scheduled arrivals
+    pub scheduled_headway: Option<i64>,
+    pub status: HeadwayStatus,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, ToSchema, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HeadwayStatus {
+    Normal,
+    /// The trips are arriving within 2 minutes of each other
+    Bunched,
+    /// The headway is at least twice the scheduled headway, or twice the stop's average when there's no schedule
+    Gap,
+}
+
+/// A predicted arrival of a route's trip at one of its stops
+#[derive(Clone, Debug, FromRow, PartialEq)]
+pub struct HeadwayArrival {
+    pub trip_id: Uuid,
+    pub original_trip_id: String,
+    /// Start of the trip, used to find its static schedule
+    pub trip_created_at: DateTime<Utc>,
+    pub direction: i16,
+    pub stop_id: String,
+    pub arrival: DateTime<Utc>,
+    /// `None` until the schedule is looked up, or if the trip isn't in the static schedule
+    pub scheduled_arrival: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,45 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// A stop time that has been passed, with the last prediction before it dropped out of the feed as the observed time
+#[derive(Clone, Debug, FromRow, PartialEq)]
+pub struct StopTimeObservation {
+    pub trip_id: Uuid,
+    pub stop_id: String,
+    pub original_trip_id: String,
+    pub route_id: String,
+    pub direction: i16,
+    /// Start of the trip, used to find its static schedule
+    pub trip_created_at: DateTime<Utc>,
+    pub arrival: DateTime<Utc>,
+    pub departure: DateTime<Utc>,
+    pub scheduled_arrival: Option<DateTime<Utc>>,
+    pub scheduled_departure: Option<DateTime<Utc>>,
+}
+
+/// Reliability of a route over a period of time
+#[derive(Clone, Debug, FromRow, Serialize, Deserialize, ToSchema)]
+pub struct RoutePerformance {
+    #[schema(example = "1")]
+    pub route_id: String,
+    /// Number of observed stop arrivals
+    pub observations: i64,
+    /// Number of observed stop arrivals that have a scheduled time
+    pub scheduled_observations: i64,
+    /// Percentage of scheduled arrivals between 1 minute early and 5 minutes late
+    #[schema(example = 87.5)]
+    pub on_time_percentage: Option<f64>,
+    /// Average difference between the observed and scheduled arrival in seconds. Positive means late.
+    #[schema(example = 94.2)]
+    pub average_delay: Option<f64>,
+    /// Average time between consecutive arrivals at the same stop and direction in seconds
+    pub average_headway: Option<f64>,
+    /// Average scheduled time between the same consecutive arrivals in seconds
+    pub average_scheduled_headway: Option<f64>,
+    /// Percentage of headways that are no more than 25% longer than scheduled
+    #[schema(example = 78.1)]
+    pub headway_adherence: Option<f64>,
+}
@@ -1,9 +1,18 @@
 pub mod alert;
+pub mod api_key;
+pub mod departure;
 pub mod geom;
+pub mod ghost;
+pub mod headway;
+pub mod history;
+pub mod plan;
 pub mod position;
+pub mod prediction;
 pub mod route;
 pub mod source;
 pub mod static_cache;
+pub mod status;
 pub mod stop;
+pub mod tile;
 pub mod trip;
 // TODO: fix source being stored in column and in the enum json data. it should only be in the column
@@ -0,0 +1,63 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::models::source::Source;
+
+/// A journey from one stop to another. Itineraries are returned in order of increasing transfers,
+/// and each one arrives earlier than every itinerary with fewer transfers.
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug, PartialEq)]
+pub struct Itinerary {
+    pub departure: DateTime<Utc>,
+    pub arrival: DateTime<Utc>,
+    /// Total time from departure to arrival in seconds
+    #[schema(example = 1860)]
+    pub duration: i64,
+    /// Number of times the rider changes vehicles
+    pub transfers: usize,
+    pub legs: Vec<Leg>,
+}
+
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Leg {
+    /// Riding a single trip between two of its stops
+    Transit {
+        source: Source,
+        #[schema(example = "1")]
+        route_id: String,
+        /// Realtime trip ID, which can be looked up in the trips endpoint
+        trip_id: Uuid,
+        from: LegStop,
+        to: LegStop,
+        departure: DateTime<Utc>,
+        arrival: DateTime<Utc>,
+        /// Number of stops ridden, not counting the boarding stop
+        stops: usize,
+        /// Seconds spent waiting at the boarding stop before departure. For every leg after the first, this
+        /// plus any walking before it is the transfer time.
+        wait: i64,
+    },
+    /// Walking between two stops using a transfer from `static.stop_transfer`
+    Walk {
+        from: LegStop,
+        to: LegStop,
+        departure: DateTime<Utc>,
+        arrival: DateTime<Utc>,
+        /// Walking time in seconds. This is the feed's `min_transfer_time` if it has one, otherwise it's estimated
+        /// from the distance between the stops.
+        duration: i64,
+        /// GTFS transfer type, or 6 for proximity transfers
+        transfer_type: i16,
+    },
+}
+
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug, PartialEq)]
+pub struct LegStop {
+    #[schema(example = "101")]
+    pub id: String,
+    pub source: Source,
+    #[schema(example = "Van Cortlandt Park-242 St")]
+    pub name: String,
+}
@@ -8,7 +8,7 @@ use crate::{
     api::util::point_schema,
     feed::vehicle_position::OccupancyStatus,
     impl_discriminated_data,
-    models::{geom::Geom, source::Source},
+    models::{geom::Geom, source::Source, trip::TripDetailStopTime},
 };
 
 /// Current vehicle position (for upsert into vehicle_position table)
@@ -25,6 +25,29 @@ pub struct VehiclePosition {
     pub geom: Option<Geom>,
 }
 
+/// Vehicle position returned by the API, which is moved along the vehicle's route when it can be estimated
+#[derive(Clone, ToSchema, Deserialize, Serialize)]
+pub struct ApiVehiclePosition {
+    #[serde(flatten)]
+    pub position: VehiclePosition,
+    /// Whether `geom` is estimated from the trip's stop times or snapped to the route, rather than from the feed
+    pub estimated: bool,
+    /// Direction of travel in degrees clockwise from north
+    pub bearing: Option<f32>,
+    /// The trip's next stops. Only included when filtering by bounding box.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_departures: Option<Vec<TripDetailStopTime>>,
+}
+
+/// A point on a vehicle's route line where the vehicle is estimated to be
+#[derive(Clone, FromRow)]
+pub struct PositionEstimate {
+    pub vehicle_id: String,
+    pub geom: Geom,
+    /// Direction of the route line at the point, for trains placed between stops
+    pub bearing: Option<f32>,
+}
+
 // the struct names must be unique, otherwise the generated OpenAPI schema will have issues
 #[derive(Clone, Serialize, Deserialize, ToSchema)]
 pub struct MtaSubwayPositionData {
@@ -46,12 +69,42 @@ pub struct NjtBusPositionData {
     pub occupancy_status: OccupancyStatus,
 }
 
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct LirrPositionData {
+    pub status: Option<String>,
+    /// Number of cars in the consist, from the feed's carriage details
+    pub car_count: Option<i32>,
+}
+
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct MnrPositionData {
+    pub status: Option<String>,
+    /// Number of cars in the consist, from the feed's carriage details
+    pub car_count: Option<i32>,
+}
+
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct NjtRailPositionData {
+    pub status: Option<String>,
+}
+
+/// Used by config-driven GTFS sources
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct GtfsPositionData {
+    pub bearing: Option<f32>,
+    pub occupancy_status: Option<OccupancyStatus>,
+}
+
 #[derive(Clone, Serialize, Deserialize, ToSchema)]
 #[serde(tag = "source", rename_all = "snake_case")]
 pub enum PositionData {
     MtaSubway(MtaSubwayPositionData),
     MtaBus(MtaBusPositionData),
     NjtBus(NjtBusPositionData),
+    Lirr(LirrPositionData),
+    Mnr(MnrPositionData),
+    NjtRail(NjtRailPositionData),
+    Gtfs(GtfsPositionData),
 }
 
 impl_discriminated_data!(
@@ -61,5 +114,9 @@ impl_discriminated_data!(
         MtaBus => MtaBusPositionData,
         MtaSubway => MtaSubwayPositionData,
         NjtBus => NjtBusPositionData,
+        Lirr => LirrPositionData,
+        Mnr => MnrPositionData,
+        NjtRail => NjtRailPositionData,
+        Gtfs(..) => GtfsPositionData,
     }
 );
@@ -0,0 +1,28 @@
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// A stop on the route of a trip that needs predictions from the backend, along with where its vehicle is.
+/// Distances are in meters along the route line closest to the vehicle.
+#[derive(Clone, Debug, FromRow, PartialEq)]
+pub struct PredictionStop {
+    pub trip_id: Uuid,
+    pub route_id: String,
+    pub direction: i16,
+    pub position_updated_at: DateTime<Utc>,
+    pub vehicle_distance: f64,
+    pub stop_id: String,
+    pub stop_sequence: i16,
+    pub stop_distance: f64,
+}
+
+/// Typical time to travel between two consecutive stops of a route
+#[derive(Clone, Debug, FromRow, PartialEq)]
+pub struct SegmentTravelTime {
+    pub route_id: String,
+    pub direction: i16,
+    pub from_stop_id: String,
+    pub to_stop_id: String,
+    /// Median travel time in seconds
+    pub travel_time: f32,
+}
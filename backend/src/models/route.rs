@@ -31,6 +31,12 @@ pub struct MtaBusRouteData {
     pub shuttle: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MnrRouteData {
+    /// Line page on the Metro-North website, from GTFS `route_url`
+    pub url: Option<String>,
+}
+
 /// Stop data changes based on the `Source`
 #[derive(Serialize, Deserialize, ToSchema)]
 #[serde(tag = "source", rename_all = "snake_case")]
@@ -38,6 +44,10 @@ pub enum RouteData {
     MtaBus(MtaBusRouteData),
     MtaSubway,
     NjtBus,
+    Lirr,
+    Mnr(MnrRouteData),
+    NjtRail,
+    Gtfs,
 }
 
 impl_discriminated_data!(
@@ -47,5 +57,9 @@ impl_discriminated_data!(
         MtaBus => MtaBusRouteData,
         MtaSubway,
         NjtBus,
+        Lirr,
+        Mnr => MnrRouteData,
+        NjtRail,
+        Gtfs(..),
     }
 );
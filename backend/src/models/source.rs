@@ -1,28 +1,218 @@
-use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use std::borrow::Cow;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
 
-#[derive(sqlx::Type, Copy, Clone, Hash, Eq, PartialEq, Debug, Serialize, Deserialize, ToSchema)]
-#[sqlx(type_name = "source_enum", rename_all = "snake_case")]
-#[serde(rename_all = "snake_case")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::{
+    Decode, Encode, Postgres, Type,
+    encode::IsNull,
+    error::BoxDynError,
+    postgres::{PgArgumentBuffer, PgHasArrayType, PgTypeInfo, PgValueRef},
+};
+use utoipa::{
+    PartialSchema, ToSchema,
+    openapi::{ObjectBuilder, RefOr, Schema, Type as SchemaType},
+};
+
+/// Ids of the config-driven GTFS sources, registered once at startup.
+static DYNAMIC_SOURCES: OnceLock<Vec<&'static str>> = OnceLock::new();
+
+/// `source_enum` values left in the database by GTFS sources that were removed from the config.
+/// Postgres can't drop enum values, so rows from these sources can still be read.
+static RETIRED_SOURCES: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+/// Stored as `source_enum` in Postgres and as its snake_case id in JSON.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
 pub enum Source {
     MtaSubway,
     MtaBus,
     NjtBus,
-    // below not implemented yet
-    // Lirr,
-    // Mnr,
-    // NjtRail,
+    Lirr,
+    Mnr,
+    NjtRail,
+    /// A generic GTFS source loaded from the sources config file. The id is also its `source_enum` value.
+    Gtfs(&'static str),
 }
 
 impl Source {
+    /// All built-in sources, not including dynamic GTFS sources
+    pub const BUILT_IN: [Source; 6] = [
+        Source::MtaSubway,
+        Source::MtaBus,
+        Source::NjtBus,
+        Source::Lirr,
+        Source::Mnr,
+        Source::NjtRail,
+    ];
+
     pub fn as_str(&self) -> &'static str {
         match self {
             Source::MtaSubway => "mta_subway",
             Source::MtaBus => "mta_bus",
             Source::NjtBus => "njt_bus",
-            // Source::Lirr => "lirr",
-            // Source::Mnr => "mnr",
-            // Source::NjtRail => "njt_rail",
+            Source::Lirr => "lirr",
+            Source::Mnr => "mnr",
+            Source::NjtRail => "njt_rail",
+            Source::Gtfs(id) => id,
+        }
+    }
+
+    /// Whether the source is a railroad, whose stations are spread out more than subway and bus stops
+    pub fn is_rail(&self) -> bool {
+        matches!(self, Source::Lirr | Source::Mnr | Source::NjtRail)
+    }
+
+    /// Parses a source id, including registered dynamic sources.
+    pub fn from_id(id: &str) -> Option<Self> {
+        if let Some(source) = Self::BUILT_IN.into_iter().find(|s| s.as_str() == id) {
+            return Some(source);
+        }
+
+        DYNAMIC_SOURCES
+            .get()?
+            .iter()
+            .find(|&&dynamic| dynamic == id)
+            .map(|&dynamic| Source::Gtfs(dynamic))
+    }
+
+    /// Whether `id` matches `[a-z0-9_]+`, which all source ids must
+    pub fn is_valid_id(id: &str) -> bool {
+        !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    }
+
+    /// Registers the ids of config-driven GTFS sources. Can only be called once, before any
+    /// dynamic source is parsed from the database or a request.
+    pub fn register_dynamic(ids: Vec<String>) -> anyhow::Result<Vec<Source>> {
+        for id in &ids {
+            if Self::BUILT_IN.iter().any(|s| s.as_str() == id) {
+                anyhow::bail!("GTFS source id {id} conflicts with a built-in source");
+            }
+            if !Self::is_valid_id(id) {
+                anyhow::bail!("GTFS source id {id} must be lowercase snake_case");
+            }
         }
+
+        // Leaked once at startup so Source can stay Copy
+        let ids: Vec<&'static str> = ids
+            .into_iter()
+            .map(|id| &*Box::leak(id.into_boxed_str()))
+            .collect();
+        DYNAMIC_SOURCES
+            .set(ids.clone())
+            .map_err(|_| anyhow::anyhow!("Dynamic sources were already registered"))?;
+
+        Ok(ids.into_iter().map(Source::Gtfs).collect())
+    }
+
+    /// Maps a `source_enum` value that isn't a registered source to a `Gtfs` source, so rows left behind by a
+    /// removed GTFS source still decode. Retired sources aren't included in `all` or accepted by `from_id`.
+    fn retired(id: &str) -> Self {
+        let mut retired = RETIRED_SOURCES.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(&existing) = retired.iter().find(|&&r| r == id) {
+            return Source::Gtfs(existing);
+        }
+
+        // Bounded by the number of values in source_enum, so leaking is fine
+        let id: &'static str = Box::leak(id.to_owned().into_boxed_str());
+        retired.push(id);
+        Source::Gtfs(id)
+    }
+
+    /// All built-in and registered dynamic sources
+    pub fn all() -> Vec<Source> {
+        let mut sources = Self::BUILT_IN.to_vec();
+        if let Some(dynamic) = DYNAMIC_SOURCES.get() {
+            sources.extend(dynamic.iter().map(|&id| Source::Gtfs(id)));
+        }
+        sources
+    }
+}
+
+/// An id scoped to a source, written as `{source}:{id}` (e.g. `mta_subway:127`) in query strings and messages.
+/// Source ids never contain a colon, so everything after the first one is the id.
+#[derive(Clone, Hash, Eq, PartialEq, Debug)]
+pub struct SourceId {
+    pub source: Source,
+    pub id: String,
+}
+
+impl FromStr for SourceId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (source, id) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected {{source}}:{{id}}, got {s}"))?;
+        let source = Source::from_id(source).ok_or_else(|| format!("unknown source {source}"))?;
+        Ok(SourceId {
+            source,
+            id: id.to_owned(),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for SourceId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Cow::<'de, str>::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Source {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Source {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let id = Cow::<'de, str>::deserialize(deserializer)?;
+        Source::from_id(&id).ok_or_else(|| serde::de::Error::custom(format!("unknown source {id}")))
+    }
+}
+
+impl Type<Postgres> for Source {
+    fn type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("source_enum")
+    }
+}
+
+impl PgHasArrayType for Source {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_source_enum")
+    }
+}
+
+impl Encode<'_, Postgres> for Source {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        <&str as Encode<Postgres>>::encode_by_ref(&self.as_str(), buf)
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for Source {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        let id = <&str as Decode<Postgres>>::decode(value)?;
+        Ok(Source::from_id(id).unwrap_or_else(|| Source::retired(id)))
+    }
+}
+
+impl PartialSchema for Source {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new()
+            .schema_type(SchemaType::String)
+            .description(Some(
+                "Built-in sources, or the id of a GTFS source from the sources config file",
+            ))
+            .examples([serde_json::json!("mta_subway")])
+            .into()
+    }
+}
+
+impl ToSchema for Source {
+    fn name() -> Cow<'static, str> {
+        Cow::Borrowed("Source")
     }
 }
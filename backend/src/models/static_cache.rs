@@ -1,4 +1,5 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,3 +21,25 @@ pub struct CachedTrip {
     pub start_time: DateTime<Utc>,
     pub stop_times: Vec<CachedStopTime>,
 }
+
+/// The instant GTFS times on a service day are measured from: noon minus 12h. That is midnight except on DST
+/// transition days, where it is 11 PM (spring forward) or 1 AM (fall back) local time.
+pub fn service_day_start(date: NaiveDate, tz: Tz) -> Option<DateTime<Utc>> {
+    // Noon is never skipped or repeated by a DST transition
+    let noon = tz
+        .from_local_datetime(&date.and_hms_opt(12, 0, 0)?)
+        .single()?;
+    Some((noon - chrono::Duration::hours(12)).with_timezone(&Utc))
+}
+
+/// Convert a GTFS time (seconds since the start of the service day, which can be past 24:00:00) to UTC.
+///
+/// Measuring from the start of the service day (see [`service_day_start`]) means every time maps to exactly one
+/// instant, even ones that fall in the skipped or repeated hour.
+pub fn calculate_datetime(
+    date: NaiveDate,
+    seconds_since_midnight: u32,
+    tz: Tz,
+) -> Option<DateTime<Utc>> {
+    Some(service_day_start(date, tz)? + chrono::Duration::seconds(seconds_since_midnight as i64))
+}
@@ -0,0 +1,82 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::models::source::Source;
+
+/// How fresh a source's data is, from best to worst
+#[derive(Clone, Copy, Debug, Serialize, ToSchema, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Health {
+    Healthy,
+    /// Some data is stale or the last run failed, but the source is still being updated
+    Degraded,
+    /// No fresh realtime data, or the pipeline is stuck
+    Unhealthy,
+}
+
+/// What a feed is for. Only trip and vehicle feeds can make a source unhealthy.
+#[derive(Clone, Copy, Debug, Default, Serialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedKind {
+    #[default]
+    Realtime,
+    Alerts,
+}
+
+/// The last fetch of one GTFS-RT feed
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct FeedStatus {
+    #[schema(example = "mta_subway-ace")]
+    pub label: String,
+    pub kind: FeedKind,
+    pub status: Health,
+    pub last_success: Option<DateTime<Utc>>,
+    /// Timestamp in the feed's header, which is when the agency generated it
+    pub header_timestamp: Option<DateTime<Utc>>,
+    /// Entities in the last successful fetch
+    #[schema(example = 212)]
+    pub entities: usize,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct SourceStatus {
+    pub source: Source,
+    /// Worst of the pipeline, feed and static statuses
+    pub status: Health,
+    /// When the realtime pipeline last finished a run, successful or not
+    pub last_run: Option<DateTime<Utc>>,
+    pub last_run_error: Option<String>,
+    /// Whether the realtime pipeline panicked. It isn't restarted, so the source stops updating.
+    pub panicked: bool,
+    /// When static data was last imported, from `source.updated_at`
+    pub static_updated_at: Option<DateTime<Utc>>,
+    /// Whether a static import is running. Realtime data isn't updated until it's done.
+    pub importing: bool,
+    pub feeds: Vec<FeedStatus>,
+}
+
+/// How old data can get before it's degraded or unhealthy
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct Thresholds {
+    /// Realtime data older than this is degraded
+    #[schema(example = 180)]
+    pub degraded_after_secs: u64,
+    /// Realtime data older than this is unhealthy, as is a pipeline that hasn't finished a run in this long
+    #[schema(example = 600)]
+    pub unhealthy_after_secs: u64,
+    /// Static data is degraded once it's this many refresh intervals old
+    #[schema(example = 2)]
+    pub static_degraded_intervals: u32,
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct StatusReport {
+    /// Worst status of every source
+    pub status: Health,
+    pub checked_at: DateTime<Utc>,
+    pub thresholds: Thresholds,
+    pub sources: Vec<SourceStatus>,
+}
@@ -62,6 +62,35 @@ pub struct NjtBusStopData {
     pub stop_code: String,
 }
 
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct LirrStopData {
+    /// Fare zone from GTFS `zone_id`. Peak and off-peak fares are priced between zones.
+    #[schema(example = "1")]
+    pub fare_zone: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct MnrStopData {
+    /// Whether the station is wheelchair accessible, from GTFS `wheelchair_boarding`
+    pub ada: bool,
+    /// Fare zone from GTFS `zone_id`
+    pub fare_zone: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct NjtRailStopData {
+    /// Two letter station code (e.g. "NY" for New York Penn Station) used by NJT's rail APIs
+    #[schema(example = "NY")]
+    pub stop_code: Option<String>,
+}
+
+/// Used by config-driven GTFS sources
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct GtfsStopData {
+    /// Public-facing stop code from GTFS `stop_code`
+    pub code: Option<String>,
+}
+
 /// Stop data changes based on the `Source`
 #[derive(Serialize, Deserialize, ToSchema, Debug)]
 #[serde(tag = "source", rename_all = "snake_case")]
@@ -69,6 +98,10 @@ pub enum StopData {
     MtaSubway(MtaSubwayStopData),
     MtaBus(MtaBusStopData),
     NjtBus(NjtBusStopData),
+    Lirr(LirrStopData),
+    Mnr(MnrStopData),
+    NjtRail(NjtRailStopData),
+    Gtfs(GtfsStopData),
 }
 
 impl_discriminated_data!(
@@ -78,6 +111,10 @@ impl_discriminated_data!(
         MtaBus => MtaBusStopData,
         MtaSubway => MtaSubwayStopData,
         NjtBus => NjtBusStopData,
+        Lirr => LirrStopData,
+        Mnr => MnrStopData,
+        NjtRail => NjtRailStopData,
+        Gtfs(..) => GtfsStopData,
     }
 );
 
@@ -111,6 +148,19 @@ pub enum RouteStopData {
         /// Populated by the backend based on proximity and direction. Not guaranteed to be accurate.
         opposite_stop_id: Option<String>,
     },
+    /// Trains serve stops in both directions, so there is no per-direction data
+    Lirr,
+    /// Trains serve stops in both directions, so there is no per-direction data
+    Mnr,
+    /// Trains serve stops in both directions, so there is no per-direction data
+    NjtRail,
+    Gtfs {
+        headsign: String,
+        /// 0 or 1 from GTFS direction_id
+        direction: i16,
+        /// Populated by the backend based on proximity and direction. Not guaranteed to be accurate.
+        opposite_stop_id: Option<String>,
+    },
 }
 
 #[derive(sqlx::Type, Clone, ToSchema, Deserialize, Serialize, Debug)]
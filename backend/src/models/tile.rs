@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Vector tile layers served by the API
+#[derive(Clone, Copy, Serialize, Deserialize, ToSchema, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TileLayer {
+    Routes,
+    Stops,
+    Positions,
+}
+
+impl TileLayer {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TileLayer::Routes => "routes",
+            TileLayer::Stops => "stops",
+            TileLayer::Positions => "positions",
+        }
+    }
+
+    /// Lowest zoom the layer has features at. Zoomed out tiles would have thousands of stops and vehicles that are
+    /// too small to see anyway.
+    pub fn min_zoom(&self) -> u32 {
+        match self {
+            TileLayer::Routes => 0,
+            TileLayer::Stops => 12,
+            TileLayer::Positions => 10,
+        }
+    }
+
+    /// Feature properties besides the source's `data`, as name and TileJSON type
+    pub fn fields(&self) -> BTreeMap<&'static str, &'static str> {
+        let fields: &[(&'static str, &'static str)] = match self {
+            TileLayer::Routes => &[
+                ("id", "String"),
+                ("source", "String"),
+                ("short_name", "String"),
+                ("long_name", "String"),
+                ("color", "String"),
+            ],
+            TileLayer::Stops => &[
+                ("id", "String"),
+                ("source", "String"),
+                ("name", "String"),
+                ("route_ids", "String"),
+            ],
+            TileLayer::Positions => &[
+                ("vehicle_id", "String"),
+                ("source", "String"),
+                ("trip_id", "String"),
+                ("stop_id", "String"),
+                ("updated_at", "Number"),
+                ("estimated", "Boolean"),
+                ("bearing", "Number"),
+            ],
+        };
+        fields.iter().copied().collect()
+    }
+}
+
+/// Highest zoom tiles are served at
+pub const MAX_ZOOM: u32 = 22;
+/// Tile extent in MVT coordinates and the buffer around it, which keeps symbols from being cut off at tile edges
+pub const EXTENT: u32 = 4096;
+pub const BUFFER: u32 = 64;
+
+/// A tile in the XYZ scheme
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileCoord {
+    pub z: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl TileCoord {
+    /// `None` if the tile is outside the grid for its zoom
+    pub fn new(z: u32, x: u32, y: u32) -> Option<Self> {
+        if z > MAX_ZOOM || x >= 1 << z || y >= 1 << z {
+            return None;
+        }
+        Some(Self { z, x, y })
+    }
+}
+
+/// An encoded Mapbox Vector Tile and the hash of its contents
+#[derive(Clone, Debug)]
+pub struct Tile {
+    pub data: Vec<u8>,
+    pub etag: String,
+}
+
+/// TileJSON 3.0.0 descriptor for a layer, so map libraries can find its tiles
+#[derive(Serialize, ToSchema)]
+pub struct TileJson {
+    #[schema(example = "3.0.0")]
+    pub tilejson: &'static str,
+    pub name: &'static str,
+    pub tiles: Vec<String>,
+    pub minzoom: u32,
+    pub maxzoom: u32,
+    pub vector_layers: Vec<VectorLayer>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct VectorLayer {
+    pub id: &'static str,
+    pub minzoom: u32,
+    pub maxzoom: u32,
+    /// Feature properties, as name and type. Every feature also has the properties of its source's `data`.
+    pub fields: BTreeMap<&'static str, &'static str>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile_coord() {
+        assert!(TileCoord::new(2, 4, 0).is_none());
+        assert!(TileCoord::new(MAX_ZOOM + 1, 0, 0).is_none());
+        assert!(TileCoord::new(14, 4825, 6157).is_some());
+    }
+}
@@ -1,9 +1,12 @@
-use crate::{impl_discriminated_data, models::source::Source};
-use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
-use chrono_tz::America::New_York;
+use crate::{
+    feed::{trip_descriptor, trip_update::stop_time_update},
+    impl_discriminated_data,
+    models::{position::VehiclePosition, source::Source, static_cache::calculate_datetime},
+};
+use chrono::{DateTime, NaiveDate, NaiveTime, Timelike, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
-use tracing::warn;
 use utoipa::ToSchema;
 use uuid::Uuid;
 
@@ -13,8 +16,9 @@ pub struct Trip {
     /// Original trip identifier from the data source
     #[schema(example = "097550_1..S03R")]
     pub original_id: String,
+    /// `None` for canceled trips that never had a vehicle assigned.
     #[schema(example = "01 1615+ 242/SFT")]
-    pub vehicle_id: String,
+    pub vehicle_id: Option<String>,
     #[schema(example = "1")]
     pub route_id: String,
     /// For the MTA subway, 1 is northbound, 3 is southbound.
@@ -24,10 +28,54 @@ pub struct Trip {
     /// For the MTA buses, this is the start date of the trip + the current time the trip was first seen in the feed.
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub status: TripStatus,
+    /// How likely it is that the trip's predictions aren't backed by a vehicle actually running it, from 0 to 1.
+    /// `None` until ghost detection has checked the trip.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ghost_confidence: Option<f32>,
     #[sqlx(json)]
     pub data: TripData,
 }
 
+/// Whether a trip runs as scheduled, from the GTFS-RT trip `schedule_relationship`
+#[derive(
+    sqlx::Type, Clone, Copy, Default, Serialize, Deserialize, ToSchema, PartialEq, Eq, Debug,
+)]
+#[sqlx(type_name = "trip_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum TripStatus {
+    #[default]
+    Scheduled,
+    /// Extra trip that isn't in the static schedule
+    Added,
+    /// Scheduled trip that won't run. Canceled trips are kept so they don't just disappear from departure boards.
+    Canceled,
+    /// Copy of a scheduled trip that runs at a different time
+    Duplicated,
+}
+
+impl TripStatus {
+    pub fn from_gtfs(schedule_relationship: Option<i32>) -> Self {
+        use trip_descriptor::ScheduleRelationship;
+
+        match schedule_relationship.and_then(|s| ScheduleRelationship::try_from(s).ok()) {
+            Some(ScheduleRelationship::Added | ScheduleRelationship::Unscheduled) => Self::Added,
+            Some(ScheduleRelationship::Canceled | ScheduleRelationship::Deleted) => Self::Canceled,
+            Some(ScheduleRelationship::Duplicated) => Self::Duplicated,
+            _ => Self::Scheduled,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, ToSchema, PartialEq, Debug)]
+pub struct MtaSubwayData {
+    /// Delay in seconds at the trip's next stop compared to the static schedule. Positive means late.
+    /// `None` if the trip isn't in the static schedule, like trains added for service changes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay: Option<i32>,
+}
+
 #[derive(Clone, Serialize, Deserialize, ToSchema, PartialEq, Debug)]
 pub struct MtaBusData {
     /// Deviation from the schedule in seconds.
@@ -45,13 +93,59 @@ pub struct NjtBusData {
     pub headsign: String,
 }
 
+#[derive(Clone, Serialize, Deserialize, ToSchema, PartialEq, Debug)]
+pub struct LirrData {
+    /// Public train number shown on station departure boards
+    #[schema(example = "2711")]
+    pub train_number: String,
+    pub headsign: String,
+    /// Number of cars in the consist. Only known once the train has a vehicle position.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub car_count: Option<i32>,
+    /// Whether peak fares apply to this trip. `None` if the trip isn't in the static schedule.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak: Option<bool>,
+}
+
+#[derive(Clone, Serialize, Deserialize, ToSchema, PartialEq, Debug)]
+pub struct MnrData {
+    /// Public train number shown on station departure boards
+    #[schema(example = "1537")]
+    pub train_number: String,
+    pub headsign: String,
+    /// Number of cars in the consist. Only known once the train has a vehicle position.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub car_count: Option<i32>,
+}
+
+#[derive(Clone, Serialize, Deserialize, ToSchema, PartialEq, Debug)]
+pub struct NjtRailData {
+    /// Public train number shown on station departure boards
+    #[schema(example = "3855")]
+    pub train_number: String,
+    pub headsign: String,
+}
+
+/// Used by config-driven GTFS sources
+#[derive(Clone, Serialize, Deserialize, ToSchema, PartialEq, Debug)]
+pub struct GtfsTripData {
+    pub headsign: String,
+    /// Delay in seconds from the trip update, if the feed provides one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay: Option<i32>,
+}
+
 /// Trip data changes based on the `Source`
 #[derive(Clone, Serialize, Deserialize, ToSchema, PartialEq, Debug)]
 #[serde(tag = "source", rename_all = "snake_case")]
 pub enum TripData {
     MtaBus(MtaBusData),
-    MtaSubway,
+    MtaSubway(MtaSubwayData),
     NjtBus(NjtBusData),
+    Lirr(LirrData),
+    Mnr(MnrData),
+    NjtRail(NjtRailData),
+    Gtfs(GtfsTripData),
 }
 
 impl_discriminated_data!(
@@ -59,34 +153,57 @@ impl_discriminated_data!(
     Source,
     {
         MtaBus => MtaBusData,
-        MtaSubway,
+        MtaSubway => MtaSubwayData,
         NjtBus => NjtBusData,
+        Lirr => LirrData,
+        Mnr => MnrData,
+        NjtRail => NjtRailData,
+        Gtfs(..) => GtfsTripData,
     }
 );
 
 impl Trip {
-    // when daylight savings time changes, this will error so we need to handle that
-    // im not sure if its correct to choose earliest time or latest time
-    pub fn created_at(start_date: NaiveDate, start_time: NaiveTime) -> Option<DateTime<Utc>> {
-        let local_time = NaiveDateTime::new(start_date, start_time);
-
-        let dt = match New_York.from_local_datetime(&local_time) {
-            chrono::LocalResult::Single(dt) => dt,
-            chrono::LocalResult::Ambiguous(dt1, _dt2) => dt1, // Choose the earliest time
-            chrono::LocalResult::None => {
-                warn!(
-                    "Failed to convert local time to datetime: {} {}",
-                    start_date, start_time
-                );
-                return None;
-            }
-        }
-        .with_timezone(&Utc);
-
-        Some(dt)
+    /// The start of a trip from its GTFS(-RT) start date and time in the source's timezone.
+    /// Like static stop times, start times are measured from noon minus 12h so DST transition days are handled.
+    pub fn created_at(
+        start_date: NaiveDate,
+        start_time: NaiveTime,
+        tz: Tz,
+    ) -> Option<DateTime<Utc>> {
+        calculate_datetime(start_date, start_time.num_seconds_from_midnight(), tz)
     }
 }
 
+/// A single trip with its stops, vehicle and path
+#[derive(Serialize, ToSchema)]
+pub struct TripDetail {
+    pub trip: Trip,
+    /// Stop times in the order the trip serves them
+    pub stop_times: Vec<TripDetailStopTime>,
+    /// Latest position of the trip's vehicle, if there is one
+    pub position: Option<VehiclePosition>,
+    /// GeoJSON LineString of where the vehicle has been. Null until it has at least 2 recorded positions.
+    #[schema(value_type = Option<Object>)]
+    pub path: Option<serde_json::Value>,
+    /// GeoJSON LineString of the route's geometry between the trip's first and last stops. Only included with
+    /// `include=shape` if the route has a geometry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub shape: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Serialize, Deserialize, ToSchema, FromRow)]
+pub struct TripDetailStopTime {
+    pub stop_id: String,
+    #[schema(example = "Times Sq-42 St")]
+    pub stop_name: String,
+    pub arrival: DateTime<Utc>,
+    pub departure: DateTime<Utc>,
+    pub status: StopTimeStatus,
+    #[sqlx(json)]
+    pub data: StopTimeData,
+}
+
 // TODO: prob move StopTime to its own file
 
 #[derive(PartialEq, Clone, Serialize, Deserialize, Hash, Eq, ToSchema, FromRow)]
@@ -95,11 +212,40 @@ pub struct StopTime {
     pub stop_id: String,
     pub arrival: DateTime<Utc>,
     pub departure: DateTime<Utc>,
+    #[serde(default)]
+    pub status: StopTimeStatus,
     #[sqlx(json)]
     pub data: StopTimeData,
 }
 
-#[derive(PartialEq, Clone, Serialize, Deserialize, ToSchema, Hash, Eq)]
+/// Whether the trip stops here, from the GTFS-RT stop time `schedule_relationship`
+#[derive(
+    sqlx::Type, Clone, Copy, Default, Serialize, Deserialize, ToSchema, PartialEq, Eq, Hash, Debug,
+)]
+#[sqlx(type_name = "stop_time_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum StopTimeStatus {
+    #[default]
+    Scheduled,
+    /// The trip passes the stop without stopping. Every stop of a canceled trip is skipped.
+    Skipped,
+    /// The feed has no realtime prediction for the stop
+    NoData,
+}
+
+impl StopTimeStatus {
+    pub fn from_gtfs(schedule_relationship: Option<i32>) -> Self {
+        use stop_time_update::ScheduleRelationship;
+
+        match schedule_relationship.and_then(|s| ScheduleRelationship::try_from(s).ok()) {
+            Some(ScheduleRelationship::Skipped) => Self::Skipped,
+            Some(ScheduleRelationship::NoData) => Self::NoData,
+            _ => Self::Scheduled,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Default, Serialize, Deserialize, ToSchema, Hash, Eq)]
 pub struct MtaSubwayStopTimeData {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[schema(example = "B2")]
@@ -107,14 +253,83 @@ pub struct MtaSubwayStopTimeData {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[schema(example = "B2")]
     pub actual_track: Option<String>,
+    /// Delay in seconds compared to the static schedule. Positive means late.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay: Option<i32>,
+}
+
+#[derive(PartialEq, Clone, Default, Serialize, Deserialize, ToSchema, Hash, Eq)]
+pub struct MtaBusStopTimeData {
+    #[serde(default)]
+    pub predicted_by: PredictedBy,
+}
+
+/// Where an arrival prediction came from
+#[derive(Clone, Copy, Default, Serialize, Deserialize, ToSchema, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PredictedBy {
+    /// The realtime feed
+    #[default]
+    Feed,
+    /// The backend, from historical travel times between stops, when the feed's predictions are missing or stale
+    Backend,
+}
+
+#[derive(PartialEq, Clone, Default, Serialize, Deserialize, ToSchema, Hash, Eq)]
+pub struct LirrStopTimeData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "17")]
+    pub track: Option<String>,
+    /// Train status from the MTA railroad extension (e.g. "On-Time", "Late")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "On-Time")]
+    pub status: Option<String>,
+}
+
+#[derive(PartialEq, Clone, Default, Serialize, Deserialize, ToSchema, Hash, Eq)]
+pub struct MnrStopTimeData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "42")]
+    pub track: Option<String>,
+    /// Train status from the MTA railroad extension (e.g. "On-Time", "Late")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "On-Time")]
+    pub status: Option<String>,
+}
+
+#[derive(PartialEq, Clone, Default, Serialize, Deserialize, ToSchema, Hash, Eq)]
+pub struct NjtRailStopTimeData {
+    /// Only known at major stations, and usually only shortly before departure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "9")]
+    pub track: Option<String>,
 }
 
 #[derive(PartialEq, Clone, Serialize, Deserialize, ToSchema, Hash, Eq)]
 #[serde(tag = "source", rename_all = "snake_case")]
 pub enum StopTimeData {
     MtaSubway(MtaSubwayStopTimeData),
-    MtaBus,
+    MtaBus(MtaBusStopTimeData),
     NjtBus,
+    Lirr(LirrStopTimeData),
+    Mnr(MnrStopTimeData),
+    NjtRail(NjtRailStopTimeData),
+    Gtfs,
+}
+
+impl StopTimeData {
+    /// Data for a stop time that isn't in the realtime feed
+    pub fn empty(source: Source) -> Self {
+        match source {
+            Source::MtaSubway => Self::MtaSubway(Default::default()),
+            Source::MtaBus => Self::MtaBus(Default::default()),
+            Source::NjtBus => Self::NjtBus,
+            Source::Lirr => Self::Lirr(Default::default()),
+            Source::Mnr => Self::Mnr(Default::default()),
+            Source::NjtRail => Self::NjtRail(Default::default()),
+            Source::Gtfs(_) => Self::Gtfs,
+        }
+    }
 }
 
 impl_discriminated_data!(
@@ -122,7 +337,11 @@ impl_discriminated_data!(
     Source,
     {
         MtaSubway => MtaSubwayStopTimeData,
-        MtaBus,
+        MtaBus => MtaBusStopTimeData,
         NjtBus,
+        Lirr => LirrStopTimeData,
+        Mnr => MnrStopTimeData,
+        NjtRail => NjtRailStopTimeData,
+        Gtfs(..),
     }
 );
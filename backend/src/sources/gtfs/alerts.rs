@@ -0,0 +1,201 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::header::HeaderMap;
+use uuid::Uuid;
+
+use crate::{
+    feed::{Alert as GtfsAlert, FeedMessage, TranslatedString},
+    integrations::{
+        gtfs_alert::{self, GtfsAlertSource},
+        gtfs_realtime,
+    },
+    models::{
+        alert::{
+            ActivePeriod, AffectedEntity, Alert, AlertData, AlertFormat, AlertSection,
+            AlertTranslation,
+        },
+        source::Source,
+        status::FeedKind,
+    },
+    sources::AlertsAdapter,
+    stores::alert::AlertStore,
+};
+
+use super::{GtfsSourceConfig, feed_name, get_bytes_with_headers};
+
+pub struct GtfsAlerts {
+    source: Source,
+    config: Arc<GtfsSourceConfig>,
+    headers: HeaderMap,
+}
+
+impl GtfsAlerts {
+    pub fn new(source: Source, config: Arc<GtfsSourceConfig>, headers: HeaderMap) -> Self {
+        Self {
+            source,
+            config,
+            headers,
+        }
+    }
+}
+
+fn push_translations(
+    translations: &mut Vec<AlertTranslation>,
+    alert_id: Uuid,
+    section: AlertSection,
+    text: Option<&TranslatedString>,
+) {
+    let Some(text) = text else {
+        return;
+    };
+    for translation in &text.translation {
+        translations.push(AlertTranslation {
+            alert_id,
+            section,
+            format: AlertFormat::Plain,
+            language: translation
+                .language
+                .clone()
+                .unwrap_or_else(|| "en".to_string()),
+            text: translation.text.clone(),
+        });
+    }
+}
+
+#[async_trait]
+impl GtfsAlertSource for GtfsAlerts {
+    fn source(&self) -> Source {
+        self.source
+    }
+
+    async fn fetch_feeds(&self) -> Vec<FeedMessage> {
+        gtfs_realtime::fetch_feeds(
+            self.source,
+            FeedKind::Alerts,
+            self.config
+                .alerts_urls
+                .iter()
+                .enumerate()
+                .map(|(i, url)| {
+                    (
+                        feed_name(self.source, "alerts", i),
+                        get_bytes_with_headers(url.clone(), self.headers.clone()),
+                    )
+                })
+                .collect(),
+        )
+        .await
+    }
+
+    fn process_alert(
+        &self,
+        entity_id: String,
+        alert: GtfsAlert,
+    ) -> Option<(
+        Alert,
+        Vec<AlertTranslation>,
+        Vec<ActivePeriod>,
+        Vec<AffectedEntity>,
+    )> {
+        // Standard GTFS-RT has no created_at, so use the first active period start to keep
+        // upserts stable on (created_at, original_id, source).
+        let created_at = alert
+            .active_period
+            .first()
+            .and_then(|ap| ap.start)
+            .and_then(|s| DateTime::from_timestamp(s as i64, 0))?;
+
+        let alert_id = Uuid::now_v7();
+
+        let parsed_alert = Alert {
+            id: alert_id,
+            original_id: entity_id,
+            source: self.source,
+            created_at,
+            updated_at: Utc::now(),
+            recorded_at: Utc::now(),
+            data: AlertData::Gtfs,
+        };
+
+        let mut translations = Vec::new();
+        push_translations(
+            &mut translations,
+            alert_id,
+            AlertSection::Header,
+            alert.header_text.as_ref(),
+        );
+        push_translations(
+            &mut translations,
+            alert_id,
+            AlertSection::Description,
+            alert.description_text.as_ref(),
+        );
+
+        if translations.is_empty() {
+            tracing::warn!(
+                "{} alert has no header or description text, skipping",
+                self.config.display_name()
+            );
+            return None;
+        }
+
+        let active_periods: Vec<ActivePeriod> = alert
+            .active_period
+            .iter()
+            .filter_map(|ap| {
+                let start = DateTime::from_timestamp(ap.start? as i64, 0)?;
+                let end = ap.end.and_then(|e| DateTime::from_timestamp(e as i64, 0));
+                Some(ActivePeriod {
+                    alert_id,
+                    start_time: start,
+                    end_time: end,
+                })
+            })
+            .collect();
+
+        let affected_entities: Vec<AffectedEntity> = alert
+            .informed_entity
+            .iter()
+            .filter_map(|entity| {
+                let route_id = entity.route_id.clone();
+                let stop_id = entity.stop_id.clone();
+
+                if route_id.is_none() && stop_id.is_none() {
+                    return None;
+                }
+
+                Some(AffectedEntity {
+                    alert_id,
+                    route_id,
+                    source: self.source,
+                    stop_id,
+                    sort_order: 0,
+                })
+            })
+            .collect();
+
+        Some((
+            parsed_alert,
+            translations,
+            active_periods,
+            affected_entities,
+        ))
+    }
+}
+
+#[async_trait]
+impl AlertsAdapter for GtfsAlerts {
+    fn source(&self) -> Source {
+        self.source
+    }
+
+    fn refresh_interval(&self) -> std::time::Duration {
+        self.config.alerts_refresh_interval()
+    }
+
+    async fn run(&self, alert_store: &AlertStore) -> anyhow::Result<()> {
+        gtfs_alert::run_pipeline(self, alert_store).await
+    }
+}
@@ -0,0 +1,140 @@
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use anyhow::Context;
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+/// Top level of the GTFS sources config file. Each `[[source]]` table is one agency.
+#[derive(Debug, Deserialize)]
+pub struct GtfsSourcesConfig {
+    #[serde(default, rename = "source")]
+    pub sources: Vec<GtfsSourceConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GtfsSourceConfig {
+    /// Source id used in the API and database, e.g. `path`. Must be lowercase snake_case.
+    pub id: String,
+    /// Display name used in logs
+    pub name: Option<String>,
+    pub static_url: String,
+    #[serde(default)]
+    pub trip_updates_urls: Vec<String>,
+    #[serde(default)]
+    pub vehicle_positions_urls: Vec<String>,
+    #[serde(default)]
+    pub alerts_urls: Vec<String>,
+    /// Headers sent with every request to the agency. Values starting with `$` are read from that env var.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// IANA timezone of the agency. Defaults to `agency_timezone` from the static GTFS.
+    pub timezone: Option<String>,
+    /// Fallback color for routes without one in GTFS
+    pub default_route_color: Option<String>,
+    #[serde(default = "default_static_refresh_secs")]
+    pub static_refresh_secs: u64,
+    #[serde(default = "default_realtime_refresh_secs")]
+    pub realtime_refresh_secs: u64,
+    #[serde(default = "default_alerts_refresh_secs")]
+    pub alerts_refresh_secs: u64,
+}
+
+fn default_static_refresh_secs() -> u64 {
+    60 * 60 * 24
+}
+
+fn default_realtime_refresh_secs() -> u64 {
+    30
+}
+
+fn default_alerts_refresh_secs() -> u64 {
+    60
+}
+
+impl GtfsSourceConfig {
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.id)
+    }
+
+    pub fn timezone(&self) -> anyhow::Result<Option<Tz>> {
+        self.timezone
+            .as_deref()
+            .map(|tz| {
+                tz.parse::<Tz>()
+                    .map_err(|e| anyhow::anyhow!("Invalid timezone {tz} for {}: {e}", self.id))
+            })
+            .transpose()
+    }
+
+    pub fn static_refresh_interval(&self) -> Duration {
+        Duration::from_secs(self.static_refresh_secs)
+    }
+
+    pub fn realtime_refresh_interval(&self) -> Duration {
+        Duration::from_secs(self.realtime_refresh_secs)
+    }
+
+    pub fn alerts_refresh_interval(&self) -> Duration {
+        Duration::from_secs(self.alerts_refresh_secs)
+    }
+
+    /// Resolve configured headers, reading `$VAR` values from the environment.
+    pub fn header_map(&self) -> anyhow::Result<reqwest::header::HeaderMap> {
+        let mut map = reqwest::header::HeaderMap::new();
+        for (name, value) in &self.headers {
+            let value = match value.strip_prefix('$') {
+                Some(var) => std::env::var(var)
+                    .with_context(|| format!("{var} must be set for GTFS source {}", self.id))?,
+                None => value.clone(),
+            };
+            map.insert(
+                reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                    .with_context(|| format!("Invalid header name {name}"))?,
+                reqwest::header::HeaderValue::from_str(&value)
+                    .with_context(|| format!("Invalid value for header {name}"))?,
+            );
+        }
+        Ok(map)
+    }
+}
+
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<GtfsSourcesConfig> {
+    let path = path.as_ref();
+    let raw = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read GTFS sources config {}", path.display()))?;
+    toml::from_str(&raw)
+        .with_context(|| format!("Failed to parse GTFS sources config {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sources_with_defaults() {
+        let config: GtfsSourcesConfig = toml::from_str(
+            r#"
+            [[source]]
+            id = "path"
+            name = "PATH"
+            static_url = "https://example.com/path.zip"
+            trip_updates_urls = ["https://example.com/path/trip_updates"]
+            timezone = "America/New_York"
+
+            [source.headers]
+            x-api-key = "$PATH_API_KEY"
+            "#,
+        )
+        .unwrap();
+
+        let source = &config.sources[0];
+        assert_eq!(source.id, "path");
+        assert_eq!(source.realtime_refresh_interval(), Duration::from_secs(30));
+        assert!(source.alerts_urls.is_empty());
+        assert_eq!(
+            source.timezone().unwrap(),
+            Some(chrono_tz::America::New_York)
+        );
+        assert_eq!(source.headers["x-api-key"], "$PATH_API_KEY");
+    }
+}
@@ -0,0 +1,114 @@
+//! Config-driven sources for agencies that publish standard GTFS and GTFS-RT feeds without
+//! anything that needs custom handling. See `gtfs_sources.example.toml` for the config format.
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use chrono_tz::America::New_York;
+use reqwest::header::HeaderMap;
+use sqlx::PgPool;
+
+use crate::{
+    integrations::gtfs_realtime::FeedFuture,
+    models::source::Source,
+    sources::{AlertsAdapter, RealtimeAdapter, StaticAdapter},
+};
+
+pub mod alerts;
+pub mod config;
+pub mod realtime;
+pub mod static_data;
+
+pub use config::{GtfsSourceConfig, GtfsSourcesConfig};
+
+/// Adapters for every configured GTFS source. Sources without realtime or alert URLs don't get those adapters.
+#[derive(Default)]
+pub struct GtfsAdapters {
+    pub static_adapters: Vec<Arc<dyn StaticAdapter>>,
+    pub realtime_adapters: Vec<Arc<dyn RealtimeAdapter>>,
+    pub alert_adapters: Vec<Arc<dyn AlertsAdapter>>,
+}
+
+/// Registers the configured sources as dynamic `Source`s, adds them to `source_enum`, and builds their adapters.
+pub async fn register(pool: &PgPool, config: GtfsSourcesConfig) -> anyhow::Result<GtfsAdapters> {
+    let sources = Source::register_dynamic(config.sources.iter().map(|s| s.id.clone()).collect())?;
+
+    let mut adapters = GtfsAdapters::default();
+    for (source, source_config) in sources.into_iter().zip(config.sources) {
+        // ADD VALUE can't be parameterized, so the id is formatted into the query and has to be checked first
+        anyhow::ensure!(
+            Source::is_valid_id(source.as_str()),
+            "GTFS source id {} must match [a-z0-9_]+",
+            source.as_str()
+        );
+        sqlx::query(&format!(
+            "ALTER TYPE source_enum ADD VALUE IF NOT EXISTS '{}'",
+            source.as_str()
+        ))
+        .execute(pool)
+        .await
+        .with_context(|| format!("Failed to add {} to source_enum", source.as_str()))?;
+
+        let headers = source_config.header_map()?;
+        let timezone = source_config.timezone()?.unwrap_or(New_York);
+        let source_config = Arc::new(source_config);
+
+        tracing::info!(
+            "Registered GTFS source {} ({})",
+            source.as_str(),
+            source_config.display_name()
+        );
+
+        adapters
+            .static_adapters
+            .push(Arc::new(static_data::GtfsStatic::new(
+                source,
+                source_config.clone(),
+                headers.clone(),
+                timezone,
+            )));
+
+        if !source_config.trip_updates_urls.is_empty()
+            || !source_config.vehicle_positions_urls.is_empty()
+        {
+            adapters
+                .realtime_adapters
+                .push(Arc::new(realtime::GtfsRealtime::new(
+                    source,
+                    source_config.clone(),
+                    headers.clone(),
+                )));
+        }
+
+        if !source_config.alerts_urls.is_empty() {
+            adapters
+                .alert_adapters
+                .push(Arc::new(alerts::GtfsAlerts::new(
+                    source,
+                    source_config,
+                    headers,
+                )));
+        }
+    }
+
+    Ok(adapters)
+}
+
+/// Returns a [`FeedFuture`] that issues a GET with the source's configured headers.
+pub(crate) fn get_bytes_with_headers(url: String, headers: HeaderMap) -> FeedFuture {
+    Box::pin(async move {
+        Ok(reqwest::Client::new()
+            .get(&url)
+            .headers(headers)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?)
+    })
+}
+
+/// Feed name used in logs and debug output, e.g. `path_trip_updates_0`.
+fn feed_name(source: Source, kind: &str, index: usize) -> String {
+    format!("{}_{kind}_{index}", source.as_str())
+}
@@ -0,0 +1,335 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveTime, Utc};
+use geo::Point;
+use reqwest::header::HeaderMap;
+use uuid::Uuid;
+
+use crate::{
+    engines::static_data::StaticController,
+    feed::{
+        FeedMessage, TripUpdate, VehiclePosition as GtfsVehiclePosition,
+        vehicle_position::OccupancyStatus,
+    },
+    integrations::gtfs_realtime::{self, GtfsSource},
+    models::{
+        position::{GtfsPositionData, PositionData, VehiclePosition},
+        source::Source,
+        status::FeedKind,
+        trip::{GtfsTripData, StopTime, StopTimeData, StopTimeStatus, Trip, TripData, TripStatus},
+    },
+    sources::RealtimeAdapter,
+    stores::{position::PositionStore, static_cache::StaticCacheStore, trip::TripStore},
+};
+
+use super::{GtfsSourceConfig, feed_name, get_bytes_with_headers};
+
+pub struct GtfsRealtime {
+    source: Source,
+    config: Arc<GtfsSourceConfig>,
+    headers: HeaderMap,
+}
+
+impl GtfsRealtime {
+    pub fn new(source: Source, config: Arc<GtfsSourceConfig>, headers: HeaderMap) -> Self {
+        Self {
+            source,
+            config,
+            headers,
+        }
+    }
+}
+
+#[async_trait]
+impl GtfsSource for GtfsRealtime {
+    fn source(&self) -> Source {
+        self.source
+    }
+
+    async fn fetch_feeds(&self) -> Vec<FeedMessage> {
+        let trip_updates = self
+            .config
+            .trip_updates_urls
+            .iter()
+            .enumerate()
+            .map(|(i, url)| (feed_name(self.source, "trip_updates", i), url));
+        let vehicle_positions = self
+            .config
+            .vehicle_positions_urls
+            .iter()
+            .enumerate()
+            .map(|(i, url)| (feed_name(self.source, "vehicle_positions", i), url));
+
+        gtfs_realtime::fetch_feeds(
+            self.source,
+            FeedKind::Realtime,
+            trip_updates
+                .chain(vehicle_positions)
+                .map(|(name, url)| {
+                    (
+                        name,
+                        get_bytes_with_headers(url.clone(), self.headers.clone()),
+                    )
+                })
+                .collect(),
+        )
+        .await
+    }
+
+    async fn process_trip(
+        &self,
+        update: TripUpdate,
+        static_cache_store: &StaticCacheStore,
+    ) -> (Option<Trip>, Vec<StopTime>) {
+        // Many feeds leave out start_date for trips that aren't frequency based
+        let Some((start_date, cached_trip)) =
+            gtfs_realtime::service_date(self.source, self.timezone(), &update, static_cache_store)
+                .await
+        else {
+            return (None, vec![]);
+        };
+
+        let trip_desc = update.trip;
+
+        let trip_id = match trip_desc.trip_id {
+            Some(id) => id,
+            None => return (None, vec![]),
+        };
+
+        let route_id = match trip_desc
+            .route_id
+            .or_else(|| cached_trip.as_ref().map(|ct| ct.route_id.clone()))
+        {
+            Some(id) => id,
+            None => return (None, vec![]),
+        };
+
+        let direction = trip_desc
+            .direction_id
+            .map(|d| d as i16)
+            .or_else(|| cached_trip.as_ref().map(|ct| ct.direction_id))
+            .unwrap_or(0);
+
+        // Cached start times are already in UTC, so only the feed's start_time needs converting
+        let created_at = match trip_desc
+            .start_time
+            .as_deref()
+            .and_then(|t| NaiveTime::parse_from_str(t, "%H:%M:%S").ok())
+        {
+            Some(t) => Trip::created_at(start_date, t, self.timezone()),
+            None => cached_trip.as_ref().map(|ct| ct.start_time),
+        };
+        let Some(created_at) = created_at else {
+            return (None, vec![]);
+        };
+
+        let vehicle_id = update
+            .vehicle
+            .as_ref()
+            .and_then(|v| v.id.clone().or_else(|| v.label.clone()))
+            .unwrap_or_else(|| trip_id.clone());
+
+        let headsign = cached_trip
+            .as_ref()
+            .map(|ct| ct.headsign.clone())
+            .unwrap_or_default();
+
+        let trip = Trip {
+            id: Uuid::now_v7(),
+            original_id: trip_id,
+            route_id,
+            direction,
+            created_at,
+            vehicle_id: Some(vehicle_id),
+            updated_at: Utc::now(),
+            status: TripStatus::from_gtfs(trip_desc.schedule_relationship),
+            ghost_confidence: None,
+            data: TripData::Gtfs(GtfsTripData {
+                headsign,
+                delay: update.delay,
+            }),
+        };
+
+        let stop_times: Vec<StopTime> = update
+            .stop_time_update
+            .into_iter()
+            .filter_map(|st| {
+                let stop_id = st.stop_id?;
+
+                let arrival = match st.arrival {
+                    Some(a) => a.time?,
+                    None => st.departure.as_ref()?.time?,
+                };
+                let departure = match st.departure {
+                    Some(d) => d.time?,
+                    None => st.arrival.as_ref()?.time?,
+                };
+
+                let arrival = DateTime::from_timestamp(arrival, 0)?;
+                let departure = DateTime::from_timestamp(departure, 0)?;
+
+                Some(StopTime {
+                    trip_id: trip.id,
+                    stop_id,
+                    arrival,
+                    departure,
+                    status: StopTimeStatus::from_gtfs(st.schedule_relationship),
+                    data: StopTimeData::Gtfs,
+                })
+            })
+            .collect();
+
+        (Some(trip), stop_times)
+    }
+
+    async fn process_vehicle(
+        &self,
+        vehicle: GtfsVehiclePosition,
+        _static_cache_store: &StaticCacheStore,
+    ) -> Option<VehiclePosition> {
+        let vehicle_id = vehicle
+            .vehicle
+            .as_ref()
+            .and_then(|v| v.id.clone().or_else(|| v.label.clone()))
+            .or_else(|| vehicle.trip.as_ref()?.trip_id.clone())?;
+
+        let updated_at = vehicle
+            .timestamp
+            .and_then(|t| DateTime::from_timestamp(t as i64, 0))
+            .unwrap_or_else(Utc::now);
+
+        let occupancy_status = vehicle
+            .occupancy_status
+            .and_then(|o| OccupancyStatus::try_from(o).ok());
+        let bearing = vehicle.position.as_ref().and_then(|p| p.bearing);
+
+        let geom = vehicle.position.map(|p| {
+            let geom: geo::Geometry = Point::new(p.longitude as f64, p.latitude as f64).into();
+            geom.into()
+        });
+
+        Some(VehiclePosition {
+            vehicle_id,
+            trip_id: None,
+            stop_id: vehicle.stop_id,
+            updated_at,
+            geom,
+            data: PositionData::Gtfs(GtfsPositionData {
+                bearing,
+                occupancy_status,
+            }),
+        })
+    }
+}
+
+#[async_trait]
+impl RealtimeAdapter for GtfsRealtime {
+    fn source(&self) -> Source {
+        self.source
+    }
+
+    fn refresh_interval(&self) -> std::time::Duration {
+        self.config.realtime_refresh_interval()
+    }
+
+    async fn run(
+        &self,
+        static_controller: &StaticController,
+        static_cache_store: &StaticCacheStore,
+        trip_store: &TripStore,
+        position_store: &PositionStore,
+    ) -> anyhow::Result<()> {
+        gtfs_realtime::run_pipeline(
+            self,
+            static_controller,
+            static_cache_store,
+            trip_store,
+            position_store,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        feed::{
+            TripDescriptor,
+            trip_update::{StopTimeEvent, StopTimeUpdate},
+        },
+        models::static_cache::{CachedStopTime, CachedTrip},
+    };
+    use bb8_redis::RedisConnectionManager;
+    use chrono::TimeZone;
+    use chrono_tz::America::New_York;
+
+    #[tokio::test]
+    async fn test_trip_after_midnight_without_start_date() {
+        let manager = RedisConnectionManager::new("redis://localhost").unwrap();
+        let redis_pool = bb8::Pool::builder().build(manager).await.unwrap();
+        let cache = StaticCacheStore::new(redis_pool);
+
+        let source = Source::Gtfs("test_after_midnight");
+        let ny = |d, h, m| {
+            New_York
+                .with_ymd_and_hms(2025, 6, d, h, m, 0)
+                .unwrap()
+                .to_utc()
+        };
+        let stop_time = |stop_id: &str, time| CachedStopTime {
+            stop_id: stop_id.into(),
+            arrival: time,
+            departure: time,
+            stop_sequence: 1,
+        };
+        // Leaves at 11:30 PM on June 3 and runs until 12:40 AM on June 4
+        cache
+            .cache_trips(
+                source,
+                &[CachedTrip {
+                    trip_id: "late_night".into(),
+                    route_id: "blue".into(),
+                    headsign: "Downtown".into(),
+                    direction_id: 1,
+                    start_date: "20250603".into(),
+                    start_time: ny(3, 23, 30),
+                    stop_times: vec![stop_time("a", ny(3, 23, 30)), stop_time("b", ny(4, 0, 40))],
+                }],
+            )
+            .await
+            .unwrap();
+
+        let config: GtfsSourceConfig = toml::from_str(
+            r#"
+            id = "test_after_midnight"
+            static_url = "https://example.com/gtfs.zip"
+            "#,
+        )
+        .unwrap();
+        let adapter = GtfsRealtime::new(source, Arc::new(config), HeaderMap::new());
+        let update = TripUpdate {
+            trip: TripDescriptor {
+                trip_id: Some("late_night".into()),
+                ..Default::default()
+            },
+            stop_time_update: vec![StopTimeUpdate {
+                stop_id: Some("b".into()),
+                arrival: Some(StopTimeEvent {
+                    time: Some(ny(4, 0, 42).timestamp()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let (trip, stop_times) = adapter.process_trip(update, &cache).await;
+        let trip = trip.expect("trip should be matched to the previous day's service");
+        assert_eq!(trip.route_id, "blue");
+        assert_eq!(trip.direction, 1);
+        assert_eq!(trip.created_at, ny(3, 23, 30));
+        assert_eq!(stop_times.len(), 1);
+    }
+}
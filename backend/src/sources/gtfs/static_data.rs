@@ -0,0 +1,130 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono_tz::Tz;
+use reqwest::header::HeaderMap;
+
+use crate::{
+    engines::static_cache::{expand_gtfs, update_timezone},
+    integrations::gtfs_static::{self, DefaultMapper, GtfsArchive, ParserOptions},
+    models::source::Source,
+    sources::{StaticAdapter, set_source_timezone},
+    stores::{route::RouteStore, static_cache::StaticCacheStore, stop::StopStore},
+};
+
+use super::GtfsSourceConfig;
+
+pub struct GtfsStatic {
+    source: Source,
+    config: Arc<GtfsSourceConfig>,
+    headers: HeaderMap,
+    /// Configured timezone, or New York
+    timezone: Tz,
+}
+
+impl GtfsStatic {
+    pub fn new(
+        source: Source,
+        config: Arc<GtfsSourceConfig>,
+        headers: HeaderMap,
+        timezone: Tz,
+    ) -> Self {
+        Self {
+            source,
+            config,
+            headers,
+            timezone,
+        }
+    }
+}
+
+#[async_trait]
+impl StaticAdapter for GtfsStatic {
+    fn source(&self) -> Source {
+        self.source
+    }
+
+    fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    fn refresh_interval(&self) -> Duration {
+        self.config.static_refresh_interval()
+    }
+
+    async fn import(
+        &self,
+        route_store: &RouteStore,
+        stop_store: &StopStore,
+        static_cache_store: &StaticCacheStore,
+    ) -> anyhow::Result<()> {
+        let name = self.config.display_name();
+
+        let bytes = reqwest::Client::new()
+            .get(&self.config.static_url)
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await
+            .with_context(|| format!("{name} GTFS download failed"))?;
+
+        let gtfs = gtfs_static::read_archive(GtfsArchive {
+            name: name.to_owned(),
+            bytes: bytes.to_vec(),
+        })
+        .await?;
+
+        tracing::info!(
+            "{name} GTFS parsed: {} routes, {} stops, {} trips",
+            gtfs.routes.len(),
+            gtfs.stops.len(),
+            gtfs.trips.len()
+        );
+
+        // An explicitly configured timezone wins over agency_timezone
+        let timezone = match self.config.timezone()? {
+            Some(tz) => {
+                set_source_timezone(self.source, tz);
+                tz
+            }
+            None => update_timezone(self.source, &gtfs, self.timezone()),
+        };
+
+        let cached_trips = expand_gtfs(self.source, &gtfs, timezone);
+        static_cache_store
+            .cache_trips(self.source, &cached_trips)
+            .await
+            .with_context(|| format!("Failed to cache {name} trips in Redis"))?;
+
+        let mut options = ParserOptions::default();
+        if let Some(color) = &self.config.default_route_color {
+            options.default_route_color = color.clone();
+        }
+        let parsed = gtfs_static::parse_gtfs(self.source, &gtfs, &DefaultMapper, &options)?;
+
+        tracing::info!(
+            "{name}: built {} routes, {} stops, {} route_stops",
+            parsed.routes.len(),
+            parsed.stops.len(),
+            parsed.route_stops.len()
+        );
+
+        route_store
+            .save_all(self.source, &parsed.routes)
+            .await
+            .with_context(|| format!("Failed to save {name} routes to database"))?;
+        stop_store
+            .save_all(self.source, &parsed.stops)
+            .await
+            .with_context(|| format!("Failed to save {name} stops to database"))?;
+        stop_store
+            .save_all_route_stops(self.source, &parsed.route_stops)
+            .await
+            .with_context(|| format!("Failed to save {name} route_stops to database"))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,8 @@
+use crate::{models::source::Source, sources::mta_railroad::alerts::MtaRailroadAlerts};
+
+const LIRR_ALERTS_URL: &str =
+    "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/camsys%2Flirr-alerts";
+
+pub fn adapter() -> MtaRailroadAlerts {
+    MtaRailroadAlerts::new(Source::Lirr, "lirr_alerts", LIRR_ALERTS_URL)
+}
@@ -0,0 +1,90 @@
+use chrono::{Datelike, Timelike, Weekday};
+use chrono_tz::Tz;
+
+use crate::{
+    models::{source::Source, static_cache::CachedTrip},
+    sources::mta_railroad::realtime::MtaRailroadRealtime,
+};
+
+const LIRR_GTFS_RT_URL: &str =
+    "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/lirr%2Fgtfs-lirr";
+
+pub fn adapter() -> MtaRailroadRealtime {
+    MtaRailroadRealtime::new(Source::Lirr, "lirr-gtfs", LIRR_GTFS_RT_URL)
+}
+
+// --- Helpers ---
+
+/// Peak fares apply on weekdays to inbound trains arriving at a NYC terminal between 6 and 10 AM,
+/// and to outbound trains departing a NYC terminal between 4 and 8 PM.
+/// LIRR holiday schedules (which are off-peak all day) aren't accounted for.
+pub(crate) fn is_peak(direction: i16, cached_trip: &CachedTrip, tz: Tz) -> bool {
+    // direction_id 1 is inbound (westbound to the city), 0 is outbound
+    let terminal_time = if direction == 1 {
+        cached_trip.stop_times.last().map(|st| st.arrival)
+    } else {
+        cached_trip.stop_times.first().map(|st| st.departure)
+    };
+    let Some(terminal_time) = terminal_time else {
+        return false;
+    };
+
+    let local = terminal_time.with_timezone(&tz);
+    if matches!(local.weekday(), Weekday::Sat | Weekday::Sun) {
+        return false;
+    }
+
+    let window = if direction == 1 { 6..10 } else { 16..20 };
+    window.contains(&local.hour())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::static_cache::CachedStopTime;
+    use chrono::{DateTime, TimeZone, Utc};
+    use chrono_tz::America::New_York;
+
+    fn cached_trip(first: DateTime<Utc>, last: DateTime<Utc>) -> CachedTrip {
+        let stop_time = |stop_id: &str, time| CachedStopTime {
+            stop_id: stop_id.into(),
+            arrival: time,
+            departure: time,
+            stop_sequence: 1,
+        };
+        CachedTrip {
+            trip_id: "GO103_25_2711".into(),
+            route_id: "1".into(),
+            headsign: "Penn Station".into(),
+            direction_id: 1,
+            start_date: first.with_timezone(&New_York).format("%Y%m%d").to_string(),
+            start_time: first,
+            stop_times: vec![stop_time("1", first), stop_time("237", last)],
+        }
+    }
+
+    fn ny(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        New_York
+            .with_ymd_and_hms(y, m, d, h, min, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_is_peak() {
+        // Wednesday inbound, arriving at Penn at 8:15 AM
+        let trip = cached_trip(ny(2025, 6, 4, 7, 0), ny(2025, 6, 4, 8, 15));
+        assert!(is_peak(1, &trip, New_York));
+
+        // Same times outbound leave the city in the morning, so off-peak
+        assert!(!is_peak(0, &trip, New_York));
+
+        // Outbound leaving Penn at 5:30 PM
+        let trip = cached_trip(ny(2025, 6, 4, 17, 30), ny(2025, 6, 4, 18, 45));
+        assert!(is_peak(0, &trip, New_York));
+
+        // Saturday trains are always off-peak
+        let trip = cached_trip(ny(2025, 6, 7, 7, 0), ny(2025, 6, 7, 8, 15));
+        assert!(!is_peak(1, &trip, New_York));
+    }
+}
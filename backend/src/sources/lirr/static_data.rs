@@ -0,0 +1,7 @@
+use crate::{models::source::Source, sources::mta_railroad::static_data::MtaRailroadStatic};
+
+const LIRR_GTFS_URL: &str = "https://rrgtfsfeeds.s3.amazonaws.com/gtfslirr.zip";
+
+pub fn adapter() -> MtaRailroadStatic {
+    MtaRailroadStatic::new(Source::Lirr, LIRR_GTFS_URL)
+}
@@ -0,0 +1,8 @@
+use crate::{models::source::Source, sources::mta_railroad::alerts::MtaRailroadAlerts};
+
+const MNR_ALERTS_URL: &str =
+    "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/camsys%2Fmnr-alerts";
+
+pub fn adapter() -> MtaRailroadAlerts {
+    MtaRailroadAlerts::new(Source::Mnr, "mnr_alerts", MNR_ALERTS_URL)
+}
@@ -0,0 +1,3 @@
+pub mod alerts;
+pub mod realtime;
+pub mod static_data;
@@ -0,0 +1,8 @@
+use crate::{models::source::Source, sources::mta_railroad::realtime::MtaRailroadRealtime};
+
+const MNR_GTFS_RT_URL: &str =
+    "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/mnr%2Fgtfs-mnr";
+
+pub fn adapter() -> MtaRailroadRealtime {
+    MtaRailroadRealtime::new(Source::Mnr, "mnr-gtfs", MNR_GTFS_RT_URL)
+}
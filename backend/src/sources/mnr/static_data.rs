@@ -0,0 +1,7 @@
+use crate::{models::source::Source, sources::mta_railroad::static_data::MtaRailroadStatic};
+
+const MNR_GTFS_URL: &str = "https://rrgtfsfeeds.s3.amazonaws.com/gtfsmnr.zip";
+
+pub fn adapter() -> MtaRailroadStatic {
+    MtaRailroadStatic::new(Source::Mnr, MNR_GTFS_URL)
+}
@@ -1,24 +1,41 @@
 use crate::{
     engines::static_data::StaticController,
-    models::source::Source,
+    models::{
+        source::Source,
+        stop::{RouteStop, RouteStopData},
+    },
     stores::{
         alert::AlertStore, position::PositionStore, route::RouteStore,
         static_cache::StaticCacheStore, stop::StopStore, trip::TripStore,
     },
 };
 use async_trait::async_trait;
+use chrono_tz::Tz;
+use geo::{LineString, MultiLineString};
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
 use titlecase::Titlecase;
 use tokio::time::Duration;
 
+pub mod gtfs;
+pub mod lirr;
+pub mod mnr;
 pub mod mta_bus;
+pub mod mta_railroad;
 pub mod mta_subway;
 pub mod njt_bus;
+pub mod njt_rail;
 
 // need for Dyn traits
 #[async_trait]
 pub trait RealtimeAdapter: Send + Sync {
     fn source(&self) -> Source;
 
+    /// Timezone that trip start dates and times in the feed are in
+    fn timezone(&self) -> Tz {
+        source_timezone(self.source())
+    }
+
     fn refresh_interval(&self) -> Duration;
 
     async fn run(
@@ -49,6 +66,9 @@ pub trait StaticAdapter: Send + Sync {
     // implement string on source enum instead?
     // fn name(&self) -> &str;
 
+    /// Timezone used until the static feed is imported, and after if it doesn't have a valid `agency_timezone`
+    fn timezone(&self) -> Tz;
+
     fn refresh_interval(&self) -> Duration;
 
     async fn import(
@@ -59,6 +79,26 @@ pub trait StaticAdapter: Send + Sync {
     ) -> anyhow::Result<()>;
 }
 
+fn timezones() -> &'static RwLock<HashMap<Source, Tz>> {
+    static TIMEZONES: OnceLock<RwLock<HashMap<Source, Tz>>> = OnceLock::new();
+    TIMEZONES.get_or_init(Default::default)
+}
+
+/// Timezone that a source's trip start dates and times are in. Starts as its static adapter's timezone and is
+/// replaced by the feed's `agency_timezone` on each static import. Sources without a static adapter use New York.
+pub fn source_timezone(source: Source) -> Tz {
+    timezones()
+        .read()
+        .unwrap()
+        .get(&source)
+        .copied()
+        .unwrap_or(chrono_tz::America::New_York)
+}
+
+pub fn set_source_timezone(source: Source, tz: Tz) {
+    timezones().write().unwrap().insert(source, tz);
+}
+
 ///// various utilities for parsing and normalizing static data
 
 /// Trim leading/trailing whitespace and collapse internal whitespace runs.
@@ -77,3 +117,90 @@ pub fn normalize_whitespace(value: &str) -> String {
 pub fn normalize_title(value: &str) -> String {
     normalize_whitespace(value).titlecase()
 }
+
+/// Build a MultiLineString per route from the distinct shapes its trips use.
+pub fn route_shape_geometries(gtfs: &gtfs_structures::Gtfs) -> HashMap<String, MultiLineString> {
+    let mut route_shape_ids: HashMap<&str, Vec<&str>> = HashMap::new();
+    for trip in gtfs.trips.values() {
+        let Some(shape_id) = trip.shape_id.as_deref() else {
+            continue;
+        };
+        let shape_ids = route_shape_ids.entry(trip.route_id.as_str()).or_default();
+        if !shape_ids.contains(&shape_id) {
+            shape_ids.push(shape_id);
+        }
+    }
+
+    route_shape_ids
+        .into_iter()
+        .filter_map(|(route_id, shape_ids)| {
+            let lines: Vec<LineString> = shape_ids
+                .into_iter()
+                .filter_map(|shape_id| {
+                    let mut points = gtfs.shapes.get(shape_id)?.clone();
+                    points.sort_by_key(|p| p.sequence);
+                    Some(LineString::new(
+                        points
+                            .iter()
+                            .map(|p| geo::Coord {
+                                x: p.longitude,
+                                y: p.latitude,
+                            })
+                            .collect(),
+                    ))
+                })
+                .filter(|line| line.0.len() >= 2)
+                .collect();
+
+            (!lines.is_empty()).then(|| (route_id.to_owned(), MultiLineString::new(lines)))
+        })
+        .collect()
+}
+
+/// Build route_stops for rail sources. Trains stop at the same platforms in both directions, so
+/// there is a single route_stop per (route_id, stop_id). The sequence is taken from outbound
+/// (direction_id 0) trips so stops are ordered from the terminal outwards.
+pub fn build_rail_route_stops(
+    gtfs: &gtfs_structures::Gtfs,
+    data: RouteStopData,
+) -> Vec<RouteStop> {
+    // (route_id, stop_id) -> (direction, min stop_sequence)
+    let mut accum: HashMap<(String, String), (i16, i16)> = HashMap::new();
+
+    for trip in gtfs.trips.values() {
+        let direction: i16 = trip.direction_id.map(|d| d as i16).unwrap_or(0);
+
+        for (idx, st) in trip.stop_times.iter().enumerate() {
+            // Inbound trips are walked in reverse so both directions share an ordering
+            let sequence = if direction == 0 {
+                st.stop_sequence as i16
+            } else {
+                (trip.stop_times.len() - idx) as i16
+            };
+
+            let key = (trip.route_id.clone(), st.stop.id.clone());
+            accum
+                .entry(key)
+                .and_modify(|(existing_dir, existing_seq)| {
+                    // Outbound sequences win over inbound ones
+                    if direction < *existing_dir
+                        || (direction == *existing_dir && sequence < *existing_seq)
+                    {
+                        *existing_dir = direction;
+                        *existing_seq = sequence;
+                    }
+                })
+                .or_insert((direction, sequence));
+        }
+    }
+
+    accum
+        .into_iter()
+        .map(|((route_id, stop_id), (_, stop_sequence))| RouteStop {
+            route_id,
+            stop_id,
+            stop_sequence,
+            data: data.clone(),
+        })
+        .collect()
+}
@@ -8,6 +8,7 @@ use crate::models::alert::{
     MtaAlertData,
 };
 use crate::models::source::Source;
+use crate::models::status::FeedKind;
 use crate::sources::AlertsAdapter;
 use crate::stores::alert::AlertStore;
 use async_trait::async_trait;
@@ -17,7 +18,7 @@ use uuid::Uuid;
 /// Parses MTA's language field (e.g., "en", "en-html") into format and language.
 /// "en" -> (Plain, "en")
 /// "en-html" -> (Html, "en")
-fn parse_mta_language(lang: Option<&str>) -> (AlertFormat, String) {
+pub(crate) fn parse_mta_language(lang: Option<&str>) -> (AlertFormat, String) {
     match lang {
         Some(l) if l.ends_with("-html") => {
             let language = l.strip_suffix("-html").unwrap_or("en").to_string();
@@ -37,12 +38,16 @@ impl GtfsAlertSource for MtaBusAlerts {
     }
 
     async fn fetch_feeds(&self) -> Vec<FeedMessage> {
-        gtfs_realtime::fetch_feeds(vec![(
-            "mta_bus_alerts".into(),
-            gtfs_realtime::get_bytes(
-                "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/camsys%2Fbus-alerts",
-            ),
-        )])
+        gtfs_realtime::fetch_feeds(
+            Source::MtaBus,
+            FeedKind::Alerts,
+            vec![(
+                "mta_bus_alerts".into(),
+                gtfs_realtime::get_bytes(
+                    "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/camsys%2Fbus-alerts",
+                ),
+            )],
+        )
         .await
     }
 
@@ -1,13 +1,14 @@
 use crate::engines::static_data::StaticController;
+use crate::engines::status;
 use crate::integrations::gtfs_realtime;
 use crate::integrations::oba;
 use crate::models::source::Source;
+use crate::models::status::FeedKind;
 use crate::models::trip::Trip;
 use crate::models::{
     position::{MtaBusPositionData, PositionData, VehiclePosition},
-    trip::{StopTime, StopTimeData},
+    trip::{MtaBusStopTimeData, StopTime, StopTimeData, StopTimeStatus, TripStatus},
 };
-use crate::mta_oba_api_key;
 use crate::sources::RealtimeAdapter;
 use crate::sources::mta_bus::AGENCIES;
 use crate::sources::mta_subway::realtime::parse_origin_time;
@@ -18,17 +19,19 @@ use crate::{
     feed::{FeedMessage, TripUpdate, VehiclePosition as GtfsVehiclePosition},
     integrations::gtfs_realtime::GtfsSource,
 };
+use crate::{metrics, mta_oba_api_key};
 use async_trait::async_trait;
 use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use geo::Point;
 use std::collections::HashMap;
+use std::time::Instant;
 use tracing::{debug, error, info, instrument, warn};
 use uuid::Uuid;
 
 pub struct MtaBusRealtime;
 
 impl MtaBusRealtime {
-    /// Fetch OBA data from all MTA agencies
+    /// Fetch OBA data from all MTA agencies. Each agency is recorded in metrics like a realtime feed.
     async fn fetch_oba_data(&self) -> anyhow::Result<Vec<oba::VehicleStatus>> {
         let mut all_vehicles = vec![];
 
@@ -37,14 +40,40 @@ impl MtaBusRealtime {
                 "https://bustime.mta.info/api/where/vehicles-for-agency/{}.json",
                 agency
             );
+            let feed = format!("mta_bus-oba-{}", agency);
 
-            match oba::fetch_vehicles(&url, mta_oba_api_key()).await {
+            let start = Instant::now();
+            let result = oba::fetch_vehicles(&url, mta_oba_api_key()).await;
+            metrics::histogram!(metrics::FEED_FETCH_SECONDS, "feed" => feed.clone())
+                .record(start.elapsed().as_secs_f64());
+
+            match result {
                 Ok(vehicles) => {
                     debug!("Fetched {} vehicles from {}", vehicles.len(), agency);
+                    metrics::counter!(metrics::FEED_ENTITIES, "feed" => feed.clone())
+                        .increment(vehicles.len() as u64);
+                    metrics::gauge!(metrics::FEED_LAST_SUCCESS, "feed" => feed.clone())
+                        .set(Utc::now().timestamp() as f64);
+                    // OBA responses don't have a header time, so only the fetch counts
+                    status::record_feed(
+                        Source::MtaBus,
+                        FeedKind::Realtime,
+                        &feed,
+                        None,
+                        vehicles.len(),
+                    );
                     all_vehicles.extend(vehicles);
                 }
                 Err(e) => {
                     warn!("Failed to fetch OBA data from {}: {:?}", agency, e);
+                    metrics::counter!(metrics::FEED_FETCH_FAILURES, "feed" => feed.clone(), "stage" => "fetch")
+                        .increment(1);
+                    status::record_feed_error(
+                        Source::MtaBus,
+                        FeedKind::Realtime,
+                        &feed,
+                        format!("{:#}", e),
+                    );
                 }
             }
         }
@@ -64,16 +93,20 @@ impl GtfsSource for MtaBusRealtime {
     }
 
     async fn fetch_feeds(&self) -> Vec<FeedMessage> {
-        gtfs_realtime::fetch_feeds(vec![
-            (
-                "mta_bus-trips".into(),
-                gtfs_realtime::get_bytes("https://gtfsrt.prod.obanyc.com/tripUpdates"),
-            ),
-            (
-                "mta_bus-positions".into(),
-                gtfs_realtime::get_bytes("https://gtfsrt.prod.obanyc.com/vehiclePositions"),
-            ),
-        ])
+        gtfs_realtime::fetch_feeds(
+            Source::MtaBus,
+            FeedKind::Realtime,
+            vec![
+                (
+                    "mta_bus-trips".into(),
+                    gtfs_realtime::get_bytes("https://gtfsrt.prod.obanyc.com/tripUpdates"),
+                ),
+                (
+                    "mta_bus-positions".into(),
+                    gtfs_realtime::get_bytes("https://gtfsrt.prod.obanyc.com/vehiclePositions"),
+                ),
+            ],
+        )
         .await
     }
 
@@ -94,14 +127,12 @@ impl GtfsSource for MtaBusRealtime {
             None => return (None, vec![]),
         };
 
-        // TODO: handle cancelled trips using vehicle.schedule_relationship
         // Extract vehicle/bus ID from the TripUpdate
-        let vehicle = match update.vehicle {
-            Some(v) => v,
-            None => return (None, vec![]),
-        };
-        let vehicle_id = match vehicle.id {
-            Some(id) => parse_prefixed_id(id),
+        let status = TripStatus::from_gtfs(trip_desc.schedule_relationship);
+        let vehicle_id = match update.vehicle.and_then(|v| v.id) {
+            Some(id) => Some(parse_prefixed_id(id)),
+            // Canceled trips don't have a bus assigned, but are kept so they don't just disappear
+            None if status == TripStatus::Canceled => None,
             None => return (None, vec![]),
         };
 
@@ -143,7 +174,7 @@ impl GtfsSource for MtaBusRealtime {
             NaiveTime::from_hms_opt(0, 0, 0).unwrap()
         });
 
-        let created_at = match Trip::created_at(start_date, start_time) {
+        let created_at = match Trip::created_at(start_date, start_time, self.timezone()) {
             Some(ca) => ca,
             None => return (None, vec![]),
         };
@@ -156,12 +187,16 @@ impl GtfsSource for MtaBusRealtime {
             created_at,
             vehicle_id,
             updated_at: Utc::now(),
+            status,
+            ghost_confidence: None,
             data: crate::models::trip::TripData::MtaBus(crate::models::trip::MtaBusData {
                 deviation: update.delay,
             }),
         };
 
-        // Process stop times for bus
+        // Process stop times for bus. Canceled trips usually come without stop time updates, and since there's no
+        // cached static schedule for MTA buses to fill them in from, they're saved without stop times. They show up
+        // in trip listings but not on departure boards.
         let stop_times: Vec<StopTime> = update
             .stop_time_update
             .into_iter()
@@ -187,7 +222,8 @@ impl GtfsSource for MtaBusRealtime {
                     stop_id,
                     arrival,
                     departure,
-                    data: StopTimeData::MtaBus,
+                    status: StopTimeStatus::from_gtfs(st.schedule_relationship),
+                    data: StopTimeData::MtaBus(MtaBusStopTimeData::default()),
                 })
             })
             .collect();
@@ -291,7 +327,9 @@ impl RealtimeAdapter for MtaBusRealtime {
                         self.process_trip(update, static_cache_store).await;
                     if let Some(trip) = trip_opt {
                         // Map vehicle_id to trip_id for position linking
-                        vehicle_to_trip.insert(trip.vehicle_id.clone(), trip.id);
+                        if let Some(vehicle_id) = &trip.vehicle_id {
+                            vehicle_to_trip.insert(vehicle_id.clone(), trip.id);
+                        }
                         data.push((trip, new_stop_times));
                     }
                 }
@@ -445,7 +483,7 @@ mod tests {
                 if let Some(trip) = trip {
                     trip_count += 1;
                     assert!(!trip.original_id.is_empty());
-                    assert!(!trip.vehicle_id.is_empty());
+                    assert!(trip.vehicle_id.as_ref().is_none_or(|v| !v.is_empty()));
                     // MTA Bus direction is usually 0 or 1
                     assert!(trip.direction == 0 || trip.direction == 1);
                     
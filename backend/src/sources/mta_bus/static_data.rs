@@ -2,6 +2,7 @@ use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use anyhow::Context;
 use async_trait::async_trait;
+use chrono_tz::{America::New_York, Tz};
 use futures::FutureExt;
 use geo::{Distance, Euclidean, LineString, MultiLineString, Point};
 use indicatif::{ProgressBar, ProgressStyle};
@@ -74,6 +75,10 @@ impl StaticAdapter for MtaBusStatic {
         Source::MtaBus
     }
 
+    fn timezone(&self) -> Tz {
+        New_York
+    }
+
     fn refresh_interval(&self) -> Duration {
         Duration::from_secs(60 * 60 * 24 * 3) // 3 days
     }
@@ -0,0 +1,187 @@
+use crate::feed::{Alert as GtfsAlert, FeedMessage};
+use crate::integrations::{
+    gtfs_alert::{self, GtfsAlertSource},
+    gtfs_realtime,
+};
+use crate::models::alert::{
+    ActivePeriod, AffectedEntity, Alert, AlertData, AlertSection, AlertTranslation, MtaAlertData,
+};
+use crate::models::source::Source;
+use crate::models::status::FeedKind;
+use crate::sources::AlertsAdapter;
+use crate::sources::mta_bus::alerts::parse_mta_language;
+use crate::stores::alert::AlertStore;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Alerts adapter for an MTA railroad. Only the LIRR and Metro-North use this format.
+pub struct MtaRailroadAlerts {
+    source: Source,
+    feed_label: &'static str,
+    feed_url: &'static str,
+}
+
+impl MtaRailroadAlerts {
+    pub fn new(source: Source, feed_label: &'static str, feed_url: &'static str) -> Self {
+        assert!(
+            matches!(source, Source::Lirr | Source::Mnr),
+            "{source:?} isn't an MTA railroad"
+        );
+        Self {
+            source,
+            feed_label,
+            feed_url,
+        }
+    }
+}
+
+#[async_trait]
+impl GtfsAlertSource for MtaRailroadAlerts {
+    fn source(&self) -> Source {
+        self.source
+    }
+
+    async fn fetch_feeds(&self) -> Vec<FeedMessage> {
+        gtfs_realtime::fetch_feeds(
+            self.source,
+            FeedKind::Alerts,
+            vec![(
+                self.feed_label.into(),
+                gtfs_realtime::get_bytes(self.feed_url),
+            )],
+        )
+        .await
+    }
+
+    fn process_alert(
+        &self,
+        entity_id: String,
+        alert: GtfsAlert,
+    ) -> Option<(
+        Alert,
+        Vec<AlertTranslation>,
+        Vec<ActivePeriod>,
+        Vec<AffectedEntity>,
+    )> {
+        // Railroad alerts come from the same Mercury system as the subway and bus alerts
+        let mercury_alert = alert.mercury_alert.as_ref()?;
+
+        let created_at = DateTime::from_timestamp(mercury_alert.created_at as i64, 0)?;
+        let updated_at = DateTime::from_timestamp(mercury_alert.updated_at as i64, 0)?;
+
+        let alert_id = Uuid::now_v7();
+
+        let mta_data = MtaAlertData {
+            display_before_active: mercury_alert.display_before_active.unwrap_or(0) as i32,
+            alert_type: mercury_alert.alert_type.clone(),
+            clone_id: mercury_alert.clone_id.clone(),
+        };
+
+        let parsed_alert = Alert {
+            id: alert_id,
+            original_id: entity_id,
+            source: self.source,
+            created_at,
+            updated_at,
+            recorded_at: Utc::now(),
+            data: match self.source {
+                Source::Lirr => AlertData::Lirr(mta_data),
+                _ => AlertData::Mnr(mta_data),
+            },
+        };
+
+        let mut translations = Vec::new();
+
+        if let Some(header_text) = &alert.header_text {
+            for translation in header_text.translation.iter() {
+                let (format, language) = parse_mta_language(translation.language.as_deref());
+                translations.push(AlertTranslation {
+                    alert_id,
+                    section: AlertSection::Header,
+                    format,
+                    language,
+                    text: translation.text.clone(),
+                });
+            }
+        }
+
+        if let Some(description_text) = &alert.description_text {
+            for translation in description_text.translation.iter() {
+                let (format, language) = parse_mta_language(translation.language.as_deref());
+                translations.push(AlertTranslation {
+                    alert_id,
+                    section: AlertSection::Description,
+                    format,
+                    language,
+                    text: translation.text.clone(),
+                });
+            }
+        }
+
+        let active_periods: Vec<ActivePeriod> = alert
+            .active_period
+            .iter()
+            .filter_map(|ap| {
+                let start = DateTime::from_timestamp(ap.start? as i64, 0)?;
+                let end = ap.end.and_then(|e| DateTime::from_timestamp(e as i64, 0));
+                Some(ActivePeriod {
+                    alert_id,
+                    start_time: start,
+                    end_time: end,
+                })
+            })
+            .collect();
+
+        let affected_entities: Vec<AffectedEntity> = alert
+            .informed_entity
+            .iter()
+            .map(|entity| {
+                let route_id = entity.route_id.clone().and_then(|r| self.parse_route_id(r));
+                let stop_id = entity.stop_id.clone().and_then(|s| self.parse_stop_id(s));
+
+                let sort_order = entity
+                    .mercury_entity_selector
+                    .as_ref()
+                    .and_then(|selector| {
+                        selector
+                            .sort_order
+                            .split(':')
+                            .next_back()
+                            .and_then(|s| s.parse().ok())
+                    })
+                    .unwrap_or(0);
+
+                AffectedEntity {
+                    alert_id,
+                    route_id,
+                    source: self.source,
+                    stop_id,
+                    sort_order,
+                }
+            })
+            .collect();
+
+        Some((
+            parsed_alert,
+            translations,
+            active_periods,
+            affected_entities,
+        ))
+    }
+}
+
+#[async_trait]
+impl AlertsAdapter for MtaRailroadAlerts {
+    fn source(&self) -> Source {
+        self.source
+    }
+
+    fn refresh_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(60)
+    }
+
+    async fn run(&self, alert_store: &AlertStore) -> anyhow::Result<()> {
+        gtfs_alert::run_pipeline(self, alert_store).await
+    }
+}
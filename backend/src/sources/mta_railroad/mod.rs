@@ -0,0 +1,6 @@
+//! Adapters shared by the LIRR and Metro-North, which publish static GTFS, GTFS-RT trip updates, vehicle positions
+//! and alerts in the same MTA railroad format. Each source passes in its own feed URLs.
+
+pub mod alerts;
+pub mod realtime;
+pub mod static_data;
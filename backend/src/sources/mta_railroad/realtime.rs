@@ -0,0 +1,288 @@
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveTime, Utc};
+use geo::Point;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::{
+    engines::static_data::StaticController,
+    feed::{FeedMessage, TripUpdate, VehiclePosition as GtfsVehiclePosition},
+    integrations::gtfs_realtime::{self, GtfsSource},
+    models::{
+        position::{LirrPositionData, MnrPositionData, PositionData, VehiclePosition},
+        source::Source,
+        status::FeedKind,
+        trip::{
+            LirrData, LirrStopTimeData, MnrData, MnrStopTimeData, StopTime, StopTimeData,
+            StopTimeStatus, Trip, TripData, TripStatus,
+        },
+    },
+    sources::{RealtimeAdapter, lirr},
+    stores::{position::PositionStore, static_cache::StaticCacheStore, trip::TripStore},
+};
+
+/// Realtime adapter for an MTA railroad. Only the LIRR and Metro-North use this format.
+pub struct MtaRailroadRealtime {
+    source: Source,
+    feed_label: &'static str,
+    feed_url: &'static str,
+}
+
+impl MtaRailroadRealtime {
+    pub fn new(source: Source, feed_label: &'static str, feed_url: &'static str) -> Self {
+        assert!(
+            matches!(source, Source::Lirr | Source::Mnr),
+            "{source:?} isn't an MTA railroad"
+        );
+        Self {
+            source,
+            feed_label,
+            feed_url,
+        }
+    }
+}
+
+#[async_trait]
+impl GtfsSource for MtaRailroadRealtime {
+    fn source(&self) -> Source {
+        self.source
+    }
+
+    async fn fetch_feeds(&self) -> Vec<FeedMessage> {
+        gtfs_realtime::fetch_feeds(
+            self.source,
+            FeedKind::Realtime,
+            vec![(
+                self.feed_label.into(),
+                gtfs_realtime::get_bytes(self.feed_url),
+            )],
+        )
+        .await
+    }
+
+    async fn process_trip(
+        &self,
+        update: TripUpdate,
+        static_cache_store: &StaticCacheStore,
+    ) -> (Option<Trip>, Vec<StopTime>) {
+        // Metro-North leaves out start_date, so it usually comes from the schedule
+        let Some((start_date, cached_trip)) =
+            gtfs_realtime::service_date(self.source, self.timezone(), &update, static_cache_store)
+                .await
+        else {
+            return (None, vec![]);
+        };
+
+        let trip_desc = update.trip;
+
+        let trip_id = match trip_desc.trip_id {
+            Some(id) => id,
+            None => return (None, vec![]),
+        };
+
+        // The train number is the vehicle id in both railroads' feeds
+        let train_number = match update
+            .vehicle
+            .as_ref()
+            .and_then(|v| v.id.clone().or_else(|| v.label.clone()))
+        {
+            Some(id) => id,
+            None => {
+                debug!(trip_id, source = ?self.source, "Missing train number for trip update");
+                return (None, vec![]);
+            }
+        };
+
+        let route_id = match trip_desc
+            .route_id
+            .or_else(|| cached_trip.as_ref().map(|ct| ct.route_id.clone()))
+        {
+            Some(id) => id,
+            None => return (None, vec![]),
+        };
+
+        let direction = trip_desc
+            .direction_id
+            .map(|d| d as i16)
+            .or_else(|| cached_trip.as_ref().map(|ct| ct.direction_id))
+            .unwrap_or(0);
+
+        // Cached start times are already in UTC, so only the feed's start_time needs converting
+        let created_at = match trip_desc
+            .start_time
+            .as_deref()
+            .and_then(|t| NaiveTime::parse_from_str(t, "%H:%M:%S").ok())
+        {
+            Some(t) => Trip::created_at(start_date, t, self.timezone()),
+            None => cached_trip.as_ref().map(|ct| ct.start_time),
+        };
+        let Some(created_at) = created_at else {
+            return (None, vec![]);
+        };
+
+        let headsign = cached_trip
+            .as_ref()
+            .map(|ct| ct.headsign.clone())
+            .unwrap_or_default();
+
+        let data = match self.source {
+            Source::Lirr => TripData::Lirr(LirrData {
+                train_number: train_number.clone(),
+                headsign,
+                // filled in from the vehicle position by merge_vehicle
+                car_count: None,
+                peak: cached_trip
+                    .as_ref()
+                    .map(|ct| lirr::realtime::is_peak(direction, ct, self.timezone())),
+            }),
+            _ => TripData::Mnr(MnrData {
+                train_number: train_number.clone(),
+                headsign,
+                car_count: None,
+            }),
+        };
+
+        let trip = Trip {
+            id: Uuid::now_v7(),
+            original_id: trip_id,
+            route_id,
+            direction,
+            created_at,
+            vehicle_id: Some(train_number),
+            updated_at: Utc::now(),
+            status: TripStatus::from_gtfs(trip_desc.schedule_relationship),
+            ghost_confidence: None,
+            data,
+        };
+
+        let stop_times: Vec<StopTime> = update
+            .stop_time_update
+            .into_iter()
+            .filter_map(|st| {
+                let stop_id = st.stop_id?;
+
+                let arrival = match st.arrival {
+                    Some(a) => a.time?,
+                    None => st.departure.as_ref()?.time?,
+                };
+                let departure = match st.departure {
+                    Some(d) => d.time?,
+                    None => st.arrival.as_ref()?.time?,
+                };
+
+                let arrival = DateTime::from_timestamp(arrival, 0)?;
+                let departure = DateTime::from_timestamp(departure, 0)?;
+
+                // Track info from the MTA railroad extension
+                let (track, status) = match st.mta_railroad_stop_time_update {
+                    Some(rr) => (rr.track, rr.train_status),
+                    None => (None, None),
+                };
+
+                let data = match self.source {
+                    Source::Lirr => StopTimeData::Lirr(LirrStopTimeData { track, status }),
+                    _ => StopTimeData::Mnr(MnrStopTimeData { track, status }),
+                };
+
+                Some(StopTime {
+                    trip_id: trip.id,
+                    stop_id,
+                    arrival,
+                    departure,
+                    status: StopTimeStatus::from_gtfs(st.schedule_relationship),
+                    data,
+                })
+            })
+            .collect();
+
+        (Some(trip), stop_times)
+    }
+
+    async fn process_vehicle(
+        &self,
+        vehicle: GtfsVehiclePosition,
+        _static_cache_store: &StaticCacheStore,
+    ) -> Option<VehiclePosition> {
+        let vehicle_desc = vehicle.vehicle.as_ref()?;
+        let vehicle_id = vehicle_desc
+            .id
+            .clone()
+            .or_else(|| vehicle_desc.label.clone())?;
+
+        let status = match vehicle.current_status {
+            Some(0) => Some("incoming".into()),
+            Some(1) => Some("at_stop".into()),
+            Some(2) => Some("in_transit_to".into()),
+            _ => None,
+        };
+
+        let car_count = match vehicle.multi_carriage_details.len() {
+            0 => None,
+            n => Some(n as i32),
+        };
+
+        let updated_at = vehicle
+            .timestamp
+            .and_then(|t| DateTime::from_timestamp(t as i64, 0))
+            .unwrap_or_else(Utc::now);
+
+        let geom = vehicle.position.map(|p| {
+            let geom: geo::Geometry = Point::new(p.longitude as f64, p.latitude as f64).into();
+            geom.into()
+        });
+
+        let data = match self.source {
+            Source::Lirr => PositionData::Lirr(LirrPositionData { status, car_count }),
+            _ => PositionData::Mnr(MnrPositionData { status, car_count }),
+        };
+
+        Some(VehiclePosition {
+            vehicle_id,
+            trip_id: None,
+            stop_id: vehicle.stop_id,
+            updated_at,
+            geom,
+            data,
+        })
+    }
+
+    fn merge_vehicle(&self, trip: &mut Trip, position: &VehiclePosition) {
+        match (&mut trip.data, &position.data) {
+            (TripData::Lirr(trip_data), PositionData::Lirr(position_data)) => {
+                trip_data.car_count = position_data.car_count;
+            }
+            (TripData::Mnr(trip_data), PositionData::Mnr(position_data)) => {
+                trip_data.car_count = position_data.car_count;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[async_trait]
+impl RealtimeAdapter for MtaRailroadRealtime {
+    fn source(&self) -> Source {
+        self.source
+    }
+
+    fn refresh_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(30)
+    }
+
+    async fn run(
+        &self,
+        static_controller: &StaticController,
+        static_cache_store: &StaticCacheStore,
+        trip_store: &TripStore,
+        position_store: &PositionStore,
+    ) -> anyhow::Result<()> {
+        gtfs_realtime::run_pipeline(
+            self,
+            static_controller,
+            static_cache_store,
+            trip_store,
+            position_store,
+        )
+        .await
+    }
+}
@@ -0,0 +1,218 @@
+use std::{io::Cursor, time::Duration};
+
+use crate::{
+    engines::static_cache::{expand_gtfs, update_timezone},
+    models::{
+        route::{MnrRouteData, Route, RouteData},
+        source::Source,
+        stop::{LirrStopData, MnrStopData, RouteStopData, Stop, StopData},
+    },
+    sources::{
+        StaticAdapter, build_rail_route_stops, normalize_whitespace, route_shape_geometries,
+    },
+    stores::{route::RouteStore, static_cache::StaticCacheStore, stop::StopStore},
+};
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono_tz::{America::New_York, Tz};
+use geo::Point;
+use gtfs_structures::Availability;
+
+const MTA_RAILROAD_DEFAULT_COLOR: &str = "0039A6";
+
+/// Static adapter for an MTA railroad. Only the LIRR and Metro-North use this format.
+pub struct MtaRailroadStatic {
+    source: Source,
+    gtfs_url: &'static str,
+}
+
+impl MtaRailroadStatic {
+    pub fn new(source: Source, gtfs_url: &'static str) -> Self {
+        assert!(
+            matches!(source, Source::Lirr | Source::Mnr),
+            "{source:?} isn't an MTA railroad"
+        );
+        Self { source, gtfs_url }
+    }
+
+    /// Name used in logs and errors
+    fn name(&self) -> &'static str {
+        match self.source {
+            Source::Lirr => "LIRR",
+            _ => "MNR",
+        }
+    }
+
+    fn route_data(&self, route: &gtfs_structures::Route) -> RouteData {
+        match self.source {
+            Source::Lirr => RouteData::Lirr,
+            _ => RouteData::Mnr(MnrRouteData {
+                url: route.url.clone(),
+            }),
+        }
+    }
+
+    fn stop_data(&self, stop: &gtfs_structures::Stop) -> StopData {
+        match self.source {
+            Source::Lirr => StopData::Lirr(LirrStopData {
+                fare_zone: stop.zone_id.clone(),
+            }),
+            _ => StopData::Mnr(MnrStopData {
+                ada: matches!(stop.wheelchair_boarding, Availability::Available),
+                fare_zone: stop.zone_id.clone(),
+            }),
+        }
+    }
+
+    fn route_stop_data(&self) -> RouteStopData {
+        match self.source {
+            Source::Lirr => RouteStopData::Lirr,
+            _ => RouteStopData::Mnr,
+        }
+    }
+}
+
+#[async_trait]
+impl StaticAdapter for MtaRailroadStatic {
+    fn source(&self) -> Source {
+        self.source
+    }
+
+    fn timezone(&self) -> Tz {
+        New_York
+    }
+
+    fn refresh_interval(&self) -> Duration {
+        Duration::from_secs(60 * 60 * 24) // 24 hours
+    }
+
+    async fn import(
+        &self,
+        route_store: &RouteStore,
+        stop_store: &StopStore,
+        static_cache_store: &StaticCacheStore,
+    ) -> anyhow::Result<()> {
+        let name = self.name();
+
+        let gtfs_bytes = reqwest::get(self.gtfs_url)
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await
+            .with_context(|| format!("{name} GTFS download failed"))?;
+
+        let gtfs = tokio::task::spawn_blocking(move || {
+            gtfs_structures::Gtfs::from_reader(Cursor::new(gtfs_bytes))
+        })
+        .await
+        .context("GTFS parse task panicked")?
+        .with_context(|| format!("Failed to parse {name} GTFS"))?;
+
+        tracing::info!(
+            "{name} GTFS parsed: {} routes, {} stops, {} trips",
+            gtfs.routes.len(),
+            gtfs.stops.len(),
+            gtfs.trips.len()
+        );
+
+        // Expand and cache yesterday's, today's and tomorrow's trips in Redis. Realtime uses these for service dates,
+        // headsigns and LIRR peak fares.
+        let cached_trips = expand_gtfs(
+            self.source,
+            &gtfs,
+            update_timezone(self.source, &gtfs, self.timezone()),
+        );
+        static_cache_store
+            .cache_trips(self.source, &cached_trips)
+            .await
+            .with_context(|| format!("Failed to cache {name} trips in Redis"))?;
+
+        let routes = self.build_routes(&gtfs);
+        let stops = self.build_stops(&gtfs);
+        let route_stops = build_rail_route_stops(&gtfs, self.route_stop_data());
+
+        tracing::info!(
+            "{name}: built {} routes, {} stops, {} route_stops",
+            routes.len(),
+            stops.len(),
+            route_stops.len()
+        );
+
+        route_store
+            .save_all(self.source, &routes)
+            .await
+            .with_context(|| format!("Failed to save {name} routes to database"))?;
+        stop_store
+            .save_all(self.source, &stops)
+            .await
+            .with_context(|| format!("Failed to save {name} stops to database"))?;
+        stop_store
+            .save_all_route_stops(self.source, &route_stops)
+            .await
+            .with_context(|| format!("Failed to save {name} route_stops to database"))?;
+
+        Ok(())
+    }
+}
+
+impl MtaRailroadStatic {
+    // ── Build routes ──────────────────────────────────────────────────────────
+
+    fn build_routes(&self, gtfs: &gtfs_structures::Gtfs) -> Vec<Route> {
+        let geom_map = route_shape_geometries(gtfs);
+
+        gtfs.routes
+            .values()
+            .map(|r| {
+                let color = r
+                    .color
+                    .map(|c| format!("{:02X}{:02X}{:02X}", c.r, c.g, c.b))
+                    .filter(|hex| hex != "000000")
+                    .unwrap_or_else(|| MTA_RAILROAD_DEFAULT_COLOR.to_owned());
+
+                Route {
+                    id: r.id.clone(),
+                    long_name: r
+                        .long_name
+                        .as_deref()
+                        .map(normalize_whitespace)
+                        .unwrap_or_default(),
+                    short_name: r
+                        .short_name
+                        .as_deref()
+                        .map(normalize_whitespace)
+                        .unwrap_or_default(),
+                    color,
+                    data: self.route_data(r),
+                    geom: geom_map.get(&r.id).cloned().map(Into::into),
+                }
+            })
+            .collect()
+    }
+
+    // ── Build stops ───────────────────────────────────────────────────────────
+
+    fn build_stops(&self, gtfs: &gtfs_structures::Gtfs) -> Vec<Stop> {
+        gtfs.stops
+            .values()
+            .filter_map(|s| {
+                let lat = s.latitude?;
+                let lon = s.longitude?;
+                let name = s
+                    .name
+                    .as_deref()
+                    .map(normalize_whitespace)
+                    .unwrap_or_else(|| s.id.clone());
+
+                Some(Stop {
+                    id: s.id.clone(),
+                    name,
+                    geom: Point::new(lon, lat).into(),
+                    transfers: vec![],
+                    routes: vec![],
+                    data: self.stop_data(s),
+                })
+            })
+            .collect()
+    }
+}
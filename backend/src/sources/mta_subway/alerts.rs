@@ -8,6 +8,7 @@ use crate::models::alert::{
     MtaAlertData,
 };
 use crate::models::source::Source;
+use crate::models::status::FeedKind;
 use crate::sources::AlertsAdapter;
 use crate::stores::alert::AlertStore;
 use async_trait::async_trait;
@@ -38,12 +39,16 @@ impl GtfsAlertSource for MtaSubwayAlerts {
     }
 
     async fn fetch_feeds(&self) -> Vec<FeedMessage> {
-        gtfs_realtime::fetch_feeds(vec![(
-            "mta_subway_alerts".into(),
-            gtfs_realtime::get_bytes(
-                "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/camsys%2Fsubway-alerts",
-            ),
-        )])
+        gtfs_realtime::fetch_feeds(
+            Source::MtaSubway,
+            FeedKind::Alerts,
+            vec![(
+                "mta_subway_alerts".into(),
+                gtfs_realtime::get_bytes(
+                    "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/camsys%2Fsubway-alerts",
+                ),
+            )],
+        )
         .await
     }
 
@@ -1,10 +1,15 @@
 use crate::engines::static_data::StaticController;
 use crate::integrations::gtfs_realtime;
 use crate::models::source::Source;
+use crate::models::static_cache::CachedTrip;
+use crate::models::status::FeedKind;
 use crate::models::stop::FAKE_STOP_IDS;
 use crate::models::{
     position::{MtaSubwayPositionData, PositionData, VehiclePosition},
-    trip::{MtaSubwayStopTimeData, StopTime, StopTimeData, Trip, TripData},
+    trip::{
+        MtaSubwayData, MtaSubwayStopTimeData, StopTime, StopTimeData, StopTimeStatus, Trip,
+        TripData, TripStatus,
+    },
 };
 use crate::sources::RealtimeAdapter;
 use crate::stores::position::PositionStore;
@@ -28,63 +33,67 @@ impl GtfsSource for MtaSubwayRealtime {
     }
 
     async fn fetch_feeds(&self) -> Vec<FeedMessage> {
-        gtfs_realtime::fetch_feeds(vec![
-            (
-                "mta_subway-ace".into(),
-                gtfs_realtime::get_bytes(
-                    "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-ace",
+        gtfs_realtime::fetch_feeds(
+            Source::MtaSubway,
+            FeedKind::Realtime,
+            vec![
+                (
+                    "mta_subway-ace".into(),
+                    gtfs_realtime::get_bytes(
+                        "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-ace",
+                    ),
                 ),
-            ),
-            (
-                "mta_subway-bdfm".into(),
-                gtfs_realtime::get_bytes(
-                    "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-bdfm",
+                (
+                    "mta_subway-bdfm".into(),
+                    gtfs_realtime::get_bytes(
+                        "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-bdfm",
+                    ),
                 ),
-            ),
-            (
-                "mta_subway-g".into(),
-                gtfs_realtime::get_bytes(
-                    "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-g",
+                (
+                    "mta_subway-g".into(),
+                    gtfs_realtime::get_bytes(
+                        "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-g",
+                    ),
                 ),
-            ),
-            (
-                "mta_subway-jz".into(),
-                gtfs_realtime::get_bytes(
-                    "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-jz",
+                (
+                    "mta_subway-jz".into(),
+                    gtfs_realtime::get_bytes(
+                        "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-jz",
+                    ),
                 ),
-            ),
-            (
-                "mta_subway-nqrw".into(),
-                gtfs_realtime::get_bytes(
-                    "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-nqrw",
+                (
+                    "mta_subway-nqrw".into(),
+                    gtfs_realtime::get_bytes(
+                        "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-nqrw",
+                    ),
                 ),
-            ),
-            (
-                "mta_subway-l".into(),
-                gtfs_realtime::get_bytes(
-                    "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-l",
+                (
+                    "mta_subway-l".into(),
+                    gtfs_realtime::get_bytes(
+                        "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-l",
+                    ),
                 ),
-            ),
-            (
-                "mta_subway-gtfs-1234567".into(),
-                gtfs_realtime::get_bytes(
-                    "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs",
+                (
+                    "mta_subway-gtfs-1234567".into(),
+                    gtfs_realtime::get_bytes(
+                        "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs",
+                    ),
+                ), // 1234567
+                (
+                    "mta_subway-si".into(),
+                    gtfs_realtime::get_bytes(
+                        "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-si",
+                    ),
                 ),
-            ), // 1234567
-            (
-                "mta_subway-si".into(),
-                gtfs_realtime::get_bytes(
-                    "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-si",
-                ),
-            ),
-        ])
+            ],
+        )
         .await
     }
 
     async fn process_trip(
         &self,
         update: TripUpdate,
-        _static_cache_store: &StaticCacheStore,
+        static_cache_store: &StaticCacheStore,
     ) -> (Option<Trip>, Vec<StopTime>) {
         let trip_desc = update.trip;
 
@@ -173,21 +182,18 @@ impl GtfsSource for MtaSubwayRealtime {
                 }
             }
         };
-        let created_at = match Trip::created_at(start_date, start_time) {
+        let created_at = match Trip::created_at(start_date, start_time, self.timezone()) {
             Some(ca) => ca,
             None => return (None, vec![]),
         };
 
-        let trip = Trip {
-            id: Uuid::now_v7(),
-            original_id: mta_id,
-            route_id,
-            direction,
-            created_at,
-            vehicle_id: train_id,
-            updated_at: Utc::now(),
-            data: TripData::MtaSubway,
-        };
+        // The static cache uses realtime trip IDs, so trips that aren't in the schedule just have no delay
+        let schedule = static_cache_store
+            .get_trip(Source::MtaSubway, &mta_id, &start_date_str)
+            .await
+            .unwrap_or(None);
+
+        let trip_id = Uuid::now_v7();
 
         // Process stop times
         let stop_times: Vec<StopTime> = update
@@ -221,19 +227,43 @@ impl GtfsSource for MtaSubwayRealtime {
                     None => (None, None),
                 };
 
+                let delay = schedule
+                    .as_ref()
+                    .and_then(|schedule| scheduled_delay(schedule, &stop_id, arrival));
+
                 Some(StopTime {
-                    trip_id: trip.id,
+                    trip_id,
                     stop_id,
                     arrival,
                     departure,
+                    status: StopTimeStatus::from_gtfs(st.schedule_relationship),
                     data: StopTimeData::MtaSubway(MtaSubwayStopTimeData {
                         scheduled_track,
                         actual_track,
+                        delay,
                     }),
                 })
             })
             .collect();
 
+        // Stop times that were already passed are dropped from the feed, so the first one is the next stop
+        let delay = stop_times.iter().find_map(|st| match &st.data {
+            StopTimeData::MtaSubway(data) => data.delay,
+            _ => None,
+        });
+        let trip = Trip {
+            id: trip_id,
+            original_id: mta_id,
+            route_id,
+            direction,
+            created_at,
+            vehicle_id: Some(train_id),
+            updated_at: Utc::now(),
+            status: TripStatus::from_gtfs(trip_desc.schedule_relationship),
+            ghost_confidence: None,
+            data: TripData::MtaSubway(MtaSubwayData { delay }),
+        };
+
         (Some(trip), stop_times)
     }
 
@@ -323,6 +353,24 @@ fn parse_route_id(route_id: String) -> String {
     }
 }
 
+/// Convert a static GTFS trip ID to the ID realtime feeds use for the same trip. Static IDs are prefixed with the
+/// service they belong to (e.g. `AFA25GEN-1038-Weekday-00_000600_1..S03R` is `000600_1..S03R` in realtime).
+pub fn realtime_trip_id(static_trip_id: &str) -> Option<String> {
+    let mut parts = static_trip_id.rsplitn(3, '_');
+    let path = parts.next()?;
+    let origin_time = parts.next()?;
+    Some(format!("{origin_time}_{path}"))
+}
+
+/// Seconds between a realtime arrival and the scheduled arrival at a stop. Positive means late.
+fn scheduled_delay(schedule: &CachedTrip, stop_id: &str, arrival: DateTime<Utc>) -> Option<i32> {
+    let stop_time = schedule
+        .stop_times
+        .iter()
+        .find(|st| st.stop_id == stop_id)?;
+    Some((arrival - stop_time.arrival).num_seconds() as i32)
+}
+
 /// Parses the MTA's origin time format into NaiveTime.
 pub fn parse_origin_time(origin_time: i32) -> Option<NaiveTime> {
     let minutes = origin_time as f64 / 100.0;
@@ -357,9 +405,7 @@ mod tests {
         let fixture = load_fixture("mta_subway/mta_subway-ace.pb");
         let adapter = MtaSubwayRealtime;
         
-        // We don't need a real redis for this unit test as _static_cache_store is unused in subway process_trip
-        // but we need a placeholder if we were to call it. 
-        // For now, subway doesn't use it.
+        // The static cache is only used for delays, so trips are still processed when it's empty
         
         let mut trip_count = 0;
         for entity in fixture.entity {
@@ -372,7 +418,7 @@ mod tests {
                 if let Some(trip) = trip {
                     trip_count += 1;
                     assert!(!trip.original_id.is_empty());
-                    assert!(!trip.vehicle_id.is_empty());
+                    assert!(trip.vehicle_id.as_ref().is_some_and(|v| !v.is_empty()));
                     // MTA Subway direction is usually 1 or 3
                     assert!(trip.direction == 1 || trip.direction == 3);
                     
@@ -386,4 +432,34 @@ mod tests {
         }
         assert!(trip_count > 0, "Should have processed at least one trip from fixture");
     }
+
+    #[test]
+    fn test_scheduled_delay() {
+        use crate::models::static_cache::CachedStopTime;
+        use chrono::TimeZone;
+
+        let static_trip_id = "AFA25GEN-1038-Weekday-00_000600_1..S03R";
+        assert_eq!(
+            realtime_trip_id(static_trip_id).as_deref(),
+            Some("000600_1..S03R")
+        );
+
+        let time = |h, m| Utc.with_ymd_and_hms(2025, 3, 3, h, m, 0).unwrap();
+        let schedule = CachedTrip {
+            trip_id: "000600_1..S03R".into(),
+            route_id: "1".into(),
+            headsign: "South Ferry".into(),
+            direction_id: 1,
+            start_date: "20250303".into(),
+            start_time: time(5, 6),
+            stop_times: vec![CachedStopTime {
+                stop_id: "101".into(),
+                arrival: time(5, 6),
+                departure: time(5, 6),
+                stop_sequence: 1,
+            }],
+        };
+        assert_eq!(scheduled_delay(&schedule, "101", time(5, 12)), Some(360));
+        assert_eq!(scheduled_delay(&schedule, "103", time(5, 12)), None);
+    }
 }
@@ -2,16 +2,20 @@ use std::{collections::HashMap, time::Duration};
 
 use anyhow::Context;
 use async_trait::async_trait;
+use chrono_tz::{America::New_York, Tz};
 use geo::{Geometry, LineString, MultiLineString, Point};
 use geojson::FeatureCollection;
 use gtfs_structures::StopTransfer;
 use rayon::prelude::*;
 use serde::{Deserialize, Deserializer};
 
+use super::realtime::realtime_trip_id;
 use crate::{
+    engines::static_cache::{expand_gtfs, update_timezone},
     models::{
         route::{Route, RouteData},
         source::Source,
+        static_cache::CachedTrip,
         stop::{
             Borough, FAKE_STOP_IDS, MtaSubwayStopData, RouteStop, RouteStopData, Stop, StopData,
             StopType,
@@ -155,15 +159,20 @@ impl StaticAdapter for MtaSubwayStatic {
         Source::MtaSubway
     }
 
+    fn timezone(&self) -> Tz {
+        New_York
+    }
+
     fn refresh_interval(&self) -> Duration {
-        Duration::from_secs(60 * 60 * 24 * 7) // 7 days
+        // Daily so the cached schedule always covers today and tomorrow
+        Duration::from_secs(60 * 60 * 24) // 24 hours
     }
 
     async fn import(
         &self,
         route_store: &RouteStore,
         stop_store: &StopStore,
-        _static_cache_store: &StaticCacheStore,
+        static_cache_store: &StaticCacheStore,
     ) -> anyhow::Result<()> {
         // TODO: use gtfsReader to only select wanted files
         let gtfs = gtfs_structures::Gtfs::from_url_async(GTFS_SCHEDULE_URL)
@@ -227,6 +236,27 @@ impl StaticAdapter for MtaSubwayStatic {
             .await
             .context("Failed to save transfers to database")?;
 
+        // Cache the schedule under realtime trip and stop IDs so realtime trips can be matched to it
+        let cached_trips: Vec<CachedTrip> = expand_gtfs(
+            Source::MtaSubway,
+            &gtfs,
+            update_timezone(Source::MtaSubway, &gtfs, self.timezone()),
+        )
+        .into_iter()
+        .filter_map(|mut trip| {
+            trip.trip_id = realtime_trip_id(&trip.trip_id)?;
+            for stop_time in trip.stop_times.iter_mut() {
+                // Remove N or S
+                stop_time.stop_id.pop();
+            }
+            Some(trip)
+        })
+        .collect();
+        static_cache_store
+            .cache_trips(Source::MtaSubway, &cached_trips)
+            .await
+            .context("Failed to cache subway trips in Redis")?;
+
         Ok(())
     }
 }
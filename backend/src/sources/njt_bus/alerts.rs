@@ -14,6 +14,7 @@ use crate::{
             AlertTranslation,
         },
         source::Source,
+        status::FeedKind,
     },
     sources::AlertsAdapter,
     stores::alert::AlertStore,
@@ -38,10 +39,14 @@ impl GtfsAlertSource for NjtBusAlerts {
             }
         };
 
-        gtfs_realtime::fetch_feeds(vec![(
-            "njt_bus_alerts".into(),
-            njt_post_future(NJT_ALERTS_URL, token),
-        )])
+        gtfs_realtime::fetch_feeds(
+            Source::NjtBus,
+            FeedKind::Alerts,
+            vec![(
+                "njt_bus_alerts".into(),
+                njt_post_future(NJT_ALERTS_URL, token),
+            )],
+        )
         .await
     }
 
@@ -19,11 +19,20 @@ pub(super) const NJT_VEHICLE_POSITIONS_URL: &str =
     "https://pcsdata.njtransit.com/api/GTFSG2/getVehiclePositions";
 pub(super) const NJT_ALERTS_URL: &str = "https://pcsdata.njtransit.com/api/GTFSG2/getAlerts";
 pub(super) const NJT_BUS_ROUTES_URL: &str = "https://pcsdata.njtransit.com/api/BUSDV2/getBusRoutes";
+// Rail uses a separate API host, but the same credentials and auth flow
+pub(super) const NJT_RAIL_GTFS_AUTH_URL: &str =
+    "https://raildata.njtransit.com/api/GTFSRT/authenticateUser";
+pub(super) const NJT_RAIL_DATA_AUTH_URL: &str =
+    "https://raildata.njtransit.com/api/TrainData/getToken";
 
 #[derive(Clone, Copy, Debug)]
 pub(super) enum NjtApi {
     GtfsG2,
     BusDv2,
+    /// Rail GTFS and GTFS-RT
+    RailGtfs,
+    /// Rail DepartureVision data (track assignments)
+    RailData,
 }
 
 impl NjtApi {
@@ -31,6 +40,8 @@ impl NjtApi {
         match self {
             Self::GtfsG2 => NJT_GTFS_AUTH_URL,
             Self::BusDv2 => NJT_BUSDV2_AUTH_URL,
+            Self::RailGtfs => NJT_RAIL_GTFS_AUTH_URL,
+            Self::RailData => NJT_RAIL_DATA_AUTH_URL,
         }
     }
 }
@@ -44,12 +55,16 @@ struct AuthResponse {
 /// Cached (token, acquired_at). Refreshed after 23 hours.
 static NJT_GTFS_TOKEN: OnceLock<Mutex<Option<(String, Instant)>>> = OnceLock::new();
 static NJT_BUSDV2_TOKEN: OnceLock<Mutex<Option<(String, Instant)>>> = OnceLock::new();
+static NJT_RAIL_GTFS_TOKEN: OnceLock<Mutex<Option<(String, Instant)>>> = OnceLock::new();
+static NJT_RAIL_DATA_TOKEN: OnceLock<Mutex<Option<(String, Instant)>>> = OnceLock::new();
 
 /// Returns a valid NJT API token, re-authenticating if the cached one is stale.
 pub(super) async fn get_token(api: NjtApi) -> anyhow::Result<String> {
     let mutex = match api {
         NjtApi::GtfsG2 => NJT_GTFS_TOKEN.get_or_init(|| Mutex::new(None)),
         NjtApi::BusDv2 => NJT_BUSDV2_TOKEN.get_or_init(|| Mutex::new(None)),
+        NjtApi::RailGtfs => NJT_RAIL_GTFS_TOKEN.get_or_init(|| Mutex::new(None)),
+        NjtApi::RailData => NJT_RAIL_DATA_TOKEN.get_or_init(|| Mutex::new(None)),
     };
     let mut guard = mutex.lock().await;
 
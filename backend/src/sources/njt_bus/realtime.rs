@@ -11,7 +11,8 @@ use crate::{
     models::{
         position::{NjtBusPositionData, PositionData, VehiclePosition},
         source::Source,
-        trip::{StopTime, StopTimeData, Trip, TripData},
+        status::FeedKind,
+        trip::{StopTime, StopTimeData, StopTimeStatus, Trip, TripData, TripStatus},
     },
     sources::RealtimeAdapter,
     stores::{position::PositionStore, static_cache::StaticCacheStore, trip::TripStore},
@@ -36,16 +37,20 @@ impl GtfsSource for NjtBusRealtime {
             }
         };
 
-        gtfs_realtime::fetch_feeds(vec![
-            (
-                "njt_bus_getTripUpdates".into(),
-                njt_post_future(NJT_TRIP_UPDATES_URL, token.clone()),
-            ),
-            (
-                "njt_bus_getVehiclePositions".into(),
-                njt_post_future(NJT_VEHICLE_POSITIONS_URL, token),
-            ),
-        ])
+        gtfs_realtime::fetch_feeds(
+            Source::NjtBus,
+            FeedKind::Realtime,
+            vec![
+                (
+                    "njt_bus_getTripUpdates".into(),
+                    njt_post_future(NJT_TRIP_UPDATES_URL, token.clone()),
+                ),
+                (
+                    "njt_bus_getVehiclePositions".into(),
+                    njt_post_future(NJT_VEHICLE_POSITIONS_URL, token),
+                ),
+            ],
+        )
         .await
     }
 
@@ -66,7 +71,7 @@ impl GtfsSource for NjtBusRealtime {
         // TODO: stop guessing start_date, it will cause issues near midnight.
         let start_date_str = trip_desc.start_date.clone().unwrap_or_else(|| {
             Utc::now()
-                .with_timezone(&chrono_tz::America::New_York)
+                .with_timezone(&self.timezone())
                 .format("%Y%m%d")
                 .to_string()
         });
@@ -102,31 +107,24 @@ impl GtfsSource for NjtBusRealtime {
             Err(_) => return (None, vec![]),
         };
 
-        let start_time = match trip_desc.start_time {
-            Some(ref t) => match NaiveTime::parse_from_str(t, "%H:%M:%S") {
-                Ok(time) => time,
-                Err(_) => cached_trip
-                    .as_ref()
-                    .map(|ct| {
-                        ct.start_time
-                            .with_timezone(&chrono_tz::America::New_York)
-                            .time()
-                    })
-                    .unwrap_or_else(|| NaiveTime::from_hms_opt(0, 0, 0).unwrap()),
-            },
-            None => cached_trip
-                .as_ref()
-                .map(|ct| {
-                    ct.start_time
-                        .with_timezone(&chrono_tz::America::New_York)
-                        .time()
-                })
-                .unwrap_or_else(|| NaiveTime::from_hms_opt(0, 0, 0).unwrap()),
+        // Cached start times are already in UTC, so only the feed's start_time needs converting.
+        // Falls back to the start of the service day.
+        let created_at = match trip_desc
+            .start_time
+            .as_deref()
+            .and_then(|t| NaiveTime::parse_from_str(t, "%H:%M:%S").ok())
+        {
+            Some(t) => Trip::created_at(start_date, t, self.timezone()),
+            None => cached_trip.as_ref().map(|ct| ct.start_time).or_else(|| {
+                Trip::created_at(
+                    start_date,
+                    NaiveTime::from_hms_opt(0, 0, 0).unwrap(),
+                    self.timezone(),
+                )
+            }),
         };
-
-        let created_at = match Trip::created_at(start_date, start_time) {
-            Some(ca) => ca,
-            None => return (None, vec![]),
+        let Some(created_at) = created_at else {
+            return (None, vec![]);
         };
 
         let headsign = cached_trip
@@ -154,8 +152,10 @@ impl GtfsSource for NjtBusRealtime {
             route_id,
             direction,
             created_at,
-            vehicle_id,
+            vehicle_id: Some(vehicle_id),
             updated_at: Utc::now(),
+            status: TripStatus::from_gtfs(trip_desc.schedule_relationship),
+            ghost_confidence: None,
             data: TripData::NjtBus(crate::models::trip::NjtBusData {
                 deviation: update.delay,
                 headsign,
@@ -185,6 +185,7 @@ impl GtfsSource for NjtBusRealtime {
                     stop_id,
                     arrival,
                     departure,
+                    status: StopTimeStatus::from_gtfs(st.schedule_relationship),
                     data: StopTimeData::NjtBus,
                 })
             })
@@ -5,7 +5,7 @@ use std::{
 };
 
 use crate::{
-    engines::static_cache::expand_gtfs,
+    engines::static_cache::{expand_gtfs, update_timezone},
     models::{
         route::{Route, RouteData},
         source::Source,
@@ -16,6 +16,7 @@ use crate::{
 };
 use anyhow::Context;
 use async_trait::async_trait;
+use chrono_tz::{America::New_York, Tz};
 use geo::{Distance, Euclidean, LineString, MultiLineString, Point};
 use geojson::GeoJson;
 use proj4rs::{Proj, transform::transform};
@@ -82,6 +83,10 @@ impl StaticAdapter for NjtBusStatic {
         Source::NjtBus
     }
 
+    fn timezone(&self) -> Tz {
+        New_York
+    }
+
     fn refresh_interval(&self) -> Duration {
         Duration::from_secs(60 * 60 * 24) // 24 hours
     }
@@ -116,7 +121,11 @@ impl StaticAdapter for NjtBusStatic {
         );
 
         // Expand and cache trips for 48 hours in Redis
-        let cached_trips = expand_gtfs(Source::NjtBus, &gtfs);
+        let cached_trips = expand_gtfs(
+            Source::NjtBus,
+            &gtfs,
+            update_timezone(Source::NjtBus, &gtfs, self.timezone()),
+        );
         static_cache_store
             .cache_trips(Source::NjtBus, &cached_trips)
             .await
@@ -0,0 +1,13 @@
+pub mod alerts;
+pub mod realtime;
+pub mod static_data;
+
+// Auth is shared with NJT bus, see `sources::njt_bus::get_token`
+pub(super) const NJT_RAIL_GTFS_URL: &str = "https://raildata.njtransit.com/api/GTFSRT/getGTFS";
+pub(super) const NJT_RAIL_TRIP_UPDATES_URL: &str =
+    "https://raildata.njtransit.com/api/GTFSRT/getTripUpdates";
+pub(super) const NJT_RAIL_VEHICLE_POSITIONS_URL: &str =
+    "https://raildata.njtransit.com/api/GTFSRT/getVehiclePositions";
+pub(super) const NJT_RAIL_ALERTS_URL: &str = "https://raildata.njtransit.com/api/GTFSRT/getAlerts";
+pub(super) const NJT_RAIL_TRAIN_SCHEDULE_URL: &str =
+    "https://raildata.njtransit.com/api/TrainData/getTrainSchedule";
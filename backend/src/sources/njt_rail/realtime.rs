@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveTime, Utc};
+use geo::Point;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::{
+    engines::static_data::StaticController,
+    feed::{FeedMessage, TripUpdate, VehiclePosition as GtfsVehiclePosition},
+    integrations::gtfs_realtime::{self, GtfsSource},
+    models::{
+        position::{NjtRailPositionData, PositionData, VehiclePosition},
+        source::Source,
+        status::FeedKind,
+        trip::{
+            NjtRailData, NjtRailStopTimeData, StopTime, StopTimeData, StopTimeStatus, Trip,
+            TripData, TripStatus,
+        },
+    },
+    sources::{
+        RealtimeAdapter,
+        njt_bus::{NjtApi, get_token, njt_post_future},
+    },
+    stores::{position::PositionStore, static_cache::StaticCacheStore, trip::TripStore},
+};
+
+use super::{
+    NJT_RAIL_TRAIN_SCHEDULE_URL, NJT_RAIL_TRIP_UPDATES_URL, NJT_RAIL_VEHICLE_POSITIONS_URL,
+};
+
+/// Stations where tracks are assigned shortly before departure. The GTFS-RT feed doesn't include
+/// tracks, so these are fetched from DepartureVision every cycle.
+const TRACK_STATIONS: [&str; 6] = ["NY", "NP", "SE", "HB", "ND", "TR"];
+
+#[derive(Debug, serde::Deserialize)]
+struct DepartureVision {
+    #[serde(rename = "ITEMS", default)]
+    items: Vec<DepartureVisionItem>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct DepartureVisionItem {
+    #[serde(rename = "TRAIN_ID")]
+    train_id: String,
+    #[serde(rename = "TRACK", default)]
+    track: String,
+}
+
+pub struct NjtRailRealtime {
+    /// train number -> (station code, track) from the latest DepartureVision fetch
+    tracks: Mutex<HashMap<String, Vec<(String, String)>>>,
+}
+
+impl NjtRailRealtime {
+    pub fn new() -> Self {
+        Self {
+            tracks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn fetch_tracks(&self) -> anyhow::Result<()> {
+        let token = get_token(NjtApi::RailData).await?;
+
+        let futures = TRACK_STATIONS.iter().map(|&station| {
+            let token = token.clone();
+            async move {
+                let form = reqwest::multipart::Form::new()
+                    .text("token", token)
+                    .text("station", station);
+                let res = reqwest::Client::new()
+                    .post(NJT_RAIL_TRAIN_SCHEDULE_URL)
+                    .multipart(form)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<DepartureVision>()
+                    .await?;
+                anyhow::Ok((station, res))
+            }
+        });
+
+        let mut tracks: HashMap<String, Vec<(String, String)>> = HashMap::new();
+        for result in futures::future::join_all(futures).await {
+            let (station, departures) = match result {
+                Ok(r) => r,
+                Err(e) => {
+                    warn!("Failed to fetch NJT DepartureVision: {:#}", e);
+                    continue;
+                }
+            };
+
+            for item in departures.items {
+                let track = item.track.trim();
+                if track.is_empty() {
+                    continue;
+                }
+                tracks
+                    .entry(item.train_id.trim().to_owned())
+                    .or_default()
+                    .push((station.to_owned(), track.to_owned()));
+            }
+        }
+
+        *self.tracks.lock().unwrap() = tracks;
+        Ok(())
+    }
+}
+
+impl Default for NjtRailRealtime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl GtfsSource for NjtRailRealtime {
+    fn source(&self) -> Source {
+        Source::NjtRail
+    }
+
+    async fn fetch_feeds(&self) -> Vec<FeedMessage> {
+        let token = match get_token(NjtApi::RailGtfs).await {
+            Ok(t) => t,
+            Err(e) => {
+                error!("NJT rail auth failed: {:?}", e);
+                return vec![];
+            }
+        };
+
+        // Stale tracks are worse than none, so clear them if DepartureVision is down
+        if let Err(e) = self.fetch_tracks().await {
+            error!("Failed to fetch NJT rail tracks: {:?}", e);
+            self.tracks.lock().unwrap().clear();
+        }
+
+        gtfs_realtime::fetch_feeds(
+            Source::NjtRail,
+            FeedKind::Realtime,
+            vec![
+                (
+                    "njt_rail_getTripUpdates".into(),
+                    njt_post_future(NJT_RAIL_TRIP_UPDATES_URL, token.clone()),
+                ),
+                (
+                    "njt_rail_getVehiclePositions".into(),
+                    njt_post_future(NJT_RAIL_VEHICLE_POSITIONS_URL, token),
+                ),
+            ],
+        )
+        .await
+    }
+
+    async fn process_trip(
+        &self,
+        update: TripUpdate,
+        static_cache_store: &StaticCacheStore,
+    ) -> (Option<Trip>, Vec<StopTime>) {
+        let Some((start_date, cached_trip)) = gtfs_realtime::service_date(
+            Source::NjtRail,
+            self.timezone(),
+            &update,
+            static_cache_store,
+        )
+        .await
+        else {
+            return (None, vec![]);
+        };
+
+        let trip_desc = update.trip;
+
+        let trip_id = match trip_desc.trip_id {
+            Some(id) => id,
+            None => return (None, vec![]),
+        };
+
+        // The train number is the vehicle id in the NJT rail feed
+        let Some(train_number) = update
+            .vehicle
+            .as_ref()
+            .and_then(|v| v.id.clone().or_else(|| v.label.clone()))
+        else {
+            warn!(trip_id, "Missing train number for NJT rail trip update");
+            return (None, vec![]);
+        };
+
+        let route_id = match trip_desc
+            .route_id
+            .or_else(|| cached_trip.as_ref().map(|ct| ct.route_id.clone()))
+        {
+            Some(id) => id,
+            None => return (None, vec![]),
+        };
+
+        let direction = trip_desc
+            .direction_id
+            .map(|d| d as i16)
+            .or_else(|| cached_trip.as_ref().map(|ct| ct.direction_id))
+            .unwrap_or(0);
+
+        // Cached start times are already in UTC, so only the feed's start_time needs converting
+        let created_at = match trip_desc
+            .start_time
+            .as_deref()
+            .and_then(|t| NaiveTime::parse_from_str(t, "%H:%M:%S").ok())
+        {
+            Some(t) => Trip::created_at(start_date, t, self.timezone()),
+            None => cached_trip.as_ref().map(|ct| ct.start_time),
+        };
+        let Some(created_at) = created_at else {
+            return (None, vec![]);
+        };
+
+        let headsign = cached_trip
+            .as_ref()
+            .map(|ct| ct.headsign.clone())
+            .unwrap_or_default();
+
+        // Resolve DepartureVision station codes to stop ids
+        let station_tracks = self
+            .tracks
+            .lock()
+            .unwrap()
+            .get(&train_number)
+            .cloned()
+            .unwrap_or_default();
+        let mut tracks: HashMap<String, String> = HashMap::new();
+        for (station, track) in station_tracks {
+            if let Ok(Some(stop_id)) = static_cache_store
+                .get_stop_id_by_code(Source::NjtRail, &station)
+                .await
+            {
+                tracks.insert(stop_id, track);
+            }
+        }
+
+        let trip = Trip {
+            id: Uuid::now_v7(),
+            original_id: trip_id,
+            route_id,
+            direction,
+            created_at,
+            vehicle_id: Some(train_number.clone()),
+            updated_at: Utc::now(),
+            status: TripStatus::from_gtfs(trip_desc.schedule_relationship),
+            ghost_confidence: None,
+            data: TripData::NjtRail(NjtRailData {
+                train_number,
+                headsign,
+            }),
+        };
+
+        let stop_times: Vec<StopTime> = update
+            .stop_time_update
+            .into_iter()
+            .filter_map(|st| {
+                let stop_id = st.stop_id?;
+
+                let arrival = match st.arrival {
+                    Some(a) => a.time?,
+                    None => st.departure.as_ref()?.time?,
+                };
+                let departure = match st.departure {
+                    Some(d) => d.time?,
+                    None => st.arrival.as_ref()?.time?,
+                };
+
+                let arrival = DateTime::from_timestamp(arrival, 0)?;
+                let departure = DateTime::from_timestamp(departure, 0)?;
+
+                let track = tracks.get(&stop_id).cloned();
+
+                Some(StopTime {
+                    trip_id: trip.id,
+                    stop_id,
+                    arrival,
+                    departure,
+                    status: StopTimeStatus::from_gtfs(st.schedule_relationship),
+                    data: StopTimeData::NjtRail(NjtRailStopTimeData { track }),
+                })
+            })
+            .collect();
+
+        (Some(trip), stop_times)
+    }
+
+    async fn process_vehicle(
+        &self,
+        vehicle: GtfsVehiclePosition,
+        _static_cache_store: &StaticCacheStore,
+    ) -> Option<VehiclePosition> {
+        let vehicle_desc = vehicle.vehicle.as_ref()?;
+        let vehicle_id = vehicle_desc
+            .id
+            .clone()
+            .or_else(|| vehicle_desc.label.clone())?;
+
+        let status = match vehicle.current_status {
+            Some(0) => Some("incoming".into()),
+            Some(1) => Some("at_stop".into()),
+            Some(2) => Some("in_transit_to".into()),
+            _ => None,
+        };
+
+        let updated_at = vehicle
+            .timestamp
+            .and_then(|t| DateTime::from_timestamp(t as i64, 0))
+            .unwrap_or_else(Utc::now);
+
+        let geom = vehicle.position.map(|p| {
+            let geom: geo::Geometry = Point::new(p.longitude as f64, p.latitude as f64).into();
+            geom.into()
+        });
+
+        Some(VehiclePosition {
+            vehicle_id,
+            trip_id: None,
+            stop_id: vehicle.stop_id,
+            updated_at,
+            geom,
+            data: PositionData::NjtRail(NjtRailPositionData { status }),
+        })
+    }
+}
+
+#[async_trait]
+impl RealtimeAdapter for NjtRailRealtime {
+    fn source(&self) -> Source {
+        Source::NjtRail
+    }
+
+    fn refresh_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(30)
+    }
+
+    async fn run(
+        &self,
+        static_controller: &StaticController,
+        static_cache_store: &StaticCacheStore,
+        trip_store: &TripStore,
+        position_store: &PositionStore,
+    ) -> anyhow::Result<()> {
+        gtfs_realtime::run_pipeline(
+            self,
+            static_controller,
+            static_cache_store,
+            trip_store,
+            position_store,
+        )
+        .await
+    }
+}
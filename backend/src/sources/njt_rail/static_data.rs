@@ -0,0 +1,194 @@
+use std::{collections::HashMap, io::Cursor, time::Duration};
+
+use crate::{
+    engines::static_cache::{expand_gtfs, update_timezone},
+    models::{
+        route::{Route, RouteData},
+        source::Source,
+        stop::{NjtRailStopData, RouteStopData, Stop, StopData},
+    },
+    sources::{
+        StaticAdapter, build_rail_route_stops,
+        njt_bus::{NjtApi, get_token},
+        normalize_title, normalize_whitespace, route_shape_geometries,
+    },
+    stores::{route::RouteStore, static_cache::StaticCacheStore, stop::StopStore},
+};
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono_tz::{America::New_York, Tz};
+use geo::Point;
+
+use super::NJT_RAIL_GTFS_URL;
+
+const NJT_RAIL_DEFAULT_COLOR: &str = "F7941D";
+
+pub struct NjtRailStatic;
+
+#[async_trait]
+impl StaticAdapter for NjtRailStatic {
+    fn source(&self) -> Source {
+        Source::NjtRail
+    }
+
+    fn timezone(&self) -> Tz {
+        New_York
+    }
+
+    fn refresh_interval(&self) -> Duration {
+        Duration::from_secs(60 * 60 * 24) // 24 hours
+    }
+
+    async fn import(
+        &self,
+        route_store: &RouteStore,
+        stop_store: &StopStore,
+        static_cache_store: &StaticCacheStore,
+    ) -> anyhow::Result<()> {
+        let token = get_token(NjtApi::RailGtfs)
+            .await
+            .context("NJT rail authentication failed")?;
+
+        let gtfs_bytes = download_gtfs(&token)
+            .await
+            .context("NJT rail GTFS download failed")?;
+
+        let gtfs = tokio::task::spawn_blocking(move || {
+            gtfs_structures::Gtfs::from_reader(Cursor::new(gtfs_bytes))
+        })
+        .await
+        .context("GTFS parse task panicked")?
+        .context("Failed to parse NJT rail GTFS")?;
+
+        tracing::info!(
+            "NJT rail GTFS parsed: {} routes, {} stops, {} trips",
+            gtfs.routes.len(),
+            gtfs.stops.len(),
+            gtfs.trips.len()
+        );
+
+        let cached_trips = expand_gtfs(
+            Source::NjtRail,
+            &gtfs,
+            update_timezone(Source::NjtRail, &gtfs, self.timezone()),
+        );
+        static_cache_store
+            .cache_trips(Source::NjtRail, &cached_trips)
+            .await
+            .context("Failed to cache NJT rail trips in Redis")?;
+
+        // DepartureVision identifies stations by their 2 letter code, so realtime needs a way back to stop ids
+        let stop_codes: HashMap<String, String> = gtfs
+            .stops
+            .values()
+            .filter_map(|s| Some((s.code.clone()?, s.id.clone())))
+            .collect();
+        static_cache_store
+            .cache_stop_codes(Source::NjtRail, &stop_codes)
+            .await
+            .context("Failed to cache NJT rail stop codes in Redis")?;
+
+        let routes = build_routes(&gtfs);
+        let stops = build_stops(&gtfs);
+        let route_stops = build_rail_route_stops(&gtfs, RouteStopData::NjtRail);
+
+        tracing::info!(
+            "NJT rail: built {} routes, {} stops, {} route_stops",
+            routes.len(),
+            stops.len(),
+            route_stops.len()
+        );
+
+        route_store
+            .save_all(Source::NjtRail, &routes)
+            .await
+            .context("Failed to save NJT rail routes to database")?;
+        stop_store
+            .save_all(Source::NjtRail, &stops)
+            .await
+            .context("Failed to save NJT rail stops to database")?;
+        stop_store
+            .save_all_route_stops(Source::NjtRail, &route_stops)
+            .await
+            .context("Failed to save NJT rail route_stops to database")?;
+
+        Ok(())
+    }
+}
+
+// ── GTFS download ─────────────────────────────────────────────────────────────
+
+async fn download_gtfs(token: &str) -> anyhow::Result<Vec<u8>> {
+    let form = reqwest::multipart::Form::new().text("token", token.to_owned());
+
+    let bytes = reqwest::Client::new()
+        .post(NJT_RAIL_GTFS_URL)
+        .multipart(form)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+
+    Ok(bytes.to_vec())
+}
+
+// ── Build routes ──────────────────────────────────────────────────────────────
+
+fn build_routes(gtfs: &gtfs_structures::Gtfs) -> Vec<Route> {
+    let geom_map = route_shape_geometries(gtfs);
+
+    gtfs.routes
+        .values()
+        .map(|r| {
+            let color = r
+                .color
+                .map(|c| format!("{:02X}{:02X}{:02X}", c.r, c.g, c.b))
+                .filter(|hex| hex != "000000")
+                .unwrap_or_else(|| NJT_RAIL_DEFAULT_COLOR.to_owned());
+
+            Route {
+                id: r.id.clone(),
+                long_name: r
+                    .long_name
+                    .as_deref()
+                    .map(normalize_title)
+                    .unwrap_or_default(),
+                short_name: r
+                    .short_name
+                    .as_deref()
+                    .map(normalize_whitespace)
+                    .unwrap_or_default(),
+                color,
+                data: RouteData::NjtRail,
+                geom: geom_map.get(&r.id).cloned().map(Into::into),
+            }
+        })
+        .collect()
+}
+
+// ── Build stops ───────────────────────────────────────────────────────────────
+
+fn build_stops(gtfs: &gtfs_structures::Gtfs) -> Vec<Stop> {
+    gtfs.stops
+        .values()
+        .filter_map(|s| {
+            let lat = s.latitude?;
+            let lon = s.longitude?;
+            let raw_name = s.name.as_deref().unwrap_or(&s.id);
+            // NJT rail stop names are all caps (e.g. "NEW YORK PENN STATION")
+            let name = normalize_title(raw_name);
+
+            Some(Stop {
+                id: s.id.clone(),
+                name,
+                geom: Point::new(lon, lat).into(),
+                transfers: vec![],
+                routes: vec![],
+                data: StopData::NjtRail(NjtRailStopData {
+                    stop_code: s.code.clone(),
+                }),
+            })
+        })
+        .collect()
+}
@@ -1,4 +1,5 @@
 use crate::{
+    engines::push::{self, Update},
     models::{
         alert::{
             ActivePeriod, AffectedEntity, Alert, AlertData, AlertTranslation, ApiAlertTranslation,
@@ -82,11 +83,12 @@ impl AlertStore {
         self.query_all_alerts(source, Utc::now()).await
     }
 
-    /// Populate the alerts Redis cache by re-querying from DB.
-    async fn populate_cache(&self, source: Source) -> anyhow::Result<()> {
+    /// Populate the alerts Redis cache by re-querying from DB. Returns the active alerts.
+    async fn populate_cache(&self, source: Source) -> anyhow::Result<Vec<ApiAlert>> {
         let key = format!("alerts:{}", source.as_str());
         let alerts = self.query_all_alerts(source, Utc::now()).await?;
-        cache_set(&self.redis_pool, &key, &alerts, TTL).await
+        cache_set(&self.redis_pool, &key, &alerts, TTL).await?;
+        Ok(alerts)
     }
 
     /// Internal helper function to query alerts without caching
@@ -246,6 +248,7 @@ impl AlertStore {
 
         // Use a CTE to get the actual IDs (either inserted or existing due to conflict)
         // This returns the mapping from (created_at, original_id, source) to the actual id
+        // existing sees the table from before the upsert, so it tells us which alerts are new or changed
         let id_rows = sqlx::query!(
             r#"
             WITH input_data AS (
@@ -258,6 +261,13 @@ impl AlertStore {
                     unnest($6::timestamptz[]) as recorded_at,
                     unnest($7::jsonb[]) as data
             ),
+            existing AS (
+                SELECT a.id, a.data
+                FROM realtime.alert a
+                JOIN input_data i ON i.created_at = a.created_at
+                    AND i.original_id = a.original_id
+                    AND i.source = a.source
+            ),
             upserted AS (
                 INSERT INTO realtime.alert (
                     id,
@@ -283,15 +293,19 @@ impl AlertStore {
                     data = EXCLUDED.data
                 RETURNING id, created_at, original_id, source
             )
-            SELECT i.new_id as "new_id!", u.id as "id!"
+            SELECT
+                i.new_id AS "new_id!",
+                u.id AS "id!",
+                (e.id IS NULL OR e.data IS DISTINCT FROM i.data) AS "changed!"
             FROM input_data i
             JOIN upserted u ON i.created_at = u.created_at
                 AND i.original_id = u.original_id
                 AND i.source = u.source
+            LEFT JOIN existing e ON e.id = u.id
             "#,
             &ids,
             &original_ids,
-            &sources as _,
+            &sources as &[Source],
             &created_ats,
             &updated_ats,
             &recorded_ats,
@@ -302,7 +316,10 @@ impl AlertStore {
 
         // Build a mapping from old (generated) IDs to actual (database) IDs
         let id_mapping: std::collections::HashMap<uuid::Uuid, uuid::Uuid> =
-            id_rows.into_iter().map(|r| (r.new_id, r.id)).collect();
+            id_rows.iter().map(|r| (r.new_id, r.id)).collect();
+        // Alerts that are new, or whose data, translations or active periods changed
+        let mut changed: std::collections::HashSet<Uuid> =
+            id_rows.iter().filter(|r| r.changed).map(|r| r.id).collect();
 
         tracing::debug!("Inserted {} alerts", alerts.len());
 
@@ -354,9 +371,9 @@ impl AlertStore {
                 .collect::<Vec<_>>();
 
             if !alert_ids.is_empty() {
-                sqlx::query!(
+                let changed_translations = sqlx::query_scalar!(
                     r#"
-                    INSERT INTO realtime.alert_translation (
+                    INSERT INTO realtime.alert_translation AS t (
                         alert_id,
                         section,
                         format,
@@ -370,6 +387,8 @@ impl AlertStore {
                         unnest($4::text[]),
                         unnest($5::text[])
                     ON CONFLICT (alert_id, section, format, language) DO UPDATE SET text = EXCLUDED.text
+                    WHERE t.text IS DISTINCT FROM EXCLUDED.text
+                    RETURNING t.alert_id
                     "#,
                     &alert_ids,
                     &sections as _,
@@ -377,8 +396,9 @@ impl AlertStore {
                     &languages,
                     &texts
                 )
-                .execute(&mut *tx)
+                .fetch_all(&mut *tx)
                 .await?;
+                changed.extend(changed_translations);
 
                 tracing::debug!("Inserted {} translations", filtered_translations.len());
             }
@@ -424,9 +444,9 @@ impl AlertStore {
                 .collect::<Vec<_>>();
 
             if !alert_ids.is_empty() {
-                sqlx::query!(
+                let changed_periods = sqlx::query_scalar!(
                     r#"
-                    INSERT INTO realtime.active_period (
+                    INSERT INTO realtime.active_period AS ap (
                         alert_id,
                         start_time,
                         end_time
@@ -436,13 +456,16 @@ impl AlertStore {
                         unnest($2::timestamptz[]),
                         unnest($3::timestamptz[])
                     ON CONFLICT (alert_id, start_time) DO UPDATE SET end_time = EXCLUDED.end_time
+                    WHERE ap.end_time IS DISTINCT FROM EXCLUDED.end_time
+                    RETURNING ap.alert_id
                     "#,
                     &alert_ids,
                     &start_times,
                     &end_times as &[Option<DateTime<Utc>>]
                 )
-                .execute(&mut *tx)
+                .fetch_all(&mut *tx)
                 .await?;
+                changed.extend(changed_periods);
 
                 tracing::debug!("Inserted {} active periods", filtered_periods.len());
             }
@@ -550,7 +573,21 @@ impl AlertStore {
         // TODO: probably set end time for non-active alerts here
 
         // Populate cache (write-through)
-        self.populate_cache(source).await?;
+        let active = self.populate_cache(source).await?;
+
+        let active_ids: std::collections::HashSet<Uuid> = active.iter().map(|a| a.id).collect();
+        push::publish(Update::Alerts {
+            source,
+            removed: changed
+                .iter()
+                .filter(|id| !active_ids.contains(id))
+                .copied()
+                .collect(),
+            alerts: active
+                .into_iter()
+                .filter(|a| changed.contains(&a.id))
+                .collect(),
+        });
 
         Ok(())
     }
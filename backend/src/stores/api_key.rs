@@ -0,0 +1,279 @@
+use std::{collections::HashMap, time::Duration};
+
+use bb8_redis::RedisConnectionManager;
+use chrono::{NaiveDate, Utc};
+use redis::AsyncCommands;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    models::api_key::{
+        ApiKey, ApiKeyUsage, BucketStatus, DEFAULT_RATE_LIMIT, NewApiKey, RateLimit,
+        display_prefix, generate_key, hash_key,
+    },
+    stores::{cache_get, cache_set},
+};
+
+/// How long keys are cached after being looked up. Revoking a key clears its cache entry right away.
+const KEY_TTL: Duration = Duration::from_secs(60);
+/// How long unknown keys are cached, so requests with made-up keys don't each reach Postgres
+const UNKNOWN_KEY_TTL: Duration = Duration::from_secs(10);
+/// Usage counts are kept this long in case they can't be flushed to Postgres
+const USAGE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Takes a token from a bucket stored as a hash of its tokens and when it was last updated, refilling it for the time
+/// that passed since. Returns whether a token was taken, the tokens left, and milliseconds until a token is available
+/// and until the bucket is full.
+const TOKEN_BUCKET_SCRIPT: &str = r#"
+local rate = tonumber(ARGV[1])
+local burst = tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated_at')
+local tokens = tonumber(bucket[1]) or burst
+local updated_at = tonumber(bucket[2]) or now
+tokens = math.min(burst, tokens + math.max(0, now - updated_at) * rate / 1000)
+
+local allowed = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated_at', now)
+
+local full_in = math.ceil((burst - tokens) * 1000 / rate)
+redis.call('PEXPIRE', KEYS[1], full_in + 1000)
+local retry_in = 0
+if allowed == 0 then
+    retry_in = math.ceil((1 - tokens) * 1000 / rate)
+end
+return {allowed, math.floor(tokens), retry_in, full_in}
+"#;
+
+#[derive(Clone)]
+pub struct ApiKeyStore {
+    pg_pool: PgPool,
+    redis_pool: bb8::Pool<RedisConnectionManager>,
+}
+
+impl ApiKeyStore {
+    pub fn new(pg_pool: PgPool, redis_pool: bb8::Pool<RedisConnectionManager>) -> Self {
+        Self {
+            pg_pool,
+            redis_pool,
+        }
+    }
+
+    fn cache_key(key_hash: &str) -> String {
+        format!("api_keys:{}", key_hash)
+    }
+
+    fn usage_key(day: NaiveDate) -> String {
+        format!("api_usage:{}", day)
+    }
+
+    /// Issue a new key. Returns the key along with its stored details, since only its hash is stored.
+    pub async fn create(&self, new_key: NewApiKey) -> anyhow::Result<(ApiKey, String)> {
+        let key = generate_key();
+        let rate_limit = new_key.rate_limit.unwrap_or(DEFAULT_RATE_LIMIT);
+        let burst = new_key.burst.unwrap_or(rate_limit.saturating_mul(2));
+
+        let api_key = sqlx::query_as!(
+            ApiKey,
+            r#"
+            INSERT INTO auth.api_key (id, name, key_hash, prefix, rate_limit, burst, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, name, prefix, rate_limit, burst, created_at, revoked_at
+            "#,
+            Uuid::now_v7(),
+            new_key.name,
+            hash_key(&key),
+            display_prefix(&key),
+            rate_limit,
+            burst,
+            Utc::now()
+        )
+        .fetch_one(&self.pg_pool)
+        .await?;
+
+        Ok((api_key, key))
+    }
+
+    pub async fn get_all(&self) -> anyhow::Result<Vec<ApiKey>> {
+        Ok(sqlx::query_as!(
+            ApiKey,
+            r#"
+            SELECT id, name, prefix, rate_limit, burst, created_at, revoked_at
+            FROM auth.api_key
+            ORDER BY created_at
+            "#
+        )
+        .fetch_all(&self.pg_pool)
+        .await?)
+    }
+
+    /// Look up an active key. Tries Redis first; falls back to DB on miss. Returns `None` for unknown or revoked keys.
+    pub async fn get_by_key(&self, key: &str) -> anyhow::Result<Option<ApiKey>> {
+        let key_hash = hash_key(key);
+        let cache_key = Self::cache_key(&key_hash);
+        if let Some(api_key) = cache_get::<Option<ApiKey>>(&self.redis_pool, &cache_key).await {
+            return Ok(api_key);
+        }
+
+        let api_key = sqlx::query_as!(
+            ApiKey,
+            r#"
+            SELECT id, name, prefix, rate_limit, burst, created_at, revoked_at
+            FROM auth.api_key
+            WHERE key_hash = $1 AND revoked_at IS NULL
+            "#,
+            key_hash
+        )
+        .fetch_optional(&self.pg_pool)
+        .await?;
+
+        let ttl = match api_key {
+            Some(_) => KEY_TTL,
+            None => UNKNOWN_KEY_TTL,
+        };
+        if let Err(e) = cache_set(&self.redis_pool, &cache_key, &api_key, ttl).await {
+            tracing::warn!("Failed to cache API key lookup: {:#}", e);
+        }
+        Ok(api_key)
+    }
+
+    /// Revoke a key so it's rejected from now on. Returns `false` if there's no active key with the id.
+    pub async fn revoke(&self, id: Uuid) -> anyhow::Result<bool> {
+        let key_hash = sqlx::query_scalar!(
+            r#"
+            UPDATE auth.api_key
+            SET revoked_at = $2
+            WHERE id = $1 AND revoked_at IS NULL
+            RETURNING key_hash
+            "#,
+            id,
+            Utc::now()
+        )
+        .fetch_optional(&self.pg_pool)
+        .await?;
+
+        let Some(key_hash) = key_hash else {
+            return Ok(false);
+        };
+        let mut conn = self.redis_pool.get().await?;
+        let _: () = conn.del(Self::cache_key(&key_hash)).await?;
+        Ok(true)
+    }
+
+    /// Take a token from a client's bucket
+    pub async fn take_token(&self, client: &str, limit: RateLimit) -> anyhow::Result<BucketStatus> {
+        let mut conn = self.redis_pool.get().await?;
+        let (allowed, remaining, retry_in, full_in): (u8, u32, u64, u64) =
+            redis::Script::new(TOKEN_BUCKET_SCRIPT)
+                .key(format!("rate_limit:{}", client))
+                .arg(limit.rate)
+                .arg(limit.burst)
+                .arg(Utc::now().timestamp_millis())
+                .invoke_async(&mut *conn)
+                .await?;
+
+        Ok(BucketStatus {
+            allowed: allowed == 1,
+            limit: limit.burst,
+            remaining,
+            retry_after: retry_in.div_ceil(1000),
+            reset: full_in.div_ceil(1000),
+        })
+    }
+
+    /// Count a request to an endpoint. The nil UUID is used for anonymous requests.
+    pub async fn record_usage(
+        &self,
+        key_id: Uuid,
+        endpoint: &str,
+        day: NaiveDate,
+    ) -> anyhow::Result<()> {
+        let key = Self::usage_key(day);
+        let mut conn = self.redis_pool.get().await?;
+        let _: () = redis::pipe()
+            .hincr(&key, format!("{}|{}", key_id, endpoint), 1)
+            .expire(&key, USAGE_TTL.as_secs() as i64)
+            .query_async(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Add a day's usage counts from Redis to Postgres. The counts are renamed before they're read so requests made
+    /// during the flush are counted in the next one. Returns the number of counts flushed.
+    pub async fn flush_usage(&self, day: NaiveDate) -> anyhow::Result<usize> {
+        let key = Self::usage_key(day);
+        let flushing_key = format!("{}:flushing", key);
+        let mut conn = self.redis_pool.get().await?;
+
+        // Counts left over from a failed flush are flushed before renaming new ones over them
+        let leftover: bool = conn.exists(&flushing_key).await?;
+        if !leftover {
+            let exists: bool = conn.exists(&key).await?;
+            if !exists {
+                return Ok(0);
+            }
+            let _: () = conn.rename(&key, &flushing_key).await?;
+        }
+        let counts: HashMap<String, i64> = conn.hgetall(&flushing_key).await?;
+
+        let mut key_ids = Vec::with_capacity(counts.len());
+        let mut endpoints = Vec::with_capacity(counts.len());
+        let mut requests = Vec::with_capacity(counts.len());
+        for (field, count) in counts {
+            let Some((key_id, endpoint)) = field
+                .split_once('|')
+                .and_then(|(id, endpoint)| Some((Uuid::parse_str(id).ok()?, endpoint)))
+            else {
+                tracing::warn!("Skipping invalid usage count {}", field);
+                continue;
+            };
+            key_ids.push(key_id);
+            endpoints.push(endpoint.to_owned());
+            requests.push(count);
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO auth.api_key_usage (key_id, endpoint, day, requests)
+            SELECT key_id, endpoint, $3, requests
+            FROM UNNEST($1::uuid[], $2::text[], $4::bigint[]) AS u(key_id, endpoint, requests)
+            ON CONFLICT (key_id, endpoint, day) DO UPDATE SET
+                requests = auth.api_key_usage.requests + EXCLUDED.requests
+            "#,
+            &key_ids,
+            &endpoints,
+            day,
+            &requests
+        )
+        .execute(&self.pg_pool)
+        .await?;
+
+        let _: () = conn.del(&flushing_key).await?;
+        Ok(key_ids.len())
+    }
+
+    /// Requests per endpoint and day for a key, since a day. Counts from the last minute may not be flushed yet.
+    pub async fn get_usage(
+        &self,
+        key_id: Uuid,
+        since: NaiveDate,
+    ) -> anyhow::Result<Vec<ApiKeyUsage>> {
+        Ok(sqlx::query_as!(
+            ApiKeyUsage,
+            r#"
+            SELECT endpoint, day, requests
+            FROM auth.api_key_usage
+            WHERE key_id = $1 AND day >= $2
+            ORDER BY day, endpoint
+            "#,
+            key_id,
+            since
+        )
+        .fetch_all(&self.pg_pool)
+        .await?)
+    }
+}
@@ -0,0 +1,383 @@
+use crate::models::{
+    ghost::{GhostRate, GhostSample},
+    history::{RoutePerformance, StopTimeObservation},
+    prediction::SegmentTravelTime,
+    source::Source,
+};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Arrivals earlier than this many seconds before the schedule aren't on time
+const ON_TIME_EARLY: f64 = 60.0;
+/// Arrivals later than this many seconds after the schedule aren't on time
+const ON_TIME_LATE: f64 = 300.0;
+/// A headway adheres to the schedule if it is at most this many times the scheduled headway
+const HEADWAY_TOLERANCE: f64 = 1.25;
+
+#[derive(Clone)]
+pub struct HistoryStore {
+    pg_pool: PgPool,
+}
+
+impl HistoryStore {
+    pub fn new(pg_pool: PgPool) -> Self {
+        Self { pg_pool }
+    }
+
+    /// Realtime stop times that the vehicle has passed but haven't been archived yet.
+    /// A stop is passed once its departure is a minute old and its trip was still in the feed around the time it arrived,
+    /// which covers stops that drop out of the feed as well as ones the feed keeps. Trips that disappeared before
+    /// reaching a stop (cancelled or rerouted) never get observations for it.
+    pub async fn get_passed(
+        &self,
+        source: Source,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<StopTimeObservation>> {
+        Ok(sqlx::query_as!(
+            StopTimeObservation,
+            r#"
+            SELECT
+                st.trip_id,
+                st.stop_id,
+                t.original_id AS original_trip_id,
+                t.route_id,
+                t.direction,
+                t.created_at AS trip_created_at,
+                st.arrival,
+                st.departure,
+                NULL::timestamptz AS scheduled_arrival,
+                NULL::timestamptz AS scheduled_departure
+            FROM realtime.stop_time st
+            JOIN realtime.trip t ON t.id = st.trip_id
+            WHERE
+                st.source = $1
+                AND st.status = 'scheduled'
+                AND st.departure <= $2::timestamptz - INTERVAL '1 minute'
+                AND st.arrival >= $2::timestamptz - INTERVAL '1 day'
+                AND t.updated_at >= st.arrival - INTERVAL '2 minutes'
+                AND NOT EXISTS (
+                    SELECT 1
+                    FROM history.stop_time_observation o
+                    WHERE o.trip_id = st.trip_id AND o.stop_id = st.stop_id AND o.source = st.source
+                )"#,
+            source as _,
+            now
+        )
+        .fetch_all(&self.pg_pool)
+        .await?)
+    }
+
+    /// Freeze observations. Stops that were already archived keep their first observation.
+    pub async fn save_observations(
+        &self,
+        source: Source,
+        observations: &[StopTimeObservation],
+        recorded_at: DateTime<Utc>,
+    ) -> anyhow::Result<u64> {
+        if observations.is_empty() {
+            return Ok(0);
+        }
+
+        let trip_ids: Vec<Uuid> = observations.iter().map(|o| o.trip_id).collect();
+        let stop_ids: Vec<&str> = observations.iter().map(|o| o.stop_id.as_str()).collect();
+        let original_trip_ids: Vec<&str> = observations
+            .iter()
+            .map(|o| o.original_trip_id.as_str())
+            .collect();
+        let route_ids: Vec<&str> = observations.iter().map(|o| o.route_id.as_str()).collect();
+        let directions: Vec<i16> = observations.iter().map(|o| o.direction).collect();
+        let arrivals: Vec<DateTime<Utc>> = observations.iter().map(|o| o.arrival).collect();
+        let departures: Vec<DateTime<Utc>> = observations.iter().map(|o| o.departure).collect();
+        let scheduled_arrivals: Vec<Option<DateTime<Utc>>> =
+            observations.iter().map(|o| o.scheduled_arrival).collect();
+        let scheduled_departures: Vec<Option<DateTime<Utc>>> =
+            observations.iter().map(|o| o.scheduled_departure).collect();
+
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO history.stop_time_observation (
+                trip_id, stop_id, source, original_trip_id, route_id, direction,
+                arrival, departure, scheduled_arrival, scheduled_departure, recorded_at
+            )
+            SELECT
+                trip_id, stop_id, $1, original_trip_id, route_id, direction,
+                arrival, departure, scheduled_arrival, scheduled_departure, $11
+            FROM UNNEST(
+                $2::uuid[], $3::text[], $4::text[], $5::text[], $6::smallint[],
+                $7::timestamptz[], $8::timestamptz[], $9::timestamptz[], $10::timestamptz[]
+            ) AS o(
+                trip_id, stop_id, original_trip_id, route_id, direction,
+                arrival, departure, scheduled_arrival, scheduled_departure
+            )
+            ON CONFLICT (trip_id, stop_id, source) DO NOTHING"#,
+            source as _,
+            &trip_ids,
+            &stop_ids as _,
+            &original_trip_ids as _,
+            &route_ids as _,
+            &directions,
+            &arrivals,
+            &departures,
+            &scheduled_arrivals as &[Option<DateTime<Utc>>],
+            &scheduled_departures as &[Option<DateTime<Utc>>],
+            recorded_at
+        )
+        .execute(&self.pg_pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// On-time performance and headway adherence per route for arrivals between `from` and `to`.
+    /// Headways are measured between consecutive arrivals at the same stop in the same direction.
+    pub async fn get_performance(
+        &self,
+        source: Source,
+        route_id: Option<&str>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<RoutePerformance>> {
+        Ok(sqlx::query_as!(
+            RoutePerformance,
+            r#"
+            WITH observation AS (
+                SELECT
+                    route_id,
+                    EXTRACT(EPOCH FROM arrival - scheduled_arrival)::float8 AS delay,
+                    EXTRACT(EPOCH FROM arrival - LAG(arrival) OVER w)::float8 AS headway,
+                    EXTRACT(EPOCH FROM scheduled_arrival - LAG(scheduled_arrival) OVER w)::float8 AS scheduled_headway
+                FROM history.stop_time_observation
+                WHERE
+                    source = $1
+                    AND arrival >= $2 AND arrival < $3
+                    AND ($4::text IS NULL OR route_id = $4)
+                WINDOW w AS (PARTITION BY route_id, direction, stop_id ORDER BY arrival)
+            )
+            SELECT
+                route_id AS "route_id!",
+                COUNT(*) AS "observations!",
+                COUNT(delay) AS "scheduled_observations!",
+                (100.0 * COUNT(*) FILTER (WHERE delay BETWEEN -$5::float8 AND $6)
+                    / NULLIF(COUNT(delay), 0))::float8 AS on_time_percentage,
+                AVG(delay) AS average_delay,
+                AVG(headway) AS average_headway,
+                AVG(scheduled_headway) FILTER (WHERE headway IS NOT NULL) AS average_scheduled_headway,
+                (100.0 * COUNT(*) FILTER (WHERE headway <= scheduled_headway * $7)
+                    / NULLIF(COUNT(*) FILTER (WHERE headway IS NOT NULL AND scheduled_headway > 0), 0))::float8
+                    AS headway_adherence
+            FROM observation
+            GROUP BY route_id
+            ORDER BY route_id"#,
+            source as _,
+            from,
+            to,
+            route_id.map(|r| r.to_uppercase()),
+            ON_TIME_EARLY,
+            ON_TIME_LATE,
+            HEADWAY_TOLERANCE
+        )
+        .fetch_all(&self.pg_pool)
+        .await?)
+    }
+
+    /// Record how many trips were running on each route and how many of them were suspected ghosts.
+    pub async fn save_ghost_samples(
+        &self,
+        source: Source,
+        samples: &[GhostSample],
+        recorded_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        let route_ids: Vec<&str> = samples.iter().map(|s| s.route_id.as_str()).collect();
+        let trips: Vec<i32> = samples.iter().map(|s| s.trips).collect();
+        let ghosts: Vec<i32> = samples.iter().map(|s| s.ghosts).collect();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO history.ghost_sample (source, route_id, recorded_at, trips, ghosts)
+            SELECT $1, route_id, $2, trips, ghosts
+            FROM UNNEST($3::text[], $4::int[], $5::int[]) AS s(route_id, trips, ghosts)
+            ON CONFLICT (source, route_id, recorded_at) DO NOTHING"#,
+            source as _,
+            recorded_at,
+            &route_ids as _,
+            &trips,
+            &ghosts
+        )
+        .execute(&self.pg_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete ghost samples recorded before `before`.
+    pub async fn prune_ghost_samples(
+        &self,
+        source: Source,
+        before: DateTime<Utc>,
+    ) -> anyhow::Result<u64> {
+        let result = sqlx::query!(
+            "DELETE FROM history.ghost_sample WHERE source = $1 AND recorded_at < $2",
+            source as _,
+            before
+        )
+        .execute(&self.pg_pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Ghost rate per route for each `interval` long period between `from` and `to`.
+    pub async fn get_ghost_rates(
+        &self,
+        source: Source,
+        route_id: Option<&str>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        interval: Duration,
+    ) -> anyhow::Result<Vec<GhostRate>> {
+        Ok(sqlx::query_as!(
+            GhostRate,
+            r#"
+            SELECT
+                route_id,
+                date_bin($5 * INTERVAL '1 second', recorded_at, $2) AS "time!",
+                AVG(trips)::float8 AS "average_trips!",
+                AVG(ghosts)::float8 AS "average_ghosts!",
+                (100.0 * SUM(ghosts) / NULLIF(SUM(trips), 0))::float8 AS ghost_percentage
+            FROM history.ghost_sample
+            WHERE
+                source = $1
+                AND recorded_at >= $2 AND recorded_at < $3
+                AND ($4::text IS NULL OR route_id = $4)
+            GROUP BY route_id, 2
+            ORDER BY route_id, 2"#,
+            source as _,
+            from,
+            to,
+            route_id.map(|r| r.to_uppercase()),
+            interval.num_seconds() as f64
+        )
+        .fetch_all(&self.pg_pool)
+        .await?)
+    }
+
+    /// Recompute travel times between consecutive stops from the history points of trips that started after `since`.
+    /// Each point is projected onto the route line closest to the trip's first and last points, and the time a
+    /// trip passed a stop is interpolated between the points on either side of the stop's projection.
+    pub async fn refresh_segment_travel_times(
+        &self,
+        source: Source,
+        since: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            WITH trip_line AS (
+                SELECT t.id AS trip_id, t.route_id, t.source, t.direction, l.geom AS line, ST_Length(l.geom::geography) AS length
+                FROM realtime.trip t
+                JOIN static.route r ON r.id = t.route_id AND r.source = t.source
+                CROSS JOIN LATERAL (
+                    SELECT
+                        (SELECT geom FROM realtime.trip_history_point WHERE trip_id = t.id ORDER BY recorded_at ASC LIMIT 1) AS first_point,
+                        (SELECT geom FROM realtime.trip_history_point WHERE trip_id = t.id ORDER BY recorded_at DESC LIMIT 1) AS last_point
+                ) e
+                CROSS JOIN LATERAL (
+                    SELECT d.geom
+                    FROM ST_Dump(ST_LineMerge(r.geom)) d
+                    ORDER BY ST_Distance(d.geom, e.first_point) + ST_Distance(d.geom, e.last_point)
+                    LIMIT 1
+                ) l
+                WHERE t.source = $1 AND t.created_at >= $2 AND e.first_point IS NOT NULL
+            ),
+            points AS (
+                SELECT tl.trip_id, hp.recorded_at, ST_LineLocatePoint(tl.line, hp.geom) * tl.length AS location
+                FROM trip_line tl
+                JOIN realtime.trip_history_point hp ON hp.trip_id = tl.trip_id
+            ),
+            moves AS (
+                SELECT
+                    trip_id,
+                    LAG(location) OVER w AS from_location,
+                    location AS to_location,
+                    LAG(recorded_at) OVER w AS from_time,
+                    recorded_at AS to_time
+                FROM points
+                WINDOW w AS (PARTITION BY trip_id ORDER BY recorded_at)
+            ),
+            stops AS (
+                SELECT tl.trip_id, tl.route_id, tl.direction, rs.stop_id, ST_LineLocatePoint(tl.line, s.geom) * tl.length AS location
+                FROM trip_line tl
+                JOIN static.route_stop rs ON rs.route_id = tl.route_id AND rs.source = tl.source
+                JOIN static.stop s ON s.id = rs.stop_id AND s.source = rs.source
+                -- Bus routes have stops for both directions
+                WHERE COALESCE((rs.data->>'direction')::smallint = tl.direction, true)
+            ),
+            passes AS (
+                -- GPS noise can move a vehicle back and forth over a stop, so only the first pass counts
+                SELECT DISTINCT ON (s.trip_id, s.stop_id)
+                    s.trip_id,
+                    s.route_id,
+                    s.direction,
+                    s.stop_id,
+                    m.from_time + (m.to_time - m.from_time)
+                        * ((s.location - m.from_location) / (m.to_location - m.from_location)) AS passed_at
+                FROM moves m
+                JOIN stops s ON
+                    s.trip_id = m.trip_id
+                    AND s.location >= LEAST(m.from_location, m.to_location)
+                    AND s.location < GREATEST(m.from_location, m.to_location)
+                WHERE m.from_location IS NOT NULL
+                ORDER BY s.trip_id, s.stop_id, passed_at
+            ),
+            segments AS (
+                SELECT
+                    route_id,
+                    direction,
+                    LAG(stop_id) OVER w AS from_stop_id,
+                    stop_id AS to_stop_id,
+                    EXTRACT(EPOCH FROM passed_at - LAG(passed_at) OVER w)::float8 AS travel_time
+                FROM passes
+                WINDOW w AS (PARTITION BY trip_id ORDER BY passed_at)
+            )
+            INSERT INTO history.segment_travel_time (
+                source, route_id, direction, from_stop_id, to_stop_id, travel_time, samples, updated_at
+            )
+            SELECT
+                $1, route_id, direction, from_stop_id, to_stop_id,
+                percentile_cont(0.5) WITHIN GROUP (ORDER BY travel_time), COUNT(*), $3
+            FROM segments
+            WHERE from_stop_id IS NOT NULL AND travel_time > 0
+            GROUP BY route_id, direction, from_stop_id, to_stop_id
+            ON CONFLICT (source, route_id, direction, from_stop_id, to_stop_id) DO UPDATE SET
+                travel_time = EXCLUDED.travel_time,
+                samples = EXCLUDED.samples,
+                updated_at = EXCLUDED.updated_at"#,
+            source as _,
+            since,
+            now
+        )
+        .execute(&self.pg_pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Travel times between consecutive stops of the given routes.
+    pub async fn get_segment_travel_times(
+        &self,
+        source: Source,
+        route_ids: &[String],
+    ) -> anyhow::Result<Vec<SegmentTravelTime>> {
+        Ok(sqlx::query_as!(
+            SegmentTravelTime,
+            r#"
+            SELECT route_id, direction, from_stop_id, to_stop_id, travel_time
+            FROM history.segment_travel_time
+            WHERE source = $1 AND route_id = ANY($2)"#,
+            source as _,
+            route_ids
+        )
+        .fetch_all(&self.pg_pool)
+        .await?)
+    }
+}
@@ -3,25 +3,50 @@ use redis::AsyncCommands;
 use serde::{Serialize, de::DeserializeOwned};
 use std::time::Duration;
 
+use crate::{metrics, models::source::Source};
+
 pub mod alert;
+pub mod api_key;
+pub mod history;
 pub mod position;
 pub mod route;
 pub mod stop;
 pub mod stop_time;
 pub mod static_cache;
+pub mod tile;
 pub mod trip;
 
 /// Try to get a cached value from Redis. Returns `None` on miss or error.
+/// Lookups are counted in metrics by the part of the key before the first `:`.
 pub async fn cache_get<T>(redis_pool: &bb8::Pool<RedisConnectionManager>, key: &str) -> Option<T>
 where
     T: DeserializeOwned,
 {
-    let mut conn = redis_pool.get().await.ok()?;
-    let json: String = conn.get(key).await.ok()?;
-    if json.is_empty() {
-        return None;
+    let cache = key.split(':').next().unwrap_or(key);
+    let value = cache_lookup(redis_pool, key).await;
+    let result = match &value {
+        Ok(Some(_)) => "hit",
+        Ok(None) => "miss",
+        Err(_) => "error",
+    };
+    metrics::counter!(metrics::CACHE_LOOKUPS, "cache" => cache.to_owned(), "result" => result)
+        .increment(1);
+    value.ok().flatten()
+}
+
+async fn cache_lookup<T>(
+    redis_pool: &bb8::Pool<RedisConnectionManager>,
+    key: &str,
+) -> anyhow::Result<Option<T>>
+where
+    T: DeserializeOwned,
+{
+    let mut conn = redis_pool.get().await?;
+    let json: Option<String> = conn.get(key).await?;
+    match json {
+        Some(json) if !json.is_empty() => Ok(Some(serde_json::from_str(&json)?)),
+        _ => Ok(None),
     }
-    serde_json::from_str(&json).ok()
 }
 
 /// Serialize `data` to JSON and store it in Redis with the given TTL.
@@ -65,3 +90,16 @@ where
 
     Ok(hash)
 }
+
+/// Combine the ETags that `cache_set_with_etag` stored for several sources into one, which changes whenever any of
+/// the sources' data does.
+pub fn merge_etags(etags: &[(Source, String)]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for (source, etag) in etags {
+        hasher.update(source.as_str().as_bytes());
+        hasher.update(b":");
+        hasher.update(etag.as_bytes());
+        hasher.update(b"\n");
+    }
+    hasher.finalize().to_hex().to_string()
+}
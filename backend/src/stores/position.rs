@@ -1,16 +1,32 @@
-use std::time::Duration;
+use std::{collections::HashSet, time::Duration};
 
 use crate::{
-    models::{position::VehiclePosition, source::Source},
+    engines::push::{self, Update},
+    metrics,
+    models::{
+        geom::Geom,
+        position::{ApiVehiclePosition, PositionEstimate, VehiclePosition},
+        source::Source,
+    },
     stores::{cache_get, cache_set},
 };
 use bb8_redis::RedisConnectionManager;
 use chrono::{DateTime, Utc};
-use geozero::wkb;
-use sqlx::PgPool;
+use geo::Rect;
+use geozero::{CoordDimensions, ToWkb};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
 
 const TTL: Duration = Duration::from_secs(30);
 
+#[derive(FromRow)]
+struct BboxPositionRow {
+    #[sqlx(flatten)]
+    position: VehiclePosition,
+    estimate_geom: Option<Geom>,
+    estimate_bearing: Option<f32>,
+}
+
 #[derive(Clone)]
 pub struct PositionStore {
     pg_pool: PgPool,
@@ -50,6 +66,22 @@ impl PositionStore {
         cache_set(&self.redis_pool, &key, &positions, TTL).await
     }
 
+    /// Positions with their estimates from the last realtime update, if they're still cached.
+    pub async fn get_estimated(&self, source: Source) -> Option<Vec<ApiVehiclePosition>> {
+        let key = format!("estimated_positions:{}", source.as_str());
+        cache_get(&self.redis_pool, &key).await
+    }
+
+    /// Cache positions with their estimates for the rest of the realtime update interval.
+    pub async fn cache_estimated(
+        &self,
+        source: Source,
+        positions: &[ApiVehiclePosition],
+    ) -> anyhow::Result<()> {
+        let key = format!("estimated_positions:{}", source.as_str());
+        cache_set(&self.redis_pool, &key, &positions, TTL).await
+    }
+
     /// Internal helper function to query positions without caching
     async fn query_all_positions(
         &self,
@@ -90,6 +122,221 @@ impl PositionStore {
         .await?)
     }
 
+    /// Latest position of the vehicle on a trip.
+    pub async fn get_by_trip(&self, trip_id: Uuid) -> anyhow::Result<Option<VehiclePosition>> {
+        Ok(sqlx::query_as::<_, VehiclePosition>(
+            r#"
+                SELECT
+                    p.vehicle_id,
+                    p.trip_id,
+                    p.stop_id,
+                    p.updated_at,
+                    p.data,
+                    p.geom
+                FROM realtime.vehicle_position p
+                WHERE p.trip_id = $1
+                ORDER BY p.updated_at DESC
+                LIMIT 1"#,
+        )
+        .bind(trip_id)
+        .fetch_optional(&self.pg_pool)
+        .await?)
+    }
+
+    /// Estimate where each vehicle on a trip is along its route's line.
+    /// Vehicles without coordinates are placed between the stop they last arrived at and the next one, based on how
+    /// much of the time between them has passed. Vehicles with coordinates are snapped to the closest point on the
+    /// route if they're within 100 meters of it.
+    pub async fn get_estimates(
+        &self,
+        source: Source,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<PositionEstimate>> {
+        Ok(sqlx::query_as::<_, PositionEstimate>(
+            r#"
+            WITH positions AS (
+                SELECT p.vehicle_id, p.trip_id, p.geom, t.route_id, t.source
+                FROM realtime.vehicle_position p
+                JOIN realtime.trip t ON t.id = p.trip_id
+                WHERE
+                    p.source = $1
+                    AND p.updated_at >= (($2)::timestamp with time zone - INTERVAL '5 minutes')
+            ),
+            segments AS (
+                SELECT
+                    p.vehicle_id,
+                    l.geom AS line,
+                    ST_LineLocatePoint(l.geom, prev.geom) AS from_location,
+                    ST_LineLocatePoint(l.geom, next.geom) AS to_location,
+                    -- Trains that are still dwelling at the previous stop stay there
+                    LEAST(GREATEST(COALESCE(
+                        EXTRACT(EPOCH FROM ($2)::timestamp with time zone - prev.departure)
+                            / NULLIF(EXTRACT(EPOCH FROM next.arrival - prev.departure), 0),
+                        0
+                    ), 0), 1)::double precision AS fraction
+                FROM positions p
+                JOIN static.route r ON r.id = p.route_id AND r.source = p.source
+                CROSS JOIN LATERAL (
+                    SELECT st.departure, s.geom
+                    FROM realtime.stop_time st
+                    JOIN static.stop s ON s.id = st.stop_id AND s.source = p.source
+                    WHERE st.trip_id = p.trip_id AND st.status = 'scheduled' AND st.arrival <= $2
+                    ORDER BY st.arrival DESC
+                    LIMIT 1
+                ) prev
+                CROSS JOIN LATERAL (
+                    SELECT st.arrival, s.geom
+                    FROM realtime.stop_time st
+                    JOIN static.stop s ON s.id = st.stop_id AND s.source = p.source
+                    WHERE st.trip_id = p.trip_id AND st.status = 'scheduled' AND st.arrival > $2
+                    ORDER BY st.arrival
+                    LIMIT 1
+                ) next
+                CROSS JOIN LATERAL (
+                    SELECT d.geom
+                    FROM ST_Dump(ST_LineMerge(r.geom)) d
+                    ORDER BY ST_Distance(d.geom, prev.geom) + ST_Distance(d.geom, next.geom)
+                    LIMIT 1
+                ) l
+                WHERE p.geom IS NULL
+            )
+            SELECT
+                s.vehicle_id,
+                ST_LineInterpolatePoint(
+                    s.line,
+                    s.from_location + (s.to_location - s.from_location) * s.fraction
+                ) AS geom,
+                -- Measured over a short stretch of the segment around the point, so it follows curves
+                degrees(ST_Azimuth(
+                    ST_LineInterpolatePoint(
+                        s.line,
+                        s.from_location + (s.to_location - s.from_location) * GREATEST(s.fraction - 0.05, 0)
+                    )::geography,
+                    ST_LineInterpolatePoint(
+                        s.line,
+                        s.from_location + (s.to_location - s.from_location) * LEAST(s.fraction + 0.05, 1)
+                    )::geography
+                ))::real AS bearing
+            FROM segments s
+            UNION ALL
+            SELECT
+                p.vehicle_id,
+                ST_ClosestPoint(r.geom, p.geom) AS geom,
+                NULL::real AS bearing
+            FROM positions p
+            JOIN static.route r ON r.id = p.route_id AND r.source = p.source
+            WHERE p.geom IS NOT NULL AND ST_DWithin(p.geom::geography, r.geom::geography, 100)
+            "#,
+        )
+        .bind(source)
+        .bind(at)
+        .fetch_all(&self.pg_pool)
+        .await?)
+    }
+
+    /// Replace a source's saved estimates with the ones from the latest realtime update, so current positions can be
+    /// filtered by location in the database.
+    pub async fn save_estimates(
+        &self,
+        source: Source,
+        estimates: &[PositionEstimate],
+    ) -> anyhow::Result<()> {
+        let vehicle_ids: Vec<_> = estimates.iter().map(|e| e.vehicle_id.clone()).collect();
+        let geoms = estimates
+            .iter()
+            .map(|e| e.geom.to_ewkb(CoordDimensions::xy(), Some(4326)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let bearings: Vec<_> = estimates.iter().map(|e| e.bearing).collect();
+
+        let mut tx = self.pg_pool.begin().await?;
+        sqlx::query!(
+            "DELETE FROM realtime.position_estimate WHERE source = $1",
+            source as _
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO realtime.position_estimate (vehicle_id, source, geom, bearing)
+            SELECT e.vehicle_id, $1, ST_GeomFromEWKB(e.geom), e.bearing
+            FROM UNNEST($2::text[], $3::bytea[], $4::real[]) AS e(vehicle_id, geom, bearing)
+            -- Vehicles can be pruned between estimating and saving
+            JOIN realtime.vehicle_position p ON p.vehicle_id = e.vehicle_id AND p.source = $1
+            "#,
+            source as _,
+            &vehicle_ids,
+            &geoms,
+            &bearings as &[Option<f32>]
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Current positions of several sources inside a bounding box, closest to its center first, with their saved
+    /// estimates. A position is inside the box if its estimate is, or its feed coordinates are when it has no
+    /// estimate.
+    pub async fn get_in_bbox(
+        &self,
+        sources: &[Source],
+        bbox: Rect,
+    ) -> anyhow::Result<Vec<(VehiclePosition, Option<PositionEstimate>)>> {
+        let rows = sqlx::query_as::<_, BboxPositionRow>(
+            r#"
+            WITH envelope AS (
+                SELECT ST_MakeEnvelope($2, $3, $4, $5, 4326) AS geom
+            ),
+            -- Both lookups can use a GIST index, unlike a filter on the coalesced geometry
+            candidates AS (
+                SELECT e.vehicle_id, e.source
+                FROM realtime.position_estimate e, envelope b
+                WHERE e.source = ANY($1) AND e.geom && b.geom
+                UNION
+                SELECT p.vehicle_id, p.source
+                FROM realtime.vehicle_position p, envelope b
+                WHERE p.source = ANY($1) AND p.geom && b.geom
+            )
+            SELECT
+                p.vehicle_id,
+                p.trip_id,
+                p.stop_id,
+                p.updated_at,
+                p.data,
+                p.geom,
+                e.geom AS estimate_geom,
+                e.bearing AS estimate_bearing
+            FROM candidates c
+            JOIN realtime.vehicle_position p ON p.vehicle_id = c.vehicle_id AND p.source = c.source
+            LEFT JOIN realtime.position_estimate e ON e.vehicle_id = c.vehicle_id AND e.source = c.source
+            CROSS JOIN envelope b
+            WHERE
+                p.updated_at >= NOW() - INTERVAL '5 minutes'
+                AND COALESCE(e.geom, p.geom) && b.geom
+            ORDER BY ST_Distance(COALESCE(e.geom, p.geom)::geography, ST_Centroid(b.geom)::geography)
+            "#,
+        )
+        .bind(sources)
+        .bind(bbox.min().x)
+        .bind(bbox.min().y)
+        .bind(bbox.max().x)
+        .bind(bbox.max().y)
+        .fetch_all(&self.pg_pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let estimate = row.estimate_geom.map(|geom| PositionEstimate {
+                    vehicle_id: row.position.vehicle_id.clone(),
+                    geom,
+                    bearing: row.estimate_bearing,
+                });
+                (row.position, estimate)
+            })
+            .collect())
+    }
+
     /// Bulk upsert vehicle positions (updates current state only, no history)
     /// A database trigger appends trip history points when positions with trip_id and geom are upserted
     #[tracing::instrument(skip(self, positions), fields(source = %source.as_str(), count = positions.len()), level = "debug")]
@@ -110,40 +357,62 @@ impl PositionStore {
             .map(|v| v.stop_id.as_ref().map(|s| s.to_uppercase()))
             .collect();
         let updated_ats: Vec<_> = positions.iter().map(|v| v.updated_at).collect();
-        let geoms: Vec<_> = positions
+        // Sent as EWKB since the geometry type doesn't have an array type for bind parameters
+        let geoms = positions
             .iter()
-            .map(|v| v.geom.clone().map(wkb::Encode))
-            .collect();
+            .map(|v| {
+                v.geom
+                    .as_ref()
+                    .map(|g| g.to_ewkb(CoordDimensions::xy(), Some(4326)))
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
         let datas: Vec<_> = positions
             .iter()
             .map(|v| serde_json::to_value(&v.data).unwrap())
             .collect();
 
-        sqlx::query!(
+        // existing_rows sees the table from before the upsert, so it tells us which vehicles are new or changed.
+        // updated_at is left out of the comparison since it changes every time.
+        let changed: HashSet<String> = sqlx::query_scalar!(
             r#"
-            INSERT INTO realtime.vehicle_position (
-                vehicle_id,
-                source,
-                trip_id,
-                stop_id,
-                geom,
-                data,
-                updated_at
+            WITH input_rows AS (
+                SELECT vehicle_id, source, trip_id, stop_id, ST_GeomFromEWKB(geom) AS geom, data, updated_at
+                FROM UNNEST(
+                    $1::text[], $2::source_enum[], $3::uuid[], $4::text[], $5::bytea[], $6::jsonb[], $7::timestamptz[]
+                ) AS t(vehicle_id, source, trip_id, stop_id, geom, data, updated_at)
+            ),
+            existing_rows AS (
+                SELECT p.vehicle_id, p.trip_id, p.stop_id, p.geom, p.data
+                FROM realtime.vehicle_position p
+                JOIN input_rows ON p.vehicle_id = input_rows.vehicle_id AND p.source = input_rows.source
+            ),
+            upserted AS (
+                INSERT INTO realtime.vehicle_position (
+                    vehicle_id,
+                    source,
+                    trip_id,
+                    stop_id,
+                    geom,
+                    data,
+                    updated_at
+                )
+                SELECT vehicle_id, source, trip_id, stop_id, geom, data, updated_at
+                FROM input_rows
+                ON CONFLICT (vehicle_id, source) DO UPDATE SET
+                    trip_id = EXCLUDED.trip_id,
+                    stop_id = EXCLUDED.stop_id,
+                    geom = EXCLUDED.geom,
+                    data = EXCLUDED.data,
+                    updated_at = EXCLUDED.updated_at
             )
-            SELECT
-                unnest($1::text[]),
-                unnest($2::source_enum[]),
-                unnest($3::uuid[]),
-                unnest($4::text[]),
-                unnest($5::geometry[]),
-                unnest($6::JSONB[]),
-                unnest($7::timestamptz[])
-            ON CONFLICT (vehicle_id, source) DO UPDATE SET
-                trip_id = EXCLUDED.trip_id,
-                stop_id = EXCLUDED.stop_id,
-                geom = EXCLUDED.geom,
-                data = EXCLUDED.data,
-                updated_at = EXCLUDED.updated_at
+            SELECT input_rows.vehicle_id AS "vehicle_id!"
+            FROM input_rows
+            LEFT JOIN existing_rows ON existing_rows.vehicle_id = input_rows.vehicle_id
+            WHERE
+                existing_rows.vehicle_id IS NULL
+                OR (existing_rows.trip_id, existing_rows.stop_id, existing_rows.geom, existing_rows.data)
+                    IS DISTINCT FROM (input_rows.trip_id, input_rows.stop_id, input_rows.geom, input_rows.data)
             "#,
             &vehicle_ids,
             &vec![source; positions.len()] as _,
@@ -151,16 +420,40 @@ impl PositionStore {
             &stop_ids as _,
             &geoms as _,
             &datas,
-            &updated_ats,
+            &updated_ats
         )
-        .execute(&self.pg_pool)
-        .await?;
+        .fetch_all(&self.pg_pool)
+        .await?
+        .into_iter()
+        .collect();
 
-        tracing::debug!("Upserted {} vehicle positions", positions.len());
+        tracing::debug!(
+            "Upserted {} vehicle positions, {} changed",
+            positions.len(),
+            changed.len()
+        );
+        metrics::counter!(
+            metrics::ROWS_UPSERTED,
+            "source" => source.as_str(),
+            "table" => "vehicle_position"
+        )
+        .increment(positions.len() as u64);
 
         // Populate cache (write-through)
         self.populate_cache(source).await?;
 
+        push::publish(Update::Positions {
+            source,
+            positions: positions
+                .iter()
+                .filter(|p| changed.contains(&p.vehicle_id))
+                .map(|p| VehiclePosition {
+                    stop_id: p.stop_id.as_ref().map(|s| s.to_uppercase()),
+                    ..p.clone()
+                })
+                .collect(),
+        });
+
         Ok(())
     }
 }
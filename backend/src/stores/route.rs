@@ -35,8 +35,8 @@ impl RouteStore {
         cache_set_with_etag(&self.redis_pool, &key, &routes, TTL).await
     }
 
-    /// Raw DB query for all routes of a source.
-    async fn query_all(&self, source: Source) -> anyhow::Result<Vec<Route>> {
+    /// Raw DB query for all routes of a source. Unlike cached routes, these have their geometry.
+    pub async fn query_all(&self, source: Source) -> anyhow::Result<Vec<Route>> {
         Ok(sqlx::query_as::<_, Route>(
             r#"SELECT
                 id,
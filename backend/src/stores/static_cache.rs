@@ -1,6 +1,9 @@
 use bb8::Pool;
 use bb8_redis::RedisConnectionManager;
+use chrono::{DateTime, TimeDelta, Utc};
+use chrono_tz::Tz;
 use redis::AsyncCommands;
+use std::collections::HashMap;
 use std::time::Duration;
 
 use crate::models::source::Source;
@@ -58,4 +61,134 @@ impl StaticCacheStore {
             None => Ok(None),
         }
     }
+
+    /// Look up a trip's static schedule from when it started. The service date is usually the local date the trip
+    /// started on, but trips that start after midnight can belong to the previous day's service.
+    pub async fn scheduled_trip(
+        &self,
+        source: Source,
+        trip_id: &str,
+        created_at: DateTime<Utc>,
+        tz: Tz,
+    ) -> anyhow::Result<Option<CachedTrip>> {
+        let start_date = created_at.with_timezone(&tz).date_naive();
+        for date in [start_date, start_date - TimeDelta::days(1)] {
+            let date = date.format("%Y%m%d").to_string();
+            if let Some(trip) = self.get_trip(source, trip_id, &date).await? {
+                return Ok(Some(trip));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Find a trip whose service date isn't known, like one from a realtime feed without a `start_date`.
+    /// Trips after midnight belong to the previous day's service, so the service days starting on the local date of
+    /// `at` and the day before are both checked. The one whose scheduled stop times are closest to `at` wins.
+    pub async fn find_trip_running_at(
+        &self,
+        source: Source,
+        trip_id: &str,
+        at: DateTime<Utc>,
+        tz: Tz,
+    ) -> anyhow::Result<Option<CachedTrip>> {
+        Ok(self
+            .find_trips_running_at(source, &[(trip_id, at)], tz)
+            .await?
+            .pop()
+            .flatten())
+    }
+
+    /// Like `find_trip_running_at`, but for many trips with a single MGET. Results are in the same order as `trips`.
+    pub async fn find_trips_running_at(
+        &self,
+        source: Source,
+        trips: &[(&str, DateTime<Utc>)],
+        tz: Tz,
+    ) -> anyhow::Result<Vec<Option<CachedTrip>>> {
+        if trips.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let keys: Vec<String> = trips
+            .iter()
+            .flat_map(|&(trip_id, at)| {
+                let date = at.with_timezone(&tz).date_naive();
+                [date, date - TimeDelta::days(1)].map(|date| {
+                    format!(
+                        "static_cache:{}:trip:{}:{}",
+                        source.as_str(),
+                        trip_id,
+                        date.format("%Y%m%d")
+                    )
+                })
+            })
+            .collect();
+
+        let mut conn = self.redis_pool.get().await?;
+        let jsons: Vec<Option<String>> = conn.mget(keys).await?;
+
+        trips
+            .iter()
+            .zip(jsons.chunks(2))
+            .map(|(&(_, at), candidates)| {
+                let mut closest: Option<(TimeDelta, CachedTrip)> = None;
+                for json in candidates.iter().flatten() {
+                    let trip: CachedTrip = serde_json::from_str(json)?;
+
+                    let end = trip
+                        .stop_times
+                        .last()
+                        .map_or(trip.start_time, |st| st.arrival);
+                    let distance = if at < trip.start_time {
+                        trip.start_time - at
+                    } else if at > end {
+                        at - end
+                    } else {
+                        TimeDelta::zero()
+                    };
+
+                    if closest.as_ref().is_none_or(|(d, _)| distance < *d) {
+                        closest = Some((distance, trip));
+                    }
+                }
+                Ok(closest.map(|(_, trip)| trip))
+            })
+            .collect()
+    }
+
+    /// Store a map of public stop code -> stop_id, for realtime feeds that identify stops by code.
+    /// Replaces any previously cached codes for the source.
+    pub async fn cache_stop_codes(
+        &self,
+        source: Source,
+        stop_codes: &HashMap<String, String>,
+    ) -> anyhow::Result<()> {
+        if stop_codes.is_empty() {
+            return Ok(());
+        }
+
+        let mut conn = self.redis_pool.get().await?;
+        let key = format!("static_cache:{}:stop_code", source.as_str());
+
+        let items: Vec<(&String, &String)> = stop_codes.iter().collect();
+        let _: () = redis::pipe()
+            .atomic()
+            .del(&key)
+            .hset_multiple(&key, &items)
+            .query_async(&mut *conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Retrieve the stop_id for a public stop code.
+    pub async fn get_stop_id_by_code(
+        &self,
+        source: Source,
+        stop_code: &str,
+    ) -> anyhow::Result<Option<String>> {
+        let mut conn = self.redis_pool.get().await?;
+        let key = format!("static_cache:{}:stop_code", source.as_str());
+        Ok(conn.hget(key, stop_code).await?)
+    }
 }
@@ -1,5 +1,6 @@
 use crate::{
     models::{
+        departure::{BoardStop, NearbyStop},
         source::Source,
         stop::{RouteStop, Stop},
     },
@@ -98,6 +99,97 @@ impl StopStore {
         Ok(conn.get::<_, Option<String>>(&key).await?)
     }
 
+    /// A stop and every stop it has an in-station transfer to (GTFS transfer types 0-2), like the platforms of a
+    /// station complex. Proximity transfers aren't followed since they're usually a walk away.
+    /// The requested stop is first if it exists.
+    pub async fn get_board_stops(
+        &self,
+        source: Source,
+        stop_id: &str,
+    ) -> anyhow::Result<Vec<BoardStop>> {
+        Ok(sqlx::query_as::<_, BoardStop>(
+            r#"
+            WITH board AS (
+                SELECT $2::text AS id, $1::source_enum AS source, 0 AS position
+                UNION
+                SELECT st.to_stop_id, st.to_stop_source, 1
+                FROM static.stop_transfer st
+                WHERE st.from_stop_id = $2
+                  AND st.from_stop_source = $1
+                  AND st.transfer_type <= 2
+                  AND NOT (st.to_stop_id = $2 AND st.to_stop_source = $1)
+            )
+            SELECT
+                s.id,
+                s.source,
+                s.name,
+                s.data,
+                COALESCE(
+                    (
+                        SELECT jsonb_agg(rs.*)
+                        FROM static.route_stop rs
+                        WHERE rs.stop_id = s.id
+                          AND rs.source = s.source
+                    ),
+                    '[]'::jsonb
+                ) AS routes
+            FROM board b
+            JOIN static.stop s ON s.id = b.id AND s.source = b.source
+            ORDER BY b.position, s.id"#,
+        )
+        .bind(source)
+        .bind(stop_id.to_uppercase())
+        .fetch_all(&self.pg_pool)
+        .await?)
+    }
+
+    /// Stops within `radius` meters of a point, closest first. Distances are measured in EPSG:6538 (NY State Plane,
+    /// meters), like proximity transfers, which the stop table has a GIST index for.
+    pub async fn get_nearby(
+        &self,
+        sources: &[Source],
+        lat: f64,
+        lon: f64,
+        radius: f64,
+        limit: i64,
+    ) -> anyhow::Result<Vec<NearbyStop>> {
+        Ok(sqlx::query_as::<_, NearbyStop>(
+            r#"
+            WITH point AS (
+                SELECT ST_Transform(ST_SetSRID(ST_MakePoint($3, $2), 4326), 6538) AS projected
+            )
+            SELECT
+                s.id,
+                s.source,
+                s.name,
+                s.data,
+                COALESCE(
+                    (
+                        SELECT jsonb_agg(rs.*)
+                        FROM static.route_stop rs
+                        WHERE rs.stop_id = s.id
+                          AND rs.source = s.source
+                    ),
+                    '[]'::jsonb
+                ) AS routes,
+                s.geom,
+                ST_Distance(ST_Transform(s.geom, 6538), p.projected) AS distance
+            FROM static.stop s, point p
+            WHERE
+                s.source = ANY($1)
+                AND ST_DWithin(ST_Transform(s.geom, 6538), p.projected, $4)
+            ORDER BY distance, s.source, s.id
+            LIMIT $5"#,
+        )
+        .bind(sources)
+        .bind(lat)
+        .bind(lon)
+        .bind(radius)
+        .bind(limit)
+        .fetch_all(&self.pg_pool)
+        .await?)
+    }
+
     /// Bulk insert stops (and invalidate cache)
     pub async fn save_all(&self, source: Source, stops: &[Stop]) -> anyhow::Result<()> {
         // TODO: probably pass vec instead of slice so we don't need to clone
@@ -294,7 +386,9 @@ impl StopStore {
     /// Compute proximity-based transfers (transfer_type = 6) across all sources.
     ///
     /// Stops within 150 m of each other — measured in EPSG:6538 (NY State Plane, meters)
-    /// via ST_Transform — receive a bidirectional proximity transfer entry, unless:
+    /// via ST_Transform — receive a bidirectional proximity transfer entry. Pairs involving a
+    /// rail stop use 400 m instead, since a single rail stop point stands in for a large station
+    /// (e.g. NJT and LIRR at Penn Station to the 34 St subway stations). Pairs are skipped if:
     ///   - They already have an official (non-proximity) transfer entry, or
     ///   - They are a designated `opposite_stop_id` bus-stop pair.
     ///
@@ -310,30 +404,31 @@ impl StopStore {
         // If a source is provided, only remove and recompute transfers involving that source.
         // This is much faster than a full recompute of all sources.
         if let Some(s) = source {
-            sqlx::query(
+            sqlx::query!(
                 "DELETE FROM static.stop_transfer WHERE transfer_type = 6 AND (from_stop_source = $1 OR to_stop_source = $1)",
+                s as _
             )
-            .bind(s)
             .execute(&mut *tx)
             .await?;
         } else {
             // Remove all stale proximity transfers before recomputing everything.
-            sqlx::query("DELETE FROM static.stop_transfer WHERE transfer_type = 6")
+            sqlx::query!("DELETE FROM static.stop_transfer WHERE transfer_type = 6")
                 .execute(&mut *tx)
                 .await?;
         }
 
         // TODO: figure out why a bunch of nearby stops are missing transfers. (mta_subway has no cross-source transfers which makes no sense)
-        // Insert proximity transfers for every stop pair within 150 m.
+        // Insert proximity transfers for every stop pair within 150 m (400 m for rail).
         // Both directions (A→B and B→A) are produced by the self-join.
         //
         // For stops with a `direction` value (bus stops), only the closest candidate
         // per (from_stop, to_source, to_direction) group is kept. Stops without a
         // direction (subway) are not deduplicated and all qualifying pairs are inserted.
         //
-        // OPTIMIZATION: We use `a.geom && ST_Expand(b.geom, 0.002)` to leverage the GIST index
-        // on the 4326 geometry column. 0.002 degrees is approx 220m at NYC latitude,
-        // providing a safe bounding box for our 150m check.
+        // OPTIMIZATION: We use `a.geom && ST_Expand(b.geom, 0.005)` to leverage the GIST index
+        // on the 4326 geometry column. At NYC latitude 0.005 degrees is about 555m north-south and
+        // 420m east-west, so the box still covers our 400m rail check in every direction.
+        let rail_sources: Vec<Source> = Source::all().into_iter().filter(Source::is_rail).collect();
         sqlx::query!(
             r#"
             WITH stop_direction AS (
@@ -361,12 +456,16 @@ impl StopStore {
                 LEFT JOIN stop_direction sd_a ON a.id = sd_a.stop_id AND a.source = sd_a.source
                 JOIN static.stop b
                     ON a.id != b.id
-                    -- Spatial index filter (approx 200m in degrees)
-                    AND a.geom && ST_Expand(b.geom, 0.002)
+                    -- Spatial index filter (approx 400m in degrees)
+                    AND a.geom && ST_Expand(b.geom, 0.005)
                     AND ST_DWithin(
                         ST_Transform(a.geom, 6538),
                         ST_Transform(b.geom, 6538),
-                        150.0
+                        CASE
+                            WHEN a.source = ANY($2::source_enum[]) OR b.source = ANY($2::source_enum[])
+                            THEN 400.0
+                            ELSE 150.0
+                        END
                     )
                 LEFT JOIN stop_direction sd_b ON b.id = sd_b.stop_id AND b.source = sd_b.source
                 WHERE
@@ -414,7 +513,8 @@ impl StopStore {
             SELECT from_id, from_source, to_id, to_source, 6 FROM candidates
             ON CONFLICT DO NOTHING
             "#,
-            source as _
+            source as _,
+            &rail_sources as _,
         )
         .execute(&mut *tx)
         .await?;
@@ -427,8 +527,7 @@ impl StopStore {
         // Only repopulate if we have a source specified (or all if None).
         let sources = match source {
             Some(s) => vec![s],
-            // TODO: create a global var for all sources instead of hardcoding here
-            None => vec![Source::MtaSubway, Source::MtaBus, Source::NjtBus],
+            None => Source::all(),
         };
 
         for s in sources {
@@ -1,9 +1,16 @@
-use crate::models::{source::Source, trip::StopTime};
+use crate::models::{
+    departure::{BoardStop, Departure, DepartureRow},
+    headway::HeadwayArrival,
+    prediction::PredictionStop,
+    source::Source,
+    trip::StopTime,
+};
 use crate::stores::{cache_get, cache_set};
 use bb8_redis::RedisConnectionManager;
 use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use std::time::Duration;
+use uuid::Uuid;
 
 const TTL: Duration = Duration::from_secs(30);
 
@@ -69,6 +76,7 @@ impl StopTimeStore {
                 st.stop_id,
                 st.arrival,
                 st.departure,
+                st.status,
                 st.data
             FROM realtime.stop_time st
             INNER JOIN realtime.trip t ON t.id = st.trip_id
@@ -88,6 +96,309 @@ impl StopTimeStore {
         .await?)
     }
 
+    /// Upcoming departures from any of the board's stops, optionally filtered by direction and routes.
+    /// Directions mean different things for each source, so `direction_source` limits the direction filter to one
+    /// source's departures. Like the other realtime queries, trips that haven't been updated in the last 5 minutes
+    /// are left out.
+    pub async fn get_departures(
+        &self,
+        stops: &[BoardStop],
+        at: DateTime<Utc>,
+        direction: Option<i16>,
+        direction_source: Option<Source>,
+        route_ids: &[String],
+        limit: i64,
+    ) -> anyhow::Result<Vec<Departure>> {
+        let stop_ids: Vec<&str> = stops.iter().map(|s| s.id.as_str()).collect();
+        let sources: Vec<Source> = stops.iter().map(|s| s.source).collect();
+
+        let rows = sqlx::query_as::<_, DepartureRow>(
+            r#"
+            SELECT
+                st.trip_id,
+                st.source,
+                st.stop_id,
+                t.route_id,
+                t.direction,
+                st.arrival,
+                st.departure,
+                t.status AS trip_status,
+                st.status,
+                t.data AS trip_data,
+                st.data AS stop_time_data
+            FROM realtime.stop_time st
+            INNER JOIN realtime.trip t ON t.id = st.trip_id
+            INNER JOIN UNNEST($1::text[], $2::source_enum[]) AS b(stop_id, source)
+                ON b.stop_id = st.stop_id AND b.source = st.source
+            WHERE
+                st.departure >= $3
+                AND t.updated_at >= ($3)::timestamp with time zone - INTERVAL '5 minutes'
+                AND ($4::smallint IS NULL OR t.direction = $4 OR st.source <> $7)
+                AND (cardinality($5::text[]) = 0 OR t.route_id = ANY($5))
+            ORDER BY st.departure ASC
+            LIMIT $6
+            "#,
+        )
+        .bind(&stop_ids)
+        .bind(&sources)
+        .bind(at)
+        .bind(direction)
+        .bind(route_ids)
+        .bind(limit)
+        .bind(direction_source)
+        .fetch_all(&self.pg_pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let stop = stops
+                    .iter()
+                    .find(|s| s.source == row.source && s.id == row.stop_id)?;
+                Some(Departure::new(row, stop))
+            })
+            .collect())
+    }
+
+    /// The next `limit` departures from each stop, in one query. Like [`Self::get_departures`], trips that haven't
+    /// been updated in the last 5 minutes are left out.
+    pub async fn get_departures_by_stop(
+        &self,
+        stops: &[&BoardStop],
+        at: DateTime<Utc>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<Departure>> {
+        let stop_ids: Vec<&str> = stops.iter().map(|s| s.id.as_str()).collect();
+        let sources: Vec<Source> = stops.iter().map(|s| s.source).collect();
+
+        let rows = sqlx::query_as::<_, DepartureRow>(
+            r#"
+            SELECT d.*
+            FROM UNNEST($1::text[], $2::source_enum[]) AS b(stop_id, source)
+            CROSS JOIN LATERAL (
+                SELECT
+                    st.trip_id,
+                    st.source,
+                    st.stop_id,
+                    t.route_id,
+                    t.direction,
+                    st.arrival,
+                    st.departure,
+                    t.status AS trip_status,
+                    st.status,
+                    t.data AS trip_data,
+                    st.data AS stop_time_data
+                FROM realtime.stop_time st
+                INNER JOIN realtime.trip t ON t.id = st.trip_id
+                WHERE
+                    st.stop_id = b.stop_id
+                    AND st.source = b.source
+                    AND st.departure >= $3
+                    AND t.updated_at >= ($3)::timestamp with time zone - INTERVAL '5 minutes'
+                ORDER BY st.departure ASC
+                LIMIT $4
+            ) d
+            ORDER BY d.departure ASC
+            "#,
+        )
+        .bind(&stop_ids)
+        .bind(&sources)
+        .bind(at)
+        .bind(limit)
+        .fetch_all(&self.pg_pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let stop = stops
+                    .iter()
+                    .find(|s| s.source == row.source && s.id == row.stop_id)?;
+                Some(Departure::new(row, stop))
+            })
+            .collect())
+    }
+
+    /// Predicted arrivals of a route's running trips over the next 2 hours, grouped by direction and stop in
+    /// `route_stop` order, then ordered by arrival. Canceled trips and skipped stops are left out.
+    pub async fn get_route_arrivals(
+        &self,
+        source: Source,
+        route_id: &str,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<HeadwayArrival>> {
+        Ok(sqlx::query_as::<_, HeadwayArrival>(
+            r#"
+            SELECT
+                st.trip_id,
+                t.original_id AS original_trip_id,
+                t.created_at AS trip_created_at,
+                t.direction,
+                st.stop_id,
+                st.arrival,
+                NULL::timestamptz AS scheduled_arrival
+            FROM realtime.stop_time st
+            INNER JOIN realtime.trip t ON t.id = st.trip_id
+            LEFT JOIN static.route_stop rs
+                ON rs.route_id = t.route_id AND rs.source = t.source AND rs.stop_id = st.stop_id
+            WHERE
+                t.source = $1
+                AND t.route_id = $2
+                AND t.status <> 'canceled'
+                AND st.status = 'scheduled'
+                AND st.arrival BETWEEN $3 AND ($3 + INTERVAL '2 hours')
+                AND t.updated_at >= ($3)::timestamp with time zone - INTERVAL '5 minutes'
+            ORDER BY t.direction, rs.stop_sequence NULLS LAST, st.stop_id, st.arrival
+            "#,
+        )
+        .bind(source)
+        .bind(route_id.to_uppercase())
+        .bind(at)
+        .fetch_all(&self.pg_pool)
+        .await?)
+    }
+
+    /// Route stops of every trip that needs predictions from the backend, in `route_stop` order. A trip needs them
+    /// if its vehicle is still reporting positions but the feed has no upcoming stop times for it, or stopped
+    /// updating it.
+    pub async fn get_prediction_stops(
+        &self,
+        source: Source,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<PredictionStop>> {
+        Ok(sqlx::query_as!(
+            PredictionStop,
+            r#"
+            WITH candidates AS (
+                SELECT t.id, t.route_id, t.source, t.direction, p.geom, p.updated_at
+                FROM realtime.trip t
+                CROSS JOIN LATERAL (
+                    SELECT vp.geom, vp.updated_at
+                    FROM realtime.vehicle_position vp
+                    WHERE vp.trip_id = t.id AND vp.source = t.source AND vp.geom IS NOT NULL
+                    ORDER BY vp.updated_at DESC
+                    LIMIT 1
+                ) p
+                WHERE
+                    t.source = $1
+                    AND t.status <> 'canceled'
+                    AND t.updated_at >= $2::timestamptz - INTERVAL '5 minutes'
+                    AND p.updated_at >= $2::timestamptz - INTERVAL '2 minutes'
+                    AND (
+                        t.updated_at < p.updated_at - INTERVAL '90 seconds'
+                        OR NOT EXISTS (
+                            SELECT 1
+                            FROM realtime.stop_time st
+                            WHERE
+                                st.trip_id = t.id
+                                AND st.arrival >= $2
+                                AND st.data->>'predicted_by' IS DISTINCT FROM 'backend'
+                        )
+                    )
+            ),
+            trip_line AS (
+                SELECT c.id AS trip_id, l.geom AS line, ST_Length(l.geom::geography) AS length
+                FROM candidates c
+                JOIN static.route r ON r.id = c.route_id AND r.source = c.source
+                CROSS JOIN LATERAL (
+                    SELECT d.geom
+                    FROM ST_Dump(ST_LineMerge(r.geom)) d
+                    ORDER BY ST_Distance(d.geom, c.geom)
+                    LIMIT 1
+                ) l
+            )
+            SELECT
+                c.id AS "trip_id!",
+                c.route_id AS "route_id!",
+                c.direction AS "direction!",
+                c.updated_at AS "position_updated_at!",
+                ST_LineLocatePoint(tl.line, c.geom) * tl.length AS "vehicle_distance!",
+                rs.stop_id,
+                rs.stop_sequence,
+                ST_LineLocatePoint(tl.line, s.geom) * tl.length AS "stop_distance!"
+            FROM candidates c
+            JOIN trip_line tl ON tl.trip_id = c.id
+            JOIN static.route_stop rs ON rs.route_id = c.route_id AND rs.source = c.source
+            JOIN static.stop s ON s.id = rs.stop_id AND s.source = rs.source
+            WHERE COALESCE((rs.data->>'direction')::smallint = c.direction, true)
+            ORDER BY c.id, rs.stop_sequence
+            "#,
+            source as _,
+            at
+        )
+        .fetch_all(&self.pg_pool)
+        .await?)
+    }
+
+    /// Upsert stop times predicted by the backend, and delete upcoming ones from earlier runs that weren't predicted
+    /// again. Those belong to trips that the feed has predictions for again, whose vehicle stopped reporting, or stops
+    /// the vehicle already passed. Only the predicted times and data are updated, and stops that the feed marked as
+    /// skipped are left alone. The feed's own predictions replace these once it has some again.
+    pub async fn save_predictions(
+        &self,
+        source: Source,
+        stop_times: &[StopTime],
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<u64> {
+        let trip_ids: Vec<Uuid> = stop_times.iter().map(|st| st.trip_id).collect();
+        let stop_ids: Vec<String> = stop_times
+            .iter()
+            .map(|st| st.stop_id.to_uppercase())
+            .collect();
+        let arrivals: Vec<DateTime<Utc>> = stop_times.iter().map(|st| st.arrival).collect();
+        let departures: Vec<DateTime<Utc>> = stop_times.iter().map(|st| st.departure).collect();
+        let data = stop_times
+            .iter()
+            .map(|st| serde_json::to_value(&st.data))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut tx = self.pg_pool.begin().await?;
+        let saved = sqlx::query!(
+            r#"
+            INSERT INTO realtime.stop_time AS stop_time (trip_id, stop_id, source, arrival, departure, data)
+            SELECT trip_id, stop_id, $1, arrival, departure, data
+            FROM UNNEST($2::uuid[], $3::text[], $4::timestamptz[], $5::timestamptz[], $6::jsonb[])
+                AS st(trip_id, stop_id, arrival, departure, data)
+            ON CONFLICT (trip_id, stop_id, source) DO UPDATE SET
+                arrival = EXCLUDED.arrival,
+                departure = EXCLUDED.departure,
+                data = EXCLUDED.data
+            WHERE stop_time.status = 'scheduled'
+            "#,
+            source as _,
+            &trip_ids,
+            &stop_ids,
+            &arrivals,
+            &departures,
+            &data
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        sqlx::query!(
+            r#"
+            DELETE FROM realtime.stop_time st
+            WHERE
+                st.source = $1
+                AND st.data->>'predicted_by' = 'backend'
+                AND st.arrival >= $2
+                AND (st.trip_id, st.stop_id) NOT IN (
+                    SELECT * FROM UNNEST($3::uuid[], $4::text[])
+                )
+            "#,
+            source as _,
+            now,
+            &trip_ids,
+            &stop_ids
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(saved)
+    }
+
     // TODO: use this instead of inserting in trip store
     // /// Bulk insert stop times (and invalidate cache)
     // #[tracing::instrument(skip(self, stop_times), fields(source = %source.as_str(), count = stop_times.len()), level = "debug")]
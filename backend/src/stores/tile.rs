@@ -0,0 +1,205 @@
+use std::time::Duration;
+
+use bb8_redis::RedisConnectionManager;
+use redis::AsyncCommands;
+use sqlx::PgPool;
+
+use crate::models::tile::{BUFFER, EXTENT, Tile, TileCoord, TileLayer};
+
+/// Static tiles only change when static data is imported, so they're kept for a while
+const STATIC_TTL: Duration = Duration::from_secs(60 * 60);
+/// Positions and their estimates change with each realtime update, every 30 seconds
+const POSITIONS_TTL: Duration = Duration::from_secs(10);
+
+/// Renders Mapbox Vector Tiles with PostGIS and caches them in Redis along with their ETags
+#[derive(Clone)]
+pub struct TileStore {
+    pg_pool: PgPool,
+    redis_pool: bb8::Pool<RedisConnectionManager>,
+}
+
+impl TileStore {
+    pub fn new(pg_pool: PgPool, redis_pool: bb8::Pool<RedisConnectionManager>) -> Self {
+        Self {
+            pg_pool,
+            redis_pool,
+        }
+    }
+
+    fn cache_key(layer: TileLayer, coord: TileCoord) -> String {
+        format!(
+            "tiles:{}:{}:{}:{}",
+            layer.as_str(),
+            coord.z,
+            coord.x,
+            coord.y
+        )
+    }
+
+    /// Cached tile, if present. Returns `None` on miss or error.
+    pub async fn get_cached(&self, layer: TileLayer, coord: TileCoord) -> Option<Tile> {
+        let key = Self::cache_key(layer, coord);
+        let etag_key = format!("{}:etag", key);
+        let mut conn = self.redis_pool.get().await.ok()?;
+        let (data, etag): (Option<Vec<u8>>, Option<String>) =
+            conn.mget(&[&key, &etag_key]).await.ok()?;
+        Some(Tile {
+            data: data?,
+            etag: etag?,
+        })
+    }
+
+    /// Store the tile and the blake3 hash of its contents with the layer's TTL.
+    async fn cache(
+        &self,
+        layer: TileLayer,
+        coord: TileCoord,
+        data: Vec<u8>,
+    ) -> anyhow::Result<Tile> {
+        let etag = blake3::hash(&data).to_hex().to_string();
+        let ttl = match layer {
+            TileLayer::Positions => POSITIONS_TTL,
+            TileLayer::Routes | TileLayer::Stops => STATIC_TTL,
+        };
+        let key = Self::cache_key(layer, coord);
+        let etag_key = format!("{}:etag", key);
+
+        let mut conn = self.redis_pool.get().await?;
+        let _: () = redis::pipe()
+            .set_ex(&key, &data, ttl.as_secs())
+            .set_ex(&etag_key, &etag, ttl.as_secs())
+            .query_async(&mut *conn)
+            .await?;
+
+        Ok(Tile { data, etag })
+    }
+
+    /// Route lines of every source
+    pub async fn render_routes(&self, coord: TileCoord) -> anyhow::Result<Tile> {
+        let data = sqlx::query_scalar!(
+            r#"
+            SELECT COALESCE(ST_AsMVT(tile, 'routes', $4, 'geom'), ''::bytea) AS "data!"
+            FROM (
+                SELECT
+                    r.id,
+                    r.source::text AS source,
+                    r.short_name,
+                    r.long_name,
+                    r.color,
+                    r.data,
+                    ST_AsMVTGeom(
+                        ST_Transform(r.geom, 3857),
+                        ST_TileEnvelope($1, $2, $3),
+                        $4, $5, true
+                    ) AS geom
+                FROM static.route r
+                WHERE r.geom && ST_Transform(ST_TileEnvelope($1, $2, $3), 4326)
+            ) AS tile
+            WHERE geom IS NOT NULL
+            "#,
+            coord.z as i32,
+            coord.x as i32,
+            coord.y as i32,
+            EXTENT as i32,
+            BUFFER as i32
+        )
+        .fetch_one(&self.pg_pool)
+        .await?;
+
+        self.cache(TileLayer::Routes, coord, data).await
+    }
+
+    /// Stops of every source, with the routes that serve them as a comma-separated list
+    pub async fn render_stops(&self, coord: TileCoord) -> anyhow::Result<Tile> {
+        let data = sqlx::query_scalar!(
+            r#"
+            SELECT COALESCE(ST_AsMVT(tile, 'stops', $4, 'geom'), ''::bytea) AS "data!"
+            FROM (
+                SELECT
+                    s.id,
+                    s.source::text AS source,
+                    s.name,
+                    (
+                        SELECT string_agg(rs.route_id, ',' ORDER BY rs.route_id)
+                        FROM static.route_stop rs
+                        WHERE rs.stop_id = s.id AND rs.source = s.source
+                    ) AS route_ids,
+                    s.data,
+                    ST_AsMVTGeom(
+                        ST_Transform(s.geom, 3857),
+                        ST_TileEnvelope($1, $2, $3),
+                        $4, $5, true
+                    ) AS geom
+                FROM static.stop s
+                WHERE s.geom && ST_Transform(ST_TileEnvelope($1, $2, $3), 4326)
+            ) AS tile
+            WHERE geom IS NOT NULL
+            "#,
+            coord.z as i32,
+            coord.x as i32,
+            coord.y as i32,
+            EXTENT as i32,
+            BUFFER as i32
+        )
+        .fetch_one(&self.pg_pool)
+        .await?;
+
+        self.cache(TileLayer::Stops, coord, data).await
+    }
+
+    /// Current vehicle positions at the estimates saved by the last realtime update, or at their feed coordinates
+    /// when they don't have one. Positions without either are left out. Positions in the tile's buffer are included
+    /// so icons aren't cut off at tile edges.
+    pub async fn render_positions(&self, coord: TileCoord) -> anyhow::Result<Tile> {
+        let data = sqlx::query_scalar!(
+            r#"
+            WITH envelope AS (
+                SELECT ST_Transform(ST_TileEnvelope($1, $2, $3, margin => $6), 4326) AS geom
+            ),
+            -- Both lookups can use a GIST index, unlike a filter on the coalesced geometry
+            candidates AS (
+                SELECT e.vehicle_id, e.source
+                FROM realtime.position_estimate e, envelope b
+                WHERE e.geom && b.geom
+                UNION
+                SELECT p.vehicle_id, p.source
+                FROM realtime.vehicle_position p, envelope b
+                WHERE p.geom && b.geom
+            )
+            SELECT COALESCE(ST_AsMVT(tile, 'positions', $4, 'geom'), ''::bytea) AS "data!"
+            FROM (
+                SELECT
+                    p.vehicle_id,
+                    p.trip_id::text AS trip_id,
+                    p.stop_id,
+                    EXTRACT(EPOCH FROM p.updated_at)::bigint AS updated_at,
+                    e.vehicle_id IS NOT NULL AS estimated,
+                    -- Falls back to the feed's bearing, which only some sources' data has
+                    COALESCE(e.bearing, (p.data->>'bearing')::real) AS bearing,
+                    -- The data's tag adds the source to each feature
+                    p.data,
+                    ST_AsMVTGeom(
+                        ST_Transform(COALESCE(e.geom, p.geom), 3857),
+                        ST_TileEnvelope($1, $2, $3),
+                        $4, $5, true
+                    ) AS geom
+                FROM candidates c
+                JOIN realtime.vehicle_position p ON p.vehicle_id = c.vehicle_id AND p.source = c.source
+                LEFT JOIN realtime.position_estimate e ON e.vehicle_id = c.vehicle_id AND e.source = c.source
+                WHERE p.updated_at >= NOW() - INTERVAL '5 minutes'
+            ) AS tile
+            WHERE geom IS NOT NULL
+            "#,
+            coord.z as i32,
+            coord.x as i32,
+            coord.y as i32,
+            EXTENT as i32,
+            BUFFER as i32,
+            BUFFER as f64 / EXTENT as f64
+        )
+        .fetch_one(&self.pg_pool)
+        .await?;
+
+        self.cache(TileLayer::Positions, coord, data).await
+    }
+}
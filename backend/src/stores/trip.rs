@@ -1,19 +1,32 @@
 use crate::{
+    engines::push::{self, Update},
+    metrics,
     models::{
+        ghost::GhostSignals,
         source::Source,
-        trip::{StopTime, Trip},
+        trip::{StopTime, StopTimeStatus, Trip, TripDetailStopTime, TripStatus},
     },
     stores::{cache_get, cache_set},
 };
 use bb8_redis::RedisConnectionManager;
 use chrono::{DateTime, Utc};
-use sqlx::PgPool;
+use sqlx::{FromRow, PgPool};
 use std::time::Instant;
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 use uuid::Uuid;
 
 const TTL: Duration = Duration::from_secs(30);
 
+#[derive(FromRow)]
+struct NextStopTimeRow {
+    trip_id: Uuid,
+    #[sqlx(flatten)]
+    stop_time: TripDetailStopTime,
+}
+
 #[derive(Clone)]
 pub struct TripStore {
     pg_pool: PgPool,
@@ -70,6 +83,8 @@ impl TripStore {
                     t.direction,
                     t.created_at,
                     t.updated_at,
+                    t.status,
+                    t.ghost_confidence,
                     t.data
                 FROM realtime.trip t
                 WHERE
@@ -90,6 +105,294 @@ impl TripStore {
         .await?)
     }
 
+    /// Get a single trip by ID, regardless of when it ran.
+    pub async fn get(&self, id: Uuid) -> anyhow::Result<Option<Trip>> {
+        Ok(sqlx::query_as::<_, Trip>(
+            r#"
+                SELECT
+                    t.id,
+                    t.original_id,
+                    t.vehicle_id,
+                    t.route_id,
+                    t.source,
+                    t.direction,
+                    t.created_at,
+                    t.updated_at,
+                    t.status,
+                    t.ghost_confidence,
+                    t.data
+                FROM realtime.trip t
+                WHERE t.id = $1"#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pg_pool)
+        .await?)
+    }
+
+    /// A trip's stop times with stop names, ordered by arrival.
+    pub async fn get_stop_times(&self, id: Uuid) -> anyhow::Result<Vec<TripDetailStopTime>> {
+        Ok(sqlx::query_as::<_, TripDetailStopTime>(
+            r#"
+                SELECT
+                    st.stop_id,
+                    s.name AS stop_name,
+                    st.arrival,
+                    st.departure,
+                    st.status,
+                    st.data
+                FROM realtime.stop_time st
+                JOIN static.stop s ON s.id = st.stop_id AND s.source = st.source
+                WHERE st.trip_id = $1
+                ORDER BY st.arrival, st.departure"#,
+        )
+        .bind(id)
+        .fetch_all(&self.pg_pool)
+        .await?)
+    }
+
+    /// The next `limit` stops of each trip that the vehicle hasn't departed from yet, in one query.
+    pub async fn get_next_stop_times(
+        &self,
+        ids: &[Uuid],
+        at: DateTime<Utc>,
+        limit: i64,
+    ) -> anyhow::Result<HashMap<Uuid, Vec<TripDetailStopTime>>> {
+        let rows = sqlx::query_as::<_, NextStopTimeRow>(
+            r#"
+            SELECT
+                t.id AS trip_id,
+                st.stop_id,
+                st.stop_name,
+                st.arrival,
+                st.departure,
+                st.status,
+                st.data
+            FROM UNNEST($1::uuid[]) AS t(id)
+            CROSS JOIN LATERAL (
+                SELECT st.stop_id, s.name AS stop_name, st.arrival, st.departure, st.status, st.data
+                FROM realtime.stop_time st
+                JOIN static.stop s ON s.id = st.stop_id AND s.source = st.source
+                WHERE st.trip_id = t.id AND st.departure >= $2
+                ORDER BY st.arrival, st.departure
+                LIMIT $3
+            ) st
+            ORDER BY t.id, st.arrival, st.departure"#,
+        )
+        .bind(ids)
+        .bind(at)
+        .bind(limit)
+        .fetch_all(&self.pg_pool)
+        .await?;
+
+        let mut stop_times: HashMap<Uuid, Vec<TripDetailStopTime>> = HashMap::new();
+        for row in rows {
+            stop_times
+                .entry(row.trip_id)
+                .or_default()
+                .push(row.stop_time);
+        }
+        Ok(stop_times)
+    }
+
+    /// GeoJSON LineString through the trip's history points in the order they were recorded.
+    pub async fn get_path(&self, id: Uuid) -> anyhow::Result<Option<serde_json::Value>> {
+        Ok(sqlx::query_scalar::<_, Option<serde_json::Value>>(
+            r#"
+                SELECT ST_AsGeoJSON(ST_MakeLine(geom ORDER BY recorded_at))::jsonb
+                FROM realtime.trip_history_point
+                WHERE trip_id = $1
+                HAVING COUNT(*) >= 2"#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pg_pool)
+        .await?
+        .flatten())
+    }
+
+    /// GeoJSON LineString of the trip's route clipped between its first and last stops.
+    /// Routes are stored as multi line strings, so the line closest to both stops is used.
+    pub async fn get_shape(&self, id: Uuid) -> anyhow::Result<Option<serde_json::Value>> {
+        Ok(sqlx::query_scalar::<_, Option<serde_json::Value>>(
+            r#"
+                WITH stops AS (
+                    SELECT s.geom, st.arrival
+                    FROM realtime.stop_time st
+                    JOIN static.stop s ON s.id = st.stop_id AND s.source = st.source
+                    WHERE st.trip_id = $1
+                ),
+                ends AS (
+                    SELECT
+                        (SELECT geom FROM stops ORDER BY arrival ASC LIMIT 1) AS first_stop,
+                        (SELECT geom FROM stops ORDER BY arrival DESC LIMIT 1) AS last_stop
+                ),
+                line AS (
+                    SELECT d.geom
+                    FROM realtime.trip t
+                    JOIN static.route r ON r.id = t.route_id AND r.source = t.source
+                    CROSS JOIN LATERAL ST_Dump(ST_LineMerge(r.geom)) d
+                    CROSS JOIN ends e
+                    WHERE t.id = $1
+                    ORDER BY ST_Distance(d.geom, e.first_stop) + ST_Distance(d.geom, e.last_stop)
+                    LIMIT 1
+                ),
+                fractions AS (
+                    SELECT
+                        l.geom,
+                        ST_LineLocatePoint(l.geom, e.first_stop) AS start_fraction,
+                        ST_LineLocatePoint(l.geom, e.last_stop) AS end_fraction
+                    FROM line l
+                    CROSS JOIN ends e
+                )
+                SELECT ST_AsGeoJSON(
+                    CASE
+                        WHEN start_fraction <= end_fraction
+                            THEN ST_LineSubstring(geom, start_fraction, end_fraction)
+                        -- The line is drawn in the opposite direction of the trip
+                        ELSE ST_Reverse(ST_LineSubstring(geom, end_fraction, start_fraction))
+                    END
+                )::jsonb
+                FROM fractions"#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pg_pool)
+        .await?
+        .flatten())
+    }
+
+    /// Running trips that have been suspected as ghosts, most likely first.
+    pub async fn get_ghosts(
+        &self,
+        source: Source,
+        min_confidence: f32,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<Trip>> {
+        sqlx::query!(
+            r#"
+                SELECT
+                    t.id,
+                    t.original_id,
+                    t.vehicle_id,
+                    t.route_id,
+                    t.direction,
+                    t.created_at,
+                    t.updated_at,
+                    t.status AS "status: TripStatus",
+                    t.ghost_confidence,
+                    t.data
+                FROM realtime.trip t
+                WHERE
+                    t.source = $1
+                    AND t.ghost_confidence >= $2
+                    AND t.updated_at >= ($3::timestamptz - INTERVAL '5 minutes')
+                ORDER BY t.ghost_confidence DESC, t.route_id"#,
+            source as _,
+            min_confidence,
+            at
+        )
+        .fetch_all(&self.pg_pool)
+        .await?
+        .into_iter()
+        .map(|r| {
+            Ok(Trip {
+                id: r.id,
+                original_id: r.original_id,
+                vehicle_id: r.vehicle_id,
+                route_id: r.route_id,
+                direction: r.direction,
+                created_at: r.created_at,
+                updated_at: r.updated_at,
+                status: r.status,
+                ghost_confidence: r.ghost_confidence,
+                data: serde_json::from_value(r.data)?,
+            })
+        })
+        .collect()
+    }
+
+    /// Signals for every trip that should have a vehicle running it right now. A trip is running if it's still in the
+    /// feed, isn't canceled, has departed its first stop and has a stop left.
+    pub async fn get_ghost_signals(
+        &self,
+        source: Source,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<GhostSignals>> {
+        Ok(sqlx::query_as!(
+            GhostSignals,
+            r#"
+                WITH running AS (
+                    SELECT
+                        t.id,
+                        t.route_id,
+                        t.source,
+                        t.updated_at,
+                        (
+                            SELECT st.stop_id
+                            FROM realtime.stop_time st
+                            WHERE st.trip_id = t.id AND st.status = 'scheduled' AND st.arrival >= $2
+                            ORDER BY st.arrival
+                            LIMIT 1
+                        ) AS next_stop_id
+                    FROM realtime.trip t
+                    WHERE
+                        t.source = $1
+                        AND t.status <> 'canceled'
+                        AND t.updated_at >= ($2::timestamptz - INTERVAL '5 minutes')
+                        AND EXISTS (
+                            SELECT 1 FROM realtime.stop_time st WHERE st.trip_id = t.id AND st.departure <= $2
+                        )
+                )
+                SELECT
+                    t.id AS "trip_id!",
+                    t.route_id AS "route_id!",
+                    t.updated_at AS "trip_updated_at!",
+                    p.updated_at AS "position_updated_at?",
+                    ST_Distance(p.geom::geography, r.geom::geography) AS "distance_from_route?",
+                    ABS(gap.stops)::int AS "stop_gap?"
+                FROM running t
+                JOIN static.route r ON r.id = t.route_id AND r.source = t.source
+                LEFT JOIN LATERAL (
+                    SELECT vp.updated_at, vp.geom, vp.stop_id
+                    FROM realtime.vehicle_position vp
+                    WHERE vp.trip_id = t.id AND vp.source = t.source
+                    ORDER BY vp.updated_at DESC
+                    LIMIT 1
+                ) p ON true
+                -- Counted along the trip's own stop times, since route_stop mixes branches and both directions
+                LEFT JOIN LATERAL (
+                    SELECT
+                        MAX(s.position) FILTER (WHERE s.stop_id = t.next_stop_id)
+                        - MAX(s.position) FILTER (WHERE s.stop_id = p.stop_id) AS stops
+                    FROM (
+                        SELECT st.stop_id, ROW_NUMBER() OVER (ORDER BY st.arrival) AS position
+                        FROM realtime.stop_time st
+                        WHERE st.trip_id = t.id
+                    ) s
+                ) gap ON true
+                WHERE t.next_stop_id IS NOT NULL"#,
+            source as _,
+            at
+        )
+        .fetch_all(&self.pg_pool)
+        .await?)
+    }
+
+    /// Set the ghost confidence of trips from the last detection run.
+    pub async fn save_ghost_confidences(&self, confidences: &[(Uuid, f32)]) -> anyhow::Result<()> {
+        let (ids, values): (Vec<Uuid>, Vec<f32>) = confidences.iter().copied().unzip();
+        sqlx::query!(
+            r#"
+                UPDATE realtime.trip t
+                SET ghost_confidence = c.confidence
+                FROM UNNEST($1::uuid[], $2::real[]) AS c(id, confidence)
+                WHERE t.id = c.id"#,
+            &ids,
+            &values
+        )
+        .execute(&self.pg_pool)
+        .await?;
+        Ok(())
+    }
+
     /// Bulk insert trips with their stop times so we can remap to the correct trip IDs.
     /// Returns a map of input_id -> actual_id for callers that need to reference the saved trips.
     #[tracing::instrument(skip(self, data), fields(source = %source.as_str(), count = data.len()), level = "debug")]
@@ -109,7 +412,8 @@ impl TripStore {
         let input_ids: Vec<Uuid> = data.iter().map(|(t, _)| t.id).collect();
         // TODO: maybe make original and vehicle id uppercase for consistency
         let original_ids: Vec<String> = data.iter().map(|(t, _)| t.original_id.clone()).collect();
-        let vehicle_ids: Vec<String> = data.iter().map(|(t, _)| t.vehicle_id.clone()).collect();
+        let vehicle_ids: Vec<Option<String>> =
+            data.iter().map(|(t, _)| t.vehicle_id.clone()).collect();
         let route_ids: Vec<String> = data
             .iter()
             .map(|(t, _)| t.route_id.to_uppercase())
@@ -118,6 +422,7 @@ impl TripStore {
         let directions: Vec<i16> = data.iter().map(|(t, _)| t.direction).collect();
         let created_ats: Vec<DateTime<Utc>> = data.iter().map(|(t, _)| t.created_at).collect();
         let updated_ats: Vec<DateTime<Utc>> = data.iter().map(|(t, _)| t.updated_at).collect();
+        let statuses: Vec<TripStatus> = data.iter().map(|(t, _)| t.status).collect();
         let trip_data: Vec<serde_json::Value> = data
             .iter()
             .map(|(t, _)| serde_json::to_value(&t.data).unwrap())
@@ -127,50 +432,70 @@ impl TripStore {
         // Bulk insert/upsert trips and get mapping from input_id -> actual_id
         // We use a CTE to map the input_id (which we generated) to the actual_id (from DB)
         // matching on the unique constraint columns.
-        let records  = sqlx::query!(
+        // existing_rows sees the table from before the upsert, so it tells us which trips are new or changed.
+        let records = sqlx::query!(
             r#"
             WITH input_rows AS (
                 SELECT * FROM UNNEST(
                     $1::uuid[], $2::text[], $3::text[], $4::text[], $5::source_enum[],
-                    $6::smallint[], $7::timestamptz[], $8::timestamptz[], $9::jsonb[]
-                ) AS t(input_id, original_id, vehicle_id, route_id, source, direction, created_at, updated_at, data)
+                    $6::smallint[], $7::timestamptz[], $8::timestamptz[], $9::jsonb[], $10::trip_status[]
+                ) AS t(input_id, original_id, vehicle_id, route_id, source, direction, created_at, updated_at, data, status)
+            ),
+            existing_rows AS (
+                SELECT t.id, t.data, t.status
+                FROM realtime.trip t
+                JOIN input_rows ON
+                    t.original_id = input_rows.original_id AND
+                    t.vehicle_id IS NOT DISTINCT FROM input_rows.vehicle_id AND
+                    t.created_at = input_rows.created_at AND
+                    t.direction = input_rows.direction
             ),
             inserted_rows AS (
-                INSERT INTO realtime.trip (id, original_id, vehicle_id, route_id, source, direction, created_at, updated_at, data)
-                SELECT input_id, original_id, vehicle_id, route_id, source, direction, created_at, updated_at, data
+                INSERT INTO realtime.trip (id, original_id, vehicle_id, route_id, source, direction, created_at, updated_at, data, status)
+                SELECT input_id, original_id, vehicle_id, route_id, source, direction, created_at, updated_at, data, status
                 FROM input_rows
                 ON CONFLICT (original_id, vehicle_id, created_at, direction) DO UPDATE SET
                     data = EXCLUDED.data,
+                    status = EXCLUDED.status,
                     updated_at = EXCLUDED.updated_at
                 RETURNING id, original_id, vehicle_id, created_at, direction
             )
             SELECT
                 inserted_rows.id AS actual_id,
-                input_rows.input_id
+                input_rows.input_id AS "input_id!",
+                (
+                    existing_rows.id IS NULL
+                    OR (existing_rows.data, existing_rows.status) IS DISTINCT FROM (input_rows.data, input_rows.status)
+                ) AS "changed!"
             FROM inserted_rows
             JOIN input_rows ON
                 inserted_rows.original_id = input_rows.original_id AND
-                inserted_rows.vehicle_id = input_rows.vehicle_id AND
+                inserted_rows.vehicle_id IS NOT DISTINCT FROM input_rows.vehicle_id AND
                 inserted_rows.created_at = input_rows.created_at AND
                 inserted_rows.direction = input_rows.direction
+            LEFT JOIN existing_rows ON existing_rows.id = inserted_rows.id
             "#,
             &input_ids,
             &original_ids,
-            &vehicle_ids,
+            &vehicle_ids as &[Option<String>],
             &route_ids,
             &sources as &[Source],
             &directions as &[i16],
             &created_ats,
             &updated_ats,
-            &trip_data
+            &trip_data,
+            &statuses as &[TripStatus]
         )
         .fetch_all(&self.pg_pool)
         .await?;
 
         // Create a map for quick lookup
-        let id_map: HashMap<Uuid, Uuid> = records
-            .into_iter()
-            .filter_map(|r| r.input_id.map(|id| (id, r.actual_id)))
+        let id_map: HashMap<Uuid, Uuid> =
+            records.iter().map(|r| (r.input_id, r.actual_id)).collect();
+        let mut changed_trips: HashSet<Uuid> = records
+            .iter()
+            .filter(|r| r.changed)
+            .map(|r| r.actual_id)
             .collect();
 
         // Prepare stop times for bulk insert
@@ -178,9 +503,10 @@ impl TripStore {
         let mut st_stop_ids = Vec::new();
         let mut st_arrivals = Vec::new();
         let mut st_departures = Vec::new();
+        let mut st_statuses = Vec::new();
         let mut st_data = Vec::new();
 
-        let mut seen_stop_times = std::collections::HashSet::new();
+        let mut seen_stop_times = HashSet::new();
         let mut duplicate_count = 0usize;
 
         for (trip, sts) in data {
@@ -196,6 +522,7 @@ impl TripStore {
                     st_stop_ids.push(stop_id);
                     st_arrivals.push(st.arrival);
                     st_departures.push(st.departure);
+                    st_statuses.push(st.status);
                     st_data.push(serde_json::to_value(&st.data).unwrap());
                 }
             }
@@ -207,25 +534,43 @@ impl TripStore {
         }
         // im inserting the stop times in the same functions as trips since the stop time struct doesn't have a trip_id field
         // might want to return the mapping or put this in a separate function later (so it can cache and invalidate that cache)
+        // Unchanged stop times are skipped by the WHERE clause, so only new and changed ones are returned
+        let mut changed_stop_times = vec![];
         if !st_trip_ids.is_empty() {
-            sqlx::query!(
+            changed_stop_times = sqlx::query!(
                 r#"
-                INSERT INTO realtime.stop_time (trip_id, stop_id, source, arrival, departure, data)
-                SELECT * FROM UNNEST($1::uuid[], $2::text[], $3::source_enum[], $4::timestamptz[], $5::timestamptz[], $6::jsonb[])
+                INSERT INTO realtime.stop_time AS st (trip_id, stop_id, source, arrival, departure, data, status)
+                SELECT * FROM UNNEST($1::uuid[], $2::text[], $3::source_enum[], $4::timestamptz[], $5::timestamptz[], $6::jsonb[], $7::stop_time_status[])
                 ON CONFLICT (trip_id, stop_id, source) DO UPDATE SET
                     arrival = EXCLUDED.arrival,
                     departure = EXCLUDED.departure,
-                    data = EXCLUDED.data
+                    data = EXCLUDED.data,
+                    status = EXCLUDED.status
+                WHERE (st.arrival, st.departure, st.data, st.status) IS DISTINCT FROM (EXCLUDED.arrival, EXCLUDED.departure, EXCLUDED.data, EXCLUDED.status)
+                RETURNING st.trip_id, st.stop_id, st.arrival, st.departure, st.status AS "status: StopTimeStatus", st.data
                 "#,
                 &st_trip_ids,
                 &st_stop_ids,
                 &vec![source; st_trip_ids.len()] as _,
                 &st_arrivals,
                 &st_departures,
-                &st_data
+                &st_data,
+                &st_statuses as &[StopTimeStatus]
             )
-            .execute(&self.pg_pool)
-            .await?;
+            .fetch_all(&self.pg_pool)
+            .await?
+            .into_iter()
+            .map(|r| {
+                Ok(StopTime {
+                    trip_id: r.trip_id,
+                    stop_id: r.stop_id,
+                    arrival: r.arrival,
+                    departure: r.departure,
+                    status: r.status,
+                    data: serde_json::from_value(r.data)?,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
             // TODO: invalidate stop time cache if we have one
         }
 
@@ -237,10 +582,35 @@ impl TripStore {
             st_trip_ids.len(),
             elapsed.as_secs_f64()
         );
+        metrics::counter!(metrics::ROWS_UPSERTED, "source" => source.as_str(), "table" => "trip")
+            .increment(data.len() as u64);
+        metrics::counter!(metrics::ROWS_UPSERTED, "source" => source.as_str(), "table" => "stop_time")
+            .increment(st_trip_ids.len() as u64);
+        metrics::histogram!(metrics::REALTIME_SAVE_SECONDS, "source" => source.as_str())
+            .record(elapsed.as_secs_f64());
 
         // Populate trips cache (write-through)
         self.populate_cache(source).await?;
 
+        changed_trips.extend(changed_stop_times.iter().map(|st| st.trip_id));
+        let trips = data
+            .iter()
+            .filter_map(|(trip, _)| {
+                let actual_id = *id_map.get(&trip.id)?;
+                // Duplicate trips in the feed map to the same row, so only publish each one once
+                changed_trips.remove(&actual_id).then(|| Trip {
+                    id: actual_id,
+                    route_id: trip.route_id.to_uppercase(),
+                    ..trip.clone()
+                })
+            })
+            .collect();
+        push::publish(Update::Trips {
+            source,
+            trips,
+            stop_times: changed_stop_times,
+        });
+
         // might want to insert positions from here instead of returning the map
         Ok(id_map)
     }
@@ -1,9 +1,10 @@
 use backend::api::router;
 use backend::AppState;
+use backend::engines::planner::Planner;
 use axum_test::TestServer;
 use sqlx::postgres::PgPoolOptions;
 use bb8_redis::RedisConnectionManager;
-use backend::stores::{route::RouteStore, stop::StopStore, trip::TripStore, stop_time::StopTimeStore, position::PositionStore, alert::AlertStore};
+use backend::stores::{route::RouteStore, stop::StopStore, trip::TripStore, stop_time::StopTimeStore, position::PositionStore, alert::AlertStore, history::HistoryStore, static_cache::StaticCacheStore, tile::TileStore, api_key::ApiKeyStore};
 
 #[tokio::test]
 async fn test_health_route() {
@@ -30,5 +31,80 @@ async fn mock_app_state() -> AppState {
         StopTimeStore::new(pg_pool.clone(), redis_pool.clone()),
         PositionStore::new(pg_pool.clone(), redis_pool.clone()),
         AlertStore::new(pg_pool.clone(), redis_pool.clone()),
+        HistoryStore::new(pg_pool.clone()),
+        StaticCacheStore::new(redis_pool.clone()),
+        TileStore::new(pg_pool.clone(), redis_pool.clone()),
+        ApiKeyStore::new(pg_pool.clone(), redis_pool.clone()),
+        Planner::default(),
     )
 }
+
+#[tokio::test]
+async fn test_unknown_source_rejected() {
+    let state = mock_app_state().await;
+    let (router, _) = router(state).split_for_parts();
+    let server = TestServer::new(router);
+    let response = server.get("/stops?sources=mta_subway,nope").await;
+    response.assert_status_bad_request();
+}
+
+#[tokio::test]
+async fn test_nearby_stops_invalid_coordinates() {
+    let state = mock_app_state().await;
+    let (router, _) = router(state).split_for_parts();
+    let server = TestServer::new(router);
+    let response = server.get("/stops/nearby?lat=100&lon=-73.98").await;
+    response.assert_status_bad_request();
+}
+
+#[tokio::test]
+async fn test_admin_requires_token() {
+    let state = mock_app_state().await;
+    let (router, _) = router(state).split_for_parts();
+    let server = TestServer::new(router);
+    let response = server.get("/admin/keys").await;
+    response.assert_status_unauthorized();
+}
+
+#[tokio::test]
+async fn test_metrics_route() {
+    backend::metrics::install();
+    let state = mock_app_state().await;
+    let (router, _) = router(state).split_for_parts();
+    let server = TestServer::new(router);
+    server.get("/health").await.assert_status_ok();
+
+    // Metrics are only served on the internal listener
+    server.get("/metrics").await.assert_status_not_found();
+
+    let metrics_server = TestServer::new(backend::api::metrics::router());
+    let response = metrics_server.get("/metrics").await;
+    response.assert_status_ok();
+    assert!(response.text().contains(
+        "http_request_duration_seconds_count{method=\"GET\",route=\"/health\",status=\"200\"}"
+    ));
+}
+
+#[tokio::test]
+async fn test_status_route() {
+    let state = mock_app_state().await;
+    let (router, _) = router(state).split_for_parts();
+    let server = TestServer::new(router);
+
+    let response = server.get("/status").await;
+    response.assert_status_ok();
+    let report: serde_json::Value = response.json();
+    assert_eq!(report["status"], "healthy");
+    assert_eq!(report["thresholds"]["unhealthy_after_secs"], 600);
+}
+
+#[tokio::test]
+async fn test_trip_detail_documented_by_id() {
+    let state = mock_app_state().await;
+    let (router, openapi) = router(state).split_for_parts();
+    assert!(openapi.paths.paths.contains_key("/trip/{id}"));
+    assert!(openapi.paths.paths.contains_key("/trips/{source}"));
+    let server = TestServer::new(router);
+    server.get("/trip/nope").await.assert_status_bad_request();
+    server.get("/trips/nope").await.assert_status_bad_request();
+}
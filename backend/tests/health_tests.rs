@@ -0,0 +1,49 @@
+use axum::{Router, routing::get};
+use axum_test::TestServer;
+use backend::api::status::health_handler;
+use backend::engines::status;
+use backend::models::{source::Source, status::FeedKind};
+use chrono::Utc;
+
+// Status is tracked per process, so these run apart from the other API tests
+#[tokio::test]
+async fn test_health_readiness() {
+    // SAFETY: nothing else in this test binary reads the environment
+    unsafe { std::env::set_var("REQUIRED_SOURCES", "lirr") };
+    let server = TestServer::new(Router::new().route("/health", get(health_handler)));
+    let stale = (Utc::now() - chrono::Duration::minutes(15)).timestamp() as u64;
+
+    // Stale feeds from a source that isn't required don't make the replica unready
+    status::register(Source::MtaSubway);
+    status::record_run(Source::MtaSubway, &Ok(()));
+    status::record_feed(
+        Source::MtaSubway,
+        FeedKind::Realtime,
+        "mta_subway-ace",
+        Some(stale),
+        100,
+    );
+    server.get("/health").await.assert_text("OK");
+
+    // A required source without fresh data does
+    status::register(Source::Lirr);
+    status::record_run(Source::Lirr, &Ok(()));
+    status::record_feed(
+        Source::Lirr,
+        FeedKind::Realtime,
+        "lirr-gtfs",
+        Some(stale),
+        100,
+    );
+    let response = server.get("/health").await;
+    response.assert_status_service_unavailable();
+    response.assert_text("Unhealthy: lirr");
+
+    // And so does any pipeline that panicked
+    status::record_feed(Source::Lirr, FeedKind::Realtime, "lirr-gtfs", None, 100);
+    server.get("/health").await.assert_text("OK");
+    status::record_panic(Source::MtaSubway);
+    let response = server.get("/health").await;
+    response.assert_status_service_unavailable();
+    response.assert_text("Unhealthy: mta_subway");
+}
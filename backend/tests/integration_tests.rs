@@ -39,12 +39,14 @@ async fn test_save_trip_to_db(pool: sqlx::PgPool) {
     let trip = Trip {
         id: trip_id,
         original_id: "test_trip".to_string(),
-        vehicle_id: "test_vehicle".to_string(),
+        vehicle_id: Some("test_vehicle".to_string()),
         route_id: "1".to_string(),
         direction: 1,
         created_at: now,
         updated_at: now,
-        data: TripData::MtaSubway,
+        status: backend::models::trip::TripStatus::Scheduled,
+        ghost_confidence: None,
+        data: TripData::MtaSubway(backend::models::trip::MtaSubwayData { delay: None }),
     };
 
     let stop_time = StopTime {
@@ -52,9 +54,11 @@ async fn test_save_trip_to_db(pool: sqlx::PgPool) {
         stop_id: "101".to_string(),
         arrival,
         departure: arrival,
+        status: backend::models::trip::StopTimeStatus::Scheduled,
         data: StopTimeData::MtaSubway(backend::models::trip::MtaSubwayStopTimeData {
             scheduled_track: None,
             actual_track: None,
+            delay: None,
         }),
     };
 
@@ -0,0 +1,126 @@
+use backend::engines::static_cache::{expand_gtfs_for_dates, update_timezone};
+use backend::models::source::Source;
+use backend::models::static_cache::CachedTrip;
+use backend::models::trip::Trip;
+use backend::sources::source_timezone;
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::America::{Los_Angeles, New_York};
+use std::path::PathBuf;
+
+// The fixture agency is in America/Los_Angeles. US DST transitions in 2025 are March 9 and November 2.
+fn load_dst_fixture() -> gtfs_structures::Gtfs {
+    let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    d.push("tests/fixtures/gtfs_dst");
+    gtfs_structures::Gtfs::new(d.to_str().unwrap()).expect("Failed to read GTFS fixture")
+}
+
+fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+fn utc(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+}
+
+fn expand(dates: &[NaiveDate]) -> Vec<CachedTrip> {
+    let gtfs = load_dst_fixture();
+    let tz = update_timezone(Source::Gtfs("dst_test"), &gtfs, New_York);
+    expand_gtfs_for_dates(Source::Gtfs("dst_test"), &gtfs, tz, dates)
+}
+
+fn arrivals(trips: &[CachedTrip], trip_id: &str) -> Vec<DateTime<Utc>> {
+    let trip = trips
+        .iter()
+        .find(|t| t.trip_id == trip_id)
+        .unwrap_or_else(|| panic!("{trip_id} wasn't expanded"));
+    let mut stop_times = trip.stop_times.clone();
+    stop_times.sort_by_key(|st| st.stop_sequence);
+    stop_times.into_iter().map(|st| st.arrival).collect()
+}
+
+#[test]
+fn test_update_timezone_uses_agency_timezone() {
+    let source = Source::Gtfs("dst_timezone_test");
+    assert_eq!(
+        update_timezone(source, &load_dst_fixture(), New_York),
+        Los_Angeles
+    );
+    assert_eq!(source_timezone(source), Los_Angeles);
+}
+
+#[test]
+fn test_expand_gtfs_spring_forward() {
+    let trips = expand(&[date(2025, 3, 9)]);
+
+    // Service day starts at noon minus 12h, which is 11 PM PST the night before (07:00 UTC).
+    // 02:30 doesn't exist on the wall clock but still maps to exactly one instant.
+    assert_eq!(
+        arrivals(&trips, "spring_overnight"),
+        vec![
+            utc(2025, 3, 9, 8, 30),
+            utc(2025, 3, 9, 9, 30),
+            utc(2025, 3, 9, 10, 30)
+        ]
+    );
+
+    // After the transition, times match the PDT wall clock
+    assert_eq!(
+        arrivals(&trips, "spring_afternoon"),
+        vec![utc(2025, 3, 9, 21, 0), utc(2025, 3, 9, 21, 30)]
+    );
+
+    // Trips are only expanded on their service dates
+    assert!(trips.iter().all(|t| t.start_date == "20250309"));
+}
+
+#[test]
+fn test_expand_gtfs_fall_back() {
+    let trips = expand(&[date(2025, 11, 1), date(2025, 11, 2)]);
+
+    // Service day starts at 1 AM PDT (08:00 UTC), so 01:30 is the second (PST) 1:30 AM
+    assert_eq!(
+        arrivals(&trips, "fall_overnight"),
+        vec![
+            utc(2025, 11, 2, 8, 30),
+            utc(2025, 11, 2, 9, 30),
+            utc(2025, 11, 2, 10, 30)
+        ]
+    );
+
+    assert_eq!(
+        arrivals(&trips, "fall_afternoon"),
+        vec![utc(2025, 11, 2, 22, 0), utc(2025, 11, 2, 22, 30)]
+    );
+
+    // Times past 24:00 on the day before run into the repeated hour
+    assert_eq!(
+        arrivals(&trips, "normal_after_midnight"),
+        vec![utc(2025, 11, 2, 7, 30), utc(2025, 11, 2, 8, 30)]
+    );
+}
+
+#[test]
+fn test_trip_created_at_dst() {
+    let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+
+    // 2:30 AM is skipped on the wall clock in New York on spring forward
+    assert_eq!(
+        Trip::created_at(date(2025, 3, 9), time(2, 30), New_York),
+        Some(utc(2025, 3, 9, 6, 30))
+    );
+    // 1:30 AM happens twice on fall back, and the service day starts at the first 1 AM
+    assert_eq!(
+        Trip::created_at(date(2025, 11, 2), time(1, 30), New_York),
+        Some(utc(2025, 11, 2, 6, 30))
+    );
+
+    // Same local time in a different timezone
+    assert_eq!(
+        Trip::created_at(date(2025, 3, 9), time(14, 0), Los_Angeles),
+        Some(utc(2025, 3, 9, 21, 0))
+    );
+    assert_eq!(
+        Trip::created_at(date(2025, 3, 9), time(14, 0), New_York),
+        Some(utc(2025, 3, 9, 18, 0))
+    );
+}